@@ -1,5 +1,29 @@
 #!/bin/bash -e
-rm -f init.elf
-cargo build --target=x86_64-unknown-linux-gnu
-ld --gc-sections target/x86_64-unknown-linux-gnu/debug/libsearust_core.a -o init.elf
+rm -f init.elf bootmodules.tar
+# `./build.sh test [filter]` builds an init.elf that runs the in-target tests after boot, and exits with the result
+FEATURES=""
+if [ "$1" = "test" ]; then
+    FEATURES="ktest"
+    export KTEST_FILTER="$2"
+    [ -n "$KTEST_FILTER" ] || unset KTEST_FILTER
+    touch src/ktest/mod.rs # cargo doesn't notice when the filter changes
+fi
+# `HEAP_DEBUG=1 ./build.sh` builds with the debug heap, and with frame pointers, which it follows to find callers
+if [ -n "$HEAP_DEBUG" ]; then
+    FEATURES="$FEATURES heapdebug"
+    export RUSTFLAGS="$RUSTFLAGS -C force-frame-pointers=yes"
+fi
+# `HIRES=1 ./build.sh` shows the debug log on a high-resolution framebuffer once the display adapter is found
+if [ -n "$HIRES" ]; then
+    FEATURES="$FEATURES hires"
+fi
+cargo build --target=x86_64-unknown-linux-gnu --features "$FEATURES"
+# anything in bootmodules/ is linked in as an archive, which ends up as the contents of the root filesystem
+MODULES=""
+if [ -d bootmodules ]; then
+    tar --format=ustar -cf bootmodules.tar -C bootmodules .
+    MODULES="--format=binary bootmodules.tar --format=default"
+fi
+ld --gc-sections target/x86_64-unknown-linux-gnu/debug/libsearust_core.a $MODULES -o init.elf
+rm -f bootmodules.tar
 install -D -m 644 init.elf $SYSROOT/boot/init.elf
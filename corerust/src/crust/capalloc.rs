@@ -93,6 +93,14 @@ pub fn free_cap_slots(cs: CapSlotSet) {
     merge_caprange(cs.deconstruct());
 }
 
+pub fn free_slot_count() -> usize {
+    let mut total = 0;
+    for range in &*get_avail_caps_list() {
+        total += range.len();
+    }
+    total
+}
+
 pub fn init_cslots(cs: CapRange) {
     assert!(!cs.is_empty());
     merge_caprange(cs);
@@ -3,6 +3,8 @@
 pub mod start;
 pub mod capalloc;
 pub mod vspace;
+pub mod thread;
+pub mod shell;
 
 // TODO: find a better place
 pub const ROOT_SLOT: usize = ::mantle::kernel::CAP_INIT_CNODE;
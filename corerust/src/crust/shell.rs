@@ -0,0 +1,248 @@
+use ::core;
+use ::core::fmt::Write;
+use ::core::cell::RefCell;
+use ::mantle::concurrency::SingleThreaded;
+use ::drivers::console;
+use ::drivers::console::ConsoleWriter;
+use ::drivers::keyboard::{Key, KeyEvent};
+use ::drivers::block;
+use ::fs::vfs;
+use ::fs::fat::FatFs;
+use ::memory::Box;
+use ::crust::thread::Thread;
+use ::drivers::gdb::GdbStub;
+use ::drivers::serial;
+
+// a minimal interactive shell, attached to one of the virtual consoles
+
+const MAX_LINE: usize = 78;
+const PROMPT: &str = "\x1b[32m>\x1b[0m ";
+
+type Command = fn(&mut ConsoleWriter, &str) -> core::fmt::Result;
+
+const COMMANDS: [(&str, &str, Command); 13] = [
+    ("help", "list the available commands", cmd_help),
+    ("clear", "clear the screen", cmd_clear),
+    ("echo", "print the rest of the line", cmd_echo),
+    ("mem", "print memory usage; -v also lists the untyped pools", cmd_mem),
+    ("heap", "list outstanding heap allocations, with the debug heap", cmd_heap),
+    ("disks", "list block devices and buffer cache statistics", cmd_disks),
+    ("acpi", "list ACPI tables and what they describe", cmd_acpi),
+    ("poweroff", "sync the disks and turn the machine off", cmd_poweroff),
+    ("reboot", "sync the disks and restart the machine", cmd_reboot),
+    ("ls", "list a directory", cmd_ls),
+    ("cat", "print a file", cmd_cat),
+    ("mount", "mount a FAT disk on a directory, or list mounts", cmd_mount),
+    ("gdb", "start a thread and debug it with gdb over COM2", cmd_gdb),
+];
+
+struct Shell {
+    console: usize,
+    line: [u8; MAX_LINE],
+    len: usize
+}
+
+static SHELL: SingleThreaded<RefCell<Shell>> = SingleThreaded(RefCell::new(Shell { console: 0, line: [0; MAX_LINE], len: 0 }));
+
+fn cmd_help(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    for &(name, help, _) in COMMANDS.iter() {
+        writeln!(out, "  {:8} {}", name, help)?;
+    }
+    Ok(())
+}
+
+fn cmd_clear(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    write!(out, "\x1b[2J\x1b[H")
+}
+
+fn cmd_echo(out: &mut ConsoleWriter, args: &str) -> core::fmt::Result {
+    writeln!(out, "{}", args)
+}
+
+fn cmd_mem(out: &mut ConsoleWriter, args: &str) -> core::fmt::Result {
+    writeln!(out, "{}", ::memory::stats())?;
+    match args.trim() {
+        "" => Ok(()),
+        "-v" => ::memory::untyped::get_allocator().print_info(out),
+        _ => writeln!(out, "usage: mem [-v]")
+    }
+}
+
+fn cmd_heap(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    ::memory::dump_heap(out)
+}
+
+fn cmd_disks(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    ::drivers::block::print_devices(out)?;
+    writeln!(out, "cache: {}", ::drivers::block::stats())
+}
+
+fn cmd_acpi(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    ::drivers::acpi::print_tables(out)
+}
+
+fn cmd_poweroff(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    match ::drivers::power::shutdown() {
+        Ok(()) => Ok(()),
+        Err(err) => writeln!(out, "could not power off: {:?}", err)
+    }
+}
+
+fn cmd_reboot(_: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    ::drivers::power::reboot()
+}
+
+fn cmd_ls(out: &mut ConsoleWriter, args: &str) -> core::fmt::Result {
+    let path = if args.is_empty() { "/" } else { args };
+    let dir = match vfs::open_dir(path) {
+        Ok(dir) => dir,
+        Err(err) => return writeln!(out, "ls: {}: {:?}", path, err)
+    };
+    for entry in dir {
+        match entry {
+            Ok(ref entry) if entry.kind == vfs::Kind::Directory => writeln!(out, "  {}/", entry.name())?,
+            Ok(entry) => writeln!(out, "  {}", entry.name())?,
+            Err(err) => return writeln!(out, "ls: {}: {:?}", path, err)
+        }
+    }
+    Ok(())
+}
+
+fn cmd_cat(out: &mut ConsoleWriter, args: &str) -> core::fmt::Result {
+    let mut file = match vfs::open(args) {
+        Ok(file) => file,
+        Err(err) => return writeln!(out, "cat: {}: {:?}", args, err)
+    };
+    let mut buf = [0u8; 256];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                for &b in &buf[..n] {
+                    out.write_char(b as char)?;
+                }
+            }
+            Err(err) => return writeln!(out, "cat: {}: {:?}", args, err)
+        }
+    }
+}
+
+fn cmd_mount(out: &mut ConsoleWriter, args: &str) -> core::fmt::Result {
+    if args.is_empty() {
+        return vfs::print_mounts(out);
+    }
+    let (disk, path) = match args.find(' ') {
+        Some(i) => (&args[..i], args[i + 1..].trim()),
+        None => return writeln!(out, "usage: mount <disk> <directory>")
+    };
+    let device = match block::find(disk) {
+        Some(device) => device,
+        None => return writeln!(out, "mount: no disk called {}", disk)
+    };
+    let result = FatFs::mount(device).and_then(|fs| {
+        writeln!(out, "{}: {}", disk, fs).ok();
+        vfs::mount(path, Box::new(fs))
+    });
+    if let Err(err) = result {
+        writeln!(out, "mount: {}: {:?}", disk, err)?;
+    }
+    Ok(())
+}
+
+// something for gdb to look at, until there are threads of our own to debug: it counts, yielding each time
+static mut GDB_EXAMPLE_COUNT: usize = 0;
+
+fn gdb_example() -> ! {
+    loop {
+        unsafe { core::ptr::write_volatile(&mut GDB_EXAMPLE_COUNT, GDB_EXAMPLE_COUNT + 1) };
+        ::mantle::kio::yield_();
+    }
+}
+
+// everything else, the shell included, waits until gdb detaches
+fn cmd_gdb(out: &mut ConsoleWriter, _: &str) -> core::fmt::Result {
+    let thread = match Thread::spawn(gdb_example) {
+        Ok(thread) => thread,
+        Err(err) => return writeln!(out, "gdb: could not start a thread: {:?}", err)
+    };
+    writeln!(out, "gdb: debugging a thread at {:#X} over COM2, until gdb detaches", gdb_example as usize)?;
+    match GdbStub::attach(serial::COM2.configure(115200), thread.tcb(), Some(thread.faults())) {
+        Ok(mut stub) => {
+            stub.run();
+            stub.detach();
+        }
+        Err((err, _)) => writeln!(out, "gdb: could not attach: {:?}", err)?
+    }
+    thread.destroy();
+    writeln!(out, "gdb: detached")
+}
+
+impl Shell {
+    fn prompt(&self) {
+        let _ = write!(console::writer(self.console), "{}", PROMPT);
+    }
+
+    fn execute(&mut self) {
+        let mut out = console::writer(self.console);
+        let line = match core::str::from_utf8(&self.line[..self.len]) {
+            Ok(line) => line.trim(),
+            Err(_) => "" // only ASCII is ever typed
+        };
+        self.len = 0;
+        if line.is_empty() {
+            return;
+        }
+        let (name, args) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, "")
+        };
+        for &(cname, _, command) in COMMANDS.iter() {
+            if cname == name {
+                if command(&mut out, args).is_err() {
+                    warn!("shell: output from '{}' was lost", name);
+                }
+                return;
+            }
+        }
+        let _ = writeln!(out, "unknown command '{}'; try 'help'", name);
+    }
+
+    fn handle_key(&mut self, event: KeyEvent) {
+        if !event.pressed || event.control || event.alt {
+            return;
+        }
+        let mut out = console::writer(self.console);
+        match event.key {
+            Key::Char(c) => {
+                if self.len < MAX_LINE {
+                    self.line[self.len] = c;
+                    self.len += 1;
+                    let _ = out.write_char(c as char);
+                }
+            }
+            Key::Backspace => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    let _ = write!(out, "\x08 \x08");
+                }
+            }
+            Key::Enter => {
+                let _ = writeln!(out, "");
+                self.execute();
+                self.prompt();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn start(n: usize) {
+    {
+        let mut shell = SHELL.get().borrow_mut();
+        shell.console = n;
+        shell.len = 0;
+        let _ = writeln!(console::writer(n), "type 'help' for a list of commands");
+        shell.prompt();
+    }
+    console::set_input_handler(n, |event| SHELL.get().borrow_mut().handle_key(event));
+}
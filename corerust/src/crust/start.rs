@@ -38,11 +38,12 @@ fn set_bootinfo(bi: &BootInfo, executable_start: usize) {
     match ::drivers::vga::VGAOutput::default() {
         Ok(mut screen) => {
             writeln!(screen, "Hello, world!").unwrap();
-            mantle::debug::set_mirror(screen);
+            screen.enable_scrollback(::drivers::vga::DEFAULT_SCROLLBACK_LINES);
+            ::drivers::console::install(screen);
             crust::start::print_bootinfo(mantle::debug(), bi).unwrap();
             memory::init_allocator();
             memory::untyped::init_untyped(CapRange::range(bi.untyped.start as usize, bi.untyped.end as usize), bi.untyped_list);
-            memory::untyped::get_allocator().print_info(mantle::debug()).unwrap();
+            writeln!(mantle::debug(), "{}", memory::stats()).unwrap();
         }
         Err(err) => panic!("could not set up default VGA output: {:?}", err)
     }
@@ -0,0 +1,126 @@
+use ::core;
+use ::alloc::boxed::Box;
+use ::alloc::vec::Vec;
+use ::kobject::*;
+use ::mantle::KError;
+use ::mantle::kernel;
+use ::memory::smalluntyped;
+use ::memory::untyped;
+
+// threads of our own, in our cspace and vspace. each has a stack from the heap, an IPC buffer page, and an endpoint
+// that its faults are sent to, which nothing reads unless something like the gdb stub is watching. they run at our
+// priority, so that they get the CPU whenever we yield.
+//
+// they share all of our memory, but none of our SingleThreaded state is safe for them: whatever one runs shouldn't
+// touch the allocators, the console, or anything else of ours.
+
+const STACK_LEN: usize = 16 * kernel::PAGE_4K_SIZE;
+
+pub struct Thread {
+    tcb: TCB,
+    faults: Endpoint,
+    ipc_buffer: RegionMappedPage4K,
+    stack: Box<[u64]>
+}
+
+fn allocate_mapped_page() -> core::result::Result<RegionMappedPage4K, KError> {
+    untyped::allocate_page4k().and_then(|p| p.map_into_vspace(true).map_err(|(p, err)| {
+        untyped::free_page4k(p);
+        err
+    }))
+}
+
+impl Thread {
+    pub fn spawn(entry: fn() -> !) -> core::result::Result<Thread, KError> {
+        let tcb = untyped::allocate_tcb()?;
+        let faults = match smalluntyped::allocate_endpoint() {
+            Ok(ep) => ep,
+            Err(err) => {
+                untyped::free_tcb(tcb);
+                return Err(err);
+            }
+        };
+        let ipc_buffer = match allocate_mapped_page() {
+            Ok(page) => page,
+            Err(err) => {
+                untyped::free_tcb(tcb);
+                smalluntyped::free_endpoint(faults);
+                return Err(err);
+            }
+        };
+        let mut stack = Vec::with_capacity(STACK_LEN / 8);
+        stack.resize(STACK_LEN / 8, 0);
+        let thread = Thread { tcb, faults, ipc_buffer, stack: stack.into_boxed_slice() };
+        match thread.start(entry) {
+            Ok(()) => Ok(thread),
+            Err(err) => {
+                thread.destroy();
+                Err(err)
+            }
+        }
+    }
+
+    fn start(&self, entry: fn() -> !) -> core::result::Result<(), KError> {
+        self.tcb.set_space(Some(&self.faults))?;
+        self.tcb.set_ipc_buffer(&self.ipc_buffer)?;
+        self.tcb.set_priority(kernel::MAX_PRIORITY)?;
+        let stack_end = (self.stack.as_ptr() as usize + STACK_LEN) & !15;
+        let mut regs = [0; kernel::USER_CONTEXT_LEN];
+        regs[kernel::REG_RIP] = entry as usize;
+        // as if entry had just been called, with the return address pushed
+        regs[kernel::REG_RSP] = stack_end - 8;
+        self.tcb.write_registers(&regs, true)
+    }
+
+    pub fn tcb(&self) -> &TCB {
+        &self.tcb
+    }
+
+    pub fn faults(&self) -> &Endpoint {
+        &self.faults
+    }
+
+    // stops it wherever it is, and frees everything it had
+    pub fn destroy(self) {
+        if let Err(err) = self.tcb.suspend() {
+            debug!("could not suspend thread before destroying it: {:?}", err);
+        }
+        untyped::free_tcb(self.tcb);
+        smalluntyped::free_endpoint(self.faults);
+        untyped::free_page4k(self.ipc_buffer.unmap());
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+    use ::mantle::kio;
+
+    static mut COUNT: usize = 0;
+
+    fn count() -> ! {
+        loop {
+            unsafe { core::ptr::write_volatile(&mut COUNT, COUNT + 1) };
+            kio::yield_();
+        }
+    }
+
+    // only in the target, since the simulated kernel doesn't run anything
+    fn runs() -> TestResult {
+        let before = unsafe { core::ptr::read_volatile(&COUNT) };
+        let thread = check_ok!(Thread::spawn(count));
+        for _ in 0..100 {
+            if unsafe { core::ptr::read_volatile(&COUNT) } != before {
+                break;
+            }
+            kio::yield_();
+        }
+        let after = unsafe { core::ptr::read_volatile(&COUNT) };
+        thread.destroy();
+        check!(after != before);
+        Ok(())
+    }
+
+    test_cases!(; target: runs);
+}
@@ -2,6 +2,7 @@ use ::memory;
 use ::core;
 use ::mantle::KError;
 use ::mantle::kernel::{PAGE_4K_SIZE, PAGE_2M_SIZE};
+use ::core::cell::Cell;
 use ::core::cell::RefCell;
 use ::core::cell::RefMut;
 use ::mantle::concurrency::SingleThreaded;
@@ -90,6 +91,10 @@ const KERNEL_BASE_VADDR: usize = 0xe0000000usize;
 #[cfg(target_arch = "x86_64")]
 const KERNEL_BASE_VADDR: usize = 0xffffffff80000000usize;
 
+static IMAGE_START: SingleThreaded<Cell<usize>> = SingleThreaded(Cell::new(0));
+static IMAGE_LEN: SingleThreaded<Cell<usize>> = SingleThreaded(Cell::new(0));
+static MANAGED_LEN: SingleThreaded<Cell<usize>> = SingleThreaded(Cell::new(0));
+
 static AVAILABLE_REGIONS: SingleThreaded<RefCell<memory::LinkedList<VRegion>>> = SingleThreaded(RefCell::new(memory::LinkedList::empty()));
 
 fn get_avail_regions_list() -> RefMut<'static, memory::LinkedList<VRegion>> {
@@ -97,12 +102,108 @@ fn get_avail_regions_list() -> RefMut<'static, memory::LinkedList<VRegion>> {
 }
 
 pub fn init_vspace(executable_start: usize, image_len: usize) {
+    IMAGE_START.get().set(executable_start);
+    IMAGE_LEN.get().set(image_len);
     let region = &mut *get_avail_regions_list();
-    region.pushmut(VRegion::new(executable_start + image_len + PAGE_4K_SIZE * 8, KERNEL_BASE_VADDR));
+    let managed = VRegion::new(executable_start + image_len + PAGE_4K_SIZE * 8, KERNEL_BASE_VADDR);
+    MANAGED_LEN.get().set(managed.len());
+    region.pushmut(managed);
     //region.pushmut(VRegion::new(PAGE_2M_SIZE, executable_start));
     debug!("self was loaded to: {:#X}-{:#X}", executable_start, executable_start + image_len);
 }
 
+// true if the range lies between our image and the kernel, and none of it is available for allocation.
+// this doesn't guarantee that every page is actually mapped, just that nothing else could be there.
+pub fn is_allocated(addr: usize, len: usize) -> bool {
+    if len == 0 || addr < IMAGE_START.get().get() || addr.checked_add(len).map_or(true, |end| end > KERNEL_BASE_VADDR) {
+        return false;
+    }
+    let query = VRegion { start: addr, end: addr + len };
+    get_avail_regions_list().find(|r| r.intersection(&query).is_some()).is_none()
+}
+
+// how much of the address space between our image and the kernel is still available
+pub fn free_len() -> usize {
+    let mut total = 0;
+    for region in &*get_avail_regions_list() {
+        total += region.len();
+    }
+    total
+}
+
+pub fn used_len() -> usize {
+    MANAGED_LEN.get().get() - free_len()
+}
+
+// which pages we've mapped ourselves, a 2M stretch at a time, so that is_mapped can tell them apart from addresses
+// that are only allocated. this is kept out of the heap, since the heap maps pages of its own as it grows.
+const MAX_STRETCHES: usize = 256;
+const PAGES_PER_STRETCH: usize = PAGE_2M_SIZE / PAGE_4K_SIZE;
+
+#[derive(Copy, Clone)]
+struct Stretch {
+    base: usize, // zero if unused
+    pages: [u64; PAGES_PER_STRETCH / 64]
+}
+
+const EMPTY_STRETCH: Stretch = Stretch { base: 0, pages: [0; PAGES_PER_STRETCH / 64] };
+
+static MAPPED: SingleThreaded<RefCell<[Stretch; MAX_STRETCHES]>> = SingleThreaded(RefCell::new([EMPTY_STRETCH; MAX_STRETCHES]));
+
+// called by Page4K as it maps and unmaps
+pub fn note_mapped(vaddr: usize, mapped: bool) {
+    let base = vaddr & !(PAGE_2M_SIZE - 1);
+    let page = (vaddr - base) / PAGE_4K_SIZE;
+    let stretches = &mut *MAPPED.get().borrow_mut();
+    let index = match stretches.iter().position(|s| s.base == base) {
+        Some(i) => i,
+        None if !mapped => return,
+        None => match stretches.iter().position(|s| s.base == 0) {
+            Some(i) => {
+                stretches[i].base = base;
+                i
+            }
+            None => {
+                debug!("no room to record the mapping at {:#X}; it won't count as mapped", vaddr);
+                return;
+            }
+        }
+    };
+    let stretch = &mut stretches[index];
+    if mapped {
+        stretch.pages[page / 64] |= 1 << (page % 64);
+    } else {
+        stretch.pages[page / 64] &= !(1 << (page % 64));
+        if stretch.pages.iter().all(|&bits| bits == 0) {
+            *stretch = EMPTY_STRETCH;
+        }
+    }
+}
+
+// true if every page of the range can be touched: part of our image, or mapped since through Page4K
+pub fn is_mapped(addr: usize, len: usize) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) if len > 0 => end,
+        _ => return false
+    };
+    let image_start = IMAGE_START.get().get();
+    if addr >= image_start && end <= image_start + IMAGE_LEN.get().get() {
+        return true;
+    }
+    let stretches = &*MAPPED.get().borrow();
+    let mut page = addr & !(PAGE_4K_SIZE - 1);
+    while page < end {
+        let base = page & !(PAGE_2M_SIZE - 1);
+        let index = (page - base) / PAGE_4K_SIZE;
+        let found = stretches.iter().find(|s| s.base == base).map_or(false, |s| s.pages[index / 64] & (1 << (index % 64)) != 0);
+        if !found {
+            return false;
+        }
+        page += PAGE_4K_SIZE;
+    }
+    true
+}
+
 pub fn allocate_vregion(length: usize) -> core::result::Result<VRegion, KError> {
     assert!((length & (PAGE_4K_SIZE - 1)) == 0 && length > 0);
     let rl: &mut memory::LinkedList<VRegion> = &mut *get_avail_regions_list();
@@ -157,3 +258,78 @@ pub fn free_vregion(mut r: VRegion) {
         cur = ncur
     }
 }
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn chop() -> TestResult {
+        let mut region = VRegion::new(0x10000, 0x14000);
+        let front = region.chop_len(PAGE_4K_SIZE);
+        check_eq!(front.to_4k_address(), 0x10000);
+        check_eq!((region.start(), region.len()), (0x11000, 3 * PAGE_4K_SIZE));
+        let rest = region.chop_len(3 * PAGE_4K_SIZE);
+        check_eq!(rest.start(), 0x11000);
+        check!(region.is_empty());
+        Ok(())
+    }
+
+    fn join() -> TestResult {
+        let mut region = VRegion::new(0x10000, 0x12000);
+        check!(region.join(VRegion::new(0x12000, 0x13000)).is_none());
+        check!(region.join(VRegion::new(0xF000, 0x10000)).is_none());
+        check_eq!((region.start(), region.len()), (0xF000, 4 * PAGE_4K_SIZE));
+        check!(region.could_join(&VRegion::new(0x13000, 0x14000)));
+        check!(region.join(VRegion::new(0x14000, 0x15000)).is_some());
+        check!(region.intersection(&VRegion::new(0x12000, 0x20000)).is_some());
+        check!(region.intersection(&VRegion::new(0x13000, 0x20000)).is_none());
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn allocate_and_free() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let before = get_avail_regions_list().len();
+        let mut region = check_ok!(allocate_vregion(3 * PAGE_4K_SIZE));
+        let start = region.start();
+        check!(is_allocated(start, 3 * PAGE_4K_SIZE));
+        let first = region.chop_len(PAGE_4K_SIZE);
+        let second = region.chop_len(PAGE_4K_SIZE);
+        // back onto the region it came from, from the far end, so that each piece joins up with the last
+        free_vregion(region);
+        check!(!is_allocated(start + 2 * PAGE_4K_SIZE, PAGE_4K_SIZE));
+        check!(is_allocated(start, 2 * PAGE_4K_SIZE));
+        free_vregion(second);
+        free_vregion(first);
+        check!(!is_allocated(start, PAGE_4K_SIZE));
+        check_eq!(get_avail_regions_list().len(), before);
+        check_eq!(allocate_vregion(KERNEL_BASE_VADDR).err(), Some(KError::NotEnoughMemory));
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn mapped_pages() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let image_start = IMAGE_START.get().get();
+        let image_end = image_start + IMAGE_LEN.get().get();
+        check!(is_mapped(image_start, image_end - image_start));
+        // the guard gap after the image isn't
+        check!(!is_mapped(image_end, 1));
+        let region = check_ok!(allocate_vregion(2 * PAGE_4K_SIZE));
+        let start = region.start();
+        check!(!is_mapped(start, 1));
+        note_mapped(start, true);
+        check!(is_mapped(start + 8, 16));
+        check!(!is_mapped(start, 2 * PAGE_4K_SIZE));
+        note_mapped(start + PAGE_4K_SIZE, true);
+        check!(is_mapped(start, 2 * PAGE_4K_SIZE));
+        note_mapped(start, false);
+        note_mapped(start + PAGE_4K_SIZE, false);
+        check!(!is_mapped(start + PAGE_4K_SIZE, 1));
+        free_vregion(region);
+        Ok(())
+    }
+
+    test_cases!(chop, join; host: allocate_and_free, mapped_pages);
+}
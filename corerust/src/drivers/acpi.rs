@@ -0,0 +1,641 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::mantle::kernel::{BootInfo, PAGE_4K_SIZE};
+use ::memory::LinkedList;
+use ::memory::device;
+use ::memory::device::MappedDeviceRegion;
+
+// ACPI tables, as far as they describe hardware: the FADT, MADT, HPET and MCFG. AML is left alone, apart from the
+// scan that the power module does of the DSDT.
+
+const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+const RSDP_V1_LEN: usize = 20;
+const RSDP_V2_LEN: usize = 36;
+
+const EBDA_POINTER: usize = 0x40E;
+const EBDA_SEARCH_LEN: usize = 1024;
+const BIOS_AREA: usize = 0xE0000;
+const BIOS_AREA_LEN: usize = 0x20000;
+
+// from seL4's bootinfo.h: extra bootinfo follows the bootinfo page as a series of these chunks
+const BOOTINFO_HEADER_PADDING: usize = 0;
+const BOOTINFO_HEADER_X86_ACPI_RSDP: usize = 3;
+const BOOTINFO_HEADER_LEN: usize = 16;
+
+const HEADER_LEN: usize = 36;
+// nothing we parse is anywhere near this long; it's a limit on how much a corrupt length can make us map
+const MAX_TABLE_LEN: usize = 1 << 20;
+const MAX_TABLES: usize = 64;
+
+// as long as an ACPI 1.0 FADT, which has everything up to the flags
+const FADT_MIN_LEN: usize = 116;
+const FADT_IAPC_BOOT_ARCH: usize = 109;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REG: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+const FADT_X_PM1A_CNT: usize = 172;
+const FADT_X_PM1B_CNT: usize = 184;
+
+const BOOT_ARCH_LEGACY_DEVICES: u16 = 0x1;
+const BOOT_ARCH_8042: u16 = 0x2;
+const FLAG_RESET_REG_SUP: u32 = 1 << 10;
+
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_NMI: u8 = 4;
+const MADT_LOCAL_APIC_ADDRESS: u8 = 5;
+const MADT_LOCAL_X2APIC: u8 = 9;
+const MADT_ENTRIES: usize = 44;
+const LOCAL_APIC_ENABLED: u32 = 0x1;
+
+const MCFG_ENTRIES: usize = 44;
+const MCFG_ENTRY_LEN: usize = 16;
+
+pub const SPACE_SYSTEM_MEMORY: u8 = 0;
+pub const SPACE_SYSTEM_IO: u8 = 1;
+pub const SPACE_PCI_CONFIG: u8 = 2;
+
+fn le16(b: &[u8], offset: usize) -> u16 {
+    (b[offset] as u16) | ((b[offset + 1] as u16) << 8)
+}
+
+fn le32(b: &[u8], offset: usize) -> u32 {
+    (le16(b, offset) as u32) | ((le16(b, offset + 2) as u32) << 16)
+}
+
+fn le64(b: &[u8], offset: usize) -> u64 {
+    (le32(b, offset) as u64) | ((le32(b, offset + 4) as u64) << 32)
+}
+
+fn checksum_ok(b: &[u8]) -> bool {
+    b.iter().fold(0u8, |sum, &x| sum.wrapping_add(x)) == 0
+}
+
+fn signature_str(signature: &[u8; 4]) -> &str {
+    core::str::from_utf8(signature).unwrap_or("????")
+}
+
+// a register, in whichever address space the firmware put it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64
+}
+
+impl GenericAddress {
+    fn parse(b: &[u8], offset: usize) -> GenericAddress {
+        GenericAddress { space: b[offset], bit_width: b[offset + 1], bit_offset: b[offset + 2], access_size: b[offset + 3],
+            address: le64(b, offset + 4) }
+    }
+
+    fn io(port: u32, bits: u8) -> GenericAddress {
+        GenericAddress { space: SPACE_SYSTEM_IO, bit_width: bits, bit_offset: 0, access_size: 0, address: port as u64 }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.address != 0
+    }
+}
+
+impl core::fmt::Display for GenericAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.space {
+            SPACE_SYSTEM_MEMORY => write!(f, "mem {:#X}", self.address),
+            SPACE_SYSTEM_IO => write!(f, "io {:#X}", self.address),
+            SPACE_PCI_CONFIG => write!(f, "pci config {:#X}", self.address),
+            space => write!(f, "space {} {:#X}", space, self.address)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Fadt {
+    pub revision: u8,
+    pub dsdt: u64,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub pm1a_control: GenericAddress,
+    pub pm1b_control: GenericAddress,
+    pub boot_arch: Option<u16>, // not in ACPI 1.0 tables
+    pub flags: u32,
+    pub reset_register: Option<(GenericAddress, u8)>
+}
+
+impl Fadt {
+    fn parse(revision: u8, b: &[u8]) -> Fadt {
+        let has = |offset: usize, len: usize| b.len() >= offset + len;
+        // the extended fields take precedence over the 32-bit ones when they're filled in
+        let extended = |offset: usize, legacy: usize, bits: u8| {
+            if has(offset, 12) && le64(b, offset + 4) != 0 {
+                GenericAddress::parse(b, offset)
+            } else {
+                GenericAddress::io(le32(b, legacy), bits)
+            }
+        };
+        let flags = if has(FADT_FLAGS, 4) { le32(b, FADT_FLAGS) } else { 0 };
+        let dsdt = if has(FADT_X_DSDT, 8) && le64(b, FADT_X_DSDT) != 0 { le64(b, FADT_X_DSDT) } else { le32(b, 40) as u64 };
+        Fadt {
+            revision,
+            dsdt,
+            sci_interrupt: le16(b, 46),
+            smi_command: le32(b, 48),
+            acpi_enable: b[52],
+            pm1a_control: extended(FADT_X_PM1A_CNT, 64, 16),
+            pm1b_control: extended(FADT_X_PM1B_CNT, 68, 16),
+            boot_arch: if revision >= 2 && has(FADT_IAPC_BOOT_ARCH, 2) { Some(le16(b, FADT_IAPC_BOOT_ARCH)) } else { None },
+            flags,
+            reset_register: if flags & FLAG_RESET_REG_SUP != 0 && has(FADT_RESET_VALUE, 1) {
+                Some((GenericAddress::parse(b, FADT_RESET_REG), b[FADT_RESET_VALUE]))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LocalApic {
+    pub processor_id: u32,
+    pub apic_id: u32,
+    pub enabled: bool
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32
+}
+
+// an ISA interrupt that isn't wired to the IOAPIC input of the same number, or isn't edge-triggered active-high
+#[derive(Debug, Copy, Clone)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16
+}
+
+impl InterruptOverride {
+    // None means whatever the bus normally uses
+    pub fn active_low(&self) -> Option<bool> {
+        match self.flags & 0x3 {
+            1 => Some(false),
+            3 => Some(true),
+            _ => None
+        }
+    }
+
+    pub fn level_triggered(&self) -> Option<bool> {
+        match (self.flags >> 2) & 0x3 {
+            1 => Some(false),
+            3 => Some(true),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LocalApicNmi {
+    pub processor_id: u8, // 0xFF for all of them
+    pub flags: u16,
+    pub lint: u8
+}
+
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub pcat_compat: bool, // there are also dual 8259s, which have to be masked before using the IOAPICs
+    pub local_apics: LinkedList<LocalApic>,
+    pub io_apics: LinkedList<IoApic>,
+    pub overrides: LinkedList<InterruptOverride>,
+    pub nmis: LinkedList<LocalApicNmi>
+}
+
+impl Madt {
+    fn parse(b: &[u8]) -> Result<Madt, KError> {
+        let mut madt = Madt {
+            local_apic_address: le32(b, 36) as u64,
+            pcat_compat: le32(b, 40) & 0x1 != 0,
+            local_apics: LinkedList::empty(),
+            io_apics: LinkedList::empty(),
+            overrides: LinkedList::empty(),
+            nmis: LinkedList::empty()
+        };
+        let mut offset = MADT_ENTRIES;
+        while offset + 2 <= b.len() {
+            let (kind, len) = (b[offset], b[offset + 1] as usize);
+            if len < 2 || offset + len > b.len() {
+                warn!("MADT entry at offset {} has bad length {}", offset, len);
+                break;
+            }
+            let e = &b[offset..offset + len];
+            let pushed = match kind {
+                MADT_LOCAL_APIC if len >= 8 => madt.local_apics.push_back(LocalApic {
+                    processor_id: e[2] as u32, apic_id: e[3] as u32, enabled: le32(e, 4) & LOCAL_APIC_ENABLED != 0
+                }).is_ok(),
+                MADT_LOCAL_X2APIC if len >= 16 => madt.local_apics.push_back(LocalApic {
+                    processor_id: le32(e, 12), apic_id: le32(e, 4), enabled: le32(e, 8) & LOCAL_APIC_ENABLED != 0
+                }).is_ok(),
+                MADT_IO_APIC if len >= 12 => madt.io_apics.push_back(IoApic {
+                    id: e[2], address: le32(e, 4), gsi_base: le32(e, 8)
+                }).is_ok(),
+                MADT_INTERRUPT_OVERRIDE if len >= 10 => madt.overrides.push_back(InterruptOverride {
+                    bus: e[2], source: e[3], gsi: le32(e, 4), flags: le16(e, 8)
+                }).is_ok(),
+                MADT_LOCAL_APIC_NMI if len >= 6 => madt.nmis.push_back(LocalApicNmi {
+                    processor_id: e[2], flags: le16(e, 3), lint: e[5]
+                }).is_ok(),
+                MADT_LOCAL_APIC_ADDRESS if len >= 12 => {
+                    madt.local_apic_address = le64(e, 4);
+                    true
+                }
+                _ => true // nothing we use
+            };
+            if !pushed {
+                return Err(KError::NotEnoughMemory);
+            }
+            offset += len;
+        }
+        Ok(madt)
+    }
+
+    // where an ISA interrupt ends up, taking overrides into account
+    pub fn isa_gsi(&self, irq: u8) -> u32 {
+        match self.overrides.find(|o| o.bus == 0 && o.source == irq) {
+            Some(o) => o.gsi,
+            None => irq as u32
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hpet {
+    pub event_timer_block_id: u32,
+    pub address: GenericAddress,
+    pub number: u8,
+    pub min_tick: u16
+}
+
+impl Hpet {
+    fn parse(b: &[u8]) -> Result<Hpet, KError> {
+        if b.len() < 56 {
+            return Err(KError::TruncatedMessage);
+        }
+        Ok(Hpet { event_timer_block_id: le32(b, 36), address: GenericAddress::parse(b, 40), number: b[52], min_tick: le16(b, 53) })
+    }
+
+    pub fn comparators(&self) -> u8 {
+        (((self.event_timer_block_id >> 8) & 0x1F) + 1) as u8
+    }
+}
+
+// a range of PCI buses whose configuration space is memory-mapped
+#[derive(Debug, Copy, Clone)]
+pub struct McfgRegion {
+    pub base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8
+}
+
+impl McfgRegion {
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus {
+            return None;
+        }
+        Some(self.base + (((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TableInfo {
+    pub signature: [u8; 4],
+    pub paddr: u64,
+    pub len: usize,
+    pub revision: u8
+}
+
+impl core::fmt::Display for TableInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} rev {} at {:#X}, {} bytes", signature_str(&self.signature), self.revision, self.paddr, self.len)
+    }
+}
+
+// a table mapped for as long as it's needed. tables share pages, and a device page can only be mapped once, so only
+// one of these can be around at a time.
+pub struct MappedTable {
+    region: MappedDeviceRegion,
+    offset: usize,
+    len: usize
+}
+
+impl MappedTable {
+    pub fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts((self.region.get_addr() + self.offset) as *const u8, self.len) }
+    }
+
+    pub fn unmap(self) {
+        device::return_mapped_device_region(self.region);
+    }
+}
+
+fn map_physical(paddr: u64, len: usize) -> Result<MappedTable, KError> {
+    let region = device::get_mapped_device_region(paddr as usize, len)?;
+    let offset = paddr as usize - region.get_paddr();
+    Ok(MappedTable { region, offset, len })
+}
+
+// maps a whole table, checking its signature if one is given and its checksum
+pub fn map_table(paddr: u64, signature: Option<&[u8; 4]>) -> Result<MappedTable, KError> {
+    let header = map_physical(paddr, HEADER_LEN)?;
+    let len = le32(header.bytes(), 4) as usize;
+    let matches = signature.map_or(true, |s| &header.bytes()[..4] == &s[..]);
+    header.unmap();
+    if !matches {
+        return Err(KError::FailedLookup);
+    }
+    if len < HEADER_LEN || len > MAX_TABLE_LEN {
+        return Err(KError::RangeError);
+    }
+    let table = map_physical(paddr, len)?;
+    if !checksum_ok(table.bytes()) {
+        warn!("ACPI table at {:#X} has a bad checksum", paddr);
+        table.unmap();
+        return Err(KError::InvalidArgument);
+    }
+    Ok(table)
+}
+
+struct Acpi {
+    revision: u8,
+    oem: [u8; 6],
+    tables: LinkedList<TableInfo>,
+    fadt: Option<Fadt>,
+    madt: Option<Madt>,
+    hpet: Option<Hpet>,
+    mcfg: LinkedList<McfgRegion>
+}
+
+static ACPI: SingleThreaded<RefCell<Option<Acpi>>> = SingleThreaded(RefCell::new(None));
+
+fn find_rsdp_in(paddr: usize, len: usize) -> Option<[u8; RSDP_V2_LEN]> {
+    let area = match map_physical(paddr as u64, len) {
+        Ok(area) => area,
+        Err(err) => {
+            debug!("could not map {:#X}-{:#X} to look for the RSDP: {:?}", paddr, paddr + len, err);
+            return None;
+        }
+    };
+    let mut found = None;
+    {
+        let b = area.bytes();
+        let mut offset = 0;
+        while offset + RSDP_V1_LEN <= len {
+            if &b[offset..offset + 8] == RSDP_SIGNATURE && checksum_ok(&b[offset..offset + RSDP_V1_LEN]) {
+                let mut rsdp = [0; RSDP_V2_LEN];
+                let n = core::cmp::min(RSDP_V2_LEN, len - offset);
+                rsdp[..n].copy_from_slice(&b[offset..offset + n]);
+                found = Some(rsdp);
+                break;
+            }
+            offset += 16; // always on a paragraph boundary
+        }
+    }
+    area.unmap();
+    found
+}
+
+// newer kernels pass along the RSDP that the bootloader found
+fn find_rsdp_in_bootinfo(bootinfo: &BootInfo) -> Option<[u8; RSDP_V2_LEN]> {
+    let extra = bootinfo as *const BootInfo as usize + PAGE_4K_SIZE;
+    let mut offset = 0;
+    while offset + BOOTINFO_HEADER_LEN <= bootinfo.extra_len {
+        let (id, len) = unsafe { (*((extra + offset) as *const usize), *((extra + offset + 8) as *const usize)) };
+        if len < BOOTINFO_HEADER_LEN {
+            break;
+        }
+        if id == BOOTINFO_HEADER_X86_ACPI_RSDP && len >= BOOTINFO_HEADER_LEN + RSDP_V1_LEN {
+            let data = unsafe { core::slice::from_raw_parts((extra + offset + BOOTINFO_HEADER_LEN) as *const u8, len - BOOTINFO_HEADER_LEN) };
+            // no more trusted than one found in memory, so that a bad one falls back to searching
+            if &data[..8] != RSDP_SIGNATURE || !checksum_ok(&data[..RSDP_V1_LEN]) {
+                warn!("ignoring bootinfo RSDP with a bad signature or checksum");
+                return None;
+            }
+            let mut rsdp = [0; RSDP_V2_LEN];
+            let n = core::cmp::min(RSDP_V2_LEN, data.len());
+            rsdp[..n].copy_from_slice(&data[..n]);
+            return Some(rsdp);
+        } else if id != BOOTINFO_HEADER_PADDING {
+            debug!("skipping bootinfo extra {} of {} bytes", id, len);
+        }
+        offset += len;
+    }
+    None
+}
+
+fn find_rsdp(bootinfo: &BootInfo) -> Option<[u8; RSDP_V2_LEN]> {
+    if let Some(rsdp) = find_rsdp_in_bootinfo(bootinfo) {
+        return Some(rsdp);
+    }
+    let ebda = match map_physical(EBDA_POINTER as u64, 2) {
+        Ok(pointer) => {
+            let segment = le16(pointer.bytes(), 0) as usize;
+            pointer.unmap();
+            segment << 4
+        }
+        Err(_) => 0
+    };
+    if ebda != 0 {
+        if let Some(rsdp) = find_rsdp_in(ebda, EBDA_SEARCH_LEN) {
+            return Some(rsdp);
+        }
+    }
+    find_rsdp_in(BIOS_AREA, BIOS_AREA_LEN)
+}
+
+impl Acpi {
+    fn load(rsdp: &[u8; RSDP_V2_LEN]) -> Result<Acpi, KError> {
+        let revision = rsdp[15];
+        let mut oem = [0u8; 6];
+        oem.copy_from_slice(&rsdp[9..15]);
+        // ACPI 2.0 and up have an XSDT with 64-bit pointers, which is used in preference to the RSDT
+        let (root, entry_len) = if revision >= 2 && checksum_ok(&rsdp[..RSDP_V2_LEN]) && le64(rsdp, 24) != 0 {
+            (le64(rsdp, 24), 8)
+        } else {
+            (le32(rsdp, 16) as u64, 4)
+        };
+        let mut entries = [0u64; MAX_TABLES];
+        let count = {
+            let table = map_table(root, Some(if entry_len == 8 { b"XSDT" } else { b"RSDT" }))?;
+            let count = {
+                let b = table.bytes();
+                let count = core::cmp::min((b.len() - HEADER_LEN) / entry_len, MAX_TABLES);
+                for (i, entry) in entries[..count].iter_mut().enumerate() {
+                    let offset = HEADER_LEN + i * entry_len;
+                    *entry = if entry_len == 8 { le64(b, offset) } else { le32(b, offset) as u64 };
+                }
+                count
+            };
+            table.unmap();
+            count
+        };
+        let mut acpi = Acpi { revision, oem, tables: LinkedList::empty(), fadt: None, madt: None, hpet: None, mcfg: LinkedList::empty() };
+        for &paddr in &entries[..count] {
+            if let Err(err) = acpi.load_table(paddr) {
+                warn!("skipping ACPI table at {:#X}: {:?}", paddr, err);
+            }
+        }
+        if let Some(fadt) = acpi.fadt {
+            if let Err(err) = acpi.load_dsdt(fadt.dsdt) {
+                warn!("skipping DSDT at {:#X}: {:?}", fadt.dsdt, err);
+            }
+        }
+        Ok(acpi)
+    }
+
+    // the DSDT isn't listed in the RSDT, but it's worth knowing about
+    fn load_dsdt(&mut self, paddr: u64) -> Result<(), KError> {
+        let table = map_table(paddr, Some(b"DSDT"))?;
+        let info = TableInfo { signature: *b"DSDT", paddr, len: table.bytes().len(), revision: table.bytes()[8] };
+        table.unmap();
+        if self.tables.push_back(info).is_err() {
+            return Err(KError::NotEnoughMemory);
+        }
+        Ok(())
+    }
+
+    fn load_table(&mut self, paddr: u64) -> Result<(), KError> {
+        let table = map_table(paddr, None)?;
+        let result = self.parse_table(paddr, table.bytes());
+        table.unmap();
+        result
+    }
+
+    fn parse_table(&mut self, paddr: u64, b: &[u8]) -> Result<(), KError> {
+        let mut signature = [0u8; 4];
+        signature.copy_from_slice(&b[..4]);
+        let revision = b[8];
+        match &signature {
+            b"FACP" if b.len() < FADT_MIN_LEN => return Err(KError::TruncatedMessage),
+            b"FACP" => self.fadt = Some(Fadt::parse(revision, b)),
+            b"APIC" if b.len() < MADT_ENTRIES => return Err(KError::TruncatedMessage),
+            b"APIC" => self.madt = Some(Madt::parse(b)?),
+            b"HPET" => self.hpet = Some(Hpet::parse(b)?),
+            b"MCFG" => {
+                let mut offset = MCFG_ENTRIES;
+                while offset + MCFG_ENTRY_LEN <= b.len() {
+                    let region = McfgRegion { base: le64(b, offset), segment: le16(b, offset + 8), start_bus: b[offset + 10], end_bus: b[offset + 11] };
+                    if self.mcfg.push_back(region).is_err() {
+                        return Err(KError::NotEnoughMemory);
+                    }
+                    offset += MCFG_ENTRY_LEN;
+                }
+            }
+            _ => {}
+        }
+        if self.tables.push_back(TableInfo { signature, paddr, len: b.len(), revision }).is_err() {
+            return Err(KError::NotEnoughMemory);
+        }
+        Ok(())
+    }
+}
+
+pub fn init(bootinfo: &BootInfo) {
+    let rsdp = match find_rsdp(bootinfo) {
+        Some(rsdp) => rsdp,
+        None => {
+            warn!("no ACPI RSDP found");
+            return;
+        }
+    };
+    match Acpi::load(&rsdp) {
+        Ok(acpi) => {
+            debug!("ACPI revision {} from {}, with {} tables", acpi.revision,
+                   core::str::from_utf8(&acpi.oem).unwrap_or("?"), acpi.tables.len());
+            *ACPI.get().borrow_mut() = Some(acpi);
+        }
+        Err(err) => warn!("could not load ACPI tables: {:?}", err)
+    };
+}
+
+pub fn is_available() -> bool {
+    ACPI.get().borrow().is_some()
+}
+
+pub fn find_table(signature: &[u8; 4]) -> Option<TableInfo> {
+    match *ACPI.get().borrow() {
+        Some(ref acpi) => acpi.tables.find(|t| &t.signature == signature).map(|t| *t),
+        None => None
+    }
+}
+
+pub fn fadt() -> Option<Fadt> {
+    ACPI.get().borrow().as_ref().and_then(|acpi| acpi.fadt)
+}
+
+pub fn hpet() -> Option<Hpet> {
+    ACPI.get().borrow().as_ref().and_then(|acpi| acpi.hpet)
+}
+
+pub fn with_madt<R, F: FnOnce(&Madt) -> R>(f: F) -> Option<R> {
+    ACPI.get().borrow().as_ref().and_then(|acpi| acpi.madt.as_ref()).map(f)
+}
+
+pub fn for_each_mcfg_region<F: FnMut(&McfgRegion)>(mut f: F) {
+    if let Some(ref acpi) = *ACPI.get().borrow() {
+        for region in &acpi.mcfg {
+            f(region);
+        }
+    }
+}
+
+// whether there's a PS/2 controller, if the firmware says. it only can from ACPI 2.0 on, and then only if it doesn't
+// claim to have no legacy devices at all.
+pub fn has_8042() -> Option<bool> {
+    match fadt().and_then(|fadt| fadt.boot_arch) {
+        Some(arch) if arch & (BOOT_ARCH_8042 | BOOT_ARCH_LEGACY_DEVICES) != 0 => Some(arch & BOOT_ARCH_8042 != 0),
+        _ => None
+    }
+}
+
+pub fn print_tables(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    let acpi = ACPI.get().borrow();
+    let acpi = match *acpi {
+        Some(ref acpi) => acpi,
+        None => return writeln!(writer, "no ACPI tables")
+    };
+    for table in &acpi.tables {
+        writeln!(writer, "{}", table)?;
+    }
+    if let Some(ref fadt) = acpi.fadt {
+        writeln!(writer, "FADT: SCI {}, PM1a {}, PM1b {}, boot arch {:?}", fadt.sci_interrupt, fadt.pm1a_control,
+                 fadt.pm1b_control, fadt.boot_arch)?;
+    }
+    if let Some(ref madt) = acpi.madt {
+        writeln!(writer, "MADT: local APICs at {:#X}", madt.local_apic_address)?;
+        for lapic in &madt.local_apics {
+            writeln!(writer, "  cpu {}: APIC {}{}", lapic.processor_id, lapic.apic_id, if lapic.enabled { "" } else { " (disabled)" })?;
+        }
+        for ioapic in &madt.io_apics {
+            writeln!(writer, "  IOAPIC {} at {:#X}, GSIs from {}", ioapic.id, ioapic.address, ioapic.gsi_base)?;
+        }
+        for o in &madt.overrides {
+            writeln!(writer, "  IRQ {} -> GSI {} (flags {:#X})", o.source, o.gsi, o.flags)?;
+        }
+    }
+    if let Some(ref hpet) = acpi.hpet {
+        writeln!(writer, "HPET {} at {}, {} comparators", hpet.number, hpet.address, hpet.comparators())?;
+    }
+    for region in &acpi.mcfg {
+        writeln!(writer, "MCFG: segment {} buses {}-{} at {:#X}", region.segment, region.start_bus, region.end_bus, region.base)?;
+    }
+    Ok(())
+}
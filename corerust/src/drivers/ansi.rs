@@ -0,0 +1,300 @@
+use ::core;
+
+// a parser for the subset of VT100/ANSI escape sequences that our consoles understand
+
+const ESC: u8 = 0x1B;
+const MAX_PARAMS: usize = 8;
+// larger than any screen, so that moving this far reaches the edge all the same, but small enough to fit an i16
+const MAX_MOVE: u16 = 1024;
+
+pub const SGR_RESET: u16 = 0;
+pub const SGR_BOLD: u16 = 1;
+pub const SGR_BLINK: u16 = 5;
+pub const SGR_REVERSE: u16 = 7;
+pub const SGR_NORMAL_INTENSITY: u16 = 22;
+pub const SGR_NO_BLINK: u16 = 25;
+pub const SGR_NO_REVERSE: u16 = 27;
+pub const SGR_FG_BASE: u16 = 30;
+pub const SGR_FG_DEFAULT: u16 = 39;
+pub const SGR_BG_BASE: u16 = 40;
+pub const SGR_BG_DEFAULT: u16 = 49;
+pub const SGR_FG_BRIGHT_BASE: u16 = 90;
+pub const SGR_BG_BRIGHT_BASE: u16 = 100;
+
+pub const ERASE_TO_END: u16 = 0;
+pub const ERASE_TO_START: u16 = 1;
+pub const ERASE_ALL: u16 = 2;
+
+// ANSI orders colors as black, red, green, yellow, blue, magenta, cyan, white; VGA swaps red and blue.
+pub fn ansi_to_vga_color(ansi: u8) -> u8 {
+    [0, 4, 2, 6, 1, 5, 3, 7][(ansi & 0x7) as usize]
+}
+
+pub trait AnsiTerminal {
+    // printable bytes and the control characters (\n, \r, \t, \x08)
+    fn put_char(&mut self, char: u8);
+    fn move_cursor_by(&mut self, dx: i16, dy: i16);
+    // zero-based; None leaves that coordinate alone
+    fn set_cursor(&mut self, column: Option<u16>, row: Option<u16>);
+    fn erase_display(&mut self, mode: u16);
+    fn erase_line(&mut self, mode: u16);
+    fn save_cursor(&mut self);
+    fn restore_cursor(&mut self);
+    // zero-based and inclusive; None means the bottom of the screen
+    fn set_scroll_region(&mut self, top: u16, bottom: Option<u16>);
+    // moves up a line, scrolling the region down if already at its top
+    fn reverse_index(&mut self);
+    fn set_cursor_visible(&mut self, visible: bool);
+    // called once per SGR parameter, except for extended color parameters, which are skipped
+    fn select_graphic_rendition(&mut self, code: u16);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi
+}
+
+pub struct AnsiParser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    private: bool
+}
+
+impl AnsiParser {
+    pub const fn new() -> AnsiParser {
+        AnsiParser { state: State::Ground, params: [0; MAX_PARAMS], param_count: 0, private: false }
+    }
+
+    fn param(&self, i: usize, default: u16) -> u16 {
+        if i < self.param_count && self.params[i] != 0 {
+            self.params[i]
+        } else {
+            default
+        }
+    }
+
+    fn raw_param(&self, i: usize) -> Option<u16> {
+        if i < self.param_count { Some(self.params[i]) } else { None }
+    }
+
+    pub fn feed<T: AnsiTerminal>(&mut self, byte: u8, term: &mut T) {
+        match self.state {
+            State::Ground => {
+                if byte == ESC {
+                    self.state = State::Escape;
+                } else {
+                    term.put_char(byte);
+                }
+            }
+            State::Escape => {
+                self.state = State::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = State::Csi;
+                        self.params = [0; MAX_PARAMS];
+                        self.param_count = 0;
+                        self.private = false;
+                    }
+                    b'7' => term.save_cursor(),
+                    b'8' => term.restore_cursor(),
+                    b'D' => term.put_char(b'\n'),
+                    b'M' => term.reverse_index(),
+                    b'c' => {
+                        term.select_graphic_rendition(SGR_RESET);
+                        term.set_scroll_region(0, None);
+                        term.erase_display(ERASE_ALL);
+                        term.set_cursor(Some(0), Some(0));
+                    }
+                    _ => {} // unsupported: drop it
+                }
+            }
+            State::Csi => {
+                match byte {
+                    b'0'...b'9' => {
+                        if self.param_count == 0 {
+                            self.param_count = 1;
+                        }
+                        if self.param_count <= MAX_PARAMS {
+                            let p = &mut self.params[self.param_count - 1];
+                            *p = p.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                        }
+                    }
+                    b';' => {
+                        if self.param_count == 0 {
+                            self.param_count = 1;
+                        }
+                        self.param_count += 1;
+                    }
+                    b'?' => self.private = true,
+                    0x40...0x7E => {
+                        self.state = State::Ground;
+                        if self.param_count > MAX_PARAMS {
+                            self.param_count = MAX_PARAMS;
+                        }
+                        self.dispatch(byte, term);
+                    }
+                    _ => {} // intermediate bytes: not supported, so ignore them
+                }
+            }
+        }
+    }
+
+    fn dispatch<T: AnsiTerminal>(&mut self, command: u8, term: &mut T) {
+        if self.private {
+            // only DECTCEM (show/hide cursor) is supported among private modes
+            if self.raw_param(0) == Some(25) {
+                match command {
+                    b'h' => term.set_cursor_visible(true),
+                    b'l' => term.set_cursor_visible(false),
+                    _ => {}
+                }
+            }
+            return;
+        }
+        let n = core::cmp::min(self.param(0, 1), MAX_MOVE) as i16;
+        match command {
+            b'A' => term.move_cursor_by(0, -n),
+            b'B' => term.move_cursor_by(0, n),
+            b'C' => term.move_cursor_by(n, 0),
+            b'D' => term.move_cursor_by(-n, 0),
+            b'E' => {
+                term.move_cursor_by(0, n);
+                term.set_cursor(Some(0), None);
+            }
+            b'F' => {
+                term.move_cursor_by(0, -n);
+                term.set_cursor(Some(0), None);
+            }
+            b'G' => term.set_cursor(Some(self.param(0, 1) - 1), None),
+            b'd' => term.set_cursor(None, Some(self.param(0, 1) - 1)),
+            b'H' | b'f' => term.set_cursor(Some(self.param(1, 1) - 1), Some(self.param(0, 1) - 1)),
+            b'J' => term.erase_display(self.raw_param(0).unwrap_or(ERASE_TO_END)),
+            b'K' => term.erase_line(self.raw_param(0).unwrap_or(ERASE_TO_END)),
+            b's' => term.save_cursor(),
+            b'u' => term.restore_cursor(),
+            b'r' => {
+                let bottom = self.raw_param(1).and_then(|b| if b == 0 { None } else { Some(b - 1) });
+                term.set_scroll_region(self.param(0, 1) - 1, bottom)
+            }
+            b'm' => {
+                if self.param_count == 0 {
+                    term.select_graphic_rendition(SGR_RESET);
+                }
+                let mut i = 0;
+                while i < self.param_count {
+                    let code = self.params[i];
+                    if code == 38 || code == 48 {
+                        // 256-color and truecolor selections: skip their arguments
+                        i += if self.raw_param(i + 1) == Some(5) { 3 } else { 5 };
+                        continue;
+                    }
+                    term.select_graphic_rendition(code);
+                    i += 1;
+                }
+            }
+            _ => {} // unsupported: drop it
+        }
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum Event {
+        Char(u8),
+        MoveBy(i16, i16),
+        SetCursor(Option<u16>, Option<u16>),
+        EraseDisplay(u16),
+        EraseLine(u16),
+        Save,
+        Restore,
+        ScrollRegion(u16, Option<u16>),
+        ReverseIndex,
+        CursorVisible(bool),
+        Sgr(u16)
+    }
+
+    struct Recorder {
+        events: [Option<Event>; 16],
+        count: usize
+    }
+
+    impl Recorder {
+        fn run(input: &[u8]) -> Recorder {
+            let mut recorder = Recorder { events: [None; 16], count: 0 };
+            let mut parser = AnsiParser::new();
+            for &b in input {
+                parser.feed(b, &mut recorder);
+            }
+            recorder
+        }
+
+        fn record(&mut self, event: Event) {
+            assert!(self.count < self.events.len());
+            self.events[self.count] = Some(event);
+            self.count += 1;
+        }
+
+        fn matches(&self, expected: &[Event]) -> bool {
+            self.count == expected.len() && expected.iter().zip(self.events.iter()).all(|(e, r)| Some(*e) == *r)
+        }
+    }
+
+    impl AnsiTerminal for Recorder {
+        fn put_char(&mut self, char: u8) { self.record(Event::Char(char)) }
+        fn move_cursor_by(&mut self, dx: i16, dy: i16) { self.record(Event::MoveBy(dx, dy)) }
+        fn set_cursor(&mut self, column: Option<u16>, row: Option<u16>) { self.record(Event::SetCursor(column, row)) }
+        fn erase_display(&mut self, mode: u16) { self.record(Event::EraseDisplay(mode)) }
+        fn erase_line(&mut self, mode: u16) { self.record(Event::EraseLine(mode)) }
+        fn save_cursor(&mut self) { self.record(Event::Save) }
+        fn restore_cursor(&mut self) { self.record(Event::Restore) }
+        fn set_scroll_region(&mut self, top: u16, bottom: Option<u16>) { self.record(Event::ScrollRegion(top, bottom)) }
+        fn reverse_index(&mut self) { self.record(Event::ReverseIndex) }
+        fn set_cursor_visible(&mut self, visible: bool) { self.record(Event::CursorVisible(visible)) }
+        fn select_graphic_rendition(&mut self, code: u16) { self.record(Event::Sgr(code)) }
+    }
+
+    fn plain_text() -> TestResult {
+        check!(Recorder::run(b"hi\n").matches(&[Event::Char(b'h'), Event::Char(b'i'), Event::Char(b'\n')]));
+        Ok(())
+    }
+
+    fn cursor_movement() -> TestResult {
+        check!(Recorder::run(b"\x1b[A\x1b[3C\x1b[5;10H\x1b[H").matches(&[
+            Event::MoveBy(0, -1), Event::MoveBy(3, 0), Event::SetCursor(Some(9), Some(4)), Event::SetCursor(Some(0), Some(0))]));
+        check!(Recorder::run(b"\x1b7\x1b8\x1bM").matches(&[Event::Save, Event::Restore, Event::ReverseIndex]));
+        check!(Recorder::run(b"\x1b[40000A\x1b[99999D").matches(&[Event::MoveBy(0, -1024), Event::MoveBy(-1024, 0)]));
+        // absolute positions are passed on as they are, for the terminal to clamp
+        check!(Recorder::run(b"\x1b[40000;99999H").matches(&[Event::SetCursor(Some(65534), Some(39999))]));
+        Ok(())
+    }
+
+    fn erase() -> TestResult {
+        check!(Recorder::run(b"\x1b[J\x1b[2J\x1b[1K").matches(&[
+            Event::EraseDisplay(ERASE_TO_END), Event::EraseDisplay(ERASE_ALL), Event::EraseLine(ERASE_TO_START)]));
+        Ok(())
+    }
+
+    fn graphic_rendition() -> TestResult {
+        check!(Recorder::run(b"\x1b[m\x1b[1;31mx").matches(&[
+            Event::Sgr(SGR_RESET), Event::Sgr(SGR_BOLD), Event::Sgr(SGR_FG_BASE + 1), Event::Char(b'x')]));
+        // extended colors are skipped along with their arguments
+        check!(Recorder::run(b"\x1b[38;5;200;4m\x1b[48;2;1;2;3;7m").matches(&[Event::Sgr(4), Event::Sgr(SGR_REVERSE)]));
+        Ok(())
+    }
+
+    fn private_modes() -> TestResult {
+        check!(Recorder::run(b"\x1b[?25l\x1b[?25h\x1b[?1049h").matches(&[Event::CursorVisible(false), Event::CursorVisible(true)]));
+        check!(Recorder::run(b"\x1b[2;20r\x1b[r").matches(&[Event::ScrollRegion(1, Some(19)), Event::ScrollRegion(0, None)]));
+        check!(Recorder::run(b"\x1b[5;40000r").matches(&[Event::ScrollRegion(4, Some(39999))]));
+        Ok(())
+    }
+
+    test_cases!(plain_text, cursor_movement, erase, graphic_rendition, private_modes);
+}
@@ -0,0 +1,464 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::drivers::ioport::{IOPort, IOPortSet};
+use ::drivers::irq::IRQ;
+use ::drivers::driver::{Driver, Device, IsaDevice, Match, Resources};
+use ::drivers::block;
+use ::drivers::block::{BlockDevice, DeviceId};
+use ::memory::Box;
+
+// PIO access to ATA disks on the two legacy IDE channels. drives are numbered like hda through hdd: primary master,
+// primary slave, secondary master, secondary slave.
+
+pub const SECTOR_SIZE: usize = 512;
+pub const DRIVE_COUNT: usize = 4;
+const DRIVE_NAMES: [&'static str; DRIVE_COUNT] = ["hda", "hdb", "hdc", "hdd"];
+
+pub const ISA_IDE_PRIMARY: IsaDevice = IsaDevice { name: "ide0", ports: [Some((0x1F0, 8)), Some((0x3F6, 1))], irq: Some(14) };
+pub const ISA_IDE_SECONDARY: IsaDevice = IsaDevice { name: "ide1", ports: [Some((0x170, 8)), Some((0x376, 1))], irq: Some(15) };
+
+// offsets into the command block
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7; // read
+const REG_COMMAND: u16 = 7; // write
+
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+
+const CONTROL_NIEN: u8 = 0x02; // 1 masks the interrupt
+
+const DRIVE_LBA: u8 = 0xE0; // LBA addressing, with the obsolete always-one bits
+const DRIVE_SLAVE: u8 = 0x10;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_FLUSH_CACHE: u8 = 0xE7;
+const CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+const LBA28_LIMIT: u64 = 1 << 28;
+const LBA28_MAX_SECTORS: usize = 256;
+const LBA48_MAX_SECTORS: usize = 65536;
+
+// IDENTIFY words
+const ID_MODEL: usize = 27; // through 46
+const ID_CAPABILITIES: usize = 49;
+const ID_LBA28_SECTORS: usize = 60;
+const ID_COMMAND_SETS: usize = 83;
+const ID_LBA48_SECTORS: usize = 100;
+const CAPABILITY_LBA: u16 = 1 << 9;
+const COMMAND_SET_LBA48: u16 = 1 << 10;
+
+#[derive(Copy, Clone)]
+pub struct DriveInfo {
+    pub sectors: u64,
+    pub lba48: bool,
+    model: [u8; 40]
+}
+
+impl DriveInfo {
+    fn from_identify(id: &[u16; 256]) -> Option<DriveInfo> {
+        if id[ID_CAPABILITIES] & CAPABILITY_LBA == 0 {
+            return None; // CHS-only drives are not worth supporting
+        }
+        let lba48 = id[ID_COMMAND_SETS] & COMMAND_SET_LBA48 != 0;
+        let sectors = if lba48 {
+            (0..4).fold(0u64, |acc, i| acc | ((id[ID_LBA48_SECTORS + i] as u64) << (16 * i)))
+        } else {
+            (id[ID_LBA28_SECTORS] as u64) | ((id[ID_LBA28_SECTORS + 1] as u64) << 16)
+        };
+        let mut model = [0u8; 40];
+        for i in 0..20 {
+            // each word holds two characters, first character in the high byte
+            model[i * 2] = (id[ID_MODEL + i] >> 8) as u8;
+            model[i * 2 + 1] = id[ID_MODEL + i] as u8;
+        }
+        Some(DriveInfo { sectors, lba48, model })
+    }
+
+    pub fn model(&self) -> &str {
+        let end = self.model.iter().rposition(|&c| c != b' ' && c != 0).map_or(0, |i| i + 1);
+        core::str::from_utf8(&self.model[..end]).unwrap_or("?")
+    }
+}
+
+impl core::fmt::Display for DriveInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} ({} sectors, {} MiB{})", self.model(), self.sectors,
+               self.sectors * SECTOR_SIZE as u64 / (1024 * 1024), if self.lba48 { ", LBA48" } else { "" })
+    }
+}
+
+struct Channel {
+    command: IOPortSet,
+    control: IOPort,
+    irq: Option<IRQ<'static>>,
+    drives: [Option<DriveInfo>; 2],
+    block_ids: [Option<DeviceId>; 2]
+}
+
+impl Channel {
+    fn reg(&self, reg: u16) -> IOPort {
+        self.command.get(reg)
+    }
+
+    fn status(&self) -> u8 {
+        self.reg(REG_STATUS).get()
+    }
+
+    // reading the alternate status register doesn't acknowledge the interrupt
+    fn alt_status(&self) -> u8 {
+        self.control.get()
+    }
+
+    // the status register isn't valid until 400ns after the drive is selected or a command is sent
+    fn delay(&self) {
+        for _ in 0..4 {
+            self.alt_status();
+        }
+    }
+
+    fn wait_not_busy(&self) -> u8 {
+        loop {
+            let status = self.alt_status();
+            if status & STATUS_BSY == 0 {
+                return status;
+            }
+        }
+    }
+
+    fn check(&self, status: u8) -> Result<(), KError> {
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            warn!("ATA command failed: status {:#X}, error {:#X}", status, self.reg(REG_ERROR).get());
+            Err(KError::UnknownError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn select(&self, slave: bool, lba_top: u8) {
+        self.reg(REG_DRIVE).set(DRIVE_LBA | if slave { DRIVE_SLAVE } else { 0 } | (lba_top & 0x0F));
+        self.delay();
+    }
+
+    // waits for the drive to raise its interrupt, then acknowledges it both on the drive and on the IRQ line
+    fn wait_irq(&self) -> u8 {
+        let irq = self.irq.as_ref().unwrap();
+        irq.wait();
+        let status = self.status();
+        irq.ack().unwrap();
+        if status & STATUS_BSY != 0 {
+            self.wait_not_busy()
+        } else {
+            status
+        }
+    }
+
+    // polled, since it's used while probing, before the channel has its IRQ
+    fn identify(&self, slave: bool) -> Option<DriveInfo> {
+        self.select(slave, 0);
+        for &reg in &[REG_SECTOR_COUNT, REG_LBA_LOW, REG_LBA_MID, REG_LBA_HIGH] {
+            self.reg(reg).set(0);
+        }
+        self.reg(REG_COMMAND).set(CMD_IDENTIFY);
+        self.delay();
+        if self.status() == 0 {
+            return None; // no drive
+        }
+        let status = self.wait_not_busy();
+        if self.reg(REG_LBA_MID).get() != 0 || self.reg(REG_LBA_HIGH).get() != 0 {
+            return None; // ATAPI or SATA signature; not an ATA disk
+        }
+        if status & STATUS_ERR != 0 {
+            return None;
+        }
+        while self.alt_status() & (STATUS_DRQ | STATUS_ERR) == 0 {}
+        if self.status() & STATUS_ERR != 0 {
+            return None;
+        }
+        let mut id = [0u16; 256];
+        for word in id.iter_mut() {
+            *word = self.reg(REG_DATA).get16();
+        }
+        DriveInfo::from_identify(&id)
+    }
+
+    fn set_interrupts(&mut self, enabled: bool) {
+        self.control.set(if enabled { 0 } else { CONTROL_NIEN });
+    }
+
+    fn issue(&self, slave: bool, lba48: bool, lba: u64, count: usize, command: u8) {
+        // a count of zero means the maximum for the command
+        if lba48 {
+            self.select(slave, 0);
+            self.reg(REG_SECTOR_COUNT).set((count >> 8) as u8);
+            self.reg(REG_LBA_LOW).set((lba >> 24) as u8);
+            self.reg(REG_LBA_MID).set((lba >> 32) as u8);
+            self.reg(REG_LBA_HIGH).set((lba >> 40) as u8);
+        } else {
+            self.select(slave, (lba >> 24) as u8);
+        }
+        self.reg(REG_SECTOR_COUNT).set(count as u8);
+        self.reg(REG_LBA_LOW).set(lba as u8);
+        self.reg(REG_LBA_MID).set((lba >> 8) as u8);
+        self.reg(REG_LBA_HIGH).set((lba >> 16) as u8);
+        self.reg(REG_COMMAND).set(command);
+    }
+
+    fn read_data(&self, sector: &mut [u8]) {
+        let data = self.reg(REG_DATA);
+        for i in 0..SECTOR_SIZE / 2 {
+            let word = data.get16();
+            sector[i * 2] = word as u8;
+            sector[i * 2 + 1] = (word >> 8) as u8;
+        }
+    }
+
+    fn write_data(&self, sector: &[u8]) {
+        let mut data = self.reg(REG_DATA);
+        for i in 0..SECTOR_SIZE / 2 {
+            data.set16((sector[i * 2] as u16) | ((sector[i * 2 + 1] as u16) << 8));
+        }
+    }
+
+    // the drive interrupts once for each sector that's ready to be read
+    fn read(&self, slave: bool, lba48: bool, lba: u64, buf: &mut [u8]) -> Result<(), KError> {
+        let count = buf.len() / SECTOR_SIZE;
+        self.issue(slave, lba48, lba, count, if lba48 { CMD_READ_SECTORS_EXT } else { CMD_READ_SECTORS });
+        for sector in buf.chunks_mut(SECTOR_SIZE) {
+            let status = self.wait_irq();
+            self.check(status)?;
+            self.read_data(sector);
+        }
+        Ok(())
+    }
+
+    // the first sector is sent as soon as the drive asks for it; after that, it interrupts once each sector is written
+    fn write(&self, slave: bool, lba48: bool, lba: u64, buf: &[u8]) -> Result<(), KError> {
+        let count = buf.len() / SECTOR_SIZE;
+        self.issue(slave, lba48, lba, count, if lba48 { CMD_WRITE_SECTORS_EXT } else { CMD_WRITE_SECTORS });
+        self.delay();
+        let status = self.wait_not_busy();
+        self.check(status)?;
+        for sector in buf.chunks(SECTOR_SIZE) {
+            self.write_data(sector);
+            let status = self.wait_irq();
+            self.check(status)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self, slave: bool, lba48: bool) -> Result<(), KError> {
+        self.select(slave, 0);
+        self.reg(REG_COMMAND).set(if lba48 { CMD_FLUSH_CACHE_EXT } else { CMD_FLUSH_CACHE });
+        let status = self.wait_irq();
+        self.check(status)
+    }
+}
+
+static CHANNELS: SingleThreaded<RefCell<[Option<Channel>; 2]>> = SingleThreaded(RefCell::new([None, None]));
+
+fn with_drive<R, F: FnOnce(&Channel, bool, &DriveInfo) -> Result<R, KError>>(drive: usize, f: F) -> Result<R, KError> {
+    if drive >= DRIVE_COUNT {
+        return Err(KError::InvalidArgument);
+    }
+    let channels = CHANNELS.get().borrow();
+    let channel = match channels[drive / 2] {
+        Some(ref channel) => channel,
+        None => return Err(KError::FailedLookup)
+    };
+    match channel.drives[drive % 2] {
+        Some(ref info) => f(channel, drive % 2 == 1, info),
+        None => Err(KError::FailedLookup)
+    }
+}
+
+pub fn info(drive: usize) -> Option<DriveInfo> {
+    with_drive(drive, |_, _, info| Ok(*info)).ok()
+}
+
+// splits a transfer into as few commands as the drive allows, using LBA48 only where LBA28 can't reach
+fn transfer<F: FnMut(&Channel, bool, bool, u64, usize, usize) -> Result<(), KError>>(drive: usize, lba: u64, len: usize, mut f: F) -> Result<(), KError> {
+    if len % SECTOR_SIZE != 0 {
+        return Err(KError::AlignmentError);
+    }
+    with_drive(drive, |channel, slave, info| {
+        let count = (len / SECTOR_SIZE) as u64;
+        if lba >= info.sectors || count > info.sectors - lba {
+            return Err(KError::RangeError);
+        }
+        let mut done = 0;
+        while done < count {
+            let start = lba + done;
+            let lba48 = info.lba48 && (start + (count - done) > LBA28_LIMIT || count - done > LBA28_MAX_SECTORS as u64);
+            let max = (if lba48 { LBA48_MAX_SECTORS } else { LBA28_MAX_SECTORS }) as u64;
+            let n = core::cmp::min(count - done, max);
+            f(channel, slave, lba48, start, done as usize * SECTOR_SIZE, (done + n) as usize * SECTOR_SIZE)?;
+            done += n;
+        }
+        Ok(())
+    })
+}
+
+pub fn read_sectors(drive: usize, lba: u64, buf: &mut [u8]) -> Result<(), KError> {
+    let len = buf.len();
+    transfer(drive, lba, len, |channel, slave, lba48, start, from, to| channel.read(slave, lba48, start, &mut buf[from..to]))
+}
+
+pub fn write_sectors(drive: usize, lba: u64, buf: &[u8]) -> Result<(), KError> {
+    transfer(drive, lba, buf.len(), |channel, slave, lba48, start, from, to| channel.write(slave, lba48, start, &buf[from..to]))
+}
+
+pub fn flush(drive: usize) -> Result<(), KError> {
+    with_drive(drive, |channel, slave, info| channel.flush(slave, info.lba48))
+}
+
+pub struct AtaDisk {
+    drive: usize,
+    sectors: u64
+}
+
+impl BlockDevice for AtaDisk {
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+        read_sectors(self.drive, sector, buf)
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError> {
+        write_sectors(self.drive, sector, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), KError> {
+        flush(self.drive)
+    }
+}
+
+pub fn print_drives(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    for drive in 0..DRIVE_COUNT {
+        if let Some(info) = info(drive) {
+            writeln!(writer, "{}: {}", DRIVE_NAMES[drive], info)?;
+        }
+    }
+    Ok(())
+}
+
+const ATA_MATCHES: &'static [Match] = &[Match::Isa("ide0"), Match::Isa("ide1")];
+
+pub struct AtaDriver {
+    // the channel found by the last successful probe, until it's attached
+    probed: Option<Channel>
+}
+
+impl AtaDriver {
+    pub fn new() -> AtaDriver {
+        AtaDriver { probed: None }
+    }
+
+    fn channel_index(device: &Device) -> Option<usize> {
+        match *device {
+            Device::Isa(ref isa) if isa.name == ISA_IDE_PRIMARY.name => Some(0),
+            Device::Isa(ref isa) if isa.name == ISA_IDE_SECONDARY.name => Some(1),
+            _ => None
+        }
+    }
+}
+
+impl Driver for AtaDriver {
+    fn name(&self) -> &'static str {
+        "ata"
+    }
+
+    fn matches(&self) -> &'static [Match] {
+        ATA_MATCHES
+    }
+
+    fn probe(&mut self, _device: &Device, resources: &Resources) -> bool {
+        let (command, control) = match (resources.ports(0), resources.ports(1)) {
+            (Some(command), Some(control)) => (command, control.get(0)),
+            _ => return false
+        };
+        let mut channel = Channel { command, control, irq: None, drives: [None, None], block_ids: [None, None] };
+        if channel.status() == 0xFF {
+            return false; // floating bus: nothing attached to this channel
+        }
+        channel.set_interrupts(false);
+        channel.drives = [channel.identify(false), channel.identify(true)];
+        if channel.drives[0].is_none() && channel.drives[1].is_none() {
+            return false;
+        }
+        self.probed = Some(channel);
+        true
+    }
+
+    fn attach(&mut self, device: &Device, resources: &Resources) -> Result<(), KError> {
+        let index = match AtaDriver::channel_index(device) {
+            Some(index) => index,
+            None => return Err(KError::InvalidArgument)
+        };
+        let mut channel = match self.probed.take() {
+            Some(channel) => channel,
+            None => return Err(KError::IllegalOperation)
+        };
+        channel.irq = Some(resources.request_irq()?);
+        channel.status(); // clear anything left pending from probing
+        channel.set_interrupts(true);
+        let drives = channel.drives;
+        CHANNELS.get().borrow_mut()[index] = Some(channel);
+        for (slave, drive) in drives.iter().enumerate() {
+            if let Some(ref info) = *drive {
+                let number = index * 2 + slave;
+                debug!("{}: {}", DRIVE_NAMES[number], info);
+                match block::register(DRIVE_NAMES[number], Box::new(AtaDisk { drive: number, sectors: info.sectors })) {
+                    Ok(id) => CHANNELS.get().borrow_mut()[index].as_mut().unwrap().block_ids[slave] = Some(id),
+                    Err(err) => warn!("could not register {} as a block device: {:?}", DRIVE_NAMES[number], err)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn detach(&mut self, device: &Device) -> Result<(), KError> {
+        let index = match AtaDriver::channel_index(device) {
+            Some(index) => index,
+            None => return Err(KError::InvalidArgument)
+        };
+        // done first, since it writes back whatever is still cached for the drives
+        let block_ids = match CHANNELS.get().borrow()[index] {
+            Some(ref channel) => channel.block_ids,
+            None => return Err(KError::FailedLookup)
+        };
+        for slave in 0..2 {
+            if let Some(id) = block_ids[slave] {
+                block::unregister(id)?;
+                CHANNELS.get().borrow_mut()[index].as_mut().unwrap().block_ids[slave] = None;
+            }
+        }
+        match CHANNELS.get().borrow_mut()[index].take() {
+            Some(mut channel) => {
+                channel.set_interrupts(false);
+                channel.irq.take().unwrap().free();
+                Ok(())
+            }
+            None => Err(KError::FailedLookup)
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::drivers::ioport;
+use ::drivers::ioport::IOPort;
+use ::drivers::driver::{Driver, Device, Match, Resources};
+use ::drivers::framebuffer::Framebuffer;
+
+// the Bochs Graphics Adapter, as emulated by Bochs and QEMU (-vga std)
+
+const DISPI_IOPORT_INDEX: u16 = 0x01CE; // then data at 0x01CF
+
+const DISPI_INDEX_ID: u16 = 0x0;
+const DISPI_INDEX_XRES: u16 = 0x1;
+const DISPI_INDEX_YRES: u16 = 0x2;
+const DISPI_INDEX_BPP: u16 = 0x3;
+const DISPI_INDEX_ENABLE: u16 = 0x4;
+const DISPI_INDEX_VIRT_WIDTH: u16 = 0x6;
+const DISPI_INDEX_X_OFFSET: u16 = 0x8;
+const DISPI_INDEX_Y_OFFSET: u16 = 0x9;
+const DISPI_INDEX_VIDEO_MEMORY_64K: u16 = 0xA;
+
+const DISPI_ID_MIN: u16 = 0xB0C0;
+const DISPI_ID_MAX: u16 = 0xB0C5;
+const DISPI_ID_LFB: u16 = 0xB0C2; // the first version with a linear framebuffer
+
+const DISPI_DISABLED: u16 = 0x00;
+const DISPI_ENABLED: u16 = 0x01;
+const DISPI_LFB_ENABLED: u16 = 0x40;
+
+pub const MAX_XRES: u16 = 1600;
+pub const MAX_YRES: u16 = 1200;
+
+const PCI_VENDOR_BOCHS: u16 = 0x1234;
+const PCI_DEVICE_BGA: u16 = 0x1111;
+const LFB_BAR: usize = 0;
+
+const BGA_MATCHES: &'static [Match] = &[Match::PciId { vendor: PCI_VENDOR_BOCHS, device: PCI_DEVICE_BGA }];
+
+pub struct BGA {
+    index: IOPort,
+    data: IOPort,
+    version: u16,
+    lfb_paddr: usize
+}
+
+impl BGA {
+    // checks that the adapter speaks a version of the interface we understand
+    fn new(lfb_paddr: usize) -> Option<BGA> {
+        let ports = ioport::request(DISPI_IOPORT_INDEX, 2);
+        let mut bga = BGA { index: ports.get(0), data: ports.get(1), version: 0, lfb_paddr };
+        let version = bga.read(DISPI_INDEX_ID);
+        if version < DISPI_ID_LFB || version > DISPI_ID_MAX {
+            if version >= DISPI_ID_MIN {
+                debug!("BGA version {:#X} has no linear framebuffer", version);
+            }
+            return None;
+        }
+        bga.version = version;
+        Some(bga)
+    }
+
+    fn read(&mut self, index: u16) -> u16 {
+        self.index.set16(index);
+        self.data.get16()
+    }
+
+    fn write(&mut self, index: u16, value: u16) {
+        self.index.set16(index);
+        self.data.set16(value);
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn video_memory(&mut self) -> usize {
+        (self.read(DISPI_INDEX_VIDEO_MEMORY_64K) as usize) * 64 * 1024
+    }
+
+    // switches the display into a graphics mode and maps its framebuffer
+    pub fn set_mode(&mut self, width: u16, height: u16, bpp: u8) -> Result<Framebuffer, KError> {
+        if width == 0 || height == 0 || width > MAX_XRES || height > MAX_YRES {
+            return Err(KError::RangeError);
+        }
+        if bpp != 8 && bpp != 15 && bpp != 16 && bpp != 24 && bpp != 32 {
+            return Err(KError::InvalidArgument);
+        }
+        let pitch = (width as usize) * (((bpp as usize) + 7) / 8);
+        if pitch * (height as usize) > self.video_memory() {
+            return Err(KError::NotEnoughMemory);
+        }
+        // the registers may only be changed while the display is disabled
+        self.write(DISPI_INDEX_ENABLE, DISPI_DISABLED);
+        self.write(DISPI_INDEX_XRES, width);
+        self.write(DISPI_INDEX_YRES, height);
+        self.write(DISPI_INDEX_BPP, bpp as u16);
+        self.write(DISPI_INDEX_VIRT_WIDTH, width);
+        self.write(DISPI_INDEX_X_OFFSET, 0);
+        self.write(DISPI_INDEX_Y_OFFSET, 0);
+        self.write(DISPI_INDEX_ENABLE, DISPI_ENABLED | DISPI_LFB_ENABLED);
+        if self.read(DISPI_INDEX_XRES) != width || self.read(DISPI_INDEX_YRES) != height || self.read(DISPI_INDEX_BPP) != bpp as u16 {
+            self.disable();
+            return Err(KError::InvalidArgument);
+        }
+        match Framebuffer::new(self.lfb_paddr, width, height, bpp, pitch) {
+            Ok(fb) => Ok(fb),
+            Err(err) => {
+                self.disable();
+                Err(err)
+            }
+        }
+    }
+
+    // returns the display to VGA emulation, so text mode works again
+    pub fn disable(&mut self) {
+        self.write(DISPI_INDEX_ENABLE, DISPI_DISABLED);
+    }
+}
+
+// there's only ever one display adapter, which is held here once the driver has attached to it
+static ADAPTER: SingleThreaded<RefCell<Option<BGA>>> = SingleThreaded(RefCell::new(None));
+
+// None if no adapter has been found, or if it's busy
+pub fn with_adapter<R, F: FnOnce(&mut BGA) -> R>(f: F) -> Option<R> {
+    match ADAPTER.get().try_borrow_mut() {
+        Ok(mut adapter) => adapter.as_mut().map(f),
+        Err(_) => None
+    }
+}
+
+pub struct BgaDriver;
+
+impl Driver for BgaDriver {
+    fn name(&self) -> &'static str {
+        "bga"
+    }
+
+    fn matches(&self) -> &'static [Match] {
+        BGA_MATCHES
+    }
+
+    fn probe(&mut self, _device: &Device, resources: &Resources) -> bool {
+        ADAPTER.get().borrow().is_none() && resources.memory(LFB_BAR).map_or(false, |(paddr, _)| BGA::new(paddr).is_some())
+    }
+
+    fn attach(&mut self, _device: &Device, resources: &Resources) -> Result<(), KError> {
+        let mut bga = match resources.memory(LFB_BAR).and_then(|(paddr, _)| BGA::new(paddr)) {
+            Some(bga) => bga,
+            None => return Err(KError::FailedLookup)
+        };
+        debug!("BGA version {:#X}, {} KB of video memory", bga.version(), bga.video_memory() >> 10);
+        *ADAPTER.get().borrow_mut() = Some(bga);
+        Ok(())
+    }
+
+    fn detach(&mut self, _device: &Device) -> Result<(), KError> {
+        match ADAPTER.get().try_borrow_mut() {
+            Ok(mut adapter) => match adapter.take() {
+                Some(mut bga) => {
+                    bga.disable();
+                    Ok(())
+                }
+                None => Err(KError::FailedLookup)
+            },
+            // in the middle of being used
+            Err(_) => Err(KError::IllegalOperation)
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::{Box, LinkedList};
+use ::memory::untyped;
+use ::kobject::RegionMappedPage4K;
+
+// disks, whichever driver they come from, and a write-back cache of their sectors shared between all of them
+
+pub trait BlockDevice {
+    fn sector_size(&self) -> usize;
+    fn sector_count(&self) -> u64;
+    // buffers are a whole number of sectors
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError>;
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError>;
+    fn flush(&mut self) -> Result<(), KError>;
+}
+
+// the cache holds sectors of this size; devices with other sector sizes aren't supported
+pub const BLOCK_SIZE: usize = 512;
+
+const CACHE_PAGES: usize = 32;
+const BLOCKS_PER_PAGE: usize = PAGE_4K_SIZE / BLOCK_SIZE;
+const CACHE_BLOCKS: usize = CACHE_PAGES * BLOCKS_PER_PAGE;
+
+pub type DeviceId = usize;
+
+struct Registered {
+    id: DeviceId,
+    name: &'static str,
+    device: Box<BlockDevice>
+}
+
+#[derive(Copy, Clone)]
+struct CacheEntry {
+    device: DeviceId,
+    sector: u64,
+    valid: bool,
+    dirty: bool,
+    last_used: u64
+}
+
+const EMPTY_ENTRY: CacheEntry = CacheEntry { device: 0, sector: 0, valid: false, dirty: false, last_used: 0 };
+
+#[derive(Debug, Copy, Clone)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub write_backs: u64,
+    pub cached: usize,
+    pub dirty: usize,
+    pub capacity: usize
+}
+
+impl core::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} hits, {} misses, {} write-backs; {}/{} blocks cached, {} dirty",
+               self.hits, self.misses, self.write_backs, self.cached, self.capacity, self.dirty)
+    }
+}
+
+struct BlockLayer {
+    devices: LinkedList<Registered>,
+    next_id: DeviceId,
+    entries: [CacheEntry; CACHE_BLOCKS],
+    // allocated as the cache fills up
+    pages: [Option<RegionMappedPage4K>; CACHE_PAGES],
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    write_backs: u64
+}
+
+static BLOCK: SingleThreaded<RefCell<BlockLayer>> = SingleThreaded(RefCell::new(BlockLayer {
+    devices: LinkedList::empty(),
+    next_id: 0,
+    entries: [EMPTY_ENTRY; CACHE_BLOCKS],
+    pages: [None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+            None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None],
+    clock: 0,
+    hits: 0,
+    misses: 0,
+    write_backs: 0
+}));
+
+impl BlockLayer {
+    fn device(&mut self, id: DeviceId) -> Result<&mut BlockDevice, KError> {
+        match self.devices.find_mut(|r| r.id == id) {
+            Some(registered) => Ok(&mut *registered.device),
+            None => Err(KError::FailedLookup)
+        }
+    }
+
+    fn data(&mut self, slot: usize) -> &mut [u8] {
+        let page = self.pages[slot / BLOCKS_PER_PAGE].as_mut().unwrap();
+        let offset = (slot % BLOCKS_PER_PAGE) * BLOCK_SIZE;
+        &mut page.get_array()[offset..offset + BLOCK_SIZE]
+    }
+
+    fn lookup(&self, device: DeviceId, sector: u64) -> Option<usize> {
+        self.entries.iter().position(|e| e.valid && e.device == device && e.sector == sector)
+    }
+
+    fn write_back(&mut self, slot: usize) -> Result<(), KError> {
+        let entry = self.entries[slot];
+        assert!(entry.valid && entry.dirty);
+        let mut block = [0u8; BLOCK_SIZE];
+        block.copy_from_slice(self.data(slot));
+        self.device(entry.device)?.write(entry.sector, &block)?;
+        self.entries[slot].dirty = false;
+        self.write_backs += 1;
+        Ok(())
+    }
+
+    // picks an unused slot if there is one (allocating pages as needed), or else evicts the least recently used block
+    fn free_slot(&mut self) -> Result<usize, KError> {
+        if let Some(slot) = self.entries.iter().position(|e| !e.valid) {
+            let page = slot / BLOCKS_PER_PAGE;
+            if self.pages[page].is_some() {
+                return Ok(slot);
+            }
+            match untyped::allocate_page4k().and_then(|p| p.map_into_vspace(true).map_err(|(p, err)| {
+                untyped::free_page4k(p);
+                err
+            })) {
+                Ok(mapping) => {
+                    self.pages[page] = Some(mapping);
+                    return Ok(slot);
+                }
+                Err(err) => {
+                    if self.pages.iter().all(|p| p.is_none()) {
+                        return Err(err);
+                    }
+                    // otherwise, make do with the pages we already have
+                }
+            }
+        }
+        let slot = (0..CACHE_BLOCKS).filter(|&i| self.entries[i].valid && self.pages[i / BLOCKS_PER_PAGE].is_some())
+            .min_by_key(|&i| self.entries[i].last_used).unwrap();
+        if self.entries[slot].dirty {
+            self.write_back(slot)?;
+        }
+        self.entries[slot].valid = false;
+        Ok(slot)
+    }
+
+    fn get(&mut self, device: DeviceId, sector: u64, will_overwrite: bool) -> Result<usize, KError> {
+        self.clock += 1;
+        if let Some(slot) = self.lookup(device, sector) {
+            self.hits += 1;
+            self.entries[slot].last_used = self.clock;
+            return Ok(slot);
+        }
+        self.misses += 1;
+        if sector >= self.device(device)?.sector_count() {
+            return Err(KError::RangeError);
+        }
+        let slot = self.free_slot()?;
+        if !will_overwrite {
+            let mut block = [0u8; BLOCK_SIZE];
+            self.device(device)?.read(sector, &mut block)?;
+            self.data(slot).copy_from_slice(&block);
+        }
+        self.entries[slot] = CacheEntry { device, sector, valid: true, dirty: false, last_used: self.clock };
+        Ok(slot)
+    }
+
+    fn sync(&mut self, device: Option<DeviceId>) -> Result<(), KError> {
+        for slot in 0..CACHE_BLOCKS {
+            let entry = self.entries[slot];
+            if entry.valid && entry.dirty && device.map_or(true, |d| d == entry.device) {
+                self.write_back(slot)?;
+            }
+        }
+        for i in 0..self.devices.len() {
+            let registered = self.devices.get_mut(i).unwrap();
+            if device.map_or(true, |d| d == registered.id) {
+                registered.device.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// devices are named after their drivers' conventions, like hda or vda
+pub fn register(name: &'static str, device: Box<BlockDevice>) -> Result<DeviceId, KError> {
+    if device.sector_size() != BLOCK_SIZE {
+        warn!("{} has {}-byte sectors, which are not supported", name, device.sector_size());
+        return Err(KError::InvalidArgument);
+    }
+    let mut layer = BLOCK.get().borrow_mut();
+    let id = layer.next_id;
+    if layer.devices.pushmut(Registered { id, name, device }).is_err() {
+        return Err(KError::NotEnoughMemory);
+    }
+    layer.next_id += 1;
+    Ok(id)
+}
+
+// writes back anything cached for the device before it goes
+pub fn unregister(id: DeviceId) -> Result<(), KError> {
+    let mut layer = BLOCK.get().borrow_mut();
+    layer.sync(Some(id))?;
+    for entry in layer.entries.iter_mut().filter(|e| e.device == id) {
+        entry.valid = false;
+    }
+    match layer.devices.remove_mut(|r| r.id == id) {
+        Some(_) => Ok(()),
+        None => Err(KError::FailedLookup)
+    }
+}
+
+pub fn find(name: &str) -> Option<DeviceId> {
+    BLOCK.get().borrow().devices.find(|r| r.name == name).map(|r| r.id)
+}
+
+pub fn sector_count(id: DeviceId) -> Result<u64, KError> {
+    BLOCK.get().borrow_mut().device(id).map(|d| d.sector_count())
+}
+
+pub fn read(id: DeviceId, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+    if buf.len() % BLOCK_SIZE != 0 {
+        return Err(KError::AlignmentError);
+    }
+    let mut layer = BLOCK.get().borrow_mut();
+    for (i, block) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+        let slot = layer.get(id, sector + i as u64, false)?;
+        block.copy_from_slice(layer.data(slot));
+    }
+    Ok(())
+}
+
+// only marks the blocks dirty; they reach the device when evicted or synced
+pub fn write(id: DeviceId, sector: u64, buf: &[u8]) -> Result<(), KError> {
+    if buf.len() % BLOCK_SIZE != 0 {
+        return Err(KError::AlignmentError);
+    }
+    let mut layer = BLOCK.get().borrow_mut();
+    for (i, block) in buf.chunks(BLOCK_SIZE).enumerate() {
+        let slot = layer.get(id, sector + i as u64, true)?;
+        layer.data(slot).copy_from_slice(block);
+        layer.entries[slot].dirty = true;
+    }
+    Ok(())
+}
+
+pub fn sync(id: DeviceId) -> Result<(), KError> {
+    BLOCK.get().borrow_mut().sync(Some(id))
+}
+
+pub fn sync_all() -> Result<(), KError> {
+    BLOCK.get().borrow_mut().sync(None)
+}
+
+pub fn stats() -> CacheStats {
+    let layer = BLOCK.get().borrow();
+    let usable = layer.pages.iter().filter(|p| p.is_some()).count() * BLOCKS_PER_PAGE;
+    CacheStats {
+        hits: layer.hits,
+        misses: layer.misses,
+        write_backs: layer.write_backs,
+        cached: layer.entries.iter().filter(|e| e.valid).count(),
+        dirty: layer.entries.iter().filter(|e| e.valid && e.dirty).count(),
+        capacity: usable
+    }
+}
+
+pub fn print_devices(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    let layer = BLOCK.get().borrow();
+    for registered in &layer.devices {
+        let sectors = registered.device.sector_count();
+        writeln!(writer, "{}: {} sectors, {} MiB", registered.name, sectors, sectors * BLOCK_SIZE as u64 / (1024 * 1024))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod tests {
+    use super::*;
+    use ::core::cell::Cell;
+    use ::ktest::TestResult;
+
+    const SECTORS: usize = 8;
+
+    // a disk in a static, so that what reached it can be checked after the block layer has taken the device
+    static DISK: SingleThreaded<RefCell<[u8; SECTORS * BLOCK_SIZE]>> = SingleThreaded(RefCell::new([0; SECTORS * BLOCK_SIZE]));
+    static WRITES: SingleThreaded<Cell<usize>> = SingleThreaded(Cell::new(0));
+
+    struct RamDisk;
+
+    impl BlockDevice for RamDisk {
+        fn sector_size(&self) -> usize {
+            BLOCK_SIZE
+        }
+
+        fn sector_count(&self) -> u64 {
+            SECTORS as u64
+        }
+
+        fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+            let start = sector as usize * BLOCK_SIZE;
+            buf.copy_from_slice(&DISK.get().borrow()[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError> {
+            let start = sector as usize * BLOCK_SIZE;
+            DISK.get().borrow_mut()[start..start + buf.len()].copy_from_slice(buf);
+            WRITES.get().set(WRITES.get().get() + 1);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), KError> {
+            Ok(())
+        }
+    }
+
+    fn write_back() -> TestResult {
+        let id = check_ok!(register("ktest", Box::new(RamDisk)));
+        check_eq!(find("ktest"), Some(id));
+        check_eq!(check_ok!(sector_count(id)), SECTORS as u64);
+        WRITES.get().set(0);
+        let data = [0xA5u8; BLOCK_SIZE * 2];
+        check_ok!(write(id, 3, &data));
+        // nothing reaches the disk until it's synced, but reads see the new data straight away
+        check_eq!(WRITES.get().get(), 0);
+        let mut buf = [0u8; BLOCK_SIZE * 2];
+        check_ok!(read(id, 3, &mut buf));
+        check!(buf.iter().all(|&b| b == 0xA5));
+        check_ok!(sync(id));
+        check_eq!(WRITES.get().get(), 2);
+        check!(DISK.get().borrow()[3 * BLOCK_SIZE..5 * BLOCK_SIZE].iter().all(|&b| b == 0xA5));
+        check!(DISK.get().borrow()[..3 * BLOCK_SIZE].iter().all(|&b| b == 0));
+        check_ok!(unregister(id));
+        check_eq!(find("ktest"), None);
+        Ok(())
+    }
+
+    fn unregister_syncs() -> TestResult {
+        let id = check_ok!(register("ktest", Box::new(RamDisk)));
+        check_ok!(write(id, 0, &[0x5Au8; BLOCK_SIZE]));
+        check_ok!(unregister(id));
+        check!(DISK.get().borrow()[..BLOCK_SIZE].iter().all(|&b| b == 0x5A));
+        check_eq!(read(id, 0, &mut [0u8; BLOCK_SIZE]).err(), Some(KError::FailedLookup));
+        Ok(())
+    }
+
+    fn bad_requests() -> TestResult {
+        let id = check_ok!(register("ktest", Box::new(RamDisk)));
+        let mut buf = [0u8; BLOCK_SIZE];
+        check_eq!(read(id, 0, &mut buf[..100]).err(), Some(KError::AlignmentError));
+        check_eq!(read(id, SECTORS as u64, &mut buf).err(), Some(KError::RangeError));
+        check_ok!(unregister(id));
+        Ok(())
+    }
+
+    test_cases!(write_back, unregister_syncs, bad_requests);
+}
@@ -0,0 +1,235 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::concurrency::SingleThreaded;
+use ::memory::Box;
+use ::mantle::KError;
+use ::drivers::vga::{VGA, VGAOutput, DEFAULT_SCROLLBACK_LINES};
+use ::drivers::vgamode;
+use ::drivers::vgamode::Mode;
+use ::drivers::framebuffer::Framebuffer;
+use ::drivers::bga;
+use ::drivers::font::Font;
+use ::drivers::textconsole::TextConsole;
+use ::drivers::keyboard::{Key, KeyEvent};
+
+// virtual consoles share the physical VGA display; Alt+F1 through Alt+F6 switch between them
+pub const CONSOLE_COUNT: usize = 6;
+pub const DEBUG_CONSOLE: usize = 0;
+pub const SHELL_CONSOLE: usize = 1;
+
+type InputHandler = Box<FnMut(KeyEvent)>;
+
+struct Consoles {
+    // consoles other than the debug console are only set up once something uses them
+    outputs: [Option<VGAOutput>; CONSOLE_COUNT],
+    active: usize,
+    // the text buffer, held here while the display is in graphics mode
+    released: Option<VGA>,
+    // set once the debug log has moved to a high-resolution TextConsole, which can't be undone
+    hires: bool
+}
+
+static CONSOLES: SingleThreaded<RefCell<Consoles>> = SingleThreaded(RefCell::new(Consoles {
+    outputs: [None, None, None, None, None, None],
+    active: DEBUG_CONSOLE,
+    released: None,
+    hires: false
+}));
+
+// kept apart from CONSOLES so that handlers can write to consoles while they run
+static INPUT: SingleThreaded<RefCell<[Option<InputHandler>; CONSOLE_COUNT]>> =
+    SingleThreaded(RefCell::new([None, None, None, None, None, None]));
+
+impl Consoles {
+    fn get(&mut self, n: usize) -> Option<&mut VGAOutput> {
+        assert!(n < CONSOLE_COUNT);
+        if self.outputs[DEBUG_CONSOLE].is_none() {
+            return None; // nothing can be created until the display itself has been set up
+        }
+        if self.outputs[n].is_none() {
+            match VGAOutput::offscreen() {
+                Ok(mut output) => {
+                    output.enable_scrollback(DEFAULT_SCROLLBACK_LINES);
+                    self.outputs[n] = Some(output);
+                }
+                Err(err) => {
+                    warn!("could not set up console {}: {:?}", n + 1, err);
+                    return None;
+                }
+            }
+        }
+        self.outputs[n].as_mut()
+    }
+
+    fn switch_to(&mut self, n: usize) {
+        if n == self.active || self.released.is_some() || self.get(n).is_none() {
+            return;
+        }
+        // both are present: the active console always is, and get() just made sure of the other
+        let (old, new) = (self.active, n);
+        let mut from = self.outputs[old].take().unwrap();
+        self.outputs[new].as_mut().unwrap().take_display(&mut from);
+        self.outputs[old] = Some(from);
+        self.active = new;
+    }
+}
+
+struct DebugMirror;
+
+impl core::fmt::Write for DebugMirror {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // debug output produced while the consoles are busy (e.g. allocating) only goes to serial
+        if let Ok(mut consoles) = CONSOLES.get().try_borrow_mut() {
+            if let Some(ref mut output) = consoles.outputs[DEBUG_CONSOLE] {
+                output.put_string(s);
+            }
+        }
+        Ok(())
+    }
+}
+
+// takes over the physical display as the debug console, which the debug log is mirrored to
+pub fn install(output: VGAOutput) {
+    assert!(output.is_active());
+    {
+        let mut consoles = CONSOLES.get().borrow_mut();
+        assert!(consoles.outputs[DEBUG_CONSOLE].is_none());
+        consoles.outputs[DEBUG_CONSOLE] = Some(output);
+        consoles.active = DEBUG_CONSOLE;
+    }
+    ::mantle::debug::set_mirror(DebugMirror);
+}
+
+pub fn with_console<R, F: FnOnce(&mut VGAOutput) -> R>(n: usize, f: F) -> Option<R> {
+    match CONSOLES.get().try_borrow_mut() {
+        Ok(mut consoles) => consoles.get(n).map(f),
+        Err(_) => None
+    }
+}
+
+pub fn active() -> usize {
+    CONSOLES.get().borrow().active
+}
+
+pub fn switch_to(n: usize) {
+    assert!(n < CONSOLE_COUNT);
+    CONSOLES.get().borrow_mut().switch_to(n);
+}
+
+// consoles keep collecting output while in graphics mode, and the active one is redrawn on leaving it
+pub fn enter_graphics() -> Result<Framebuffer, KError> {
+    let mut consoles = CONSOLES.get().borrow_mut();
+    if consoles.released.is_some() {
+        return Err(KError::IllegalOperation);
+    }
+    let active = consoles.active;
+    let screen = consoles.outputs[active].as_mut().unwrap().release_display()?;
+    consoles.released = Some(screen);
+    let result = vgamode::set_mode(Mode::Graphics320x200).and_then(|()| vgamode::graphics_framebuffer());
+    if result.is_err() {
+        let screen = consoles.released.take().unwrap();
+        let _ = vgamode::set_mode(Mode::Text80x25);
+        consoles.outputs[active].as_mut().unwrap().reclaim_display(screen);
+    }
+    result
+}
+
+pub fn leave_graphics() -> Result<(), KError> {
+    let mut consoles = CONSOLES.get().borrow_mut();
+    if consoles.released.is_none() || consoles.hires {
+        return Err(KError::IllegalOperation);
+    }
+    vgamode::set_mode(Mode::Text80x25)?;
+    let (active, screen) = (consoles.active, consoles.released.take().unwrap());
+    consoles.outputs[active].as_mut().unwrap().reclaim_display(screen);
+    Ok(())
+}
+
+struct HiresMirror {
+    output: TextConsole
+}
+
+impl core::fmt::Write for HiresMirror {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.output.put_string(s);
+        // the debug console keeps its copy, even though it can no longer be shown
+        DebugMirror.write_str(s)
+    }
+}
+
+// switches the display to a BGA graphics mode and draws the debug log there from now on. the consoles keep collecting
+// output, but can't be switched to or shown again.
+pub fn enter_hires(width: u16, height: u16, bpp: u8) -> Result<(), KError> {
+    let fb = {
+        let mut consoles = CONSOLES.get().borrow_mut();
+        if consoles.released.is_some() {
+            return Err(KError::IllegalOperation);
+        }
+        let active = consoles.active;
+        let screen = consoles.outputs[active].as_mut().unwrap().release_display()?;
+        match bga::with_adapter(|adapter| adapter.set_mode(width, height, bpp)).unwrap_or(Err(KError::FailedLookup)) {
+            Ok(fb) => {
+                consoles.released = Some(screen);
+                consoles.hires = true;
+                fb
+            }
+            Err(err) => {
+                consoles.outputs[active].as_mut().unwrap().reclaim_display(screen);
+                return Err(err);
+            }
+        }
+    };
+    // loaded once the consoles are free again, so that any complaint about it is logged there too
+    let font = Font::boot_font();
+    ::mantle::debug::set_mirror(HiresMirror { output: TextConsole::new(fb, font) });
+    Ok(())
+}
+
+// key events are only delivered to the handler of the console currently on display
+pub fn set_input_handler<F: FnMut(KeyEvent) + 'static>(n: usize, f: F) {
+    assert!(n < CONSOLE_COUNT);
+    INPUT.get().borrow_mut()[n] = Some(Box::new(f));
+}
+
+pub fn handle_key(event: KeyEvent) {
+    if let Key::F(n) = event.key {
+        if event.alt && n >= 1 && (n as usize) <= CONSOLE_COUNT {
+            if event.pressed {
+                switch_to(n as usize - 1);
+            }
+            return;
+        }
+    }
+    let n = active();
+    if with_console(n, |output| output.handle_key(&event)) == Some(true) {
+        return; // used for scrolling through history
+    }
+    // taken out while it runs, so that the handler may replace itself
+    let handler = INPUT.get().borrow_mut()[n].take();
+    if let Some(mut handler) = handler {
+        handler(event);
+        let mut input = INPUT.get().borrow_mut();
+        if input[n].is_none() {
+            input[n] = Some(handler);
+        }
+    }
+}
+
+// a writer for a console, for use by whatever owns it
+pub struct ConsoleWriter {
+    console: usize
+}
+
+pub fn writer(n: usize) -> ConsoleWriter {
+    assert!(n < CONSOLE_COUNT);
+    ConsoleWriter { console: n }
+}
+
+impl core::fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        match with_console(self.console, |output| output.put_string(s)) {
+            Some(()) => Ok(()),
+            None => Err(core::fmt::Error)
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// translation from unicode to code page 437, the character set of the VGA's built-in font
+
+// the glyphs of 0x01 through 0x1F, which are also control characters
+static LOW_GLYPHS: [char; 31] = [
+    '\u{263A}', '\u{263B}', '\u{2665}', '\u{2666}', '\u{2663}', '\u{2660}', '\u{2022}', '\u{25D8}', // ☺ ☻ ♥ ♦ ♣ ♠ • ◘
+    '\u{25CB}', '\u{25D9}', '\u{2642}', '\u{2640}', '\u{266A}', '\u{266B}', '\u{263C}', '\u{25BA}', // ○ ◙ ♂ ♀ ♪ ♫ ☼ ►
+    '\u{25C4}', '\u{2195}', '\u{203C}', '\u{00B6}', '\u{00A7}', '\u{25AC}', '\u{21A8}', '\u{2191}', // ◄ ↕ ‼ ¶ § ▬ ↨ ↑
+    '\u{2193}', '\u{2192}', '\u{2190}', '\u{221F}', '\u{2194}', '\u{25B2}', '\u{25BC}', // ↓ → ← ∟ ↔ ▲ ▼
+];
+
+const HOUSE: char = '\u{2302}'; // 0x7F
+
+// 0x80 through 0xFF
+static HIGH_GLYPHS: [char; 128] = [
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}', // Ç ü é â ä à å ç
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}', // ê ë è ï î ì Ä Å
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}', // É æ Æ ô ö ò û ù
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}', // ÿ Ö Ü ¢ £ ¥ ₧ ƒ
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}', // á í ó ú ñ Ñ ª º
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}', // ¿ ⌐ ¬ ½ ¼ ¡ « »
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}', // ░ ▒ ▓ │ ┤ ╡ ╢ ╖
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}', // ╕ ╣ ║ ╗ ╝ ╜ ╛ ┐
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}', // └ ┴ ┬ ├ ─ ┼ ╞ ╟
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}', // ╚ ╔ ╩ ╦ ╠ ═ ╬ ╧
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}', // ╨ ╤ ╥ ╙ ╘ ╒ ╓ ╫
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}', // ╪ ┘ ┌ █ ▄ ▌ ▐ ▀
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}', // α ß Γ π Σ σ µ τ
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}', // Φ Θ Ω δ ∞ φ ε ∩
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}', // ≡ ± ≥ ≤ ⌠ ⌡ ÷ ≈
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}', // ° ∙ · √ ⁿ ² ■ nbsp
+];
+
+// lookalikes for common characters that code page 437 lacks
+static SUBSTITUTES: [(char, u8); 8] = [
+    ('\u{2018}', b'\''), ('\u{2019}', b'\''), ('\u{201C}', b'"'), ('\u{201D}', b'"'),
+    ('\u{2013}', b'-'), ('\u{2014}', b'-'), ('\u{2212}', b'-'), ('\u{00D7}', b'x')
+];
+
+// ASCII maps to itself, so the control characters stay control characters
+pub fn from_char(c: char) -> Option<u8> {
+    if (c as u32) < 0x80 {
+        return Some(c as u8);
+    }
+    if c == HOUSE {
+        return Some(0x7F);
+    }
+    if let Some(i) = HIGH_GLYPHS.iter().position(|&g| g == c) {
+        return Some(0x80 + i as u8);
+    }
+    if let Some(i) = LOW_GLYPHS.iter().position(|&g| g == c) {
+        return Some(0x01 + i as u8);
+    }
+    SUBSTITUTES.iter().find(|&&(from, _)| from == c).map(|&(_, to)| to)
+}
+
+pub fn to_char(b: u8) -> char {
+    match b {
+        0x01...0x1F => LOW_GLYPHS[(b - 0x01) as usize],
+        0x7F => HOUSE,
+        0x80...0xFF => HIGH_GLYPHS[(b - 0x80) as usize],
+        _ => b as char
+    }
+}
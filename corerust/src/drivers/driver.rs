@@ -0,0 +1,247 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::memory::{Box, LinkedList};
+use ::memory::device;
+use ::memory::device::MappedDeviceRegion;
+use ::drivers::ioport;
+use ::drivers::ioport::IOPortSet;
+use ::drivers::irq;
+use ::drivers::pci;
+use ::drivers::pci::{PciDevice, Bar};
+
+// the driver model: devices are discovered (on PCI, or listed as legacy ISA devices), and each is bound to the first
+// registered driver that matches it, probes it successfully and attaches to it.
+
+pub const MAX_RESOURCES: usize = 6;
+
+// legacy devices can't be discovered, so they're described by hand
+#[derive(Debug, Copy, Clone)]
+pub struct IsaDevice {
+    pub name: &'static str,
+    pub ports: [Option<(u16, u16)>; 2],
+    pub irq: Option<u8>
+}
+
+pub const ISA_PS2_CONTROLLER: IsaDevice = IsaDevice { name: "i8042", ports: [Some((0x60, 1)), Some((0x64, 1))], irq: Some(1) };
+
+#[derive(Debug, Copy, Clone)]
+pub enum Device {
+    Pci(PciDevice),
+    Isa(IsaDevice)
+}
+
+impl Device {
+    fn is_same(&self, other: &Device) -> bool {
+        match (self, other) {
+            (&Device::Pci(ref a), &Device::Pci(ref b)) => a.address == b.address,
+            (&Device::Isa(ref a), &Device::Isa(ref b)) => a.name == b.name,
+            _ => false
+        }
+    }
+}
+
+impl core::fmt::Display for Device {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match *self {
+            Device::Pci(ref pci) => write!(f, "pci {} {:04X}:{:04X}", pci.address, pci.vendor_id, pci.device_id),
+            Device::Isa(ref isa) => write!(f, "isa {}", isa.name)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Match {
+    PciId { vendor: u16, device: u16 },
+    // prog_if of None matches any programming interface
+    PciClass { class: u8, subclass: u8, prog_if: Option<u8> },
+    Isa(&'static str)
+}
+
+impl Match {
+    pub fn matches(&self, device: &Device) -> bool {
+        match (*self, device) {
+            (Match::PciId { vendor, device: id }, &Device::Pci(ref pci)) => pci.vendor_id == vendor && pci.device_id == id,
+            (Match::PciClass { class, subclass, prog_if }, &Device::Pci(ref pci)) =>
+                pci.class == class && pci.subclass == subclass && prog_if.map_or(true, |p| pci.prog_if == p),
+            (Match::Isa(name), &Device::Isa(ref isa)) => isa.name == name,
+            _ => false
+        }
+    }
+}
+
+// what a device decodes: port ranges and physical memory ranges (indexed by BAR, on PCI) and an interrupt line
+#[derive(Debug, Copy, Clone)]
+pub struct Resources {
+    ports: [Option<(u16, u16)>; MAX_RESOURCES],
+    memory: [Option<(usize, usize)>; MAX_RESOURCES],
+    irq: Option<u8>
+}
+
+impl Resources {
+    fn for_device(device: &Device) -> Resources {
+        let mut out = Resources { ports: [None; MAX_RESOURCES], memory: [None; MAX_RESOURCES], irq: None };
+        match *device {
+            Device::Pci(ref pci) => {
+                for n in 0..pci::MAX_BARS {
+                    match pci.bar(n) {
+                        Some(Bar::Io { port, size }) => out.ports[n] = Some((port, size)),
+                        Some(Bar::Memory { addr, size, .. }) => out.memory[n] = Some((addr, size)),
+                        None => {}
+                    }
+                }
+                out.irq = pci.interrupt_line;
+            }
+            Device::Isa(ref isa) => {
+                out.ports[..isa.ports.len()].copy_from_slice(&isa.ports);
+                out.irq = isa.irq;
+            }
+        }
+        out
+    }
+
+    pub fn ports(&self, n: usize) -> Option<IOPortSet> {
+        self.ports[n].map(|(first, count)| ioport::request(first, count))
+    }
+
+    pub fn memory(&self, n: usize) -> Option<(usize, usize)> {
+        self.memory[n]
+    }
+
+    // maps part of a memory resource; whole BARs can be far larger than what the driver needs
+    pub fn map_memory(&self, n: usize, offset: usize, len: usize) -> Result<MappedDeviceRegion, KError> {
+        match self.memory[n] {
+            Some((addr, size)) if offset < size && len <= size - offset => device::get_mapped_device_region(addr + offset, len),
+            Some(_) => Err(KError::RangeError),
+            None => Err(KError::FailedLookup)
+        }
+    }
+
+    pub fn irq(&self) -> Option<u8> {
+        self.irq
+    }
+
+    pub fn request_irq(&self) -> Result<irq::IRQ<'static>, KError> {
+        match self.irq {
+            Some(line) => irq::request(line as u32),
+            None => Err(KError::FailedLookup)
+        }
+    }
+}
+
+impl core::fmt::Display for Resources {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for &(first, count) in self.ports.iter().filter_map(|p| p.as_ref()) {
+            write!(f, " ports {:#X}+{:#X}", first, count)?;
+        }
+        for &(addr, size) in self.memory.iter().filter_map(|m| m.as_ref()) {
+            write!(f, " memory {:#X}+{:#X}", addr, size)?;
+        }
+        if let Some(irq) = self.irq {
+            write!(f, " irq {}", irq)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait Driver {
+    fn name(&self) -> &'static str;
+    fn matches(&self) -> &'static [Match];
+    // checks that the device is really there and usable; nothing should be kept if this fails
+    fn probe(&mut self, device: &Device, resources: &Resources) -> bool;
+    fn attach(&mut self, device: &Device, resources: &Resources) -> Result<(), KError>;
+    // once this succeeds, the driver must no longer touch the device or its resources
+    fn detach(&mut self, device: &Device) -> Result<(), KError>;
+}
+
+struct Binding {
+    device: Device,
+    resources: Resources,
+    driver: &'static str
+}
+
+struct Registry {
+    drivers: LinkedList<Box<Driver>>,
+    isa_devices: LinkedList<IsaDevice>,
+    bindings: LinkedList<Binding>
+}
+
+static REGISTRY: SingleThreaded<RefCell<Registry>> = SingleThreaded(RefCell::new(Registry {
+    drivers: LinkedList::empty(), isa_devices: LinkedList::empty(), bindings: LinkedList::empty()
+}));
+
+impl Registry {
+    fn is_bound(&self, device: &Device) -> bool {
+        self.bindings.find(|b| b.device.is_same(device)).is_some()
+    }
+
+    fn bind(&mut self, device: &Device) {
+        if self.is_bound(device) {
+            return;
+        }
+        let resources = Resources::for_device(device);
+        for i in 0..self.drivers.len() {
+            let driver = self.drivers.get_mut(i).unwrap();
+            if !driver.matches().iter().any(|m| m.matches(device)) || !driver.probe(device, &resources) {
+                continue;
+            }
+            match driver.attach(device, &resources) {
+                Ok(()) => {
+                    debug!("bound {} to {}", driver.name(), device);
+                    if self.bindings.pushmut(Binding { device: *device, resources, driver: driver.name() }).is_err() {
+                        // it's attached either way; we just won't be able to list or detach it
+                        warn!("out of memory while recording binding of {}", device);
+                    }
+                    return;
+                }
+                Err(err) => warn!("{} failed to attach to {}: {:?}", driver.name(), device, err)
+            }
+        }
+    }
+}
+
+pub fn register(driver: Box<Driver>) {
+    if REGISTRY.get().borrow_mut().drivers.push_back(driver).is_err() {
+        panic!("out of memory while registering driver");
+    }
+}
+
+pub fn add_isa_device(device: IsaDevice) {
+    if REGISTRY.get().borrow_mut().isa_devices.push_back(device).is_err() {
+        panic!("out of memory while adding ISA device");
+    }
+}
+
+// binds drivers to any devices that don't have one yet; PCI should have been scanned first
+pub fn bind_all() {
+    let registry = &mut *REGISTRY.get().borrow_mut();
+    for i in 0..registry.isa_devices.len() {
+        let device = Device::Isa(*registry.isa_devices.get(i).unwrap());
+        registry.bind(&device);
+    }
+    pci::for_each(|pci| registry.bind(&Device::Pci(*pci)));
+}
+
+// detaches every device bound to the named driver, stopping at the first that refuses
+pub fn detach_driver(name: &str) -> Result<(), KError> {
+    let registry = &mut *REGISTRY.get().borrow_mut();
+    let driver = match registry.drivers.find_mut(|d| d.name() == name) {
+        Some(driver) => driver,
+        None => return Err(KError::FailedLookup)
+    };
+    while let Some(device) = registry.bindings.find(|b| b.driver == name).map(|b| b.device) {
+        driver.detach(&device)?;
+        registry.bindings.remove_mut(|b| b.device.is_same(&device));
+        debug!("unbound {} from {}", name, device);
+    }
+    Ok(())
+}
+
+pub fn print_bindings(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    let registry = REGISTRY.get().borrow();
+    for binding in &registry.bindings {
+        writeln!(writer, "{}: {}{}", binding.driver, binding.device, binding.resources)?;
+    }
+    Ok(())
+}
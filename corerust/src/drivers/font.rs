@@ -0,0 +1,219 @@
+use ::core;
+use ::fs::archive;
+
+// bitmap fonts for drawing text on a framebuffer. glyph rows are padded to whole bytes, leftmost pixel in the high bit.
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_HEADER_LEN: usize = 4;
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_MIN_LEN: usize = 32;
+
+// where a font can be supplied in the boot archive (bootmodules/ in the build)
+const BOOT_FONT_PATH: &'static str = "fonts/console.psf";
+
+const BUILTIN_FIRST: u8 = 0x20;
+const BUILTIN_COUNT: usize = 95;
+
+// an 8x8 font covering printable ASCII, derived from the IBM PC BIOS font
+static BUILTIN_GLYPHS: [u8; BUILTIN_COUNT * 8] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // space
+    0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00, // !
+    0x6C, 0x6C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // "
+    0x6C, 0x6C, 0xFE, 0x6C, 0xFE, 0x6C, 0x6C, 0x00, // #
+    0x30, 0x7C, 0xC0, 0x78, 0x0C, 0xF8, 0x30, 0x00, // $
+    0x00, 0xC6, 0xCC, 0x18, 0x30, 0x66, 0xC6, 0x00, // %
+    0x38, 0x6C, 0x38, 0x76, 0xDC, 0xCC, 0x76, 0x00, // &
+    0x60, 0x60, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, // '
+    0x18, 0x30, 0x60, 0x60, 0x60, 0x30, 0x18, 0x00, // (
+    0x60, 0x30, 0x18, 0x18, 0x18, 0x30, 0x60, 0x00, // )
+    0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00, // *
+    0x00, 0x30, 0x30, 0xFC, 0x30, 0x30, 0x00, 0x00, // +
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x60, // ,
+    0x00, 0x00, 0x00, 0xFC, 0x00, 0x00, 0x00, 0x00, // -
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x00, // .
+    0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0x80, 0x00, // /
+    0x7C, 0xC6, 0xCE, 0xDE, 0xF6, 0xE6, 0x7C, 0x00, // 0
+    0x30, 0x70, 0x30, 0x30, 0x30, 0x30, 0xFC, 0x00, // 1
+    0x78, 0xCC, 0x0C, 0x38, 0x60, 0xCC, 0xFC, 0x00, // 2
+    0x78, 0xCC, 0x0C, 0x38, 0x0C, 0xCC, 0x78, 0x00, // 3
+    0x1C, 0x3C, 0x6C, 0xCC, 0xFE, 0x0C, 0x1E, 0x00, // 4
+    0xFC, 0xC0, 0xF8, 0x0C, 0x0C, 0xCC, 0x78, 0x00, // 5
+    0x38, 0x60, 0xC0, 0xF8, 0xCC, 0xCC, 0x78, 0x00, // 6
+    0xFC, 0xCC, 0x0C, 0x18, 0x30, 0x30, 0x30, 0x00, // 7
+    0x78, 0xCC, 0xCC, 0x78, 0xCC, 0xCC, 0x78, 0x00, // 8
+    0x78, 0xCC, 0xCC, 0x7C, 0x0C, 0x18, 0x70, 0x00, // 9
+    0x00, 0x30, 0x30, 0x00, 0x00, 0x30, 0x30, 0x00, // :
+    0x00, 0x30, 0x30, 0x00, 0x00, 0x30, 0x30, 0x60, // ;
+    0x18, 0x30, 0x60, 0xC0, 0x60, 0x30, 0x18, 0x00, // <
+    0x00, 0x00, 0xFC, 0x00, 0x00, 0xFC, 0x00, 0x00, // =
+    0x60, 0x30, 0x18, 0x0C, 0x18, 0x30, 0x60, 0x00, // >
+    0x78, 0xCC, 0x0C, 0x18, 0x30, 0x00, 0x30, 0x00, // ?
+    0x7C, 0xC6, 0xDE, 0xDE, 0xDE, 0xC0, 0x78, 0x00, // @
+    0x30, 0x78, 0xCC, 0xCC, 0xFC, 0xCC, 0xCC, 0x00, // A
+    0xFC, 0x66, 0x66, 0x7C, 0x66, 0x66, 0xFC, 0x00, // B
+    0x3C, 0x66, 0xC0, 0xC0, 0xC0, 0x66, 0x3C, 0x00, // C
+    0xF8, 0x6C, 0x66, 0x66, 0x66, 0x6C, 0xF8, 0x00, // D
+    0xFE, 0x62, 0x68, 0x78, 0x68, 0x62, 0xFE, 0x00, // E
+    0xFE, 0x62, 0x68, 0x78, 0x68, 0x60, 0xF0, 0x00, // F
+    0x3C, 0x66, 0xC0, 0xC0, 0xCE, 0x66, 0x3E, 0x00, // G
+    0xCC, 0xCC, 0xCC, 0xFC, 0xCC, 0xCC, 0xCC, 0x00, // H
+    0x78, 0x30, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00, // I
+    0x1E, 0x0C, 0x0C, 0x0C, 0xCC, 0xCC, 0x78, 0x00, // J
+    0xE6, 0x66, 0x6C, 0x78, 0x6C, 0x66, 0xE6, 0x00, // K
+    0xF0, 0x60, 0x60, 0x60, 0x62, 0x66, 0xFE, 0x00, // L
+    0xC6, 0xEE, 0xFE, 0xFE, 0xD6, 0xC6, 0xC6, 0x00, // M
+    0xC6, 0xE6, 0xF6, 0xDE, 0xCE, 0xC6, 0xC6, 0x00, // N
+    0x38, 0x6C, 0xC6, 0xC6, 0xC6, 0x6C, 0x38, 0x00, // O
+    0xFC, 0x66, 0x66, 0x7C, 0x60, 0x60, 0xF0, 0x00, // P
+    0x78, 0xCC, 0xCC, 0xCC, 0xDC, 0x78, 0x1C, 0x00, // Q
+    0xFC, 0x66, 0x66, 0x7C, 0x6C, 0x66, 0xE6, 0x00, // R
+    0x78, 0xCC, 0xE0, 0x70, 0x1C, 0xCC, 0x78, 0x00, // S
+    0xFC, 0xB4, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00, // T
+    0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xFC, 0x00, // U
+    0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x78, 0x30, 0x00, // V
+    0xC6, 0xC6, 0xC6, 0xD6, 0xFE, 0xEE, 0xC6, 0x00, // W
+    0xC6, 0xC6, 0x6C, 0x38, 0x38, 0x6C, 0xC6, 0x00, // X
+    0xCC, 0xCC, 0xCC, 0x78, 0x30, 0x30, 0x78, 0x00, // Y
+    0xFE, 0xC6, 0x8C, 0x18, 0x32, 0x66, 0xFE, 0x00, // Z
+    0x78, 0x60, 0x60, 0x60, 0x60, 0x60, 0x78, 0x00, // [
+    0xC0, 0x60, 0x30, 0x18, 0x0C, 0x06, 0x02, 0x00, // \
+    0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0x78, 0x00, // ]
+    0x10, 0x38, 0x6C, 0xC6, 0x00, 0x00, 0x00, 0x00, // ^
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, // _
+    0x30, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, // `
+    0x00, 0x00, 0x78, 0x0C, 0x7C, 0xCC, 0x76, 0x00, // a
+    0xE0, 0x60, 0x60, 0x7C, 0x66, 0x66, 0xDC, 0x00, // b
+    0x00, 0x00, 0x78, 0xCC, 0xC0, 0xCC, 0x78, 0x00, // c
+    0x1C, 0x0C, 0x0C, 0x7C, 0xCC, 0xCC, 0x76, 0x00, // d
+    0x00, 0x00, 0x78, 0xCC, 0xFC, 0xC0, 0x78, 0x00, // e
+    0x38, 0x6C, 0x60, 0xF0, 0x60, 0x60, 0xF0, 0x00, // f
+    0x00, 0x00, 0x76, 0xCC, 0xCC, 0x7C, 0x0C, 0xF8, // g
+    0xE0, 0x60, 0x6C, 0x76, 0x66, 0x66, 0xE6, 0x00, // h
+    0x30, 0x00, 0x70, 0x30, 0x30, 0x30, 0x78, 0x00, // i
+    0x0C, 0x00, 0x0C, 0x0C, 0x0C, 0xCC, 0xCC, 0x78, // j
+    0xE0, 0x60, 0x66, 0x6C, 0x78, 0x6C, 0xE6, 0x00, // k
+    0x70, 0x30, 0x30, 0x30, 0x30, 0x30, 0x78, 0x00, // l
+    0x00, 0x00, 0xCC, 0xFE, 0xFE, 0xD6, 0xC6, 0x00, // m
+    0x00, 0x00, 0xF8, 0xCC, 0xCC, 0xCC, 0xCC, 0x00, // n
+    0x00, 0x00, 0x78, 0xCC, 0xCC, 0xCC, 0x78, 0x00, // o
+    0x00, 0x00, 0xDC, 0x66, 0x66, 0x7C, 0x60, 0xF0, // p
+    0x00, 0x00, 0x76, 0xCC, 0xCC, 0x7C, 0x0C, 0x1E, // q
+    0x00, 0x00, 0xDC, 0x76, 0x66, 0x60, 0xF0, 0x00, // r
+    0x00, 0x00, 0x7C, 0xC0, 0x78, 0x0C, 0xF8, 0x00, // s
+    0x10, 0x30, 0x7C, 0x30, 0x30, 0x34, 0x18, 0x00, // t
+    0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, // u
+    0x00, 0x00, 0xCC, 0xCC, 0xCC, 0x78, 0x30, 0x00, // v
+    0x00, 0x00, 0xC6, 0xD6, 0xFE, 0xFE, 0x6C, 0x00, // w
+    0x00, 0x00, 0xC6, 0x6C, 0x38, 0x6C, 0xC6, 0x00, // x
+    0x00, 0x00, 0xCC, 0xCC, 0xCC, 0x7C, 0x0C, 0xF8, // y
+    0x00, 0x00, 0xFC, 0x98, 0x30, 0x64, 0xFC, 0x00, // z
+    0x1C, 0x30, 0x30, 0xE0, 0x30, 0x30, 0x1C, 0x00, // {
+    0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00, // |
+    0xE0, 0x30, 0x30, 0x1C, 0x30, 0x30, 0xE0, 0x00, // }
+    0x76, 0xDC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ~
+];
+
+#[derive(Copy, Clone)]
+pub struct Font {
+    glyphs: &'static [u8],
+    first: u8, // the character of the first glyph
+    count: usize,
+    width: u8,
+    height: u8
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    (data[offset] as usize) | ((data[offset + 1] as usize) << 8)
+        | ((data[offset + 2] as usize) << 16) | ((data[offset + 3] as usize) << 24)
+}
+
+impl Font {
+    pub fn builtin() -> Font {
+        Font { glyphs: &BUILTIN_GLYPHS, first: BUILTIN_FIRST, count: BUILTIN_COUNT, width: 8, height: 8 }
+    }
+
+    // accepts version 1 and 2 PC screen fonts, such as those loaded as boot modules. unicode tables are ignored.
+    pub fn from_psf(data: &'static [u8]) -> Option<Font> {
+        let (glyphs, count, width, height) = if data.len() >= PSF1_HEADER_LEN && data[0..2] == PSF1_MAGIC {
+            let count = if data[2] & PSF1_MODE_512 != 0 { 512 } else { 256 };
+            (&data[PSF1_HEADER_LEN..], count, 8, data[3] as usize)
+        } else if data.len() >= PSF2_HEADER_MIN_LEN && data[0..4] == PSF2_MAGIC {
+            let header_len = read_u32(data, 8);
+            let (count, charsize) = (read_u32(data, 16), read_u32(data, 20));
+            let (height, width) = (read_u32(data, 24), read_u32(data, 28));
+            if header_len > data.len() || charsize != height * ((width + 7) / 8) {
+                return None;
+            }
+            (&data[header_len..], count, width, height)
+        } else {
+            return None;
+        };
+        if width == 0 || height == 0 || width > 32 || height > 64 || count == 0 {
+            return None;
+        }
+        let charsize = height * ((width + 7) / 8);
+        if glyphs.len() < count * charsize {
+            return None;
+        }
+        // only the first 256 glyphs can be addressed by a byte, anyway
+        Some(Font { glyphs, first: 0, count: core::cmp::min(count, 256), width: width as u8, height: height as u8 })
+    }
+
+    // the font from the boot archive, if there's one there that parses, and otherwise the built-in one
+    pub fn boot_font() -> Font {
+        let mut found: Option<&'static [u8]> = None;
+        if let Some(data) = archive::boot_archive() {
+            let result = archive::for_each(data, |path, _kind, contents| {
+                if path == BOOT_FONT_PATH {
+                    found = Some(contents);
+                }
+                Ok(())
+            });
+            if let Err(err) = result {
+                warn!("could not read boot archive while looking for a font: {:?}", err);
+            }
+        }
+        if let Some(data) = found {
+            if let Some(font) = Font::from_psf(data) {
+                return font;
+            }
+            warn!("{} is not a usable PC screen font; using the built-in font", BOOT_FONT_PATH);
+        }
+        Font::builtin()
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    fn bytes_per_row(&self) -> usize {
+        ((self.width as usize) + 7) / 8
+    }
+
+    // characters without a glyph are drawn as '?'
+    fn glyph_index(&self, char: u8) -> usize {
+        if char >= self.first && ((char - self.first) as usize) < self.count {
+            (char - self.first) as usize
+        } else {
+            assert!(b'?' >= self.first);
+            (b'?' - self.first) as usize
+        }
+    }
+
+    // the pixels of one row of a glyph, with the leftmost pixel in bit (width - 1)
+    pub fn row(&self, char: u8, y: u8) -> u32 {
+        assert!(y < self.height);
+        let stride = self.bytes_per_row();
+        let start = (self.glyph_index(char) * (self.height as usize) + (y as usize)) * stride;
+        let mut bits: u32 = 0;
+        for byte in &self.glyphs[start..start + stride] {
+            bits = (bits << 8) | (*byte as u32);
+        }
+        bits >> (stride * 8 - self.width as usize)
+    }
+}
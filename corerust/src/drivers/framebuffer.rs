@@ -0,0 +1,154 @@
+use ::core;
+use ::core::ptr;
+use ::mantle::KError;
+use ::memory::device;
+use ::memory::device::MappedDeviceRegion;
+
+// a linear framebuffer in device memory. colors are in the framebuffer's native format; see color().
+pub struct Framebuffer {
+    region: Option<MappedDeviceRegion>,
+    base: usize,
+    width: u16,
+    height: u16,
+    bpp: u8,
+    pitch: usize // in bytes
+}
+
+impl Framebuffer {
+    pub fn new(paddr: usize, width: u16, height: u16, bpp: u8, pitch: usize) -> Result<Framebuffer, KError> {
+        assert!(bpp == 8 || bpp == 15 || bpp == 16 || bpp == 24 || bpp == 32);
+        assert!(pitch >= (width as usize) * (((bpp as usize) + 7) / 8));
+        let region = device::get_mapped_device_region(paddr, pitch * (height as usize))?;
+        let base = region.get_addr() + (paddr - region.get_paddr());
+        Ok(Framebuffer { region: Some(region), base, width, height, bpp, pitch })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn bpp(&self) -> u8 {
+        self.bpp
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        ((self.bpp as usize) + 7) / 8
+    }
+
+    // packs an RGB color into this framebuffer's pixel format; 8-bit modes use a 3-3-2 palette layout
+    pub fn color(&self, r: u8, g: u8, b: u8) -> u32 {
+        let (r, g, b) = (r as u32, g as u32, b as u32);
+        match self.bpp {
+            32 | 24 => (r << 16) | (g << 8) | b,
+            16 => ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3),
+            15 => ((r >> 3) << 10) | ((g >> 3) << 5) | (b >> 3),
+            8 => (r & 0xE0) | ((g >> 3) & 0x1C) | (b >> 6),
+            _ => unreachable!()
+        }
+    }
+
+    fn pixel_addr(&self, x: u16, y: u16) -> usize {
+        assert!(x < self.width && y < self.height);
+        self.base + (y as usize) * self.pitch + (x as usize) * self.bytes_per_pixel()
+    }
+
+    pub fn put_pixel(&mut self, x: u16, y: u16, color: u32) {
+        let addr = self.pixel_addr(x, y);
+        unsafe {
+            match self.bytes_per_pixel() {
+                4 => ptr::write_volatile(addr as *mut u32, color),
+                3 => {
+                    ptr::write_volatile(addr as *mut u8, color as u8);
+                    ptr::write_volatile((addr + 1) as *mut u8, (color >> 8) as u8);
+                    ptr::write_volatile((addr + 2) as *mut u8, (color >> 16) as u8);
+                }
+                2 => ptr::write_volatile(addr as *mut u16, color as u16),
+                _ => ptr::write_volatile(addr as *mut u8, color as u8)
+            }
+        }
+    }
+
+    pub fn get_pixel(&self, x: u16, y: u16) -> u32 {
+        let addr = self.pixel_addr(x, y);
+        unsafe {
+            match self.bytes_per_pixel() {
+                4 => ptr::read_volatile(addr as *const u32),
+                3 => (ptr::read_volatile(addr as *const u8) as u32)
+                    | ((ptr::read_volatile((addr + 1) as *const u8) as u32) << 8)
+                    | ((ptr::read_volatile((addr + 2) as *const u8) as u32) << 16),
+                2 => ptr::read_volatile(addr as *const u16) as u32,
+                _ => ptr::read_volatile(addr as *const u8) as u32
+            }
+        }
+    }
+
+    // clips the rectangle to the screen, returning its new width and height
+    fn clip(&self, x: u16, y: u16, w: u16, h: u16) -> (u16, u16) {
+        if x >= self.width || y >= self.height {
+            (0, 0)
+        } else {
+            (core::cmp::min(w, self.width - x), core::cmp::min(h, self.height - y))
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: u16, y: u16, w: u16, h: u16, color: u32) {
+        let (w, h) = self.clip(x, y, w, h);
+        for dy in 0..h {
+            for dx in 0..w {
+                self.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        let (w, h) = (self.width, self.height);
+        self.fill_rect(0, 0, w, h, color);
+    }
+
+    // pixels holds a w by h image in row-major order
+    pub fn blit(&mut self, x: u16, y: u16, w: u16, h: u16, pixels: &[u32]) {
+        assert!(pixels.len() >= (w as usize) * (h as usize));
+        let (cw, ch) = self.clip(x, y, w, h);
+        for dy in 0..ch {
+            for dx in 0..cw {
+                self.put_pixel(x + dx, y + dy, pixels[(dy as usize) * (w as usize) + (dx as usize)]);
+            }
+        }
+    }
+
+    // moves a rectangle of pixels within the framebuffer; the source and destination may overlap
+    pub fn copy_rect(&mut self, src_x: u16, src_y: u16, dst_x: u16, dst_y: u16, w: u16, h: u16) {
+        let (w, h) = self.clip(src_x, src_y, w, h);
+        let (w, h) = self.clip(dst_x, dst_y, w, h);
+        if w == 0 || h == 0 {
+            return;
+        }
+        let row_len = (w as usize) * self.bytes_per_pixel();
+        let mut copy_row = |fb: &mut Framebuffer, dy: u16| {
+            let src = fb.pixel_addr(src_x, src_y + dy) as *const u8;
+            let dst = fb.pixel_addr(dst_x, dst_y + dy) as *mut u8;
+            unsafe { ptr::copy(src, dst, row_len) };
+        };
+        if dst_y <= src_y {
+            for dy in 0..h {
+                copy_row(self, dy);
+            }
+        } else {
+            for dy in (0..h).rev() {
+                copy_row(self, dy);
+            }
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if let Some(region) = self.region.take() {
+            device::return_mapped_device_region(region);
+        }
+    }
+}
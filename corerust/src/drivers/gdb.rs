@@ -0,0 +1,483 @@
+use ::core;
+use ::crust;
+use ::drivers::serial::HardwareSerial;
+use ::kobject::*;
+use ::mantle::KError;
+use ::mantle::kernel;
+use ::mantle::kio;
+
+// a GDB remote serial protocol stub, for debugging another thread in our vspace over a COM port.
+// test.sh puts COM2 on -serial tcp::1234,server,nowait; run the shell's `gdb`, then 'target remote :1234' from gdb.
+
+const PACKET_SIZE: usize = 512; // advertised to gdb via qSupported
+const MAX_BREAKPOINTS: usize = 16;
+const INT3: u8 = 0xCC;
+const RFLAGS_TRAP: usize = 0x100;
+const INTERRUPT: u8 = 0x03; // ctrl-c from gdb
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// the order of the 64-bit registers in an amd64 'g' packet, as indices into a UserContext
+const GDB_REGS: [usize; 17] = [
+    kernel::REG_RAX, kernel::REG_RBX, kernel::REG_RCX, kernel::REG_RDX,
+    kernel::REG_RSI, kernel::REG_RDI, kernel::REG_RBP, kernel::REG_RSP,
+    kernel::REG_R8, kernel::REG_R8 + 1, kernel::REG_R8 + 2, kernel::REG_R8 + 3,
+    kernel::REG_R8 + 4, kernel::REG_R8 + 5, kernel::REG_R8 + 6, kernel::REG_R15,
+    kernel::REG_RIP
+];
+// after those come eflags and then cs, ss, ds, es, fs, gs, all 32-bit. seL4 doesn't expose the segments.
+const GDB_SEGMENT_REGS: usize = 6;
+
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: usize,
+    saved: u8
+}
+
+struct Reply {
+    buf: [u8; PACKET_SIZE],
+    len: usize
+}
+
+impl Reply {
+    fn new() -> Reply {
+        Reply { buf: [0; PACKET_SIZE], len: 0 }
+    }
+
+    fn push(&mut self, b: u8) {
+        assert!(self.len < PACKET_SIZE);
+        self.buf[self.len] = b;
+        self.len += 1;
+    }
+
+    fn push_hex_u8(&mut self, b: u8) {
+        self.push(to_hex(b >> 4));
+        self.push(to_hex(b & 0xF));
+    }
+
+    // little-endian, as gdb expects for register contents
+    fn push_hex_le(&mut self, value: usize, bytes: usize) {
+        for i in 0..bytes {
+            self.push_hex_u8((value >> (i * 8)) as u8);
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+fn to_hex(nibble: u8) -> u8 {
+    b"0123456789abcdef"[(nibble & 0xF) as usize]
+}
+
+fn from_hex(c: u8) -> Option<u8> {
+    match c {
+        b'0'...b'9' => Some(c - b'0'),
+        b'a'...b'f' => Some(c - b'a' + 10),
+        b'A'...b'F' => Some(c - b'A' + 10),
+        _ => None
+    }
+}
+
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    let mut out: usize = 0;
+    for &c in s {
+        match from_hex(c) {
+            Some(digit) => out = (out << 4) | (digit as usize),
+            None => return None
+        }
+    }
+    Some(out)
+}
+
+fn parse_hex_le(s: &[u8]) -> Option<usize> {
+    let mut out: usize = 0;
+    for i in 0..(s.len() / 2) {
+        match parse_hex(&s[i * 2..i * 2 + 2]) {
+            Some(b) => out |= b << (i * 8),
+            None => return None
+        }
+    }
+    Some(out)
+}
+
+// splits around the first instance of sep, if any
+fn split_on(s: &[u8], sep: u8) -> (&[u8], Option<&[u8]>) {
+    match s.iter().position(|&c| c == sep) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None)
+    }
+}
+
+// parses "addr,len" as found in m, M, Z and z packets
+fn parse_addr_len(s: &[u8]) -> Option<(usize, usize)> {
+    match split_on(s, b',') {
+        (addr, Some(len)) => match (parse_hex(addr), parse_hex(len)) {
+            (Some(addr), Some(len)) => Some((addr, len)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+pub struct GdbStub<'a> {
+    port: HardwareSerial,
+    target: &'a TCB,
+    faults: Option<&'a Endpoint>, // the target's fault endpoint, if we were given one
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    stepping: bool,
+    last_signal: u8
+}
+
+impl<'a> GdbStub<'a> {
+    // suspends the target; it stays suspended until gdb asks us to continue
+    pub fn attach(port: HardwareSerial, target: &'a TCB, faults: Option<&'a Endpoint>) -> core::result::Result<GdbStub<'a>, (KError, HardwareSerial)> {
+        if let Err(err) = target.suspend() {
+            return Err((err, port));
+        }
+        Ok(GdbStub { port, target, faults, breakpoints: [None; MAX_BREAKPOINTS], stepping: false, last_signal: SIGTRAP })
+    }
+
+    pub fn detach(mut self) -> HardwareSerial {
+        self.remove_all_breakpoints();
+        self.port
+    }
+
+    fn recv_packet(&mut self, buf: &mut [u8; PACKET_SIZE]) -> usize {
+        loop {
+            while self.port.recv() != b'$' {} // anything outside a packet (acks, stray interrupts) is ignored
+            let mut len = 0;
+            let mut sum: u8 = 0;
+            let mut overflowed = false;
+            loop {
+                let c = self.port.recv();
+                if c == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(c);
+                if len < PACKET_SIZE {
+                    buf[len] = c;
+                    len += 1;
+                } else {
+                    overflowed = true;
+                }
+            }
+            let high = from_hex(self.port.recv());
+            let low = from_hex(self.port.recv());
+            if !overflowed && high.is_some() && low.is_some() && (high.unwrap() << 4 | low.unwrap()) == sum {
+                self.port.send(b'+');
+                return len;
+            }
+            debug!("gdb: rejected packet with bad checksum or length");
+            self.port.send(b'-');
+        }
+    }
+
+    fn send_packet(&mut self, data: &[u8]) {
+        loop {
+            let mut sum: u8 = 0;
+            self.port.send(b'$');
+            for &c in data {
+                sum = sum.wrapping_add(c);
+                self.port.send(c);
+            }
+            self.port.send(b'#');
+            self.port.send(to_hex(sum >> 4));
+            self.port.send(to_hex(sum & 0xF));
+            match self.port.recv() {
+                b'+' => return,
+                b'-' => continue,
+                other => {
+                    debug!("gdb: unexpected acknowledgement {}", other);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn send_str(&mut self, s: &str) {
+        self.send_packet(s.as_bytes())
+    }
+
+    fn send_error(&mut self, code: u8) {
+        let mut reply = Reply::new();
+        reply.push(b'E');
+        reply.push_hex_u8(code);
+        self.send_packet(reply.as_slice())
+    }
+
+    fn send_stop(&mut self) {
+        let mut reply = Reply::new();
+        reply.push(b'S');
+        reply.push_hex_u8(self.last_signal);
+        self.send_packet(reply.as_slice())
+    }
+
+    fn read_registers(&mut self) {
+        match self.target.read_registers(false) {
+            Ok(regs) => {
+                let mut reply = Reply::new();
+                for &i in GDB_REGS.iter() {
+                    reply.push_hex_le(regs[i], 8);
+                }
+                reply.push_hex_le(regs[kernel::REG_RFLAGS], 4);
+                for _ in 0..GDB_SEGMENT_REGS {
+                    reply.push_hex_le(0, 4);
+                }
+                self.send_packet(reply.as_slice())
+            }
+            Err(err) => {
+                debug!("gdb: could not read registers: {:?}", err);
+                self.send_error(1)
+            }
+        }
+    }
+
+    fn write_registers(&mut self, hex: &[u8]) {
+        let mut regs = match self.target.read_registers(false) {
+            Ok(regs) => regs,
+            Err(err) => {
+                debug!("gdb: could not read registers: {:?}", err);
+                return self.send_error(1);
+            }
+        };
+        if hex.len() < GDB_REGS.len() * 16 + 8 {
+            return self.send_error(22);
+        }
+        for (n, &i) in GDB_REGS.iter().enumerate() {
+            match parse_hex_le(&hex[n * 16..n * 16 + 16]) {
+                Some(value) => regs[i] = value,
+                None => return self.send_error(22)
+            }
+        }
+        let flags_at = GDB_REGS.len() * 16;
+        match parse_hex_le(&hex[flags_at..flags_at + 8]) {
+            Some(value) => regs[kernel::REG_RFLAGS] = (regs[kernel::REG_RFLAGS] & !0xFFFFFFFF) | value,
+            None => return self.send_error(22)
+        }
+        match self.target.write_registers(&regs, false) {
+            Ok(()) => self.send_str("OK"),
+            Err(err) => {
+                debug!("gdb: could not write registers: {:?}", err);
+                self.send_error(1)
+            }
+        }
+    }
+
+    fn read_memory(&mut self, args: &[u8]) {
+        let (addr, len) = match parse_addr_len(args) {
+            Some(al) => al,
+            None => return self.send_error(22)
+        };
+        let len = core::cmp::min(len, PACKET_SIZE / 2);
+        if !crust::vspace::is_mapped(addr, len) {
+            return self.send_error(14);
+        }
+        let mut reply = Reply::new();
+        for i in 0..len {
+            let b = unsafe { core::ptr::read_volatile((addr + i) as *const u8) };
+            // show the original contents in place of our own breakpoints
+            reply.push_hex_u8(self.breakpoint_at(addr + i).map_or(b, |bp| bp.saved));
+        }
+        self.send_packet(reply.as_slice())
+    }
+
+    fn write_memory(&mut self, args: &[u8]) {
+        let (header, data) = split_on(args, b':');
+        let (addr, len) = match (parse_addr_len(header), data) {
+            (Some(al), Some(_)) => al,
+            _ => return self.send_error(22)
+        };
+        let data = data.unwrap();
+        if data.len() != len * 2 {
+            return self.send_error(22);
+        }
+        if !crust::vspace::is_mapped(addr, len) {
+            return self.send_error(14);
+        }
+        for i in 0..len {
+            let b = match parse_hex(&data[i * 2..i * 2 + 2]) {
+                Some(b) => b as u8,
+                None => return self.send_error(22)
+            };
+            if let Some(slot) = self.breakpoint_index(addr + i) {
+                // keep the int3 in place, but update what it restores
+                self.breakpoints[slot].as_mut().unwrap().saved = b;
+            } else {
+                unsafe { core::ptr::write_volatile((addr + i) as *mut u8, b) };
+            }
+        }
+        self.send_str("OK")
+    }
+
+    fn breakpoint_index(&self, addr: usize) -> Option<usize> {
+        self.breakpoints.iter().position(|bp| bp.map_or(false, |bp| bp.addr == addr))
+    }
+
+    fn breakpoint_at(&self, addr: usize) -> Option<Breakpoint> {
+        self.breakpoint_index(addr).and_then(|i| self.breakpoints[i])
+    }
+
+    fn insert_breakpoint(&mut self, addr: usize) -> core::result::Result<(), u8> {
+        if self.breakpoint_index(addr).is_some() {
+            return Ok(());
+        }
+        if !crust::vspace::is_mapped(addr, 1) {
+            return Err(14);
+        }
+        let slot = self.breakpoints.iter().position(|bp| bp.is_none()).ok_or(28)?;
+        let ptr = addr as *mut u8;
+        let saved = unsafe { core::ptr::read_volatile(ptr) };
+        unsafe { core::ptr::write_volatile(ptr, INT3) };
+        self.breakpoints[slot] = Some(Breakpoint { addr, saved });
+        Ok(())
+    }
+
+    fn remove_breakpoint(&mut self, addr: usize) {
+        if let Some(slot) = self.breakpoint_index(addr) {
+            let bp = self.breakpoints[slot].take().unwrap();
+            unsafe { core::ptr::write_volatile(bp.addr as *mut u8, bp.saved) };
+        }
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        for slot in 0..MAX_BREAKPOINTS {
+            if let Some(bp) = self.breakpoints[slot].take() {
+                unsafe { core::ptr::write_volatile(bp.addr as *mut u8, bp.saved) };
+            }
+        }
+    }
+
+    fn breakpoint_packet(&mut self, args: &[u8], insert: bool) {
+        // only software breakpoints (type 0) are supported
+        let (kind, rest) = split_on(args, b',');
+        if kind != b"0" || rest.is_none() {
+            return self.send_str("");
+        }
+        let addr = match parse_addr_len(rest.unwrap()) {
+            Some((addr, _)) => addr,
+            None => return self.send_error(22)
+        };
+        if insert {
+            match self.insert_breakpoint(addr) {
+                Ok(()) => self.send_str("OK"),
+                Err(code) => self.send_error(code)
+            }
+        } else {
+            self.remove_breakpoint(addr);
+            self.send_str("OK")
+        }
+    }
+
+    // runs the target until it faults, finishes a single step, or gdb interrupts it
+    fn resume(&mut self, args: &[u8], step: bool) -> core::result::Result<(), KError> {
+        if !args.is_empty() || step {
+            let mut regs = self.target.read_registers(false)?;
+            if let Some(addr) = parse_hex(args) {
+                regs[kernel::REG_RIP] = addr;
+            }
+            if step {
+                regs[kernel::REG_RFLAGS] |= RFLAGS_TRAP;
+            }
+            self.target.write_registers(&regs, false)?;
+        }
+        self.stepping = step;
+        self.target.resume()?;
+        self.last_signal = self.wait_for_stop()?;
+        self.on_stop()
+    }
+
+    fn wait_for_stop(&mut self) -> core::result::Result<u8, KError> {
+        loop {
+            if self.port.recv_opt() == Some(INTERRUPT) {
+                self.target.suspend()?;
+                return Ok(SIGINT);
+            }
+            if let Some(faults) = self.faults {
+                if let Some(label) = faults.poll() {
+                    // the target now waits for a reply, which resuming it later will cancel
+                    return Ok(match label {
+                        kernel::FAULT_VM | kernel::FAULT_CAP => SIGSEGV,
+                        _ => SIGTRAP
+                    });
+                }
+            }
+            kio::yield_();
+        }
+    }
+
+    fn on_stop(&mut self) -> core::result::Result<(), KError> {
+        let mut regs = self.target.read_registers(false)?;
+        if self.stepping {
+            regs[kernel::REG_RFLAGS] &= !RFLAGS_TRAP;
+            self.stepping = false;
+        } else if self.last_signal == SIGTRAP && self.breakpoint_index(regs[kernel::REG_RIP].wrapping_sub(1)).is_some() {
+            // int3 leaves rip after the breakpoint; gdb expects it at the breakpoint
+            regs[kernel::REG_RIP] -= 1;
+        }
+        self.target.write_registers(&regs, false)
+    }
+
+    // serves gdb until it detaches or kills the session
+    pub fn run(&mut self) {
+        let mut buf: [u8; PACKET_SIZE] = [0; PACKET_SIZE];
+        loop {
+            let len = self.recv_packet(&mut buf);
+            if len == 0 {
+                continue;
+            }
+            let packet = &buf[..len];
+            let args = &packet[1..];
+            match packet[0] {
+                b'?' => self.send_stop(),
+                b'g' => self.read_registers(),
+                b'G' => self.write_registers(args),
+                b'm' => self.read_memory(args),
+                b'M' => self.write_memory(args),
+                b'Z' => self.breakpoint_packet(args, true),
+                b'z' => self.breakpoint_packet(args, false),
+                b'c' | b's' => {
+                    match self.resume(args, packet[0] == b's') {
+                        Ok(()) => self.send_stop(),
+                        Err(err) => {
+                            debug!("gdb: could not resume target: {:?}", err);
+                            self.send_error(1)
+                        }
+                    }
+                }
+                b'H' | b'T' => self.send_str("OK"),
+                b'q' => {
+                    if args.starts_with(b"Supported") {
+                        self.send_str("PacketSize=200")
+                    } else if args == b"Attached" {
+                        self.send_str("1")
+                    } else if args == b"C" {
+                        self.send_str("QC1")
+                    } else {
+                        self.send_str("")
+                    }
+                }
+                b'D' => {
+                    self.remove_all_breakpoints();
+                    self.send_str("OK");
+                    if let Err(err) = self.target.resume() {
+                        debug!("gdb: could not resume target on detach: {:?}", err);
+                    }
+                    return;
+                }
+                b'k' => {
+                    // no reply is expected; leave the target suspended
+                    self.remove_all_breakpoints();
+                    return;
+                }
+                _ => self.send_str("")
+            }
+        }
+    }
+}
@@ -21,7 +21,7 @@ pub fn request_one(port: u16) -> IOPort {
 
 impl IOPort {
     pub fn get(&self) -> u8 {
-        let (kerr, out) = mantle::x86_ioport_in8(mantle::kernel::CAP_INIT_IOPORT, self.port);
+        let (kerr, out) = mantle::syscalls().x86_ioport_in8(mantle::kernel::CAP_INIT_IOPORT, self.port);
         if kerr.is_error() {
             panic!("could not read from IO port: {:?}", kerr);
         }
@@ -29,7 +29,37 @@ impl IOPort {
     }
 
     pub fn set(&mut self, value: u8) {
-        let kerr = mantle::x86_ioport_out8(mantle::kernel::CAP_INIT_IOPORT, self.port, value);
+        let kerr = mantle::syscalls().x86_ioport_out8(mantle::kernel::CAP_INIT_IOPORT, self.port, value);
+        if kerr.is_error() {
+            panic!("could not write to IO port: {:?}", kerr);
+        }
+    }
+
+    pub fn get16(&self) -> u16 {
+        let (kerr, out) = mantle::syscalls().x86_ioport_in16(mantle::kernel::CAP_INIT_IOPORT, self.port);
+        if kerr.is_error() {
+            panic!("could not read from IO port: {:?}", kerr);
+        }
+        out
+    }
+
+    pub fn set16(&mut self, value: u16) {
+        let kerr = mantle::syscalls().x86_ioport_out16(mantle::kernel::CAP_INIT_IOPORT, self.port, value);
+        if kerr.is_error() {
+            panic!("could not write to IO port: {:?}", kerr);
+        }
+    }
+
+    pub fn get32(&self) -> u32 {
+        let (kerr, out) = mantle::syscalls().x86_ioport_in32(mantle::kernel::CAP_INIT_IOPORT, self.port);
+        if kerr.is_error() {
+            panic!("could not read from IO port: {:?}", kerr);
+        }
+        out
+    }
+
+    pub fn set32(&mut self, value: u32) {
+        let kerr = mantle::syscalls().x86_ioport_out32(mantle::kernel::CAP_INIT_IOPORT, self.port, value);
         if kerr.is_error() {
             panic!("could not write to IO port: {:?}", kerr);
         }
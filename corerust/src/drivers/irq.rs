@@ -5,8 +5,7 @@ use ::kobject::*;
 use ::mantle;
 use ::mantle::concurrency::SingleThreaded;
 use ::mantle::KError;
-use ::core::cell::RefCell;
-use ::core::cell::RefMut;
+use ::core::cell::{Cell, RefCell};
 use ::memory::Box;
 
 pub const IRQ_MAX: u32 = 32;
@@ -14,7 +13,9 @@ pub const IRQ_MAX: u32 = 32;
 struct IRQManager {
     irqcontrol: IRQControl,
     notification: Notification,
-    callbacks: RefCell<[Option<Box<FnMut()>>; IRQ_MAX as usize]>
+    callbacks: RefCell<[Option<Box<FnMut()>>; IRQ_MAX as usize]>,
+    // IRQs that arrived while a driver was waiting on a different one, not yet passed to their callbacks
+    deferred: Cell<u32>
 }
 
 pub struct IRQ<'a> {
@@ -29,7 +30,8 @@ impl IRQManager {
         let irqc = IRQControl::from_cap(CapSlot::from_index(mantle::kernel::CAP_INIT_IRQCONTROL).assert_populated());
         Ok(IRQManager { irqcontrol: irqc, notification: notify, callbacks: RefCell::new(
             [None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None]) })
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None, None, None]),
+            deferred: Cell::new(0) })
     }
 
     fn request<'a>(&'a self, irq: u32) -> core::result::Result<IRQ<'a>, KError> {
@@ -47,7 +49,7 @@ impl IRQManager {
         }
     }
 
-    fn on_bit(&mut self, bit: u32) {
+    fn on_bit(&self, bit: u32) {
         if let &Some(ref cb) = &self.callbacks.borrow()[bit as usize] {
             debug!("invoking IRQ callback for {}", bit);
             cb();
@@ -56,9 +58,13 @@ impl IRQManager {
         }
     }
 
-    fn mainloop(&mut self) {
+    fn mainloop(&self) {
         loop {
-            let mut sender = self.notification.wait();
+            let mut sender = self.deferred.get() as usize;
+            self.deferred.set(0);
+            if sender == 0 {
+                sender = self.notification.wait();
+            }
             debug!("got IRQ notification: {:#b}", sender);
             while sender != 0 {
                 let active_bit = sender.trailing_zeros();
@@ -69,6 +75,20 @@ impl IRQManager {
         }
     }
 
+    // blocks until the IRQ fires. anything else that arrives meanwhile is left for the mainloop, because this is
+    // usually called from within some other callback.
+    fn wait_for(&self, irq: u32) {
+        let bit = 1 << irq;
+        loop {
+            let pending = self.deferred.get();
+            if pending & bit != 0 {
+                self.deferred.set(pending & !bit);
+                return;
+            }
+            self.deferred.set(pending | self.notification.wait() as u32);
+        }
+    }
+
     fn set_callback<F: Fn() + 'static>(&self, irq: u32, cb: F) {
         assert!(self.callbacks.borrow()[irq as usize].is_none());
         self.callbacks.borrow_mut()[irq as usize] = Some(Box::new(cb));
@@ -91,6 +111,11 @@ impl<'a> IRQ<'a> {
         self.irqhandler.ack()
     }
 
+    // for drivers that wait on their device synchronously instead of setting a callback
+    pub fn wait(&self) {
+        self.manager.wait_for(self.irq)
+    }
+
     pub fn set_cb<F: Fn() + 'static>(&self, cb: F) {
         self.manager.set_callback(self.irq, cb)
     }
@@ -102,20 +127,21 @@ impl<'a> IRQ<'a> {
 
 static MANAGER: SingleThreaded<RefCell<Option<IRQManager>>> = SingleThreaded(RefCell::new(None));
 
-fn get_manager() -> RefMut<'static, IRQManager> {
-    let m = MANAGER.get().borrow_mut();
+// not a RefMut: callbacks run inside the mainloop, and need to be able to request and wait for IRQs of their own
+fn get_manager() -> &'static IRQManager {
+    let mut m = MANAGER.get().borrow_mut();
     if (*m).is_none() {
         *m = Some(IRQManager::new().unwrap());
     }
-    RefMut::map(m, |b| &mut b.unwrap())
+    let manager: *const IRQManager = m.as_ref().unwrap();
+    // the manager is never replaced or dropped once created
+    unsafe { &*manager }
 }
 
 pub fn mainloop() {
-    let m = &mut *get_manager();
-    m.mainloop();
+    get_manager().mainloop();
 }
 
 pub fn request(irq: u32) -> core::result::Result<IRQ<'static>, KError> {
-    let m = &mut *get_manager();
-    m.request(irq)
+    get_manager().request(irq)
 }
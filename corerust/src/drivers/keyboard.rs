@@ -2,8 +2,11 @@ use ::mantle::concurrency::SingleThreaded;
 use ::core::cell::RefCell;
 use ::core::cell::RefMut;
 use ::memory::Box;
+use ::mantle::KError;
+use ::drivers::driver::{Driver, Device, Match, Resources};
 
 mod ps2 {
+    use ::drivers::acpi;
     use ::drivers::irq;
     use ::drivers::ioport;
     use ::mantle::concurrency::SingleThreaded;
@@ -32,8 +35,8 @@ mod ps2 {
     const CONF_PORT1_TRANSLATE: u8 = 0x40;
 
     impl PS2Controller {
-        fn new() -> PS2Controller {
-            let mut ctrl = PS2Controller { port_data: ioport::request_one(0x60), port_command: ioport::request_one(0x64), works: (false, false), port_1_irq: None, port_2_irq: None };
+        fn new(port_data: ioport::IOPort, port_command: ioport::IOPort) -> PS2Controller {
+            let mut ctrl = PS2Controller { port_data, port_command, works: (false, false), port_1_irq: None, port_2_irq: None };
             ctrl.initialize();
             ctrl
         }
@@ -93,7 +96,10 @@ mod ps2 {
 
         fn initialize(&mut self) -> (bool, bool) {
             // TODO: turn off USB legacy support? (requires USB driver)
-            // TODO: confirm via BIOS that ps/2 controller exists (requires ACPI driver)
+            if acpi::has_8042() == Some(false) {
+                warn!("ACPI says there is no PS/2 controller");
+                return (false, false);
+            }
             self.command(0xAD); // disable first PS/2 port
             self.command(0xA7); // disable second PS/2 port
             while self.read_opt().is_some() {} // flush buffer
@@ -107,7 +113,7 @@ mod ps2 {
             let selftest = self.read();
             if selftest != 0x55 {
                 // not working!
-                debug!("ps/2 controller self-test failed! (expected 0x55, got {})", selftest);
+                warn!("ps/2 controller self-test failed! (expected 0x55, got {})", selftest);
                 return (false, false);
             }
 
@@ -136,7 +142,7 @@ mod ps2 {
                 }
             }
             if !works.0 && !works.1 {
-                debug!("no working PS/2 ports found!");
+                warn!("no working PS/2 ports found!");
                 return (false, false);
             }
 
@@ -206,13 +212,29 @@ mod ps2 {
 
     static CONTROLLER: SingleThreaded<RefCell<Option<PS2Controller>>> = SingleThreaded(RefCell::new(None));
 
+    // sets up the controller on the ports given by the driver model, and reports which ports work
+    pub fn probe_controller(port_data: ioport::IOPort, port_command: ioport::IOPort) -> (bool, bool) {
+        let mut m: RefMut<Option<PS2Controller>> = CONTROLLER.get().borrow_mut();
+        if (*m).is_none() {
+            *(&mut *m) = Some(PS2Controller::new(port_data, port_command));
+        }
+        m.as_ref().unwrap().get_works()
+    }
+
     pub fn get_and_init_controller() -> RefMut<'static, PS2Controller> {
         let mut m: RefMut<Option<PS2Controller>> = CONTROLLER.get().borrow_mut();
         if (*m).is_none() {
-            *(&mut *m) = Some(PS2Controller::new());
+            *(&mut *m) = Some(PS2Controller::new(ioport::request_one(0x60), ioport::request_one(0x64)));
         }
         RefMut::map(m, |b: &mut Option<PS2Controller>| b.as_mut().unwrap())
     }
+
+    // uses the standard ports directly, rather than CONTROLLER, which may well be borrowed by whatever wants to reset
+    pub fn cpu_reset() {
+        let mut ctrl = PS2Controller { port_data: ioport::request_one(0x60), port_command: ioport::request_one(0x64),
+            works: (false, false), port_1_irq: None, port_2_irq: None };
+        ctrl.cpu_reset();
+    }
 }
 
 struct PS2Handler {
@@ -231,7 +253,183 @@ enum PS2HandlerState {
     IgnoredDevice,
     FailedInit,
     FoundKeyboard,
-    GotEcho
+    GotEcho,
+    Scanning
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(u8), // already adjusted for shift and caps lock
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Insert,
+    Delete,
+    F(u8),
+    Shift,
+    Control,
+    Alt,
+    CapsLock,
+    Unknown(u8)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool
+}
+
+// (unshifted, shifted) for the printable keys of scan code set 2
+fn set2_printable(code: u8) -> Option<(u8, u8)> {
+    let pair = match code {
+        0x1C => (b'a', b'A'), 0x32 => (b'b', b'B'), 0x21 => (b'c', b'C'), 0x23 => (b'd', b'D'),
+        0x24 => (b'e', b'E'), 0x2B => (b'f', b'F'), 0x34 => (b'g', b'G'), 0x33 => (b'h', b'H'),
+        0x43 => (b'i', b'I'), 0x3B => (b'j', b'J'), 0x42 => (b'k', b'K'), 0x4B => (b'l', b'L'),
+        0x3A => (b'm', b'M'), 0x31 => (b'n', b'N'), 0x44 => (b'o', b'O'), 0x4D => (b'p', b'P'),
+        0x15 => (b'q', b'Q'), 0x2D => (b'r', b'R'), 0x1B => (b's', b'S'), 0x2C => (b't', b'T'),
+        0x3C => (b'u', b'U'), 0x2A => (b'v', b'V'), 0x1D => (b'w', b'W'), 0x22 => (b'x', b'X'),
+        0x35 => (b'y', b'Y'), 0x1A => (b'z', b'Z'),
+        0x45 => (b'0', b')'), 0x16 => (b'1', b'!'), 0x1E => (b'2', b'@'), 0x26 => (b'3', b'#'),
+        0x25 => (b'4', b'$'), 0x2E => (b'5', b'%'), 0x36 => (b'6', b'^'), 0x3D => (b'7', b'&'),
+        0x3E => (b'8', b'*'), 0x46 => (b'9', b'('),
+        0x0E => (b'`', b'~'), 0x4E => (b'-', b'_'), 0x55 => (b'=', b'+'), 0x5D => (b'\\', b'|'),
+        0x54 => (b'[', b'{'), 0x5B => (b']', b'}'), 0x4C => (b';', b':'), 0x52 => (b'\'', b'"'),
+        0x41 => (b',', b'<'), 0x49 => (b'.', b'>'), 0x4A => (b'/', b'?'), 0x29 => (b' ', b' '),
+        _ => return None
+    };
+    Some(pair)
+}
+
+fn set2_function_key(code: u8) -> Option<u8> {
+    match code {
+        0x05 => Some(1), 0x06 => Some(2), 0x04 => Some(3), 0x0C => Some(4),
+        0x03 => Some(5), 0x0B => Some(6), 0x83 => Some(7), 0x0A => Some(8),
+        0x01 => Some(9), 0x09 => Some(10), 0x78 => Some(11), 0x07 => Some(12),
+        _ => None
+    }
+}
+
+// turns scan code set 2 bytes into key events
+struct ScanDecoder {
+    extended: bool,
+    releasing: bool,
+    skip: u8, // for the pause key, which we ignore
+    shift: (bool, bool),
+    control: bool,
+    alt: bool,
+    caps_lock: bool
+}
+
+impl ScanDecoder {
+    const fn new() -> ScanDecoder {
+        ScanDecoder { extended: false, releasing: false, skip: 0, shift: (false, false), control: false, alt: false, caps_lock: false }
+    }
+
+    fn decode(&self, code: u8, extended: bool) -> Key {
+        if extended {
+            match code {
+                0x75 => Key::Up,
+                0x72 => Key::Down,
+                0x6B => Key::Left,
+                0x74 => Key::Right,
+                0x7D => Key::PageUp,
+                0x7A => Key::PageDown,
+                0x6C => Key::Home,
+                0x69 => Key::End,
+                0x70 => Key::Insert,
+                0x71 => Key::Delete,
+                0x5A => Key::Enter,
+                0x4A => Key::Char(b'/'),
+                0x14 => Key::Control,
+                0x11 => Key::Alt,
+                _ => Key::Unknown(code)
+            }
+        } else if let Some((lower, upper)) = set2_printable(code) {
+            let shifted = self.shift.0 || self.shift.1;
+            let is_letter = lower >= b'a' && lower <= b'z';
+            Key::Char(if shifted != (is_letter && self.caps_lock) { upper } else { lower })
+        } else if let Some(n) = set2_function_key(code) {
+            Key::F(n)
+        } else {
+            match code {
+                0x5A => Key::Enter,
+                0x66 => Key::Backspace,
+                0x0D => Key::Tab,
+                0x76 => Key::Escape,
+                0x12 | 0x59 => Key::Shift,
+                0x14 => Key::Control,
+                0x11 => Key::Alt,
+                0x58 => Key::CapsLock,
+                _ => Key::Unknown(code)
+            }
+        }
+    }
+
+    fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+        match byte {
+            0xE0 => {
+                self.extended = true;
+                return None;
+            }
+            0xE1 => {
+                self.skip = 7; // the pause key sends E1 14 77 E1 F0 14 F0 77, with no release
+                return None;
+            }
+            0xF0 => {
+                self.releasing = true;
+                return None;
+            }
+            _ => {}
+        }
+        let (extended, pressed) = (self.extended, !self.releasing);
+        self.extended = false;
+        self.releasing = false;
+        if extended && (byte == 0x12 || byte == 0x59) {
+            return None; // fake shifts around print screen and friends
+        }
+        let key = self.decode(byte, extended);
+        match key {
+            Key::Shift => if byte == 0x12 { self.shift.0 = pressed } else { self.shift.1 = pressed },
+            Key::Control => self.control = pressed,
+            Key::Alt => self.alt = pressed,
+            Key::CapsLock => if pressed { self.caps_lock = !self.caps_lock },
+            _ => {}
+        }
+        Some(KeyEvent { key, pressed, shift: self.shift.0 || self.shift.1, control: self.control, alt: self.alt })
+    }
+}
+
+static DECODER: SingleThreaded<RefCell<ScanDecoder>> = SingleThreaded(RefCell::new(ScanDecoder::new()));
+static HANDLER: SingleThreaded<RefCell<Option<Box<FnMut(KeyEvent)>>>> = SingleThreaded(RefCell::new(None));
+
+// the handler receives every key press and release from any attached keyboard
+pub fn set_handler<F: FnMut(KeyEvent) + 'static>(handler: F) {
+    *HANDLER.get().borrow_mut() = Some(Box::new(handler));
+}
+
+fn dispatch_scan_code(byte: u8) {
+    let event = DECODER.get().borrow_mut().feed(byte);
+    if let Some(event) = event {
+        if let Some(ref mut handler) = *HANDLER.get().borrow_mut() {
+            handler(event);
+        }
+    }
 }
 
 impl PS2Handler {
@@ -280,13 +478,20 @@ impl PS2Handler {
             }, PS2HandlerState::FoundKeyboard => {
                 // we sent ECHO earlier
                 if byte == 0xEE {
-                    self.change_state(PS2HandlerState::GotEcho)
+                    self.write_and_state(ctrl, 0xF4, PS2HandlerState::GotEcho) // enable scanning
+                } else {
+                    self.change_state(PS2HandlerState::FailedInit)
+                }
+            }, PS2HandlerState::GotEcho => {
+                if byte == 0xFA {
+                    self.change_state(PS2HandlerState::Scanning)
                 } else {
                     self.change_state(PS2HandlerState::FailedInit)
                 }
+            }, PS2HandlerState::Scanning => {
+                dispatch_scan_code(byte)
             }, PS2HandlerState::FailedInit => {
             }, PS2HandlerState::IgnoredDevice => {
-            }, PS2HandlerState::GotEcho => {
             }
         }
     }
@@ -323,6 +528,11 @@ struct GlobalPS2 {
 
 static STATE: SingleThreaded<RefCell<GlobalPS2>> = SingleThreaded(RefCell::new(GlobalPS2 { inited: false, first: None, second: None }));
 
+// pulses the CPU reset line through the 8042
+pub fn cpu_reset() {
+    ps2::cpu_reset()
+}
+
 // NOTE: requires irq mainloop to be used
 pub fn init() {
     let stateref = &mut *STATE.get().borrow_mut();
@@ -338,3 +548,37 @@ pub fn init() {
     }
     // TODO: use mainloop of some sort?
 }
+
+const PS2_MATCHES: &'static [Match] = &[Match::Isa("i8042")];
+
+pub struct PS2Driver;
+
+impl Driver for PS2Driver {
+    fn name(&self) -> &'static str {
+        "ps2"
+    }
+
+    fn matches(&self) -> &'static [Match] {
+        PS2_MATCHES
+    }
+
+    fn probe(&mut self, _device: &Device, resources: &Resources) -> bool {
+        match (resources.ports(0), resources.ports(1)) {
+            (Some(data), Some(command)) => {
+                let works = ps2::probe_controller(data.get(0), command.get(0));
+                works.0 || works.1
+            }
+            _ => false
+        }
+    }
+
+    fn attach(&mut self, _device: &Device, _resources: &Resources) -> Result<(), KError> {
+        init();
+        Ok(())
+    }
+
+    fn detach(&mut self, _device: &Device) -> Result<(), KError> {
+        // the port handlers hold on to their IRQs for good
+        Err(KError::IllegalOperation)
+    }
+}
@@ -1,6 +1,25 @@
 pub mod vga;
+pub mod console;
+pub mod ansi;
+pub mod scrollback;
 pub mod serial;
 pub mod ioport;
+pub mod debugexit;
+pub mod acpi;
+pub mod power;
+pub mod pci;
+pub mod driver;
+pub mod block;
+pub mod ata;
+pub mod virtio;
+pub mod virtioblk;
+pub mod framebuffer;
+pub mod bga;
+pub mod font;
+pub mod textconsole;
+pub mod vgamode;
+pub mod cp437;
 pub mod bits;
 pub mod keyboard;
 pub mod irq;
+pub mod gdb;
\ No newline at end of file
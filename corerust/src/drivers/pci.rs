@@ -0,0 +1,406 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::concurrency::SingleThreaded;
+use ::memory::LinkedList;
+use ::drivers::ioport;
+
+// PCI configuration space, accessed through configuration mechanism #1, and a registry of the devices found on it
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const VENDOR_NONE: u16 = 0xFFFF;
+
+pub const CONFIG_VENDOR_ID: u8 = 0x00;
+pub const CONFIG_COMMAND: u8 = 0x04;
+pub const CONFIG_CLASS: u8 = 0x08;
+pub const CONFIG_HEADER_TYPE: u8 = 0x0E;
+pub const CONFIG_BAR0: u8 = 0x10;
+pub const CONFIG_BUS_NUMBERS: u8 = 0x18; // bridges only
+pub const CONFIG_CAPABILITIES: u8 = 0x34;
+pub const CONFIG_INTERRUPT: u8 = 0x3C;
+
+pub const COMMAND_IO: u16 = 0x1;
+pub const COMMAND_MEMORY: u16 = 0x2;
+pub const COMMAND_BUS_MASTER: u16 = 0x4;
+pub const COMMAND_INTERRUPT_DISABLE: u16 = 0x400;
+const STATUS_CAPABILITIES: u16 = 0x10;
+
+const HEADER_TYPE_MASK: u8 = 0x7F;
+const HEADER_MULTIFUNCTION: u8 = 0x80;
+pub const HEADER_GENERAL: u8 = 0x00;
+pub const HEADER_PCI_BRIDGE: u8 = 0x01;
+
+pub const CLASS_MASS_STORAGE: u8 = 0x01;
+pub const CLASS_DISPLAY: u8 = 0x03;
+pub const CLASS_BRIDGE: u8 = 0x06;
+pub const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_VENDOR: u8 = 0x09;
+pub const CAPABILITY_PCI_EXPRESS: u8 = 0x10;
+pub const CAPABILITY_MSI_X: u8 = 0x11;
+
+const BAR_IO: u32 = 0x1;
+const BAR_TYPE_MASK: u32 = 0x6;
+const BAR_TYPE_64: u32 = 0x4;
+const BAR_PREFETCHABLE: u32 = 0x8;
+
+pub const MAX_BARS: usize = 6;
+const BRIDGE_BARS: usize = 2;
+pub const MAX_CAPABILITIES: usize = 16;
+const MAX_BUSES: usize = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8
+}
+
+impl PciAddress {
+    pub fn new(bus: u8, device: u8, function: u8) -> PciAddress {
+        assert!(device < 32 && function < 8);
+        PciAddress { bus, device, function }
+    }
+
+    fn config_address(&self, offset: u8) -> u32 {
+        assert!(offset & 0x3 == 0);
+        0x8000_0000 | ((self.bus as u32) << 16) | ((self.device as u32) << 11)
+            | ((self.function as u32) << 8) | (offset as u32)
+    }
+
+    pub fn read32(&self, offset: u8) -> u32 {
+        ioport::request_one(CONFIG_ADDRESS).set32(self.config_address(offset));
+        ioport::request_one(CONFIG_DATA).get32()
+    }
+
+    pub fn write32(&self, offset: u8, value: u32) {
+        ioport::request_one(CONFIG_ADDRESS).set32(self.config_address(offset));
+        ioport::request_one(CONFIG_DATA).set32(value);
+    }
+
+    // narrower accesses are done through the containing dword
+    pub fn read16(&self, offset: u8) -> u16 {
+        assert!(offset & 0x1 == 0);
+        (self.read32(offset & !0x3) >> ((offset & 0x2) * 8)) as u16
+    }
+
+    pub fn read8(&self, offset: u8) -> u8 {
+        (self.read32(offset & !0x3) >> ((offset & 0x3) * 8)) as u8
+    }
+
+    pub fn write16(&self, offset: u8, value: u16) {
+        assert!(offset & 0x1 == 0);
+        let shift = (offset & 0x2) * 8;
+        let old = self.read32(offset & !0x3);
+        self.write32(offset & !0x3, (old & !(0xFFFF << shift)) | ((value as u32) << shift));
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.read16(CONFIG_VENDOR_ID)
+    }
+
+    pub fn header_type(&self) -> u8 {
+        self.read8(CONFIG_HEADER_TYPE)
+    }
+
+    pub fn command(&self) -> u16 {
+        self.read16(CONFIG_COMMAND)
+    }
+
+    pub fn status(&self) -> u16 {
+        self.read16(CONFIG_COMMAND + 2)
+    }
+
+    // the status half is write-one-to-clear, so it's written as zeros rather than read back
+    pub fn set_command(&self, command: u16) {
+        self.write32(CONFIG_COMMAND, command as u32);
+    }
+
+    // writes all ones to a BAR to find which address bits it decodes, and puts it back
+    fn probe_bar(&self, offset: u8) -> u32 {
+        let original = self.read32(offset);
+        self.write32(offset, 0xFFFF_FFFF);
+        let mask = self.read32(offset);
+        self.write32(offset, original);
+        mask
+    }
+}
+
+impl core::fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:02X}:{:02X}.{}", self.bus, self.device, self.function)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bar {
+    Io { port: u16, size: u16 },
+    Memory { addr: usize, size: usize, prefetchable: bool, is_64: bool }
+}
+
+impl core::fmt::Display for Bar {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match *self {
+            Bar::Io { port, size } => write!(f, "io {:#X}+{:#X}", port, size),
+            Bar::Memory { addr, size, prefetchable, is_64 } =>
+                write!(f, "mem{} {:#X}+{:#X}{}", if is_64 { "64" } else { "32" }, addr, size,
+                       if prefetchable { " prefetchable" } else { "" })
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capability {
+    pub id: u8,
+    pub offset: u8
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    bars: [Option<Bar>; MAX_BARS],
+    capabilities: [Option<Capability>; MAX_CAPABILITIES],
+    // the legacy PIC line the firmware routed the interrupt pin to, if any
+    pub interrupt_line: Option<u8>,
+    pub interrupt_pin: u8 // 0 for none, then 1 through 4 for INTA# through INTD#
+}
+
+fn decode_bars(addr: PciAddress, count: usize) -> [Option<Bar>; MAX_BARS] {
+    let mut bars = [None; MAX_BARS];
+    // decoding is turned off while sizing, so that the all-ones address doesn't claim anything
+    let command = addr.command();
+    addr.set_command(command & !(COMMAND_IO | COMMAND_MEMORY));
+    let mut i = 0;
+    while i < count {
+        let offset = CONFIG_BAR0 + (i as u8) * 4;
+        let low = addr.read32(offset);
+        if low & BAR_IO != 0 {
+            if low & !0x3 != 0 {
+                let size = (!(addr.probe_bar(offset) & 0xFFFC) as u16).wrapping_add(1);
+                bars[i] = Some(Bar::Io { port: (low & 0xFFFC) as u16, size });
+            }
+            i += 1;
+        } else {
+            let is_64 = low & BAR_TYPE_MASK == BAR_TYPE_64 && i + 1 < count;
+            let mut base = (low & !0xF) as usize;
+            let mut mask = (addr.probe_bar(offset) & !0xF) as usize;
+            if is_64 {
+                base |= (addr.read32(offset + 4) as usize) << 32;
+                mask |= (addr.probe_bar(offset + 4) as usize) << 32;
+            } else {
+                mask |= !0xFFFF_FFFFusize;
+            }
+            if mask != !0xFusize && base != 0 {
+                bars[i] = Some(Bar::Memory { addr: base, size: (!mask).wrapping_add(1), prefetchable: low & BAR_PREFETCHABLE != 0, is_64 });
+            }
+            i += if is_64 { 2 } else { 1 };
+        }
+    }
+    addr.set_command(command);
+    bars
+}
+
+fn decode_capabilities(addr: PciAddress) -> [Option<Capability>; MAX_CAPABILITIES] {
+    let mut capabilities = [None; MAX_CAPABILITIES];
+    if addr.status() & STATUS_CAPABILITIES == 0 {
+        return capabilities;
+    }
+    let mut offset = addr.read8(CONFIG_CAPABILITIES) & !0x3;
+    // the count bounds the walk, in case the list is malformed and loops
+    for slot in capabilities.iter_mut() {
+        if offset == 0 {
+            break;
+        }
+        *slot = Some(Capability { id: addr.read8(offset), offset });
+        offset = addr.read8(offset + 1) & !0x3;
+    }
+    capabilities
+}
+
+pub struct Capabilities<'a> {
+    iter: core::slice::Iter<'a, Option<Capability>>
+}
+
+impl<'a> Iterator for Capabilities<'a> {
+    type Item = Capability;
+
+    // the list is packed at the start of the array
+    fn next(&mut self) -> Option<Capability> {
+        match self.iter.next() {
+            Some(&Some(capability)) => Some(capability),
+            _ => None
+        }
+    }
+}
+
+impl PciDevice {
+    fn decode(address: PciAddress) -> PciDevice {
+        let ids = address.read32(CONFIG_VENDOR_ID);
+        let class = address.read32(CONFIG_CLASS);
+        let header_type = address.header_type() & HEADER_TYPE_MASK;
+        let bar_count = match header_type {
+            HEADER_GENERAL => MAX_BARS,
+            HEADER_PCI_BRIDGE => BRIDGE_BARS,
+            _ => 0
+        };
+        let interrupt = address.read16(CONFIG_INTERRUPT);
+        let (line, pin) = (interrupt as u8, (interrupt >> 8) as u8);
+        PciDevice {
+            address, vendor_id: ids as u16, device_id: (ids >> 16) as u16,
+            class: (class >> 24) as u8, subclass: (class >> 16) as u8, prog_if: (class >> 8) as u8, revision: class as u8,
+            header_type, bars: decode_bars(address, bar_count), capabilities: decode_capabilities(address),
+            interrupt_line: if pin == 0 || line == 0xFF { None } else { Some(line) }, interrupt_pin: pin
+        }
+    }
+
+    pub fn bar(&self, n: usize) -> Option<Bar> {
+        self.bars[n]
+    }
+
+    // the physical address of a memory BAR, or None if it's an IO BAR or unassigned
+    pub fn memory_bar(&self, n: usize) -> Option<usize> {
+        match self.bars[n] {
+            Some(Bar::Memory { addr, .. }) => Some(addr),
+            _ => None
+        }
+    }
+
+    pub fn io_bar(&self, n: usize) -> Option<(u16, u16)> {
+        match self.bars[n] {
+            Some(Bar::Io { port, size }) => Some((port, size)),
+            _ => None
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities { iter: self.capabilities.iter() }
+    }
+
+    pub fn find_capability(&self, id: u8) -> Option<Capability> {
+        self.capabilities().find(|c| c.id == id)
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.header_type == HEADER_PCI_BRIDGE && self.class == CLASS_BRIDGE && self.subclass == SUBCLASS_PCI_BRIDGE
+    }
+
+    fn secondary_bus(&self) -> u8 {
+        self.address.read8(CONFIG_BUS_NUMBERS + 1)
+    }
+
+    pub fn enable(&self, flags: u16) {
+        let command = self.address.command();
+        self.address.set_command(command | flags);
+    }
+}
+
+impl core::fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} {:04X}:{:04X} class {:02X}.{:02X}.{:02X} rev {}", self.address, self.vendor_id, self.device_id,
+               self.class, self.subclass, self.prog_if, self.revision)?;
+        if let Some(line) = self.interrupt_line {
+            write!(f, " irq {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+static DEVICES: SingleThreaded<RefCell<LinkedList<PciDevice>>> = SingleThreaded(RefCell::new(LinkedList::empty()));
+
+struct Scanner {
+    found: LinkedList<PciDevice>,
+    scanned: [bool; MAX_BUSES]
+}
+
+impl Scanner {
+    fn scan_function(&mut self, address: PciAddress) {
+        let device = PciDevice::decode(address);
+        if device.is_bridge() {
+            let secondary = device.secondary_bus();
+            self.scan_bus(secondary);
+        }
+        if self.found.pushmut(device).is_err() {
+            warn!("out of memory while recording PCI device {}", address);
+        }
+    }
+
+    fn scan_bus(&mut self, bus: u8) {
+        if self.scanned[bus as usize] {
+            return;
+        }
+        self.scanned[bus as usize] = true;
+        for dev in 0..32 {
+            let first = PciAddress::new(bus, dev, 0);
+            if first.vendor_id() == VENDOR_NONE {
+                continue;
+            }
+            let functions = if first.header_type() & HEADER_MULTIFUNCTION != 0 { 8 } else { 1 };
+            for function in 0..functions {
+                let address = PciAddress::new(bus, dev, function);
+                if address.vendor_id() != VENDOR_NONE {
+                    self.scan_function(address);
+                }
+            }
+        }
+    }
+}
+
+// walks the hierarchy from bus 0 through every bridge. a multifunction host bridge means one root bus per function.
+pub fn scan() {
+    let mut scanner = Scanner { found: LinkedList::empty(), scanned: [false; MAX_BUSES] };
+    let host = PciAddress::new(0, 0, 0);
+    if host.header_type() & HEADER_MULTIFUNCTION == 0 {
+        scanner.scan_bus(0);
+    } else {
+        for function in 0..8 {
+            if PciAddress::new(0, 0, function).vendor_id() != VENDOR_NONE {
+                scanner.scan_bus(function);
+            }
+        }
+    }
+    debug!("found {} PCI devices", scanner.found.len());
+    *DEVICES.get().borrow_mut() = scanner.found;
+}
+
+pub fn find<P: Fn(&PciDevice) -> bool>(predicate: P) -> Option<PciDevice> {
+    DEVICES.get().borrow().find(predicate).map(|d| *d)
+}
+
+pub fn find_device(vendor: u16, device: u16) -> Option<PciDevice> {
+    find(|d| d.vendor_id == vendor && d.device_id == device)
+}
+
+// prog_if of None matches any programming interface
+pub fn find_class(class: u8, subclass: u8, prog_if: Option<u8>) -> Option<PciDevice> {
+    find(|d| d.class == class && d.subclass == subclass && prog_if.map_or(true, |p| d.prog_if == p))
+}
+
+pub fn for_each<F: FnMut(&PciDevice)>(mut f: F) {
+    for device in &*DEVICES.get().borrow() {
+        f(device);
+    }
+}
+
+pub fn print_devices(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    for device in &*DEVICES.get().borrow() {
+        writeln!(writer, "{}", device)?;
+        for n in 0..MAX_BARS {
+            if let Some(bar) = device.bar(n) {
+                writeln!(writer, "    bar {}: {}", n, bar)?;
+            }
+        }
+        for capability in device.capabilities() {
+            writeln!(writer, "    capability {:#04X} at {:#04X}", capability.id, capability.offset)?;
+        }
+    }
+    Ok(())
+}
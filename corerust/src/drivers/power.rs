@@ -0,0 +1,181 @@
+use ::core;
+use ::mantle::KError;
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::device;
+use ::drivers::acpi;
+use ::drivers::acpi::{Fadt, GenericAddress};
+use ::drivers::block;
+use ::drivers::ioport;
+use ::drivers::keyboard;
+use ::drivers::pci::PciAddress;
+use ::fs::vfs;
+
+// turning the machine off, through ACPI, and rebooting it, through ACPI or else the 8042
+
+const PM1_SCI_EN: u16 = 0x1;
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+const PM1_SLP_TYP_MASK: u16 = 0x7 << PM1_SLP_TYP_SHIFT;
+const PM1_SLP_EN: u16 = 1 << 13;
+
+const AML_ZERO: u8 = 0x00;
+const AML_ONE: u8 = 0x01;
+const AML_NAME: u8 = 0x08;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_WORD_PREFIX: u8 = 0x0B;
+const AML_DWORD_PREFIX: u8 = 0x0C;
+const AML_PACKAGE: u8 = 0x12;
+const AML_ROOT_CHAR: u8 = b'\\';
+
+const ACPI_ENABLE_TRIES: usize = 1000;
+const SETTLE_READS: usize = 100000;
+
+// gives a write to a power register a moment to take effect, by way of port reads that go nowhere
+fn settle() {
+    let port = ioport::request_one(0x80);
+    for _ in 0..SETTLE_READS {
+        port.get();
+    }
+}
+
+fn aml_integer(b: &[u8]) -> Option<(u32, usize)> {
+    if b.is_empty() {
+        return None;
+    }
+    match b[0] {
+        AML_ZERO => Some((0, 1)),
+        AML_ONE => Some((1, 1)),
+        AML_BYTE_PREFIX if b.len() >= 2 => Some((b[1] as u32, 2)),
+        AML_WORD_PREFIX if b.len() >= 3 => Some(((b[1] as u32) | ((b[2] as u32) << 8), 3)),
+        AML_DWORD_PREFIX if b.len() >= 5 => Some(((b[1] as u32) | ((b[2] as u32) << 8) | ((b[3] as u32) << 16) | ((b[4] as u32) << 24), 5)),
+        _ => None
+    }
+}
+
+// finds Name(_S5, Package() { SLP_TYPa, SLP_TYPb, ... }) without interpreting the rest of the AML around it
+fn find_s5(aml: &[u8]) -> Option<(u8, u8)> {
+    let mut start = 0;
+    while let Some(found) = aml[start..].windows(4).position(|w| w == b"_S5_") {
+        let i = start + found;
+        start = i + 1;
+        let named = (i >= 1 && aml[i - 1] == AML_NAME) || (i >= 2 && aml[i - 1] == AML_ROOT_CHAR && aml[i - 2] == AML_NAME);
+        if !named || aml.len() < i + 6 || aml[i + 4] != AML_PACKAGE {
+            continue; // a reference to _S5, or something else that happens to contain the bytes
+        }
+        // the package length takes one to four bytes, with the count of extra bytes in the top two bits of the first
+        let mut offset = i + 5;
+        offset += 1 + (aml[offset] >> 6) as usize;
+        offset += 1; // number of elements
+        if offset >= aml.len() {
+            return None;
+        }
+        return match aml_integer(&aml[offset..]) {
+            Some((a, used)) => aml_integer(&aml[offset + used..]).map(|(b, _)| ((a & 0x7) as u8, (b & 0x7) as u8)),
+            None => None
+        };
+    }
+    None
+}
+
+fn sleep_types_s5(fadt: &Fadt) -> Result<(u8, u8), KError> {
+    let table = acpi::map_table(fadt.dsdt, Some(b"DSDT"))?;
+    let found = find_s5(&table.bytes()[36..]);
+    table.unmap();
+    found.ok_or(KError::FailedLookup)
+}
+
+fn io_port(reg: &GenericAddress) -> Result<ioport::IOPort, KError> {
+    if reg.space != acpi::SPACE_SYSTEM_IO || reg.address > 0xFFFF {
+        return Err(KError::IllegalOperation); // hardware-reduced ACPI, which we don't do
+    }
+    Ok(ioport::request_one(reg.address as u16))
+}
+
+// firmware that starts out in legacy mode has to be told to hand over the power management registers
+fn enable_acpi(fadt: &Fadt) -> Result<(), KError> {
+    let port = io_port(&fadt.pm1a_control)?;
+    if port.get16() & PM1_SCI_EN != 0 || fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return Ok(());
+    }
+    ioport::request_one(fadt.smi_command as u16).set(fadt.acpi_enable);
+    for _ in 0..ACPI_ENABLE_TRIES {
+        if port.get16() & PM1_SCI_EN != 0 {
+            return Ok(());
+        }
+        settle();
+    }
+    Err(KError::UnknownError)
+}
+
+fn write_sleep(reg: &GenericAddress, slp_typ: u8) -> Result<(), KError> {
+    if !reg.is_present() {
+        return Ok(()); // PM1b is optional
+    }
+    let mut port = io_port(reg)?;
+    let value = (port.get16() & !PM1_SLP_TYP_MASK) | ((slp_typ as u16) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN;
+    port.set16(value);
+    Ok(())
+}
+
+fn write_reset(reg: &GenericAddress, value: u8) -> Result<(), KError> {
+    match reg.space {
+        acpi::SPACE_SYSTEM_IO => io_port(reg)?.set(value),
+        acpi::SPACE_SYSTEM_MEMORY => {
+            let addr = reg.address as usize;
+            let mut page = device::get_mapped_device_page(addr & !(PAGE_4K_SIZE - 1))?;
+            unsafe { core::ptr::write_volatile(page.get_ptr().offset((addr & (PAGE_4K_SIZE - 1)) as isize), value) };
+            device::return_mapped_device_page(addr & !(PAGE_4K_SIZE - 1), page);
+        }
+        acpi::SPACE_PCI_CONFIG => {
+            // always on bus 0, with the device and function in the upper words of the address
+            let (device, function, offset) = ((reg.address >> 32) as u16, (reg.address >> 16) as u16, reg.address as u16);
+            if device >= 32 || function >= 8 || offset > 0xFF {
+                return Err(KError::InvalidArgument);
+            }
+            let address = PciAddress::new(0, device as u8, function as u8);
+            let shift = (offset & 0x3) * 8;
+            let old = address.read32(offset as u8 & !0x3);
+            address.write32(offset as u8 & !0x3, (old & !(0xFF << shift)) | ((value as u32) << shift));
+        }
+        _ => return Err(KError::IllegalOperation)
+    }
+    Ok(())
+}
+
+// gets everything written to disk before the power goes
+fn sync() {
+    if let Err(err) = vfs::sync_all() {
+        warn!("could not sync filesystems: {:?}", err);
+    }
+    if let Err(err) = block::sync_all() {
+        warn!("could not sync block devices: {:?}", err);
+    }
+}
+
+// soft-off, through the S5 sleep state. only returns if that didn't work.
+pub fn shutdown() -> Result<(), KError> {
+    let fadt = acpi::fadt().ok_or(KError::FailedLookup)?;
+    let (typ_a, typ_b) = sleep_types_s5(&fadt)?;
+    sync();
+    debug!("powering off");
+    enable_acpi(&fadt)?;
+    write_sleep(&fadt.pm1a_control, typ_a)?;
+    write_sleep(&fadt.pm1b_control, typ_b)?;
+    settle();
+    Err(KError::UnknownError) // still here
+}
+
+pub fn reboot() -> ! {
+    sync();
+    debug!("rebooting");
+    if let Some((reg, value)) = acpi::fadt().and_then(|fadt| fadt.reset_register) {
+        match write_reset(&reg, value) {
+            Ok(()) => settle(),
+            Err(err) => {
+                warn!("could not write ACPI reset register at {}: {:?}", reg, err);
+            }
+        }
+    }
+    keyboard::cpu_reset();
+    settle();
+    panic!("could not reboot");
+}
@@ -0,0 +1,85 @@
+use ::core;
+use ::drivers::vga::{TextLine, VGA_WIDTH};
+use ::memory::Box;
+
+// lines are stored in chunks, so that memory is only taken as the history fills up. each fits in the heap's largest size
+// class, rather than taking pages of its own.
+const LINES_PER_CHUNK: usize = 12; // 12 * 160 = 1920 bytes
+const MAX_CHUNKS: usize = 128;
+pub const MAX_SCROLLBACK_LINES: usize = LINES_PER_CHUNK * MAX_CHUNKS;
+
+type Chunk = [TextLine; LINES_PER_CHUNK];
+
+// a ring of the lines that have scrolled off the top of the screen. chunks are allocated as they fill.
+pub struct Scrollback {
+    chunks: Box<[*mut Chunk; MAX_CHUNKS]>,
+    capacity: usize,
+    next: usize,
+    count: usize
+}
+
+impl Scrollback {
+    pub fn new(lines: usize) -> Scrollback {
+        let capacity = core::cmp::min(lines, MAX_SCROLLBACK_LINES);
+        assert!(capacity > 0);
+        Scrollback { chunks: Box::new([core::ptr::null_mut(); MAX_CHUNKS]), capacity, next: 0, count: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.count = 0;
+    }
+
+    fn chunk_for(&mut self, index: usize) -> &mut Chunk {
+        let slot = index / LINES_PER_CHUNK;
+        if self.chunks[slot].is_null() {
+            let chunk: Chunk = [[0; VGA_WIDTH as usize]; LINES_PER_CHUNK];
+            self.chunks[slot] = at_site!(Box::into_raw(Box::new(chunk)));
+        }
+        unsafe { &mut *self.chunks[slot] }
+    }
+
+    // the oldest line is dropped once the ring is full
+    pub fn push(&mut self, line: &TextLine) {
+        let index = self.next;
+        self.chunk_for(index)[index % LINES_PER_CHUNK] = *line;
+        self.next = (self.next + 1) % self.capacity;
+        if self.count < self.capacity {
+            self.count += 1;
+        }
+    }
+
+    // age 0 is the most recently pushed line
+    pub fn get(&self, age: usize) -> Option<&TextLine> {
+        if age >= self.count {
+            return None;
+        }
+        let index = (self.next + self.capacity - 1 - age) % self.capacity;
+        let chunk = self.chunks[index / LINES_PER_CHUNK];
+        assert!(!chunk.is_null());
+        Some(unsafe { &(*chunk)[index % LINES_PER_CHUNK] })
+    }
+}
+
+impl Drop for Scrollback {
+    fn drop(&mut self) {
+        for slot in 0..MAX_CHUNKS {
+            if !self.chunks[slot].is_null() {
+                core::mem::drop(unsafe { Box::from_raw(self.chunks[slot]) });
+                self.chunks[slot] = core::ptr::null_mut();
+            }
+        }
+    }
+}
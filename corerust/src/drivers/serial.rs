@@ -71,6 +71,14 @@ impl HardwareSerial {
         self.r_data.get()
     }
 
+    pub fn recv_opt(&mut self) -> Option<u8> {
+        if self.recv_ready() {
+            Some(self.r_data.get())
+        } else {
+            None
+        }
+    }
+
     pub fn recv_char(&mut self) -> char {
         self.recv() as char
     }
@@ -109,3 +117,15 @@ impl HardwareSerial {
         }
     }
 }
+
+impl core::fmt::Write for HardwareSerial {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for b in s.bytes() {
+            if b == b'\n' {
+                self.send(b'\r'); // terminals expect CRLF
+            }
+            self.send(b);
+        }
+        Ok(())
+    }
+}
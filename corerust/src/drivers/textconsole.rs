@@ -0,0 +1,357 @@
+use ::core;
+use ::drivers::ansi;
+use ::drivers::cp437;
+use ::drivers::ansi::{AnsiParser, AnsiTerminal};
+use ::drivers::framebuffer::Framebuffer;
+use ::drivers::font::Font;
+use ::drivers::vga;
+
+// a text console drawn onto a framebuffer, with the same cursor, scrolling and escape sequence handling as VGAOutput.
+// colors are the 16 VGA colors, so attributes mean the same thing on both.
+
+const CURSOR_LINES: u8 = 2;
+
+const VGA_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0x00, 0x00, 0xAA), (0x00, 0xAA, 0x00), (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00), (0xAA, 0x00, 0xAA), (0xAA, 0x55, 0x00), (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55), (0x55, 0x55, 0xFF), (0x55, 0xFF, 0x55), (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55), (0xFF, 0x55, 0xFF), (0xFF, 0xFF, 0x55), (0xFF, 0xFF, 0xFF)
+];
+
+pub struct TextConsole {
+    fb: Framebuffer,
+    font: Font,
+    palette: [u32; 16],
+    columns: u16,
+    rows: u16,
+    cur_x: u16,
+    cur_y: u16,
+    attr: u8,
+    reverse: bool,
+    saved: (u16, u16, u8),
+    scroll_top: u16,
+    scroll_bottom: u16,
+    parser: AnsiParser,
+    cursor_visible: bool,
+    cursor_drawn: bool
+}
+
+impl TextConsole {
+    pub fn new(fb: Framebuffer, font: Font) -> TextConsole {
+        let columns = fb.width() / (font.width() as u16);
+        let rows = fb.height() / (font.height() as u16);
+        assert!(columns > 0 && rows > 1);
+        let mut palette = [0; 16];
+        for (i, &(r, g, b)) in VGA_PALETTE.iter().enumerate() {
+            palette[i] = fb.color(r, g, b);
+        }
+        let mut out = TextConsole {
+            fb, font, palette, columns, rows, cur_x: 0, cur_y: 0, attr: vga::default_attribute(), reverse: false,
+            saved: (0, 0, vga::default_attribute()), scroll_top: 0, scroll_bottom: rows - 1, parser: AnsiParser::new(),
+            cursor_visible: true, cursor_drawn: false
+        };
+        let background = out.background(vga::default_attribute());
+        out.fb.clear(background);
+        out.sync_cursor();
+        out
+    }
+
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    fn foreground(&self, attr: u8) -> u32 {
+        self.palette[(attr & 0x0F) as usize]
+    }
+
+    fn background(&self, attr: u8) -> u32 {
+        self.palette[((attr >> 4) & 0x07) as usize]
+    }
+
+    fn cell_attribute(&self) -> u8 {
+        if self.reverse { vga::reverse_attribute(self.attr) } else { self.attr }
+    }
+
+    fn blank_attribute(&self) -> u8 {
+        (self.cell_attribute() & 0x70) | (vga::default_attribute() & 0x0F)
+    }
+
+    pub fn put_cell(&mut self, x: u16, y: u16, char: u8, attr: u8) {
+        assert!(x < self.columns && y < self.rows);
+        let (fg, bg) = (self.foreground(attr), self.background(attr));
+        let (w, h) = (self.font.width(), self.font.height());
+        let (px, py) = (x * (w as u16), y * (h as u16));
+        for row in 0..h {
+            let bits = self.font.row(char, row);
+            for col in 0..w {
+                let lit = bits & (1 << (w - 1 - col)) != 0;
+                self.fb.put_pixel(px + (col as u16), py + (row as u16), if lit { fg } else { bg });
+            }
+        }
+    }
+
+    fn clear_cells(&mut self, x: u16, y: u16, count: u16, attr: u8) {
+        let (w, h) = (self.font.width() as u16, self.font.height() as u16);
+        let bg = self.background(attr);
+        self.fb.fill_rect(x * w, y * h, count * w, h, bg);
+    }
+
+    fn clear_line(&mut self, y: u16, attr: u8) {
+        let columns = self.columns;
+        self.clear_cells(0, y, columns, attr);
+    }
+
+    fn scroll_region_up(&mut self, top: u16, bottom: u16, attr: u8) {
+        assert!(top <= bottom && bottom < self.rows);
+        let h = self.font.height() as u16;
+        let width = self.columns * (self.font.width() as u16);
+        self.fb.copy_rect(0, (top + 1) * h, 0, top * h, width, (bottom - top) * h);
+        self.clear_line(bottom, attr);
+    }
+
+    fn scroll_region_down(&mut self, top: u16, bottom: u16, attr: u8) {
+        assert!(top <= bottom && bottom < self.rows);
+        let h = self.font.height() as u16;
+        let width = self.columns * (self.font.width() as u16);
+        self.fb.copy_rect(0, top * h, 0, (top + 1) * h, width, (bottom - top) * h);
+        self.clear_line(top, attr);
+    }
+
+    // the cursor is an inverted underline, so drawing it twice removes it
+    fn toggle_cursor(&mut self) {
+        let (w, h) = (self.font.width() as u16, self.font.height() as u16);
+        let mask = if self.fb.bpp() >= 24 { 0xFFFFFF } else { (1 << self.fb.bpp()) - 1 };
+        let (px, py) = (self.cur_x * w, self.cur_y * h);
+        for row in h.saturating_sub(CURSOR_LINES as u16)..h {
+            for col in 0..w {
+                let pixel = self.fb.get_pixel(px + col, py + row);
+                self.fb.put_pixel(px + col, py + row, pixel ^ mask);
+            }
+        }
+        self.cursor_drawn = !self.cursor_drawn;
+    }
+
+    fn hide_drawn_cursor(&mut self) {
+        if self.cursor_drawn {
+            self.toggle_cursor();
+        }
+    }
+
+    // drawing the cursor means touching pixels, so it's only redrawn once per batch of output
+    fn sync_cursor(&mut self) {
+        self.hide_drawn_cursor();
+        if self.cursor_visible {
+            self.toggle_cursor();
+        }
+    }
+
+    pub fn move_cursor(&mut self, x: u16, y: u16) {
+        assert!(x < self.columns && y < self.rows);
+        self.hide_drawn_cursor();
+        self.cur_x = x;
+        self.cur_y = y;
+        self.sync_cursor();
+    }
+
+    pub fn show_cursor(&mut self) {
+        self.cursor_visible = true;
+        self.sync_cursor();
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor_visible = false;
+        self.hide_drawn_cursor();
+    }
+
+    pub fn get_attribute(&self) -> u8 {
+        self.attr
+    }
+
+    pub fn set_attribute(&mut self, attr: u8) {
+        self.attr = attr;
+    }
+
+    pub fn clear_screen(&mut self) {
+        self.hide_drawn_cursor();
+        let background = self.background(vga::default_attribute());
+        self.fb.clear(background);
+        self.cur_x = 0;
+        self.cur_y = 0;
+        self.sync_cursor();
+    }
+
+    pub fn next_line(&mut self) {
+        self.cur_x = 0;
+        if self.cur_y == self.scroll_bottom {
+            let (attr, top, bottom) = (self.blank_attribute(), self.scroll_top, self.scroll_bottom);
+            self.scroll_region_up(top, bottom, attr);
+        } else if self.cur_y < self.rows - 1 {
+            self.cur_y += 1;
+        }
+    }
+
+    // raw output: only newline, carriage return, tab and backspace are interpreted
+    pub fn put_char(&mut self, char: u8) {
+        match char as char {
+            '\n' => {
+                self.next_line()
+            }
+            '\r' => {
+                self.cur_x = 0;
+            }
+            '\t' => {
+                let next_stop = (self.cur_x | 7) + 1;
+                self.cur_x = core::cmp::min(next_stop, self.columns - 1);
+            }
+            '\x08' => {
+                if self.cur_x > 0 {
+                    self.cur_x -= 1;
+                }
+            }
+            _ => self.put_glyph(char)
+        }
+    }
+
+    // fonts are assumed to be in code page 437 order, as PC screen fonts usually are
+    pub fn put_glyph(&mut self, glyph: u8) {
+        let (attr, x, y) = (self.cell_attribute(), self.cur_x, self.cur_y);
+        self.put_cell(x, y, glyph, attr);
+        if self.cur_x == self.columns - 1 {
+            self.next_line()
+        } else {
+            self.cur_x += 1
+        }
+    }
+
+    pub fn put_string(&mut self, str: &str) {
+        self.hide_drawn_cursor();
+        let mut parser = core::mem::replace(&mut self.parser, AnsiParser::new());
+        for chr in str.chars() {
+            if (chr as u32) < 0x80 {
+                parser.feed(chr as u8, self);
+            } else {
+                self.put_glyph(cp437::from_char(chr).unwrap_or(b'?'));
+            }
+        }
+        self.parser = parser;
+        self.sync_cursor();
+    }
+}
+
+fn clamp(value: i16, max: u16) -> u16 {
+    if value < 0 {
+        0
+    } else if value as u16 > max {
+        max
+    } else {
+        value as u16
+    }
+}
+
+impl AnsiTerminal for TextConsole {
+    fn put_char(&mut self, char: u8) {
+        TextConsole::put_char(self, char)
+    }
+
+    fn move_cursor_by(&mut self, dx: i16, dy: i16) {
+        let (max_x, max_y) = (self.columns - 1, self.rows - 1);
+        self.cur_x = clamp(self.cur_x as i16 + dx, max_x);
+        self.cur_y = clamp(self.cur_y as i16 + dy, max_y);
+    }
+
+    fn set_cursor(&mut self, column: Option<u16>, row: Option<u16>) {
+        if let Some(column) = column {
+            self.cur_x = core::cmp::min(column, self.columns - 1);
+        }
+        if let Some(row) = row {
+            self.cur_y = core::cmp::min(row, self.rows - 1);
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let attr = self.blank_attribute();
+        let (cx, cy, columns, rows) = (self.cur_x, self.cur_y, self.columns, self.rows);
+        match mode {
+            ansi::ERASE_TO_END => {
+                self.clear_cells(cx, cy, columns - cx, attr);
+                for y in (cy + 1)..rows {
+                    self.clear_line(y, attr);
+                }
+            }
+            ansi::ERASE_TO_START => {
+                for y in 0..cy {
+                    self.clear_line(y, attr);
+                }
+                self.clear_cells(0, cy, cx + 1, attr);
+            }
+            _ => {
+                for y in 0..rows {
+                    self.clear_line(y, attr);
+                }
+            }
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let attr = self.blank_attribute();
+        let (cx, cy, columns) = (self.cur_x, self.cur_y, self.columns);
+        match mode {
+            ansi::ERASE_TO_END => self.clear_cells(cx, cy, columns - cx, attr),
+            ansi::ERASE_TO_START => self.clear_cells(0, cy, cx + 1, attr),
+            _ => self.clear_line(cy, attr)
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = (self.cur_x, self.cur_y, self.attr);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (x, y, attr) = self.saved;
+        self.cur_x = x;
+        self.cur_y = y;
+        self.attr = attr;
+    }
+
+    fn set_scroll_region(&mut self, top: u16, bottom: Option<u16>) {
+        let bottom = bottom.map_or(self.rows - 1, |b| core::cmp::min(b, self.rows - 1));
+        let top = core::cmp::min(top, self.rows - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+            self.cur_x = 0;
+            self.cur_y = 0;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cur_y == self.scroll_top {
+            let (attr, top, bottom) = (self.blank_attribute(), self.scroll_top, self.scroll_bottom);
+            self.scroll_region_down(top, bottom, attr);
+        } else if self.cur_y > 0 {
+            self.cur_y -= 1;
+        }
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        // the cursor is already hidden while a string is being interpreted, and is redrawn afterwards
+        self.cursor_visible = visible;
+    }
+
+    fn select_graphic_rendition(&mut self, code: u16) {
+        let (attr, reverse) = vga::apply_sgr(self.attr, self.reverse, code);
+        self.attr = attr;
+        self.reverse = reverse;
+    }
+}
+
+impl core::fmt::Write for TextConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.put_string(s);
+        Ok(())
+    }
+}
@@ -1,18 +1,38 @@
 use ::core;
 use ::kobject::*;
 use ::memory::device;
+use ::memory::untyped;
 use ::mantle::KError;
+use ::drivers::ioport;
+use ::drivers::ansi;
+use ::drivers::cp437;
+use ::drivers::ansi::{AnsiParser, AnsiTerminal};
+use ::drivers::keyboard::{Key, KeyEvent};
+use ::drivers::scrollback::Scrollback;
 
 const VGA_BUFFER: usize = 0xb8000;
+const CRTC_PORTS: u16 = 0x3D4; // index, then data at 0x3D5
+
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_END: u8 = 0x0B;
+const CRTC_CURSOR_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOW: u8 = 0x0F;
+const CURSOR_DISABLE: u8 = 0x20;
 
 pub struct VGA {
-    addr: usize,
+    addr: Option<usize>, // None for an offscreen buffer in ordinary memory
     mapping: Option<RegionMappedPage4K> // None only during deconstruction
 }
 
 pub const VGA_WIDTH: u8 = 80;
 pub const VGA_HEIGHT: u8 = 25;
 
+pub const DEFAULT_SCROLLBACK_LINES: usize = 1000;
+
+// one row of cells: the character in the low byte, the attribute in the high byte
+pub type TextLine = [u16; VGA_WIDTH as usize];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Color {
     Black = 0,
     Blue = 1,
@@ -32,13 +52,66 @@ pub enum Color {
     White = 15
 }
 
+pub const WARNING_COLOR: Color = Color::Yellow;
+pub const ERROR_COLOR: Color = Color::Red;
+
+pub fn attribute(fg: Color, bg: Color, blink: bool) -> u8 {
+    ((fg as u8) & 0xF) | (((bg as u8) & 0x7) << 4) | (if blink { 0x80 } else { 0 })
+}
+
+pub fn default_attribute() -> u8 {
+    attribute(Color::LightGray, Color::Black, false)
+}
+
+// swaps foreground and background, leaving the blink bit alone
+pub fn reverse_attribute(attr: u8) -> u8 {
+    (attr & 0x80) | ((attr & 0x07) << 4) | ((attr >> 4) & 0x07)
+}
+
+// applies one SGR parameter to an attribute and reverse video flag; shared by every text console
+pub fn apply_sgr(attr: u8, reverse: bool, code: u16) -> (u8, bool) {
+    match code {
+        ansi::SGR_RESET => (default_attribute(), false),
+        ansi::SGR_BOLD => (attr | 0x08, reverse),
+        ansi::SGR_NORMAL_INTENSITY => (attr & !0x08, reverse),
+        ansi::SGR_BLINK => (attr | 0x80, reverse),
+        ansi::SGR_NO_BLINK => (attr & !0x80, reverse),
+        ansi::SGR_REVERSE => (attr, true),
+        ansi::SGR_NO_REVERSE => (attr, false),
+        30...37 => ((attr & 0xF8) | ansi::ansi_to_vga_color((code - ansi::SGR_FG_BASE) as u8), reverse),
+        ansi::SGR_FG_DEFAULT => ((attr & 0xF8) | (default_attribute() & 0x07), reverse),
+        40...47 => ((attr & 0x8F) | (ansi::ansi_to_vga_color((code - ansi::SGR_BG_BASE) as u8) << 4), reverse),
+        ansi::SGR_BG_DEFAULT => ((attr & 0x8F) | (default_attribute() & 0x70), reverse),
+        90...97 => ((attr & 0xF0) | 0x08 | ansi::ansi_to_vga_color((code - ansi::SGR_FG_BRIGHT_BASE) as u8), reverse),
+        // the high background bit is the blink bit, so bright backgrounds map to normal ones
+        100...107 => ((attr & 0x8F) | (ansi::ansi_to_vga_color((code - ansi::SGR_BG_BRIGHT_BASE) as u8) << 4), reverse),
+        _ => (attr, reverse)
+    }
+}
+
 impl VGA {
     pub fn vga_default_port() -> core::result::Result<VGA, KError> {
         VGA::vga_port(VGA_BUFFER)
     }
 
     pub fn vga_port(addr: usize) -> core::result::Result<VGA, KError> {
-        Ok(VGA { addr, mapping: Some(device::get_mapped_device_page(addr)?) })
+        Ok(VGA { addr: Some(addr), mapping: Some(device::get_mapped_device_page(addr)?) })
+    }
+
+    // a buffer with the same layout as the screen, for output that isn't currently displayed
+    pub fn offscreen() -> core::result::Result<VGA, KError> {
+        let page = untyped::allocate_page4k()?;
+        match page.map_into_vspace(true) {
+            Ok(mapping) => {
+                let mut out = VGA { addr: None, mapping: Some(mapping) };
+                out.clear_screen();
+                Ok(out)
+            }
+            Err((page, err)) => {
+                untyped::free_page4k(page);
+                Err(err)
+            }
+        }
     }
 
     fn mapping(&mut self) -> &mut RegionMappedPage4K {
@@ -50,80 +123,431 @@ impl VGA {
         }
     }
 
-    pub fn put_raw_char(&mut self, x: u8, y: u8, char: u8, fg: Color, bg: Color, blink: bool) {
+    // the buffer is device memory, so every access goes through here and stays volatile
+    fn cell_ptr(&mut self, x: u8, y: u8) -> *mut u16 {
         assert!(x < VGA_WIDTH && y < VGA_HEIGHT);
-        let i = ((x as usize) + (y as usize) * (VGA_WIDTH as usize)) * 2;
-        let high_byte: u8 = ((fg as u8) & 0xF) | (((bg as u8) & 0x7) << 4) | (if blink { 0x80 } else { 0 });
-        let array = self.mapping().get_array();
-        // TODO: change this to use volatile memory accesses
-        array[i] = char;
-        array[i + 1] = high_byte;
+        let i = (x as usize) + (y as usize) * (VGA_WIDTH as usize);
+        unsafe { (self.mapping().get_ptr() as *mut u16).offset(i as isize) }
+    }
+
+    pub fn put_cell(&mut self, x: u8, y: u8, char: u8, attr: u8) {
+        let ptr = self.cell_ptr(x, y);
+        unsafe { core::ptr::write_volatile(ptr, (char as u16) | ((attr as u16) << 8)) }
+    }
+
+    pub fn get_cell(&mut self, x: u8, y: u8) -> (u8, u8) {
+        let ptr = self.cell_ptr(x, y);
+        let cell = unsafe { core::ptr::read_volatile(ptr) };
+        (cell as u8, (cell >> 8) as u8)
+    }
+
+    pub fn put_raw_char(&mut self, x: u8, y: u8, char: u8, fg: Color, bg: Color, blink: bool) {
+        self.put_cell(x, y, char, attribute(fg, bg, blink))
     }
 
     pub fn put_char(&mut self, x: u8, y: u8, char: u8) {
         self.put_raw_char(x, y, char, Color::White, Color::Black, false)
     }
 
-    pub fn scroll_up_one_line(&mut self) {
-        let array = self.mapping().get_array();
-        for y in 0..(VGA_HEIGHT - 1) {
-            for x in 0..VGA_WIDTH {
-                let i = ((x as usize) + (y as usize) * (VGA_WIDTH as usize)) * 2;
-                array[i] = array[i + (VGA_WIDTH as usize) * 2];
-                array[i + 1] = array[i + (VGA_WIDTH as usize) * 2 + 1];
-            }
+    pub fn get_line(&mut self, y: u8) -> TextLine {
+        let mut line: TextLine = [0; VGA_WIDTH as usize];
+        for x in 0..VGA_WIDTH {
+            let ptr = self.cell_ptr(x, y);
+            line[x as usize] = unsafe { core::ptr::read_volatile(ptr) };
         }
+        line
+    }
+
+    pub fn put_line(&mut self, y: u8, line: &TextLine) {
         for x in 0..VGA_WIDTH {
-            let i = ((x as usize) + ((VGA_HEIGHT - 1) as usize) * (VGA_WIDTH as usize)) * 2;
-            array[i] = 0;
-            array[i + 1] = 0;
+            let ptr = self.cell_ptr(x, y);
+            unsafe { core::ptr::write_volatile(ptr, line[x as usize]) };
         }
     }
 
-    pub fn clear_screen(&mut self) {
+    pub fn swap_contents(&mut self, other: &mut VGA) {
+        for y in 0..VGA_HEIGHT {
+            let (mine, theirs) = (self.get_line(y), other.get_line(y));
+            self.put_line(y, &theirs);
+            other.put_line(y, &mine);
+        }
+    }
+
+    pub fn copy_from(&mut self, other: &mut VGA) {
+        for y in 0..VGA_HEIGHT {
+            let line = other.get_line(y);
+            self.put_line(y, &line);
+        }
+    }
+
+    pub fn clear_line(&mut self, y: u8, attr: u8) {
         for x in 0..VGA_WIDTH {
-            for y in 0..VGA_HEIGHT {
-                self.put_raw_char(x, y, 0, Color::Black, Color::Black, false);
-            }
+            self.put_cell(x, y, b' ', attr);
         }
     }
+
+    fn copy_line(&mut self, from_y: u8, to_y: u8) {
+        let line = self.get_line(from_y);
+        self.put_line(to_y, &line);
+    }
+
+    // scrolls lines top through bottom (inclusive) up by one, and blanks the bottom line
+    pub fn scroll_region_up(&mut self, top: u8, bottom: u8, attr: u8) {
+        assert!(top <= bottom && bottom < VGA_HEIGHT);
+        for y in top..bottom {
+            self.copy_line(y + 1, y);
+        }
+        self.clear_line(bottom, attr);
+    }
+
+    // scrolls lines top through bottom (inclusive) down by one, and blanks the top line
+    pub fn scroll_region_down(&mut self, top: u8, bottom: u8, attr: u8) {
+        assert!(top <= bottom && bottom < VGA_HEIGHT);
+        for y in (top..bottom).rev() {
+            self.copy_line(y, y + 1);
+        }
+        self.clear_line(top, attr);
+    }
+
+    pub fn scroll_up_one_line(&mut self) {
+        // blank cells keep a visible attribute so that the hardware cursor shows up on them
+        self.scroll_region_up(0, VGA_HEIGHT - 1, default_attribute());
+    }
+
+    pub fn clear_screen(&mut self) {
+        for y in 0..VGA_HEIGHT {
+            self.clear_line(y, default_attribute());
+        }
+    }
+}
+
+pub struct VGACursor {
+    index: ioport::IOPort,
+    data: ioport::IOPort
+}
+
+impl VGACursor {
+    pub fn new() -> VGACursor {
+        let ports = ioport::request(CRTC_PORTS, 2);
+        VGACursor { index: ports.get(0), data: ports.get(1) }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        self.index.set(register);
+        self.data.set(value);
+    }
+
+    fn read_register(&mut self, register: u8) -> u8 {
+        self.index.set(register);
+        self.data.get()
+    }
+
+    pub fn set_position(&mut self, x: u8, y: u8) {
+        assert!(x < VGA_WIDTH && y < VGA_HEIGHT);
+        let pos = (x as u16) + (y as u16) * (VGA_WIDTH as u16);
+        self.write_register(CRTC_CURSOR_LOW, pos as u8);
+        self.write_register(CRTC_CURSOR_HIGH, (pos >> 8) as u8);
+    }
+
+    // an underline cursor on the bottom two scanlines of each 16-scanline cell
+    pub fn show(&mut self) {
+        let start = self.read_register(CRTC_CURSOR_START);
+        self.write_register(CRTC_CURSOR_START, (start & 0xC0) | 14);
+        let end = self.read_register(CRTC_CURSOR_END);
+        self.write_register(CRTC_CURSOR_END, (end & 0xE0) | 15);
+    }
+
+    pub fn hide(&mut self) {
+        self.write_register(CRTC_CURSOR_START, CURSOR_DISABLE);
+    }
 }
 
 impl Drop for VGA {
     fn drop(&mut self) {
         let mapping = core::mem::replace(&mut self.mapping, None).unwrap();
-        device::return_mapped_device_page(self.addr, mapping);
+        if let Some(addr) = self.addr {
+            device::return_mapped_device_page(addr, mapping);
+        } else {
+            untyped::free_page4k(mapping.unmap());
+        }
     }
 }
 
 pub struct VGAOutput {
     cur_x: u8,
     cur_y: u8,
-    screen: VGA
+    attr: u8,
+    reverse: bool,
+    saved: (u8, u8, u8),
+    scroll_top: u8,
+    scroll_bottom: u8,
+    parser: AnsiParser,
+    screen: VGA,
+    cursor: VGACursor,
+    cursor_visible: bool,
+    active: bool, // whether screen is the real display rather than an offscreen buffer
+    scrollback: Option<Scrollback>,
+    // while scrolled back, output goes to the shadow buffer and the screen shows history
+    shadow: Option<VGA>,
+    view_offset: usize
 }
 
 impl VGAOutput {
+    fn new(screen: VGA, active: bool) -> VGAOutput {
+        VGAOutput {
+            cur_x: 0, cur_y: 0, attr: default_attribute(), reverse: false, saved: (0, 0, default_attribute()),
+            scroll_top: 0, scroll_bottom: VGA_HEIGHT - 1, parser: AnsiParser::new(), screen, cursor: VGACursor::new(),
+            cursor_visible: true, active, scrollback: None, shadow: None, view_offset: 0
+        }
+    }
+
     pub fn default() -> Result<VGAOutput, KError> {
         let mut screen = VGA::vga_default_port()?;
         screen.clear_screen();
-        Ok(VGAOutput { cur_x: 0, cur_y: 0, screen })
+        let mut out = VGAOutput::new(screen, true);
+        out.cursor.show();
+        out.sync_cursor();
+        Ok(out)
+    }
+
+    // a console that isn't displayed until it takes over the display from the active one
+    pub fn offscreen() -> Result<VGAOutput, KError> {
+        Ok(VGAOutput::new(VGA::offscreen()?, false))
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // moves the physical display from the other console to this one, each keeping its own contents
+    pub fn take_display(&mut self, from: &mut VGAOutput) {
+        assert!(from.active && !self.active);
+        from.snap_to_live();
+        core::mem::swap(&mut self.screen, &mut from.screen);
+        self.screen.swap_contents(&mut from.screen);
+        from.active = false;
+        self.active = true;
+        if self.cursor_visible {
+            self.cursor.show();
+        } else {
+            self.cursor.hide();
+        }
+        self.sync_cursor();
+    }
+
+    // hands the physical display over to something else, such as a graphics mode, until reclaim_display
+    pub fn release_display(&mut self) -> Result<VGA, KError> {
+        assert!(self.active);
+        self.snap_to_live();
+        let mut buffer = VGA::offscreen()?;
+        buffer.copy_from(&mut self.screen);
+        self.active = false;
+        Ok(core::mem::replace(&mut self.screen, buffer))
+    }
+
+    pub fn reclaim_display(&mut self, mut screen: VGA) {
+        assert!(!self.active);
+        screen.copy_from(&mut self.screen);
+        core::mem::drop(core::mem::replace(&mut self.screen, screen));
+        self.active = true;
+        if self.cursor_visible {
+            self.cursor.show();
+        } else {
+            self.cursor.hide();
+        }
+        self.sync_cursor();
+    }
+
+    // the hardware cursor costs port writes, so it's only updated once per batch of output
+    fn sync_cursor(&mut self) {
+        if self.active && self.view_offset == 0 {
+            self.cursor.set_position(self.cur_x, self.cur_y);
+        }
+    }
+
+    // where output currently lands: the screen, unless we're scrolled back
+    fn target(&mut self) -> &mut VGA {
+        if self.view_offset > 0 {
+            self.shadow.as_mut().unwrap()
+        } else {
+            &mut self.screen
+        }
     }
 
     pub fn move_cursor(&mut self, x: u8, y: u8) {
         assert!(x < VGA_WIDTH && y < VGA_HEIGHT);
         self.cur_x = x;
         self.cur_y = y;
+        self.sync_cursor();
+    }
+
+    pub fn show_cursor(&mut self) {
+        self.cursor_visible = true;
+        if self.active && self.view_offset == 0 {
+            self.cursor.show();
+        }
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor_visible = false;
+        if self.active {
+            self.cursor.hide();
+        }
+    }
+
+    pub fn enable_scrollback(&mut self, lines: usize) {
+        self.snap_to_live();
+        self.scrollback = Some(Scrollback::new(lines));
+    }
+
+    pub fn is_scrolled_back(&self) -> bool {
+        self.view_offset > 0
+    }
+
+    fn redraw_view(&mut self) {
+        let VGAOutput { ref mut screen, ref mut shadow, ref scrollback, view_offset, .. } = *self;
+        let (history, shadow) = (scrollback.as_ref().unwrap(), shadow.as_mut().unwrap());
+        for y in 0..VGA_HEIGHT {
+            // index into the history followed by the live screen
+            let virtual_line = history.len() - view_offset + (y as usize);
+            if virtual_line < history.len() {
+                screen.put_line(y, history.get(history.len() - 1 - virtual_line).unwrap());
+            } else {
+                let line = shadow.get_line((virtual_line - history.len()) as u8);
+                screen.put_line(y, &line);
+            }
+        }
+    }
+
+    pub fn scroll_back(&mut self, lines: usize) {
+        let available = self.scrollback.as_ref().map_or(0, |s| s.len());
+        if !self.active || available == 0 || self.view_offset == available {
+            return;
+        }
+        if self.view_offset == 0 {
+            if self.shadow.is_none() {
+                match VGA::offscreen() {
+                    Ok(buffer) => self.shadow = Some(buffer),
+                    Err(_) => return // can't scroll back without somewhere to put live output
+                }
+            }
+            self.shadow.as_mut().unwrap().copy_from(&mut self.screen);
+            self.cursor.hide();
+        }
+        self.view_offset = core::cmp::min(self.view_offset + lines, available);
+        self.redraw_view();
+    }
+
+    pub fn scroll_forward(&mut self, lines: usize) {
+        if lines >= self.view_offset {
+            self.snap_to_live();
+        } else {
+            self.view_offset -= lines;
+            self.redraw_view();
+        }
+    }
+
+    pub fn snap_to_live(&mut self) {
+        if self.view_offset > 0 {
+            self.view_offset = 0;
+            self.screen.copy_from(self.shadow.as_mut().unwrap());
+            if self.cursor_visible {
+                self.cursor.show();
+            }
+            self.sync_cursor();
+        }
+    }
+
+    // returns true if the key was consumed by the console
+    pub fn handle_key(&mut self, event: &KeyEvent) -> bool {
+        if !event.pressed {
+            return false;
+        }
+        match event.key {
+            Key::PageUp if event.shift => {
+                self.scroll_back((VGA_HEIGHT / 2) as usize);
+                true
+            }
+            Key::PageDown if event.shift => {
+                self.scroll_forward((VGA_HEIGHT / 2) as usize);
+                true
+            }
+            Key::Shift | Key::Control | Key::Alt | Key::CapsLock => false,
+            _ => {
+                self.snap_to_live();
+                false
+            }
+        }
+    }
+
+    pub fn set_colors(&mut self, fg: Color, bg: Color) {
+        self.attr = attribute(fg, bg, false);
+    }
+
+    pub fn set_foreground(&mut self, fg: Color) {
+        self.attr = (self.attr & 0xF0) | (fg as u8);
+    }
+
+    pub fn reset_colors(&mut self) {
+        self.attr = default_attribute();
+        self.reverse = false;
+    }
+
+    pub fn get_attribute(&self) -> u8 {
+        self.attr
+    }
+
+    pub fn set_attribute(&mut self, attr: u8) {
+        self.attr = attr;
+    }
+
+    // the attribute actually written to the screen, after reverse video
+    fn cell_attribute(&self) -> u8 {
+        if self.reverse { reverse_attribute(self.attr) } else { self.attr }
+    }
+
+    // blanked cells take the current background, like a real terminal
+    fn blank_attribute(&self) -> u8 {
+        (self.cell_attribute() & 0x70) | (default_attribute() & 0x0F)
+    }
+
+    pub fn write_colored(&mut self, fg: Color, args: core::fmt::Arguments) -> core::fmt::Result {
+        let saved = self.attr;
+        self.set_foreground(fg);
+        let result = core::fmt::write(self, args);
+        self.attr = saved;
+        result
+    }
+
+    pub fn warning(&mut self, args: core::fmt::Arguments) -> core::fmt::Result {
+        self.write_colored(WARNING_COLOR, args)
+    }
+
+    pub fn error(&mut self, args: core::fmt::Arguments) -> core::fmt::Result {
+        self.write_colored(ERROR_COLOR, args)
     }
 
     pub fn next_line(&mut self) {
         self.cur_x = 0;
-        if self.cur_y == VGA_HEIGHT - 1 {
-            self.screen.scroll_up_one_line();
-        } else {
+        if self.cur_y == self.scroll_bottom {
+            let (attr, top, bottom) = (self.blank_attribute(), self.scroll_top, self.scroll_bottom);
+            if top == 0 && self.scrollback.is_some() {
+                let line = self.target().get_line(0);
+                self.scrollback.as_mut().unwrap().push(&line);
+            }
+            self.target().scroll_region_up(top, bottom, attr);
+            if self.view_offset > 0 {
+                // keep showing the same history, as far as the ring allows
+                let available = self.scrollback.as_ref().unwrap().len();
+                self.view_offset = core::cmp::min(self.view_offset + 1, available);
+                self.redraw_view();
+            }
+        } else if self.cur_y < VGA_HEIGHT - 1 {
             self.cur_y += 1;
         }
     }
 
+    // raw output: only newline, carriage return, tab and backspace are interpreted
     pub fn put_char(&mut self, char: u8) {
         match char as char {
             '\n' => {
@@ -132,30 +556,156 @@ impl VGAOutput {
             '\r' => {
                 self.cur_x = 0;
             }
-            _ => {
-                self.screen.put_char(self.cur_x, self.cur_y, char);
-                if self.cur_x == VGA_WIDTH - 1 {
-                    self.next_line()
-                } else {
-                    self.cur_x += 1
+            '\t' => {
+                let next_stop = (self.cur_x | 7) + 1;
+                self.cur_x = core::cmp::min(next_stop, VGA_WIDTH - 1);
+            }
+            '\x08' => {
+                if self.cur_x > 0 {
+                    self.cur_x -= 1;
                 }
             }
+            _ => self.put_glyph(char)
         }
     }
 
-    pub fn put_rchar(&mut self, char: char) {
-        if char as u32 >= 256 {
-            self.put_char('?' as u8)
+    // draws any code page 437 character, including those that double as control characters
+    pub fn put_glyph(&mut self, glyph: u8) {
+        let (attr, x, y) = (self.cell_attribute(), self.cur_x, self.cur_y);
+        self.target().put_cell(x, y, glyph, attr);
+        if self.cur_x == VGA_WIDTH - 1 {
+            self.next_line()
         } else {
+            self.cur_x += 1
+        }
+    }
+
+    pub fn put_rchar(&mut self, char: char) {
+        if (char as u32) < 0x80 {
             self.put_char(char as u8)
+        } else {
+            self.put_glyph(cp437::from_char(char).unwrap_or(b'?'))
         }
     }
 
+    // interprets escape sequences, so that output meant for a serial terminal looks the same here
     pub fn put_string(&mut self, str: &str) {
+        let mut parser = core::mem::replace(&mut self.parser, AnsiParser::new());
         for chr in str.chars() {
-            self.put_rchar(chr);
+            if (chr as u32) < 0x80 {
+                parser.feed(chr as u8, self);
+            } else {
+                self.put_glyph(cp437::from_char(chr).unwrap_or(b'?'));
+            }
+        }
+        self.parser = parser;
+        self.sync_cursor();
+    }
+}
+
+fn clamp(value: i16, max: u8) -> u8 {
+    if value < 0 {
+        0
+    } else if value > max as i16 {
+        max
+    } else {
+        value as u8
+    }
+}
+
+impl AnsiTerminal for VGAOutput {
+    fn put_char(&mut self, char: u8) {
+        VGAOutput::put_char(self, char)
+    }
+
+    fn move_cursor_by(&mut self, dx: i16, dy: i16) {
+        self.cur_x = clamp(self.cur_x as i16 + dx, VGA_WIDTH - 1);
+        self.cur_y = clamp(self.cur_y as i16 + dy, VGA_HEIGHT - 1);
+    }
+
+    fn set_cursor(&mut self, column: Option<u16>, row: Option<u16>) {
+        if let Some(column) = column {
+            self.cur_x = core::cmp::min(column, (VGA_WIDTH - 1) as u16) as u8;
+        }
+        if let Some(row) = row {
+            self.cur_y = core::cmp::min(row, (VGA_HEIGHT - 1) as u16) as u8;
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let attr = self.blank_attribute();
+        let (cx, cy) = (self.cur_x, self.cur_y);
+        for y in 0..VGA_HEIGHT {
+            for x in 0..VGA_WIDTH {
+                let erase = match mode {
+                    ansi::ERASE_TO_END => (y, x) >= (cy, cx),
+                    ansi::ERASE_TO_START => (y, x) <= (cy, cx),
+                    _ => true
+                };
+                if erase {
+                    self.target().put_cell(x, y, b' ', attr);
+                }
+            }
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let attr = self.blank_attribute();
+        let (from, to) = match mode {
+            ansi::ERASE_TO_END => (self.cur_x, VGA_WIDTH - 1),
+            ansi::ERASE_TO_START => (0, self.cur_x),
+            _ => (0, VGA_WIDTH - 1)
+        };
+        for x in from..(to + 1) {
+            let y = self.cur_y;
+            self.target().put_cell(x, y, b' ', attr);
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = (self.cur_x, self.cur_y, self.attr);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (x, y, attr) = self.saved;
+        self.cur_x = x;
+        self.cur_y = y;
+        self.attr = attr;
+    }
+
+    fn set_scroll_region(&mut self, top: u16, bottom: Option<u16>) {
+        let bottom = bottom.map_or(VGA_HEIGHT - 1, |b| core::cmp::min(b, (VGA_HEIGHT - 1) as u16) as u8);
+        let top = core::cmp::min(top, (VGA_HEIGHT - 1) as u16) as u8;
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+            self.cur_x = 0;
+            self.cur_y = 0;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cur_y == self.scroll_top {
+            let (attr, top, bottom) = (self.blank_attribute(), self.scroll_top, self.scroll_bottom);
+            self.target().scroll_region_down(top, bottom, attr);
+        } else if self.cur_y > 0 {
+            self.cur_y -= 1;
         }
     }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        if visible {
+            self.show_cursor();
+        } else {
+            self.hide_cursor();
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, code: u16) {
+        let (attr, reverse) = apply_sgr(self.attr, self.reverse, code);
+        self.attr = attr;
+        self.reverse = reverse;
+    }
 }
 
 impl core::fmt::Write for VGAOutput {
@@ -164,3 +714,23 @@ impl core::fmt::Write for VGAOutput {
         Ok(())
     }
 }
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    // only in the target, since an offscreen buffer is a page of our own
+    fn out_of_range_positions() -> TestResult {
+        let mut out = check_ok!(VGAOutput::offscreen());
+        out.put_string("\x1b[40000;40000H");
+        check_eq!((out.cur_x, out.cur_y), (VGA_WIDTH - 1, VGA_HEIGHT - 1));
+        out.put_string("\x1b[5;40000r");
+        check_eq!((out.scroll_top, out.scroll_bottom), (4, VGA_HEIGHT - 1));
+        out.put_string("\x1b[40000;40000r");
+        check_eq!((out.scroll_top, out.scroll_bottom), (4, VGA_HEIGHT - 1));
+        Ok(())
+    }
+
+    test_cases!(; target: out_of_range_positions);
+}
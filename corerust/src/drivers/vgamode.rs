@@ -0,0 +1,364 @@
+use ::core;
+use ::core::ptr;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::kobject::*;
+use ::memory::device;
+use ::memory::untyped;
+use ::drivers::ioport;
+use ::drivers::framebuffer::Framebuffer;
+use ::drivers::font::Font;
+
+// register-level VGA programming: switching between text and graphics modes, fonts and the DAC palette.
+// the register tables are the standard ones set up by the BIOS for modes 03h and 13h.
+
+const MISC_WRITE: u16 = 0x3C2;
+const SEQ_INDEX: u16 = 0x3C4; // then data at 0x3C5
+const CRTC_INDEX: u16 = 0x3D4; // then data at 0x3D5
+const GC_INDEX: u16 = 0x3CE; // then data at 0x3CF
+const AC_INDEX: u16 = 0x3C0; // also written for data
+const INPUT_STATUS_1: u16 = 0x3DA; // reading this resets the attribute controller's flip-flop
+const DAC_WRITE_INDEX: u16 = 0x3C8; // then data at 0x3C9
+
+const SEQ_COUNT: usize = 5;
+const CRTC_COUNT: usize = 25;
+const GC_COUNT: usize = 9;
+const AC_COUNT: usize = 21;
+
+const SEQ_MAP_MASK: u8 = 0x02;
+const SEQ_MEMORY_MODE: u8 = 0x04;
+const CRTC_HORIZONTAL_BLANK_END: u8 = 0x03;
+const CRTC_VERTICAL_RETRACE_END: u8 = 0x11;
+const GC_READ_MAP: u8 = 0x04;
+const GC_MODE: u8 = 0x05;
+const GC_MISC: u8 = 0x06;
+const AC_PALETTE_ENABLE: u8 = 0x20;
+
+const GRAPHICS_BUFFER: usize = 0xA0000;
+pub const GRAPHICS_WIDTH: u16 = 320;
+pub const GRAPHICS_HEIGHT: u16 = 200;
+
+// fonts live in plane 2, with room for 32 scanlines per glyph
+const FONT_PLANE: u8 = 2;
+const FONT_STRIDE: usize = 32;
+const FONT_GLYPHS: usize = 256;
+const SAVED_FONT_HEIGHT: usize = 16;
+
+// a glyph for 80x25 text mode: eight pixels wide, leftmost pixel in the high bit
+pub type Glyph = [u8; 16];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Text80x25,
+    Text80x50,
+    Graphics320x200
+}
+
+struct ModeRegisters {
+    misc: u8,
+    seq: [u8; SEQ_COUNT],
+    crtc: [u8; CRTC_COUNT],
+    gc: [u8; GC_COUNT],
+    ac: [u8; AC_COUNT]
+}
+
+const TEXT_GC: [u8; GC_COUNT] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0E, 0x00, 0xFF];
+const TEXT_AC: [u8; AC_COUNT] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F,
+    0x0C, 0x00, 0x0F, 0x08, 0x00
+];
+
+static TEXT_80X25: ModeRegisters = ModeRegisters {
+    misc: 0x67,
+    seq: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x4F, 0x0D, 0x0E, 0x00, 0x00, 0x00, 0x50,
+        0x9C, 0x0E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF
+    ],
+    gc: TEXT_GC,
+    ac: TEXT_AC
+};
+
+// the same timings as 80x25, but with eight scanlines per character
+static TEXT_80X50: ModeRegisters = ModeRegisters {
+    misc: 0x67,
+    seq: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x47, 0x06, 0x07, 0x00, 0x00, 0x01, 0x40,
+        0x9C, 0x8E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF
+    ],
+    gc: TEXT_GC,
+    ac: TEXT_AC
+};
+
+static GRAPHICS_320X200X256: ModeRegisters = ModeRegisters {
+    misc: 0x63,
+    seq: [0x03, 0x01, 0x0F, 0x00, 0x0E],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0xBF, 0x1F, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x9C, 0x0E, 0x8F, 0x28, 0x40, 0x96, 0xB9, 0xA3, 0xFF
+    ],
+    gc: [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF],
+    ac: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        0x41, 0x00, 0x0F, 0x00, 0x00
+    ]
+};
+
+fn registers_for(mode: Mode) -> &'static ModeRegisters {
+    match mode {
+        Mode::Text80x25 => &TEXT_80X25,
+        Mode::Text80x50 => &TEXT_80X50,
+        Mode::Graphics320x200 => &GRAPHICS_320X200X256
+    }
+}
+
+fn write_indexed(index_port: u16, index: u8, value: u8) {
+    ioport::request_one(index_port).set(index);
+    ioport::request_one(index_port + 1).set(value);
+}
+
+fn read_indexed(index_port: u16, index: u8) -> u8 {
+    ioport::request_one(index_port).set(index);
+    ioport::request_one(index_port + 1).get()
+}
+
+fn write_attribute(index: u8, value: u8) {
+    ioport::request_one(INPUT_STATUS_1).get();
+    let mut ac = ioport::request_one(AC_INDEX);
+    ac.set(index);
+    ac.set(value);
+}
+
+fn write_registers(regs: &ModeRegisters) {
+    ioport::request_one(MISC_WRITE).set(regs.misc);
+    for (i, value) in regs.seq.iter().enumerate() {
+        write_indexed(SEQ_INDEX, i as u8, *value);
+    }
+    // the CRTC timing registers are write-protected until unlocked, and must stay unlocked
+    let hbe = read_indexed(CRTC_INDEX, CRTC_HORIZONTAL_BLANK_END);
+    write_indexed(CRTC_INDEX, CRTC_HORIZONTAL_BLANK_END, hbe | 0x80);
+    let vre = read_indexed(CRTC_INDEX, CRTC_VERTICAL_RETRACE_END);
+    write_indexed(CRTC_INDEX, CRTC_VERTICAL_RETRACE_END, vre & !0x80);
+    for (i, value) in regs.crtc.iter().enumerate() {
+        let value = match i as u8 {
+            CRTC_HORIZONTAL_BLANK_END => *value | 0x80,
+            CRTC_VERTICAL_RETRACE_END => *value & !0x80,
+            _ => *value
+        };
+        write_indexed(CRTC_INDEX, i as u8, value);
+    }
+    for (i, value) in regs.gc.iter().enumerate() {
+        write_indexed(GC_INDEX, i as u8, *value);
+    }
+    for (i, value) in regs.ac.iter().enumerate() {
+        write_attribute(i as u8, *value);
+    }
+    // lock the palette and turn the display back on
+    ioport::request_one(INPUT_STATUS_1).get();
+    ioport::request_one(AC_INDEX).set(AC_PALETTE_ENABLE);
+}
+
+// the DAC takes six bits per component; these take eight and drop the low bits
+pub fn set_palette_entry(index: u8, r: u8, g: u8, b: u8) {
+    ioport::request_one(DAC_WRITE_INDEX).set(index);
+    let mut data = ioport::request_one(DAC_WRITE_INDEX + 1);
+    data.set(r >> 2);
+    data.set(g >> 2);
+    data.set(b >> 2);
+}
+
+pub fn set_palette(first: u8, colors: &[(u8, u8, u8)]) {
+    assert!((first as usize) + colors.len() <= 256);
+    for (i, &(r, g, b)) in colors.iter().enumerate() {
+        set_palette_entry(first + (i as u8), r, g, b);
+    }
+}
+
+// text modes index the DAC through the attribute controller using the 64 EGA colors
+pub fn load_text_palette() {
+    for i in 0..64u8 {
+        let level = |high: u8, low: u8| ((i >> high) & 1) * 0xAA + ((i >> low) & 1) * 0x55;
+        set_palette_entry(i, level(2, 5), level(1, 4), level(0, 3));
+    }
+}
+
+// graphics mode uses a 3-3-2 RGB palette, which is what Framebuffer::color assumes for 8-bit modes
+pub fn load_graphics_palette() {
+    for i in 0..256usize {
+        let (r, g, b) = ((i >> 5) & 7, (i >> 2) & 7, i & 3);
+        set_palette_entry(i as u8, (r * 255 / 7) as u8, (g * 255 / 7) as u8, (b * 255 / 3) as u8);
+    }
+}
+
+// gives sequential access to the font plane at the graphics window, restoring the text mode setup afterwards
+fn with_font_plane<R, F: FnOnce(*mut u8) -> R>(f: F) -> Result<R, KError> {
+    let mut region = device::get_mapped_device_region(GRAPHICS_BUFFER, FONT_GLYPHS * FONT_STRIDE)?;
+    let (map_mask, memory_mode) = (read_indexed(SEQ_INDEX, SEQ_MAP_MASK), read_indexed(SEQ_INDEX, SEQ_MEMORY_MODE));
+    let (read_map, gc_mode, gc_misc) = (read_indexed(GC_INDEX, GC_READ_MAP), read_indexed(GC_INDEX, GC_MODE), read_indexed(GC_INDEX, GC_MISC));
+    write_indexed(SEQ_INDEX, SEQ_MAP_MASK, 1 << FONT_PLANE);
+    write_indexed(SEQ_INDEX, SEQ_MEMORY_MODE, 0x07); // sequential addressing, no odd/even
+    write_indexed(GC_INDEX, GC_READ_MAP, FONT_PLANE);
+    write_indexed(GC_INDEX, GC_MODE, 0x00);
+    write_indexed(GC_INDEX, GC_MISC, 0x04); // 64K at 0xA0000, no chaining
+    let result = f(region.get_ptr());
+    write_indexed(SEQ_INDEX, SEQ_MAP_MASK, map_mask);
+    write_indexed(SEQ_INDEX, SEQ_MEMORY_MODE, memory_mode);
+    write_indexed(GC_INDEX, GC_READ_MAP, read_map);
+    write_indexed(GC_INDEX, GC_MODE, gc_mode);
+    write_indexed(GC_INDEX, GC_MISC, gc_misc);
+    device::return_mapped_device_region(region);
+    Ok(result)
+}
+
+struct ModeState {
+    mode: Mode,
+    // the BIOS font, which graphics mode overwrites, saved when first leaving 80x25 text mode
+    saved_font: Option<RegionMappedPage4K>
+}
+
+static STATE: SingleThreaded<RefCell<ModeState>> = SingleThreaded(RefCell::new(ModeState { mode: Mode::Text80x25, saved_font: None }));
+
+fn save_font(state: &mut ModeState) -> Result<(), KError> {
+    if state.saved_font.is_some() {
+        return Ok(());
+    }
+    let mut page = match untyped::allocate_page4k()?.map_into_vspace(true) {
+        Ok(page) => page,
+        Err((page, err)) => {
+            untyped::free_page4k(page);
+            return Err(err);
+        }
+    };
+    let result = {
+        let buffer = page.get_array();
+        with_font_plane(|plane| {
+            for glyph in 0..FONT_GLYPHS {
+                for row in 0..SAVED_FONT_HEIGHT {
+                    buffer[glyph * SAVED_FONT_HEIGHT + row] = unsafe { ptr::read_volatile(plane.offset((glyph * FONT_STRIDE + row) as isize)) };
+                }
+            }
+        })
+    };
+    match result {
+        Ok(()) => {
+            state.saved_font = Some(page);
+            Ok(())
+        }
+        Err(err) => {
+            untyped::free_page4k(page.unmap());
+            Err(err)
+        }
+    }
+}
+
+fn restore_font(state: &mut ModeState) -> Result<(), KError> {
+    if let Some(ref mut page) = state.saved_font {
+        let buffer = page.get_array();
+        with_font_plane(|plane| {
+            for glyph in 0..FONT_GLYPHS {
+                for row in 0..FONT_STRIDE {
+                    let value = if row < SAVED_FONT_HEIGHT { buffer[glyph * SAVED_FONT_HEIGHT + row] } else { 0 };
+                    unsafe { ptr::write_volatile(plane.offset((glyph * FONT_STRIDE + row) as isize), value) };
+                }
+            }
+        })
+    } else {
+        Ok(())
+    }
+}
+
+// overlays the printable ASCII glyphs with the built-in 8x8 font, for 80x50 mode
+fn load_small_font() -> Result<(), KError> {
+    let font = Font::builtin();
+    assert!(font.width() == 8 && font.height() == 8);
+    with_font_plane(|plane| {
+        for char in 0x20..0x7Fu8 {
+            for row in 0..font.height() {
+                let offset = (char as usize) * FONT_STRIDE + (row as usize);
+                unsafe { ptr::write_volatile(plane.offset(offset as isize), font.row(char, row) as u8) };
+            }
+        }
+    })
+}
+
+// replaces glyphs of the 80x25 text mode font, e.g. to add icons. the change survives trips through other modes.
+pub fn load_glyphs(first: u8, glyphs: &[Glyph]) -> Result<(), KError> {
+    assert!((first as usize) + glyphs.len() <= FONT_GLYPHS);
+    let mut state = STATE.get().borrow_mut();
+    if state.mode != Mode::Text80x25 {
+        return Err(KError::IllegalOperation);
+    }
+    with_font_plane(|plane| {
+        for (i, glyph) in glyphs.iter().enumerate() {
+            for (row, bits) in glyph.iter().enumerate() {
+                let offset = (first as usize + i) * FONT_STRIDE + row;
+                unsafe { ptr::write_volatile(plane.offset(offset as isize), *bits) };
+            }
+        }
+    })?;
+    if let Some(ref mut page) = state.saved_font {
+        let buffer = page.get_array();
+        for (i, glyph) in glyphs.iter().enumerate() {
+            let start = (first as usize + i) * SAVED_FONT_HEIGHT;
+            buffer[start..start + SAVED_FONT_HEIGHT].copy_from_slice(glyph);
+        }
+    }
+    Ok(())
+}
+
+// replaces the whole 80x25 text mode font, which must be 8x16, such as a PC screen font from a boot module
+pub fn load_font(font: &Font) -> Result<(), KError> {
+    if font.width() != 8 || font.height() as usize != SAVED_FONT_HEIGHT {
+        return Err(KError::InvalidArgument);
+    }
+    // in batches, to keep both the stack and the number of mappings small
+    const BATCH: usize = 16;
+    for batch in 0..(FONT_GLYPHS / BATCH) {
+        let mut glyphs: [Glyph; BATCH] = [[0; SAVED_FONT_HEIGHT]; BATCH];
+        for (i, glyph) in glyphs.iter_mut().enumerate() {
+            for row in 0..SAVED_FONT_HEIGHT {
+                glyph[row] = font.row((batch * BATCH + i) as u8, row as u8) as u8;
+            }
+        }
+        load_glyphs((batch * BATCH) as u8, &glyphs)?;
+    }
+    Ok(())
+}
+
+pub fn current_mode() -> Mode {
+    STATE.get().borrow().mode
+}
+
+// whatever was on the screen is lost; it's up to the caller to redraw it
+pub fn set_mode(mode: Mode) -> Result<(), KError> {
+    let mut state = STATE.get().borrow_mut();
+    if state.mode == mode {
+        return Ok(());
+    }
+    if state.mode == Mode::Text80x25 {
+        save_font(&mut state)?;
+    }
+    write_registers(registers_for(mode));
+    match mode {
+        Mode::Text80x25 | Mode::Text80x50 => {
+            load_text_palette();
+            restore_font(&mut state)?;
+            if mode == Mode::Text80x50 {
+                load_small_font()?;
+            }
+        }
+        Mode::Graphics320x200 => load_graphics_palette()
+    }
+    state.mode = mode;
+    Ok(())
+}
+
+// mode 13h is a single 8-bit plane, one byte per pixel; colors index the DAC palette
+pub fn graphics_framebuffer() -> Result<Framebuffer, KError> {
+    if current_mode() != Mode::Graphics320x200 {
+        return Err(KError::IllegalOperation);
+    }
+    Framebuffer::new(GRAPHICS_BUFFER, GRAPHICS_WIDTH, GRAPHICS_HEIGHT, 8, GRAPHICS_WIDTH as usize)
+}
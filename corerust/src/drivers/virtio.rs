@@ -0,0 +1,246 @@
+use ::core;
+use ::core::ptr::{read_volatile, write_volatile};
+use ::core::sync::atomic::{fence, Ordering};
+use ::mantle::KError;
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::dma;
+use ::memory::dma::DmaRegion;
+use ::drivers::ioport::{IOPort, IOPortSet};
+
+// the legacy (pre-1.0) virtio PCI transport, where everything is in the I/O space of BAR 0, and split virtqueues
+
+pub const VENDOR_VIRTIO: u16 = 0x1AF4;
+
+// offsets into BAR 0
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+const REG_ISR: u16 = 0x13;
+const REG_CONFIG: u16 = 0x14; // device-specific; this is where it is as long as MSI-X is off
+
+pub const STATUS_ACKNOWLEDGE: u8 = 0x01;
+pub const STATUS_DRIVER: u8 = 0x02;
+pub const STATUS_DRIVER_OK: u8 = 0x04;
+pub const STATUS_FAILED: u8 = 0x80;
+
+const QUEUE_ALIGN: usize = PAGE_4K_SIZE; // fixed by the legacy interface, which only takes a page number
+
+pub struct LegacyDevice {
+    io: IOPortSet
+}
+
+impl LegacyDevice {
+    pub fn new(io: IOPortSet) -> LegacyDevice {
+        LegacyDevice { io }
+    }
+
+    fn reg(&self, offset: u16) -> IOPort {
+        self.io.get(offset)
+    }
+
+    pub fn reset(&self) {
+        self.reg(REG_STATUS).set(0);
+    }
+
+    pub fn status(&self) -> u8 {
+        self.reg(REG_STATUS).get()
+    }
+
+    pub fn add_status(&self, status: u8) {
+        let current = self.status();
+        self.reg(REG_STATUS).set(current | status);
+    }
+
+    pub fn device_features(&self) -> u32 {
+        self.reg(REG_DEVICE_FEATURES).get32()
+    }
+
+    // accepts whichever of the wanted features the device offers, and returns those
+    pub fn negotiate(&self, wanted: u32) -> u32 {
+        let features = self.device_features() & wanted;
+        self.reg(REG_GUEST_FEATURES).set32(features);
+        features
+    }
+
+    // acknowledges the interrupt; bit 0 means a queue was used, bit 1 means the configuration changed
+    pub fn read_isr(&self) -> u8 {
+        self.reg(REG_ISR).get()
+    }
+
+    pub fn config8(&self, offset: u16) -> u8 {
+        self.reg(REG_CONFIG + offset).get()
+    }
+
+    pub fn config32(&self, offset: u16) -> u32 {
+        self.reg(REG_CONFIG + offset).get32()
+    }
+
+    pub fn config64(&self, offset: u16) -> u64 {
+        (self.config32(offset) as u64) | ((self.config32(offset + 4) as u64) << 32)
+    }
+
+    fn select_queue(&self, index: u16) {
+        self.reg(REG_QUEUE_SELECT).set16(index);
+    }
+
+    pub fn notify(&self, index: u16) {
+        self.reg(REG_QUEUE_NOTIFY).set16(index);
+    }
+}
+
+const DESC_F_NEXT: u16 = 0x1;
+const DESC_F_WRITE: u16 = 0x2; // the device writes to this buffer, rather than reading from it
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16
+}
+
+// a buffer for a request, by physical address
+#[derive(Debug, Copy, Clone)]
+pub struct Buffer {
+    pub paddr: usize,
+    pub len: u32,
+    pub device_writes: bool
+}
+
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    region: DmaRegion,
+    used_offset: usize,
+    free_head: u16,
+    free_count: u16,
+    avail_idx: u16,
+    last_used: u16
+}
+
+impl Virtqueue {
+    // descriptor table, then the available ring, then the used ring on the next aligned boundary
+    fn layout(size: usize) -> (usize, usize) {
+        let align = |x: usize| (x + QUEUE_ALIGN - 1) & !(QUEUE_ALIGN - 1);
+        let used_offset = align(16 * size + 6 + 2 * size);
+        (used_offset, used_offset + align(6 + 8 * size))
+    }
+
+    pub fn new(device: &LegacyDevice, index: u16) -> Result<Virtqueue, KError> {
+        device.select_queue(index);
+        let size = device.reg(REG_QUEUE_SIZE).get16();
+        if size == 0 {
+            return Err(KError::FailedLookup); // no such queue
+        }
+        let (used_offset, total) = Virtqueue::layout(size as usize);
+        if total > dma::MAX_LEN {
+            return Err(KError::RangeError);
+        }
+        let region = dma::allocate_dma_region(total)?;
+        let mut queue = Virtqueue { index, size, region, used_offset, free_head: 0, free_count: size, avail_idx: 0, last_used: 0 };
+        for i in 0..size {
+            unsafe { write_volatile(&mut (*queue.descriptor(i)).next, i.wrapping_add(1)) };
+        }
+        device.reg(REG_QUEUE_PFN).set32((queue.region.get_paddr() / QUEUE_ALIGN) as u32);
+        Ok(queue)
+    }
+
+    // the device must have been reset first, so that it's no longer using the queue
+    pub fn free(self, device: &LegacyDevice) {
+        device.select_queue(self.index);
+        device.reg(REG_QUEUE_PFN).set32(0);
+        dma::free_dma_region(self.region);
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    fn descriptor(&self, i: u16) -> *mut Descriptor {
+        assert!(i < self.size);
+        (self.region.get_addr() + 16 * i as usize) as *mut Descriptor
+    }
+
+    fn avail_field(&self, offset: usize) -> *mut u16 {
+        (self.region.get_addr() + 16 * self.size as usize + offset) as *mut u16
+    }
+
+    fn used_field(&self, offset: usize) -> *mut u16 {
+        (self.region.get_addr() + self.used_offset + offset) as *mut u16
+    }
+
+    // places a chain of buffers on the available ring, returning the id that the device will report on completion.
+    // the device isn't told about it until it's notified.
+    pub fn submit(&mut self, buffers: &[Buffer]) -> Result<u16, KError> {
+        assert!(!buffers.is_empty());
+        if buffers.len() > self.free_count as usize {
+            return Err(KError::NotEnoughMemory);
+        }
+        let head = self.free_head;
+        let mut i = head;
+        for (n, buffer) in buffers.iter().enumerate() {
+            let last = n == buffers.len() - 1;
+            let desc = self.descriptor(i);
+            let flags = if last { 0 } else { DESC_F_NEXT } | if buffer.device_writes { DESC_F_WRITE } else { 0 };
+            unsafe {
+                write_volatile(&mut (*desc).addr, buffer.paddr as u64);
+                write_volatile(&mut (*desc).len, buffer.len);
+                write_volatile(&mut (*desc).flags, flags);
+                // free descriptors are already linked together, so next is left alone
+                let next = read_volatile(&(*desc).next);
+                if last {
+                    self.free_head = next;
+                } else {
+                    i = next;
+                }
+            }
+        }
+        self.free_count -= buffers.len() as u16;
+        unsafe {
+            write_volatile(self.avail_field(4 + 2 * (self.avail_idx % self.size) as usize), head);
+            // the ring entry has to be visible before the index that covers it
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(self.avail_field(2), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    // takes the next completed chain off the used ring, returning its id and how many bytes the device wrote
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        fence(Ordering::SeqCst);
+        let used_idx = unsafe { read_volatile(self.used_field(2)) };
+        if used_idx == self.last_used {
+            return None;
+        }
+        let elem = 4 + 8 * (self.last_used % self.size) as usize;
+        let (id, len) = unsafe {
+            (read_volatile(self.used_field(elem) as *mut u32) as u16, read_volatile(self.used_field(elem + 4) as *mut u32))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        // put the chain back at the front of the free list
+        let mut tail = id;
+        let mut count = 1;
+        unsafe {
+            while read_volatile(&(*self.descriptor(tail)).flags) & DESC_F_NEXT != 0 {
+                tail = read_volatile(&(*self.descriptor(tail)).next);
+                count += 1;
+            }
+            write_volatile(&mut (*self.descriptor(tail)).next, self.free_head);
+        }
+        self.free_head = id;
+        self.free_count += count;
+        Some((id, len))
+    }
+}
+
+impl core::fmt::Display for Virtqueue {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "queue {}: {} entries at {:#X}, {} free", self.index, self.size, self.region.get_paddr(), self.free_count)
+    }
+}
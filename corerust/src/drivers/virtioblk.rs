@@ -0,0 +1,339 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::core::ptr::{read_volatile, write_volatile};
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::dma;
+use ::memory::dma::DmaRegion;
+use ::drivers::irq::IRQ;
+use ::drivers::pci;
+use ::drivers::virtio;
+use ::drivers::virtio::{LegacyDevice, Virtqueue, Buffer};
+use ::drivers::driver::{Driver, Device, Match, Resources};
+use ::drivers::block;
+use ::drivers::block::{BlockDevice, DeviceId};
+use ::memory::Box;
+
+// block devices over the legacy virtio PCI transport. one request is in flight at a time, with data passing through a
+// bounce buffer, since callers' buffers aren't physically contiguous.
+
+pub const SECTOR_SIZE: usize = 512; // the unit of addressing, whatever the underlying block size
+pub const MAX_DISKS: usize = 4;
+const DISK_NAMES: [&'static str; MAX_DISKS] = ["vda", "vdb", "vdc", "vdd"];
+
+pub const DEVICE_BLOCK_LEGACY: u16 = 0x1001;
+
+const FEATURE_RO: u32 = 1 << 5;
+const FEATURE_FLUSH: u32 = 1 << 9;
+
+const CONFIG_CAPACITY: u16 = 0x00;
+
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+const REQUEST_FLUSH: u32 = 4;
+
+const RESULT_OK: u8 = 0;
+const RESULT_UNSUPPORTED: u8 = 2;
+
+const BOUNCE_LEN: usize = 16 * PAGE_4K_SIZE;
+const HEADER_LEN: u32 = 16;
+const STATUS_OFFSET: usize = 16; // in the scratch region, after the request header
+
+#[derive(Debug, Copy, Clone)]
+pub struct DiskInfo {
+    pub sectors: u64,
+    pub read_only: bool,
+    pub can_flush: bool
+}
+
+impl core::fmt::Display for DiskInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} sectors, {} MiB{}{}", self.sectors, self.sectors * SECTOR_SIZE as u64 / (1024 * 1024),
+               if self.read_only { ", read-only" } else { "" }, if self.can_flush { ", flush" } else { "" })
+    }
+}
+
+struct Disk {
+    device: LegacyDevice,
+    pci: pci::PciDevice,
+    queue: Virtqueue,
+    irq: IRQ<'static>,
+    // the request header and the status byte
+    scratch: DmaRegion,
+    bounce: DmaRegion,
+    info: DiskInfo,
+    block_id: Option<DeviceId>
+}
+
+impl Disk {
+    fn write_header(&mut self, kind: u32, sector: u64) {
+        let base = self.scratch.get_addr();
+        unsafe {
+            write_volatile(base as *mut u32, kind);
+            write_volatile((base + 4) as *mut u32, 0);
+            write_volatile((base + 8) as *mut u64, sector);
+            write_volatile((base + STATUS_OFFSET) as *mut u8, 0xFF);
+        }
+    }
+
+    // submits a request of header, optional data in the bounce buffer, and status, then waits for it to complete
+    fn request(&mut self, kind: u32, sector: u64, len: usize) -> Result<(), KError> {
+        self.write_header(kind, sector);
+        let header = Buffer { paddr: self.scratch.get_paddr(), len: HEADER_LEN, device_writes: false };
+        let data = Buffer { paddr: self.bounce.get_paddr(), len: len as u32, device_writes: kind == REQUEST_IN };
+        let status = Buffer { paddr: self.scratch.get_paddr() + STATUS_OFFSET, len: 1, device_writes: true };
+        let id = if len == 0 {
+            self.queue.submit(&[header, status])?
+        } else {
+            self.queue.submit(&[header, data, status])?
+        };
+        self.device.notify(self.queue.index());
+        loop {
+            if let Some((used, _)) = self.queue.pop_used() {
+                assert!(used == id);
+                break;
+            }
+            self.irq.wait();
+            self.device.read_isr();
+            self.irq.ack().unwrap();
+        }
+        match unsafe { read_volatile((self.scratch.get_addr() + STATUS_OFFSET) as *const u8) } {
+            RESULT_OK => Ok(()),
+            RESULT_UNSUPPORTED => Err(KError::IllegalOperation),
+            result => {
+                warn!("virtio-blk request {} at sector {} failed with status {}", kind, sector, result);
+                Err(KError::UnknownError)
+            }
+        }
+    }
+
+    fn bounce_slice(&mut self, len: usize) -> &mut [u8] {
+        assert!(len <= BOUNCE_LEN);
+        unsafe { core::slice::from_raw_parts_mut(self.bounce.get_ptr(), len) }
+    }
+
+    fn check_range(&self, sector: u64, len: usize) -> Result<(), KError> {
+        if len % SECTOR_SIZE != 0 {
+            return Err(KError::AlignmentError);
+        }
+        let count = (len / SECTOR_SIZE) as u64;
+        if sector >= self.info.sectors || count > self.info.sectors - sector {
+            return Err(KError::RangeError);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+        self.check_range(sector, buf.len())?;
+        for (i, chunk) in buf.chunks_mut(BOUNCE_LEN).enumerate() {
+            self.request(REQUEST_IN, sector + (i * BOUNCE_LEN / SECTOR_SIZE) as u64, chunk.len())?;
+            chunk.copy_from_slice(self.bounce_slice(chunk.len()));
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError> {
+        if self.info.read_only {
+            return Err(KError::IllegalOperation);
+        }
+        self.check_range(sector, buf.len())?;
+        for (i, chunk) in buf.chunks(BOUNCE_LEN).enumerate() {
+            self.bounce_slice(chunk.len()).copy_from_slice(chunk);
+            self.request(REQUEST_OUT, sector + (i * BOUNCE_LEN / SECTOR_SIZE) as u64, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), KError> {
+        if !self.info.can_flush {
+            return Ok(()); // without the feature, writes go through as they complete
+        }
+        self.request(REQUEST_FLUSH, 0, 0)
+    }
+
+    fn free(self) {
+        self.device.reset();
+        self.queue.free(&self.device);
+        self.irq.free();
+        dma::free_dma_region(self.scratch);
+        dma::free_dma_region(self.bounce);
+    }
+}
+
+static DISKS: SingleThreaded<RefCell<[Option<Disk>; MAX_DISKS]>> = SingleThreaded(RefCell::new([None, None, None, None]));
+
+fn with_disk<R, F: FnOnce(&mut Disk) -> Result<R, KError>>(disk: usize, f: F) -> Result<R, KError> {
+    if disk >= MAX_DISKS {
+        return Err(KError::InvalidArgument);
+    }
+    match DISKS.get().borrow_mut()[disk] {
+        Some(ref mut disk) => f(disk),
+        None => Err(KError::FailedLookup)
+    }
+}
+
+pub fn info(disk: usize) -> Option<DiskInfo> {
+    with_disk(disk, |d| Ok(d.info)).ok()
+}
+
+pub fn read_sectors(disk: usize, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+    with_disk(disk, |d| d.read(sector, buf))
+}
+
+pub fn write_sectors(disk: usize, sector: u64, buf: &[u8]) -> Result<(), KError> {
+    with_disk(disk, |d| d.write(sector, buf))
+}
+
+pub fn flush(disk: usize) -> Result<(), KError> {
+    with_disk(disk, |d| d.flush())
+}
+
+pub fn print_disks(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    for disk in 0..MAX_DISKS {
+        if let Some(info) = info(disk) {
+            writeln!(writer, "{}: {}", DISK_NAMES[disk], info)?;
+        }
+    }
+    Ok(())
+}
+
+pub struct VirtioDisk {
+    disk: usize,
+    sectors: u64
+}
+
+impl BlockDevice for VirtioDisk {
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+        read_sectors(self.disk, sector, buf)
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError> {
+        write_sectors(self.disk, sector, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), KError> {
+        flush(self.disk)
+    }
+}
+
+const VIRTIO_BLK_MATCHES: &'static [Match] = &[Match::PciId { vendor: virtio::VENDOR_VIRTIO, device: DEVICE_BLOCK_LEGACY }];
+
+pub struct VirtioBlkDriver;
+
+impl VirtioBlkDriver {
+    fn setup(pci: &pci::PciDevice, resources: &Resources) -> Result<Disk, KError> {
+        let device = match resources.ports(0) {
+            Some(io) => LegacyDevice::new(io),
+            None => return Err(KError::FailedLookup)
+        };
+        pci.enable(pci::COMMAND_IO | pci::COMMAND_BUS_MASTER);
+        device.reset();
+        device.add_status(virtio::STATUS_ACKNOWLEDGE | virtio::STATUS_DRIVER);
+        let features = device.negotiate(FEATURE_RO | FEATURE_FLUSH);
+        let info = DiskInfo {
+            sectors: device.config64(CONFIG_CAPACITY),
+            read_only: features & FEATURE_RO != 0,
+            can_flush: features & FEATURE_FLUSH != 0
+        };
+        let irq = resources.request_irq()?;
+        let queue = match Virtqueue::new(&device, 0) {
+            Ok(queue) => queue,
+            Err(err) => {
+                irq.free();
+                return Err(err);
+            }
+        };
+        let scratch = match dma::allocate_dma_region(PAGE_4K_SIZE) {
+            Ok(region) => region,
+            Err(err) => {
+                device.reset();
+                queue.free(&device);
+                irq.free();
+                return Err(err);
+            }
+        };
+        let bounce = match dma::allocate_dma_region(BOUNCE_LEN) {
+            Ok(region) => region,
+            Err(err) => {
+                device.reset();
+                queue.free(&device);
+                irq.free();
+                dma::free_dma_region(scratch);
+                return Err(err);
+            }
+        };
+        device.add_status(virtio::STATUS_DRIVER_OK);
+        Ok(Disk { device, pci: *pci, queue, irq, scratch, bounce, info, block_id: None })
+    }
+}
+
+impl Driver for VirtioBlkDriver {
+    fn name(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn matches(&self) -> &'static [Match] {
+        VIRTIO_BLK_MATCHES
+    }
+
+    fn probe(&mut self, _device: &Device, resources: &Resources) -> bool {
+        resources.ports(0).is_some() && resources.irq().is_some()
+    }
+
+    fn attach(&mut self, device: &Device, resources: &Resources) -> Result<(), KError> {
+        let pci = match *device {
+            Device::Pci(ref pci) => pci,
+            _ => return Err(KError::InvalidArgument)
+        };
+        let index = match DISKS.get().borrow().iter().position(|d| d.is_none()) {
+            Some(index) => index,
+            None => return Err(KError::NotEnoughMemory)
+        };
+        let disk = match VirtioBlkDriver::setup(pci, resources) {
+            Ok(disk) => disk,
+            Err(err) => {
+                if let Some(io) = resources.ports(0) {
+                    LegacyDevice::new(io).add_status(virtio::STATUS_FAILED);
+                }
+                return Err(err);
+            }
+        };
+        debug!("{}: {}", DISK_NAMES[index], disk.info);
+        let sectors = disk.info.sectors;
+        DISKS.get().borrow_mut()[index] = Some(disk);
+        match block::register(DISK_NAMES[index], Box::new(VirtioDisk { disk: index, sectors })) {
+            Ok(id) => DISKS.get().borrow_mut()[index].as_mut().unwrap().block_id = Some(id),
+            Err(err) => warn!("could not register {} as a block device: {:?}", DISK_NAMES[index], err)
+        }
+        Ok(())
+    }
+
+    fn detach(&mut self, device: &Device) -> Result<(), KError> {
+        let pci = match *device {
+            Device::Pci(ref pci) => pci,
+            _ => return Err(KError::InvalidArgument)
+        };
+        let found = DISKS.get().borrow().iter().position(|d| d.as_ref().map_or(false, |d| d.pci.address == pci.address));
+        let index = match found {
+            Some(index) => index,
+            None => return Err(KError::FailedLookup)
+        };
+        // done first, since it writes back whatever is still cached for the disk
+        let block_id = DISKS.get().borrow()[index].as_ref().unwrap().block_id;
+        if let Some(id) = block_id {
+            block::unregister(id)?;
+            DISKS.get().borrow_mut()[index].as_mut().unwrap().block_id = None;
+        }
+        DISKS.get().borrow_mut()[index].take().unwrap().free();
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use ::core;
+use ::mantle::KError;
+use ::fs::vfs::Kind;
+
+// ustar archives, as produced by `tar --format=ustar`. build.sh links one into init.elf from the bootmodules directory,
+// if there is one, which is how boot modules get to us.
+
+const HEADER_SIZE: usize = 512;
+const MAX_PATH_LEN: usize = 256;
+
+const NAME: usize = 0;
+const NAME_LEN: usize = 100;
+const SIZE: usize = 124;
+const CHECKSUM: usize = 148;
+const TYPE: usize = 156;
+const MAGIC: usize = 257;
+const PREFIX: usize = 345;
+const PREFIX_LEN: usize = 155;
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_FILE_OLD: u8 = 0;
+const TYPE_DIRECTORY: u8 = b'5';
+
+extern {
+    // defined by ld when it's given the archive with --format=binary
+    #[linkage = "extern_weak"]
+    static _binary_bootmodules_tar_start: *const u8;
+    #[linkage = "extern_weak"]
+    static _binary_bootmodules_tar_end: *const u8;
+}
+
+pub fn boot_archive() -> Option<&'static [u8]> {
+    let (start, end) = unsafe { (_binary_bootmodules_tar_start, _binary_bootmodules_tar_end) };
+    if start.is_null() || end.is_null() {
+        None
+    } else {
+        Some(unsafe { core::slice::from_raw_parts(start, end as usize - start as usize) })
+    }
+}
+
+fn octal(field: &[u8]) -> Result<usize, KError> {
+    let mut value = 0;
+    for &b in field.iter().skip_while(|&&b| b == b' ').take_while(|&&b| b != 0 && b != b' ') {
+        if b < b'0' || b > b'7' {
+            return Err(KError::InvalidArgument);
+        }
+        value = value * 8 + (b - b'0') as usize;
+    }
+    Ok(value)
+}
+
+fn field_str(field: &[u8]) -> &[u8] {
+    match field.iter().position(|&b| b == 0) {
+        Some(end) => &field[..end],
+        None => field
+    }
+}
+
+// calls back with the path, kind and contents of each file and directory, in the order they're in the archive.
+// paths are relative, without any leading ./ or trailing /. anything else, like links, is skipped.
+pub fn for_each<F: FnMut(&str, Kind, &'static [u8]) -> Result<(), KError>>(data: &'static [u8], mut f: F) -> Result<(), KError> {
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= data.len() {
+        let header = &data[offset..offset + HEADER_SIZE];
+        if header.iter().all(|&b| b == 0) {
+            return Ok(()); // the end, marked by empty blocks
+        }
+        if &header[MAGIC..MAGIC + 5] != b"ustar" {
+            return Err(KError::InvalidArgument);
+        }
+        let sum = header.iter().enumerate()
+            .map(|(i, &b)| if i >= CHECKSUM && i < CHECKSUM + 8 { b' ' as usize } else { b as usize }).sum::<usize>();
+        if octal(&header[CHECKSUM..CHECKSUM + 8])? != sum {
+            return Err(KError::InvalidArgument);
+        }
+        let size = octal(&header[SIZE..SIZE + 12])?;
+        let start = offset + HEADER_SIZE;
+        if start + size > data.len() {
+            return Err(KError::RangeError);
+        }
+        let mut path = [0u8; MAX_PATH_LEN];
+        let mut len = 0;
+        let prefix = field_str(&header[PREFIX..PREFIX + PREFIX_LEN]);
+        let name = field_str(&header[NAME..NAME + NAME_LEN]);
+        if !prefix.is_empty() {
+            path[..prefix.len()].copy_from_slice(prefix);
+            path[prefix.len()] = b'/';
+            len = prefix.len() + 1;
+        }
+        path[len..len + name.len()].copy_from_slice(name);
+        len += name.len();
+        let path = match core::str::from_utf8(&path[..len]) {
+            Ok(path) => path.trim_left_matches("./").trim_matches('/'),
+            Err(_) => return Err(KError::InvalidArgument)
+        };
+        let contents = &data[start..start + size];
+        match header[TYPE] {
+            TYPE_FILE | TYPE_FILE_OLD => f(path, Kind::File, contents)?,
+            TYPE_DIRECTORY if !path.is_empty() && path != "." => f(path, Kind::Directory, contents)?,
+            TYPE_DIRECTORY => {}
+            other => {
+                warn!("skipping {} in archive, which has type {:?}", path, other as char);
+            }
+        }
+        offset = start + (size + HEADER_SIZE - 1) / HEADER_SIZE * HEADER_SIZE;
+    }
+    Ok(())
+}
@@ -0,0 +1,1253 @@
+use ::core;
+use ::mantle::KError;
+use ::drivers::block;
+use ::drivers::block::{DeviceId, BLOCK_SIZE};
+use ::fs::vfs::{FileSystem, Inode, Kind, Stat};
+
+// FAT16 and FAT32 volumes, either covering a whole block device or in one of its MBR partitions. names are matched
+// without regard to ASCII case, and long file names are read and written. QEMU's `-drive file=fat:rw:dir` is FAT16
+// unless asked for `fat:32:rw:dir`. the FAT16 root directory has a fixed size, so it can fill up.
+
+const ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: usize = BLOCK_SIZE / ENTRY_SIZE;
+
+// the cluster count alone decides the type of a volume; anything smaller than FAT16 is FAT12, which we don't support
+const MIN_FAT16_CLUSTERS: u32 = 4085;
+const MIN_FAT32_CLUSTERS: u32 = 65525;
+// FAT16 entries are widened to their FAT32 equivalents when read, so that FAT_BAD and FAT_EOC cover both
+const FAT16_BAD: u32 = 0xFFF7;
+const FAT_MASK: u32 = 0x0FFFFFFF;
+const FAT_FREE: u32 = 0;
+const FAT_BAD: u32 = 0x0FFFFFF7;
+const FAT_EOC: u32 = 0x0FFFFFFF;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+const ATTR_LONG_NAME_MASK: u8 = 0x3F;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_LFN_ENTRIES: usize = 20;
+const MAX_NAME_CHARS: usize = 255;
+
+// case flags in the reserved byte of short entries, as written by Windows for names like "readme.txt"
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+const DATE_1980_01_01: u16 = 0x0021; // there's no clock to take the date from
+
+const FSINFO_LEAD_SIG: u32 = 0x41615252;
+const FSINFO_STRUCT_SIG: u32 = 0x61417272;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+
+const MBR_PARTITIONS: usize = 0x1BE;
+
+// how directories refer to the FAT16 root directory, which isn't in a cluster
+const FIXED_ROOT: u32 = 0;
+
+type Sector = [u8; BLOCK_SIZE];
+
+fn le16(b: &[u8], offset: usize) -> u16 {
+    (b[offset] as u16) | ((b[offset + 1] as u16) << 8)
+}
+
+fn le32(b: &[u8], offset: usize) -> u32 {
+    (le16(b, offset) as u32) | ((le16(b, offset + 2) as u32) << 16)
+}
+
+fn set_le16(b: &mut [u8], offset: usize, value: u16) {
+    b[offset] = value as u8;
+    b[offset + 1] = (value >> 8) as u8;
+}
+
+fn set_le32(b: &mut [u8], offset: usize, value: u32) {
+    set_le16(b, offset, value as u16);
+    set_le16(b, offset + 2, (value >> 16) as u16);
+}
+
+fn upper(b: u8) -> u8 {
+    if b >= b'a' && b <= b'z' { b - (b'a' - b'A') } else { b }
+}
+
+fn names_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).all(|(x, y)| upper(x) == upper(y))
+}
+
+fn short_name_checksum(name: &[u8]) -> u8 {
+    name[..11].iter().fold(0u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b))
+}
+
+// the short name a long name starts from, with how long its base is, and whether it's exactly the long name
+fn basis_name(name: &str) -> ([u8; 11], usize, bool) {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, "")
+    };
+    let valid = |b: u8| (b as u32) < 0x80 && b > b' ' && !b"\"*+,./:;<=>?[\\]|".contains(&b);
+    let exact = base.len() <= 8 && ext.len() <= 3 && base.bytes().chain(ext.bytes()).all(|b| valid(b) && upper(b) == b);
+    let mut short = [b' '; 11];
+    let mut base_len = 0;
+    for b in base.bytes().filter(|&b| valid(b)).take(8) {
+        short[base_len] = upper(b);
+        base_len += 1;
+    }
+    for (i, b) in ext.bytes().filter(|&b| valid(b)).take(3).enumerate() {
+        short[8 + i] = upper(b);
+    }
+    if base_len == 0 {
+        short[0] = b'_';
+        base_len = 1;
+    }
+    (short, base_len, exact)
+}
+
+// such as FOOBAR~1, cutting the base short to make room for the tail
+fn with_numeric_tail(short: &[u8; 11], base_len: usize, n: u32) -> [u8; 11] {
+    let mut tail = [0u8; 7];
+    let mut tail_len = 0;
+    let mut digits = n;
+    while digits > 0 {
+        tail[tail.len() - 1 - tail_len] = b'0' + (digits % 10) as u8;
+        digits /= 10;
+        tail_len += 1;
+    }
+    let prefix = core::cmp::min(base_len, 8 - 1 - tail_len);
+    let mut candidate = *short;
+    candidate[prefix] = b'~';
+    candidate[prefix + 1..prefix + 1 + tail_len].copy_from_slice(&tail[tail.len() - tail_len..]);
+    for b in candidate[prefix + 1 + tail_len..8].iter_mut() {
+        *b = b' ';
+    }
+    candidate
+}
+
+// a name decoded from a directory, as UTF-8
+#[derive(Copy, Clone)]
+pub struct Name {
+    bytes: [u8; MAX_NAME_CHARS * 3],
+    len: usize
+}
+
+impl Name {
+    fn empty() -> Name {
+        Name { bytes: [0; MAX_NAME_CHARS * 3], len: 0 }
+    }
+
+    fn push(&mut self, c: char) {
+        let mut encoded = [0u8; 4];
+        let encoded = c.encode_utf8(&mut encoded).as_bytes();
+        if self.len + encoded.len() <= self.bytes.len() {
+            self.bytes[self.len..self.len + encoded.len()].copy_from_slice(encoded);
+            self.len += encoded.len();
+        }
+    }
+
+    fn from_short(entry: &[u8]) -> Name {
+        let mut name = Name::empty();
+        let ntres = entry[12];
+        let base_end = entry[..8].iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        let ext_end = entry[8..11].iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        for (i, &b) in entry[..base_end].iter().enumerate() {
+            // 0x05 stands in for an initial 0xE5, which would mark the entry deleted
+            let b = if i == 0 && b == 0x05 { 0xE5 } else { b };
+            name.push_short_byte(b, ntres & NTRES_LOWER_BASE != 0);
+        }
+        if ext_end > 0 {
+            name.push('.');
+            for &b in &entry[8..8 + ext_end] {
+                name.push_short_byte(b, ntres & NTRES_LOWER_EXT != 0);
+            }
+        }
+        name
+    }
+
+    fn push_short_byte(&mut self, b: u8, lower: bool) {
+        if b >= 0x80 {
+            self.push('?'); // an OEM code page we don't know
+        } else if lower && b >= b'A' && b <= b'Z' {
+            self.push((b + (b'a' - b'A')) as char);
+        } else {
+            self.push(b as char);
+        }
+    }
+
+    fn from_ucs2(chars: &[u16]) -> Name {
+        let mut name = Name::empty();
+        for &c in chars.iter().take_while(|&&c| c != 0x0000 && c != 0xFFFF) {
+            name.push(core::char::from_u32(c as u32).unwrap_or('?'));
+        }
+        name
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+// where a node's short entry is, so that its size and first cluster can be updated
+#[derive(Copy, Clone)]
+struct Location {
+    dir: u32,
+    index: u32,
+    lfn_count: u32
+}
+
+#[derive(Copy, Clone)]
+pub struct Node {
+    pub cluster: u32, // zero for an empty file
+    pub size: u32,
+    pub is_dir: bool,
+    location: Option<Location> // None for the root directory
+}
+
+impl Node {
+    fn from_entry(raw: &[u8], location: Location) -> Node {
+        Node {
+            cluster: ((le16(raw, 20) as u32) << 16) | le16(raw, 26) as u32,
+            size: le32(raw, 28),
+            is_dir: raw[11] & ATTR_DIRECTORY != 0,
+            location: Some(location)
+        }
+    }
+}
+
+pub struct DirEntry {
+    name: Name,
+    pub node: Node
+}
+
+impl DirEntry {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+}
+
+// long name fragments, collected from the entries that come before a short entry
+struct LongName {
+    chars: [u16; MAX_LFN_ENTRIES * LFN_CHARS],
+    expected: u8, // sequence number of the next fragment, or zero once complete
+    entries: u32,
+    checksum: u8,
+    valid: bool
+}
+
+impl LongName {
+    fn new() -> LongName {
+        LongName { chars: [0; MAX_LFN_ENTRIES * LFN_CHARS], expected: 0, entries: 0, checksum: 0, valid: false }
+    }
+
+    fn reset(&mut self) {
+        self.valid = false;
+        self.entries = 0;
+    }
+
+    fn add(&mut self, entry: &[u8]) {
+        let seq = entry[0] & 0x1F;
+        if entry[0] & LFN_LAST != 0 {
+            self.valid = seq >= 1 && seq as usize <= MAX_LFN_ENTRIES;
+            self.checksum = entry[13];
+            self.entries = 0;
+            for c in self.chars.iter_mut() {
+                *c = 0;
+            }
+        } else if !self.valid || seq == 0 || seq != self.expected || entry[13] != self.checksum {
+            self.valid = false;
+        }
+        self.entries += 1;
+        if self.valid {
+            let base = (seq as usize - 1) * LFN_CHARS;
+            for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                self.chars[base + i] = le16(entry, offset);
+            }
+            self.expected = seq - 1;
+        }
+    }
+
+    fn matches(&self, short: &[u8]) -> bool {
+        self.valid && self.expected == 0 && self.checksum == short_name_checksum(short)
+    }
+}
+
+pub struct FatFs {
+    device: DeviceId,
+    sectors_per_cluster: u32,
+    fat_start: u64,
+    fat_sectors: u32,
+    fat_count: u32,
+    data_start: u64,
+    cluster_count: u32,
+    fat16: bool,
+    root_cluster: u32, // FIXED_ROOT on FAT16
+    // the FAT16 root directory, between the FATs and the clusters; no sectors on FAT32
+    root_start: u64,
+    root_sectors: u32,
+    fsinfo: Option<u64>,
+    next_free: u32
+}
+
+impl FatFs {
+    // looks for a volume at the start of the device, then in each MBR partition
+    pub fn mount(device: DeviceId) -> Result<FatFs, KError> {
+        let mut sector: Sector = [0; BLOCK_SIZE];
+        block::read(device, 0, &mut sector)?;
+        if let Ok(fs) = FatFs::from_boot_sector(device, 0, &sector) {
+            return Ok(fs);
+        }
+        if le16(&sector, 510) != 0xAA55 {
+            return Err(KError::InvalidArgument);
+        }
+        for i in 0..4 {
+            let entry = MBR_PARTITIONS + i * 16;
+            let start = le32(&sector, entry + 8) as u64;
+            if sector[entry + 4] == 0 || start == 0 {
+                continue;
+            }
+            let mut boot: Sector = [0; BLOCK_SIZE];
+            block::read(device, start, &mut boot)?;
+            if let Ok(fs) = FatFs::from_boot_sector(device, start, &boot) {
+                return Ok(fs);
+            }
+        }
+        Err(KError::InvalidArgument)
+    }
+
+    fn from_boot_sector(device: DeviceId, start: u64, boot: &Sector) -> Result<FatFs, KError> {
+        let bytes_per_sector = le16(boot, 11) as usize;
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved = le16(boot, 14) as u32;
+        let fat_count = boot[16] as u32;
+        let root_entries = le16(boot, 17) as u32;
+        let fat_sectors = if le16(boot, 22) != 0 { le16(boot, 22) as u32 } else { le32(boot, 36) };
+        let total = if le16(boot, 19) != 0 { le16(boot, 19) as u32 } else { le32(boot, 32) };
+        if le16(boot, 510) != 0xAA55 || bytes_per_sector != BLOCK_SIZE || !sectors_per_cluster.is_power_of_two()
+            || fat_count == 0 || reserved == 0 || fat_sectors == 0 {
+            return Err(KError::InvalidArgument); // not FAT, or not something we can use
+        }
+        let root_sectors = (root_entries * ENTRY_SIZE as u32 + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32;
+        let root_offset = reserved + fat_count * fat_sectors;
+        let data_offset = root_offset + root_sectors;
+        if total <= data_offset {
+            return Err(KError::InvalidArgument);
+        }
+        let cluster_count = (total - data_offset) / sectors_per_cluster;
+        let fat16 = cluster_count < MIN_FAT32_CLUSTERS;
+        // FAT32 has its root directory in a cluster, and FAT16 has no room for the FAT32 fields
+        if cluster_count < MIN_FAT16_CLUSTERS || fat16 != (root_entries != 0) || fat16 != (le16(boot, 22) != 0) {
+            return Err(KError::InvalidArgument);
+        }
+        let fsinfo = match le16(boot, 48) {
+            0 | 0xFFFF => None,
+            _ if fat16 => None, // the field isn't there
+            n => Some(start + n as u64)
+        };
+        let mut fs = FatFs {
+            device, sectors_per_cluster, fat_start: start + reserved as u64, fat_sectors, fat_count,
+            data_start: start + data_offset as u64, cluster_count, fat16,
+            root_cluster: if fat16 { FIXED_ROOT } else { le32(boot, 44) }, root_start: start + root_offset as u64,
+            root_sectors, fsinfo, next_free: 2
+        };
+        if let Some(sector) = fs.fsinfo {
+            let mut info: Sector = [0; BLOCK_SIZE];
+            block::read(device, sector, &mut info)?;
+            if le32(&info, 0) == FSINFO_LEAD_SIG && le32(&info, 484) == FSINFO_STRUCT_SIG {
+                let hint = le32(&info, FSINFO_NEXT_FREE);
+                if fs.is_cluster(hint) {
+                    fs.next_free = hint;
+                }
+            } else {
+                fs.fsinfo = None;
+            }
+        }
+        if !fs.fat16 && !fs.is_cluster(fs.root_cluster) {
+            return Err(KError::InvalidArgument);
+        }
+        Ok(fs)
+    }
+
+    pub fn root(&self) -> Node {
+        Node { cluster: self.root_cluster, size: 0, is_dir: true, location: None }
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * BLOCK_SIZE
+    }
+
+    fn is_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    fn is_fixed_root(&self, dir: u32) -> bool {
+        self.fat16 && dir == FIXED_ROOT
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        assert!(self.is_cluster(cluster));
+        self.data_start + (cluster - 2) as u64 * self.sectors_per_cluster as u64
+    }
+
+    fn fat_position(&self, cluster: u32) -> (u64, usize) {
+        let offset = cluster as usize * if self.fat16 { 2 } else { 4 };
+        (self.fat_start + (offset / BLOCK_SIZE) as u64, offset % BLOCK_SIZE)
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Result<u32, KError> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf: Sector = [0; BLOCK_SIZE];
+        block::read(self.device, sector, &mut buf)?;
+        if self.fat16 {
+            let entry = le16(&buf, offset) as u32;
+            Ok(if entry >= FAT16_BAD { entry | (FAT_MASK & !0xFFFF) } else { entry })
+        } else {
+            Ok(le32(&buf, offset) & FAT_MASK)
+        }
+    }
+
+    // every copy of the FAT is kept the same
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), KError> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf: Sector = [0; BLOCK_SIZE];
+        for copy in 0..self.fat_count {
+            let sector = sector + (copy * self.fat_sectors) as u64;
+            block::read(self.device, sector, &mut buf)?;
+            if self.fat16 {
+                set_le16(&mut buf, offset, value as u16);
+            } else {
+                let reserved_bits = le32(&buf, offset) & !FAT_MASK;
+                set_le32(&mut buf, offset, reserved_bits | (value & FAT_MASK));
+            }
+            block::write(self.device, sector, &buf)?;
+        }
+        Ok(())
+    }
+
+    // None at the end of the chain
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, KError> {
+        let next = self.fat_entry(cluster)?;
+        if next > FAT_BAD {
+            Ok(None)
+        } else if self.is_cluster(next) {
+            Ok(Some(next))
+        } else {
+            warn!("corrupt FAT: cluster {} links to {:#X}", cluster, next);
+            Err(KError::UnknownError)
+        }
+    }
+
+    fn nth_cluster(&self, first: u32, n: u32) -> Result<u32, KError> {
+        let mut cluster = first;
+        for _ in 0..n {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => return Err(KError::RangeError)
+            };
+        }
+        Ok(cluster)
+    }
+
+    fn chain_length(&self, first: u32) -> Result<u32, KError> {
+        let mut count = 1;
+        let mut cluster = first;
+        while let Some(next) = self.next_cluster(cluster)? {
+            cluster = next;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<(), KError> {
+        let zeros: Sector = [0; BLOCK_SIZE];
+        let first = self.cluster_sector(cluster);
+        for s in 0..self.sectors_per_cluster as u64 {
+            block::write(self.device, first + s, &zeros)?;
+        }
+        Ok(())
+    }
+
+    // allocates a cluster at the end of a chain, linked after `previous` if given
+    fn allocate_cluster(&mut self, previous: Option<u32>, zero: bool) -> Result<u32, KError> {
+        let mut cluster = self.next_free;
+        for _ in 0..self.cluster_count {
+            if !self.is_cluster(cluster) {
+                cluster = 2;
+            }
+            if self.fat_entry(cluster)? == FAT_FREE {
+                self.set_fat_entry(cluster, FAT_EOC)?;
+                if let Some(previous) = previous {
+                    self.set_fat_entry(previous, cluster)?;
+                }
+                if zero {
+                    self.zero_cluster(cluster)?;
+                }
+                self.next_free = cluster + 1;
+                return Ok(cluster);
+            }
+            cluster += 1;
+        }
+        Err(KError::NotEnoughMemory)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), KError> {
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, FAT_FREE)?;
+        }
+        Ok(())
+    }
+
+    // visits every raw entry in the directory's clusters, including free ones, until the callback returns true
+    fn scan_raw<F: FnMut(u32, &[u8]) -> Result<bool, KError>>(&self, dir: u32, mut f: F) -> Result<(), KError> {
+        let mut buf: Sector = [0; BLOCK_SIZE];
+        let mut index = 0;
+        if self.is_fixed_root(dir) {
+            for s in 0..self.root_sectors as u64 {
+                block::read(self.device, self.root_start + s, &mut buf)?;
+                for entry in buf.chunks(ENTRY_SIZE) {
+                    if f(index, entry)? {
+                        return Ok(());
+                    }
+                    index += 1;
+                }
+            }
+            return Ok(());
+        }
+        let mut cluster = dir;
+        loop {
+            let first = self.cluster_sector(cluster);
+            for s in 0..self.sectors_per_cluster as u64 {
+                block::read(self.device, first + s, &mut buf)?;
+                for entry in buf.chunks(ENTRY_SIZE) {
+                    if f(index, entry)? {
+                        return Ok(());
+                    }
+                    index += 1;
+                }
+            }
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => return Ok(())
+            };
+        }
+    }
+
+    // visits each file and directory in the directory, apart from . and .., until the callback returns true
+    fn scan<F: FnMut(&DirEntry) -> bool>(&self, dir: &Node, mut f: F) -> Result<(), KError> {
+        if !dir.is_dir {
+            return Err(KError::IllegalOperation);
+        }
+        let mut long_name = LongName::new();
+        self.scan_raw(dir.cluster, |index, raw| {
+            if raw[0] == ENTRY_END {
+                return Ok(true);
+            }
+            if raw[0] == ENTRY_DELETED {
+                long_name.reset();
+                return Ok(false);
+            }
+            let attr = raw[11];
+            if attr & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+                long_name.add(raw);
+                return Ok(false);
+            }
+            if attr & ATTR_VOLUME_ID != 0 || raw[0] == b'.' {
+                long_name.reset();
+                return Ok(false);
+            }
+            let (name, lfn_count) = if long_name.matches(raw) {
+                (Name::from_ucs2(&long_name.chars), long_name.entries)
+            } else {
+                (Name::from_short(raw), 0)
+            };
+            long_name.reset();
+            let entry = DirEntry { name, node: Node::from_entry(raw, Location { dir: dir.cluster, index, lfn_count }) };
+            Ok(f(&entry))
+        })
+    }
+
+    pub fn read_dir<F: FnMut(&DirEntry)>(&self, dir: &Node, mut f: F) -> Result<(), KError> {
+        self.scan(dir, |entry| {
+            f(entry);
+            false
+        })
+    }
+
+    pub fn find(&self, dir: &Node, name: &str) -> Result<Node, KError> {
+        let mut found = None;
+        self.scan(dir, |entry| {
+            if names_equal(entry.name(), name) {
+                found = Some(entry.node);
+            }
+            found.is_some()
+        })?;
+        found.ok_or(KError::FailedLookup)
+    }
+
+    pub fn lookup(&self, path: &str) -> Result<Node, KError> {
+        let mut node = self.root();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = self.find(&node, component)?;
+        }
+        Ok(node)
+    }
+
+    fn entry_position(&self, dir: u32, index: u32) -> Result<(u64, usize), KError> {
+        if self.is_fixed_root(dir) {
+            if index >= self.root_sectors * ENTRIES_PER_SECTOR as u32 {
+                return Err(KError::RangeError);
+            }
+            let index = index as usize;
+            return Ok((self.root_start + (index / ENTRIES_PER_SECTOR) as u64, (index % ENTRIES_PER_SECTOR) * ENTRY_SIZE));
+        }
+        let per_cluster = self.sectors_per_cluster * ENTRIES_PER_SECTOR as u32;
+        let cluster = self.nth_cluster(dir, index / per_cluster)?;
+        let within = (index % per_cluster) as usize;
+        Ok((self.cluster_sector(cluster) + (within / ENTRIES_PER_SECTOR) as u64, (within % ENTRIES_PER_SECTOR) * ENTRY_SIZE))
+    }
+
+    fn modify_entry<F: FnOnce(&mut [u8])>(&self, dir: u32, index: u32, f: F) -> Result<(), KError> {
+        let (sector, offset) = self.entry_position(dir, index)?;
+        let mut buf: Sector = [0; BLOCK_SIZE];
+        block::read(self.device, sector, &mut buf)?;
+        f(&mut buf[offset..offset + ENTRY_SIZE]);
+        block::write(self.device, sector, &buf)
+    }
+
+    fn update_entry(&self, node: &Node) -> Result<(), KError> {
+        match node.location {
+            Some(location) => self.modify_entry(location.dir, location.index, |raw| {
+                set_le16(raw, 20, (node.cluster >> 16) as u16);
+                set_le16(raw, 26, node.cluster as u16);
+                set_le32(raw, 28, if node.is_dir { 0 } else { node.size });
+            }),
+            None => Ok(()) // the root directory has no entry
+        }
+    }
+
+    // reads from the given offset, returning how much was read, which is less than asked for at the end of the file
+    pub fn read(&self, node: &Node, offset: u32, buf: &mut [u8]) -> Result<usize, KError> {
+        if node.is_dir {
+            return Err(KError::IllegalOperation);
+        }
+        if offset >= node.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = core::cmp::min(buf.len(), (node.size - offset) as usize);
+        let cluster_size = self.cluster_size();
+        let mut cluster = self.nth_cluster(node.cluster, offset / cluster_size as u32)?;
+        let mut pos = offset as usize;
+        let mut done = 0;
+        let mut sector: Sector = [0; BLOCK_SIZE];
+        while done < len {
+            let within = pos % cluster_size;
+            if within == 0 && done > 0 {
+                cluster = match self.next_cluster(cluster)? {
+                    Some(next) => next,
+                    None => break // the chain is shorter than the size says
+                };
+            }
+            block::read(self.device, self.cluster_sector(cluster) + (within / BLOCK_SIZE) as u64, &mut sector)?;
+            let start = within % BLOCK_SIZE;
+            let n = core::cmp::min(BLOCK_SIZE - start, len - done);
+            buf[done..done + n].copy_from_slice(&sector[start..start + n]);
+            done += n;
+            pos += n;
+        }
+        Ok(done)
+    }
+
+    // writes at the given offset, which may extend the file but not leave a gap after its current end
+    pub fn write(&mut self, node: &mut Node, offset: u32, data: &[u8]) -> Result<usize, KError> {
+        if node.is_dir {
+            return Err(KError::IllegalOperation);
+        }
+        if offset > node.size || offset as u64 + data.len() as u64 > core::u32::MAX as u64 {
+            return Err(KError::RangeError);
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let cluster_size = self.cluster_size();
+        let end = offset as usize + data.len();
+        let needed = ((end + cluster_size - 1) / cluster_size) as u32;
+        // grow the chain first, so that a lack of space is found before anything is written
+        let (mut have, mut last) = if node.cluster == 0 {
+            (0, None)
+        } else {
+            let have = self.chain_length(node.cluster)?;
+            (have, Some(self.nth_cluster(node.cluster, have - 1)?))
+        };
+        while have < needed {
+            let cluster = self.allocate_cluster(last, false)?;
+            if node.cluster == 0 {
+                node.cluster = cluster;
+                self.update_entry(node)?;
+            }
+            last = Some(cluster);
+            have += 1;
+        }
+        let mut cluster = self.nth_cluster(node.cluster, offset / cluster_size as u32)?;
+        let mut pos = offset as usize;
+        let mut done = 0;
+        let mut sector: Sector = [0; BLOCK_SIZE];
+        while done < data.len() {
+            let within = pos % cluster_size;
+            if within == 0 && done > 0 {
+                cluster = self.next_cluster(cluster)?.unwrap();
+            }
+            let lba = self.cluster_sector(cluster) + (within / BLOCK_SIZE) as u64;
+            let start = within % BLOCK_SIZE;
+            let n = core::cmp::min(BLOCK_SIZE - start, data.len() - done);
+            if n < BLOCK_SIZE {
+                block::read(self.device, lba, &mut sector)?;
+            }
+            sector[start..start + n].copy_from_slice(&data[done..done + n]);
+            block::write(self.device, lba, &sector)?;
+            done += n;
+            pos += n;
+        }
+        if end as u32 > node.size {
+            node.size = end as u32;
+            self.update_entry(node)?;
+        }
+        Ok(done)
+    }
+
+    // shortens a file, freeing any clusters it no longer needs
+    pub fn truncate(&mut self, node: &mut Node, len: u32) -> Result<(), KError> {
+        if node.is_dir {
+            return Err(KError::IllegalOperation);
+        }
+        if len >= node.size {
+            return Ok(());
+        }
+        let keep = (len as usize + self.cluster_size() - 1) / self.cluster_size();
+        if node.cluster != 0 {
+            if keep == 0 {
+                self.free_chain(node.cluster)?;
+                node.cluster = 0;
+            } else {
+                let last = self.nth_cluster(node.cluster, keep as u32 - 1)?;
+                if let Some(rest) = self.next_cluster(last)? {
+                    self.set_fat_entry(last, FAT_EOC)?;
+                    self.free_chain(rest)?;
+                }
+            }
+        }
+        node.size = len;
+        self.update_entry(node)
+    }
+
+    // a short name that isn't already in the directory, with a numeric tail if the long name doesn't fit 8.3 as-is
+    fn make_short_name(&self, dir: &Node, name: &str) -> Result<([u8; 11], bool), KError> {
+        let (short, base_len, exact) = basis_name(name);
+        if exact {
+            return Ok((short, false));
+        }
+        for n in 1..1000000u32 {
+            let candidate = with_numeric_tail(&short, base_len, n);
+            let mut taken = false;
+            self.scan_raw(dir.cluster, |_, raw| {
+                if raw[0] == ENTRY_END {
+                    return Ok(true);
+                }
+                taken = raw[0] != ENTRY_DELETED && raw[..11] == candidate[..];
+                Ok(taken)
+            })?;
+            if !taken {
+                return Ok((candidate, true));
+            }
+        }
+        Err(KError::NotEnoughMemory)
+    }
+
+    // finds room for `count` consecutive entries, extending the directory if it has to
+    fn find_free_entries(&mut self, dir: &Node, count: u32) -> Result<u32, KError> {
+        let mut run_start = 0;
+        let mut run_len = 0;
+        let mut total = 0;
+        self.scan_raw(dir.cluster, |index, raw| {
+            total = index + 1;
+            if raw[0] == ENTRY_END || raw[0] == ENTRY_DELETED {
+                if run_len == 0 {
+                    run_start = index;
+                }
+                run_len += 1;
+            } else {
+                run_len = 0;
+            }
+            Ok(run_len == count)
+        })?;
+        if run_len == count {
+            return Ok(run_start);
+        }
+        if self.is_fixed_root(dir.cluster) {
+            return Err(KError::NotEnoughMemory); // it can't grow
+        }
+        // any free run left at the end of the directory carries on into the new clusters
+        if run_len == 0 {
+            run_start = total;
+        }
+        let per_cluster = self.sectors_per_cluster * ENTRIES_PER_SECTOR as u32;
+        let mut last = self.nth_cluster(dir.cluster, total / per_cluster - 1)?;
+        let mut have = run_len;
+        while have < count {
+            last = self.allocate_cluster(Some(last), true)?;
+            have += per_cluster;
+        }
+        Ok(run_start)
+    }
+
+    fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name != "." && name != ".." && name.chars().count() <= MAX_NAME_CHARS
+            && name.chars().all(|c| c >= ' ' && (c as u32) < 0x10000 && !"\"*/:<>?\\|".contains(c))
+    }
+
+    pub fn create(&mut self, dir: &Node, name: &str, is_dir: bool) -> Result<Node, KError> {
+        if !FatFs::is_valid_name(name) {
+            return Err(KError::InvalidArgument);
+        }
+        match self.find(dir, name) {
+            Ok(_) => return Err(KError::IllegalOperation), // already exists
+            Err(KError::FailedLookup) => {}
+            Err(err) => return Err(err)
+        }
+        let (short, needs_lfn) = self.make_short_name(dir, name)?;
+        let name_chars = name.chars().count();
+        let lfn_count = (if needs_lfn { (name_chars + LFN_CHARS - 1) / LFN_CHARS } else { 0 }) as u32;
+        let first = self.find_free_entries(dir, lfn_count + 1)?;
+        let cluster = if is_dir { self.allocate_cluster(None, true)? } else { 0 };
+        let checksum = short_name_checksum(&short);
+        let mut ucs2 = [0xFFFFu16; MAX_LFN_ENTRIES * LFN_CHARS];
+        for (i, c) in name.chars().enumerate() {
+            ucs2[i] = c as u32 as u16;
+        }
+        if name_chars < ucs2.len() {
+            ucs2[name_chars] = 0x0000;
+        }
+        // long name entries come last fragment first
+        for k in 0..lfn_count {
+            let seq = (lfn_count - k) as usize;
+            self.modify_entry(dir.cluster, first + k, |raw| {
+                for b in raw.iter_mut() {
+                    *b = 0;
+                }
+                raw[0] = seq as u8 | if k == 0 { LFN_LAST } else { 0 };
+                raw[11] = ATTR_LONG_NAME;
+                raw[13] = checksum;
+                for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                    set_le16(raw, offset, ucs2[(seq - 1) * LFN_CHARS + i]);
+                }
+            })?;
+        }
+        let index = first + lfn_count;
+        self.modify_entry(dir.cluster, index, |raw| {
+            for b in raw.iter_mut() {
+                *b = 0;
+            }
+            raw[..11].copy_from_slice(&short);
+            raw[11] = if is_dir { ATTR_DIRECTORY } else { ATTR_ARCHIVE };
+            set_le16(raw, 16, DATE_1980_01_01); // created
+            set_le16(raw, 18, DATE_1980_01_01); // accessed
+            set_le16(raw, 24, DATE_1980_01_01); // written
+            set_le16(raw, 20, (cluster >> 16) as u16);
+            set_le16(raw, 26, cluster as u16);
+        })?;
+        if is_dir {
+            // the parent is recorded as cluster zero when it's the root
+            let parent = if dir.location.is_none() { 0 } else { dir.cluster };
+            for &(index, name, target) in &[(0, b".          ", cluster), (1, b"..         ", parent)] {
+                self.modify_entry(cluster, index, |raw| {
+                    raw[..11].copy_from_slice(name);
+                    raw[11] = ATTR_DIRECTORY;
+                    set_le16(raw, 24, DATE_1980_01_01);
+                    set_le16(raw, 20, (target >> 16) as u16);
+                    set_le16(raw, 26, target as u16);
+                })?;
+            }
+        }
+        Ok(Node { cluster, size: 0, is_dir, location: Some(Location { dir: dir.cluster, index, lfn_count }) })
+    }
+
+    pub fn remove(&mut self, dir: &Node, name: &str) -> Result<(), KError> {
+        let node = self.find(dir, name)?;
+        let location = node.location.unwrap();
+        if node.is_dir {
+            let mut empty = true;
+            self.scan(&node, |_| {
+                empty = false;
+                true
+            })?;
+            if !empty {
+                return Err(KError::DeleteFirst);
+            }
+        }
+        for index in location.index - location.lfn_count..location.index + 1 {
+            self.modify_entry(location.dir, index, |raw| raw[0] = ENTRY_DELETED)?;
+        }
+        if node.cluster != 0 {
+            self.free_chain(node.cluster)?;
+        }
+        Ok(())
+    }
+
+    // writes out the allocation hint and everything the block cache holds for the volume
+    pub fn sync(&mut self) -> Result<(), KError> {
+        if let Some(sector) = self.fsinfo {
+            let mut info: Sector = [0; BLOCK_SIZE];
+            block::read(self.device, sector, &mut info)?;
+            set_le32(&mut info, FSINFO_FREE_COUNT, 0xFFFFFFFF); // not tracked, so marked unknown
+            set_le32(&mut info, FSINFO_NEXT_FREE, self.next_free);
+            block::write(self.device, sector, &info)?;
+        }
+        block::sync(self.device)
+    }
+}
+
+impl core::fmt::Display for FatFs {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "FAT{} volume with {} clusters of {} bytes, {} FATs", if self.fat16 { 16 } else { 32 }, self.cluster_count, self.cluster_size(), self.fat_count)
+    }
+}
+
+// inodes are where the short entry is: the directory's first cluster in the upper half and one more than the entry's
+// index in the lower half, with zero for the root directory, which has no entry. the FAT16 root directory is FIXED_ROOT.
+const ROOT_INODE: Inode = 0;
+
+impl FatFs {
+    fn inode(node: &Node) -> Inode {
+        match node.location {
+            Some(location) => ((location.dir as u64) << 32) | (location.index as u64 + 1),
+            None => ROOT_INODE
+        }
+    }
+
+    fn node(&self, inode: Inode) -> Result<Node, KError> {
+        if inode == ROOT_INODE {
+            return Ok(self.root());
+        }
+        let (dir, index) = ((inode >> 32) as u32, inode as u32);
+        if index == 0 || !(self.is_cluster(dir) || self.is_fixed_root(dir)) {
+            return Err(KError::InvalidArgument);
+        }
+        let index = index - 1;
+        let (sector, offset) = self.entry_position(dir, index)?;
+        let mut buf: Sector = [0; BLOCK_SIZE];
+        block::read(self.device, sector, &mut buf)?;
+        let raw = &buf[offset..offset + ENTRY_SIZE];
+        if raw[0] == ENTRY_END || raw[0] == ENTRY_DELETED || raw[11] & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+            return Err(KError::FailedLookup); // removed since it was looked up
+        }
+        // the long name entries are only needed for removal, which goes by name
+        Ok(Node::from_entry(raw, Location { dir, index, lfn_count: 0 }))
+    }
+
+    fn offset(offset: u64) -> Result<u32, KError> {
+        if offset > core::u32::MAX as u64 {
+            Err(KError::RangeError)
+        } else {
+            Ok(offset as u32)
+        }
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &'static str {
+        "fat"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, KError> {
+        let node = self.node(inode)?;
+        Ok(Stat { kind: if node.is_dir { Kind::Directory } else { Kind::File }, size: node.size as u64 })
+    }
+
+    fn lookup(&mut self, dir: Inode, name: &str) -> Result<Inode, KError> {
+        let dir = self.node(dir)?;
+        self.find(&dir, name).map(|node| FatFs::inode(&node))
+    }
+
+    fn read_dir(&mut self, dir: Inode, f: &mut FnMut(&str, Inode, Kind) -> bool) -> Result<(), KError> {
+        let dir = self.node(dir)?;
+        self.scan(&dir, |entry| {
+            f(entry.name(), FatFs::inode(&entry.node), if entry.node.is_dir { Kind::Directory } else { Kind::File })
+        })
+    }
+
+    fn read(&mut self, inode: Inode, offset: u64, buf: &mut [u8]) -> Result<usize, KError> {
+        let node = self.node(inode)?;
+        if offset >= node.size as u64 {
+            return Ok(0);
+        }
+        FatFs::read(self, &node, offset as u32, buf)
+    }
+
+    fn write(&mut self, inode: Inode, offset: u64, data: &[u8]) -> Result<usize, KError> {
+        let mut node = self.node(inode)?;
+        FatFs::write(self, &mut node, FatFs::offset(offset)?, data)
+    }
+
+    fn truncate(&mut self, inode: Inode, len: u64) -> Result<(), KError> {
+        let mut node = self.node(inode)?;
+        if len >= node.size as u64 {
+            return Ok(());
+        }
+        FatFs::truncate(self, &mut node, len as u32)
+    }
+
+    fn create(&mut self, dir: Inode, name: &str, kind: Kind) -> Result<Inode, KError> {
+        let dir = self.node(dir)?;
+        FatFs::create(self, &dir, name, kind == Kind::Directory).map(|node| FatFs::inode(&node))
+    }
+
+    fn remove(&mut self, dir: Inode, name: &str) -> Result<(), KError> {
+        let dir = self.node(dir)?;
+        FatFs::remove(self, &dir, name)
+    }
+
+    fn sync(&mut self) -> Result<(), KError> {
+        FatFs::sync(self)
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::alloc::btree_map::BTreeMap;
+    use ::drivers::block::BlockDevice;
+    use ::ktest::TestResult;
+    use ::memory::Box;
+
+    // enough clusters of one sector to be FAT16, after a boot sector, two FATs and a 512-entry root directory
+    const CLUSTERS: u32 = 4200;
+    const FAT_SECTORS: u32 = 17;
+    const ROOT_SECTORS: u32 = 32;
+    const TOTAL_SECTORS: u32 = 1 + 2 * FAT_SECTORS + ROOT_SECTORS + CLUSTERS;
+
+    // a disk of zeroes, apart from the sectors that have been written
+    struct SparseDisk {
+        sectors: BTreeMap<u64, Sector>
+    }
+
+    impl BlockDevice for SparseDisk {
+        fn sector_size(&self) -> usize {
+            BLOCK_SIZE
+        }
+
+        fn sector_count(&self) -> u64 {
+            TOTAL_SECTORS as u64
+        }
+
+        fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), KError> {
+            for (i, chunk) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+                match self.sectors.get(&(sector + i as u64)) {
+                    Some(data) => chunk.copy_from_slice(data),
+                    None => for b in chunk.iter_mut() { *b = 0 }
+                }
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), KError> {
+            for (i, chunk) in buf.chunks(BLOCK_SIZE).enumerate() {
+                let mut data: Sector = [0; BLOCK_SIZE];
+                data.copy_from_slice(chunk);
+                self.sectors.insert(sector + i as u64, data);
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), KError> {
+            Ok(())
+        }
+    }
+
+    fn boot_sector(root_entries: u16) -> Sector {
+        let mut boot: Sector = [0; BLOCK_SIZE];
+        set_le16(&mut boot, 11, BLOCK_SIZE as u16);
+        boot[13] = 1; // sectors per cluster
+        set_le16(&mut boot, 14, 1); // reserved
+        boot[16] = 2; // FATs
+        set_le16(&mut boot, 17, root_entries);
+        set_le16(&mut boot, 19, TOTAL_SECTORS as u16);
+        set_le16(&mut boot, 22, FAT_SECTORS as u16);
+        set_le16(&mut boot, 510, 0xAA55);
+        boot
+    }
+
+    fn format(device: DeviceId) -> Result<(), KError> {
+        block::write(device, 0, &boot_sector((ROOT_SECTORS as usize * ENTRIES_PER_SECTOR) as u16))?;
+        // the first two entries of each FAT hold the media type and an end of chain
+        let mut fat: Sector = [0; BLOCK_SIZE];
+        set_le16(&mut fat, 0, 0xFFF8);
+        set_le16(&mut fat, 2, 0xFFFF);
+        block::write(device, 1, &fat)?;
+        block::write(device, 1 + FAT_SECTORS as u64, &fat)
+    }
+
+    // several clusters' worth, with no two sectors alike
+    fn pattern(i: usize) -> u8 {
+        (i * 7 + i / BLOCK_SIZE) as u8
+    }
+
+    fn files_on(device: DeviceId) -> TestResult {
+        check_ok!(format(device));
+        let mut fs = check_ok!(FatFs::mount(device));
+        check!(fs.fat16);
+        check_eq!(fs.cluster_count, CLUSTERS);
+        check_eq!(fs.cluster_size(), BLOCK_SIZE);
+        // a FAT32 volume would need many more clusters, and a root directory in one of them
+        check!(FatFs::from_boot_sector(device, 0, &boot_sector(0)).is_err());
+
+        let root = fs.root();
+        let name = "a long file name.txt";
+        let mut node = check_ok!(fs.create(&root, name, false));
+        check_eq!(node.location.map(|l| l.lfn_count), Some(2));
+        check_eq!(fs.create(&root, name, false).err(), Some(KError::IllegalOperation));
+
+        let mut data = [0u8; 1300];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = pattern(i);
+        }
+        // the second write starts partway into the first cluster and ends partway into the third
+        check_eq!(check_ok!(fs.write(&mut node, 0, &data[..300])), 300);
+        check_eq!(check_ok!(fs.write(&mut node, 300, &data[300..])), 1000);
+        check_eq!(node.size, 1300);
+        check_eq!(check_ok!(fs.chain_length(node.cluster)), 3);
+
+        let mut found = check_ok!(fs.find(&root, "A LONG FILE NAME.TXT"));
+        check_eq!((found.cluster, found.size), (node.cluster, 1300));
+        let mut listed = false;
+        check_ok!(fs.read_dir(&root, |entry| listed |= entry.name() == name));
+        check!(listed);
+        let mut buf = [0u8; 1400];
+        check_eq!(check_ok!(fs.read(&found, 0, &mut buf)), 1300);
+        check!(buf[..1300] == data[..]);
+        check_eq!(check_ok!(fs.read(&found, 1000, &mut buf[..100])), 100);
+        check!(buf[..100] == data[1000..1100]);
+
+        let third = check_ok!(fs.nth_cluster(found.cluster, 2));
+        check_ok!(fs.truncate(&mut found, 600));
+        check_eq!(check_ok!(fs.chain_length(found.cluster)), 2);
+        check_eq!(check_ok!(fs.fat_entry(third)), FAT_FREE);
+        // what's on disk says the same, once it's mounted again
+        check_ok!(fs.sync());
+        let mut fs = check_ok!(FatFs::mount(device));
+        let found = check_ok!(fs.lookup("/a long file name.txt"));
+        check_eq!(found.size, 600);
+        check_eq!(check_ok!(fs.read(&found, 0, &mut buf)), 600);
+        check!(buf[..600] == data[..600]);
+
+        check_ok!(fs.remove(&root, name));
+        check_eq!(fs.find(&root, name).err(), Some(KError::FailedLookup));
+        check_eq!(check_ok!(fs.fat_entry(found.cluster)), FAT_FREE);
+        Ok(())
+    }
+
+    // only in the target, since the block cache is in pages of its own
+    fn files() -> TestResult {
+        let device = check_ok!(block::register("ktest-fat", Box::new(SparseDisk { sectors: BTreeMap::new() })));
+        let result = files_on(device);
+        check_ok!(block::unregister(device));
+        result
+    }
+
+    fn short_entry(name: &[u8; 11], ntres: u8) -> [u8; ENTRY_SIZE] {
+        let mut raw = [0u8; ENTRY_SIZE];
+        raw[..11].copy_from_slice(name);
+        raw[11] = ATTR_ARCHIVE;
+        raw[12] = ntres;
+        raw
+    }
+
+    // fragment `seq` of a long name, as create() writes it
+    fn lfn_entry(name: &str, seq: u8, last: bool, checksum: u8) -> [u8; ENTRY_SIZE] {
+        let mut ucs2 = [0xFFFFu16; MAX_LFN_ENTRIES * LFN_CHARS];
+        for (i, c) in name.chars().enumerate() {
+            ucs2[i] = c as u32 as u16;
+        }
+        ucs2[name.chars().count()] = 0x0000;
+        let mut raw = [0u8; ENTRY_SIZE];
+        raw[0] = seq | if last { LFN_LAST } else { 0 };
+        raw[11] = ATTR_LONG_NAME;
+        raw[13] = checksum;
+        for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+            set_le16(&mut raw, offset, ucs2[(seq as usize - 1) * LFN_CHARS + i]);
+        }
+        raw
+    }
+
+    fn checksums() -> TestResult {
+        check_eq!(short_name_checksum(b"README  TXT"), 0x73);
+        check_eq!(short_name_checksum(b"ALONGN~1HTM"), 0xF6);
+        Ok(())
+    }
+
+    fn short_names() -> TestResult {
+        check_eq!(basis_name("README.TXT"), (*b"README  TXT", 6, true));
+        check_eq!(basis_name("readme.txt"), (*b"README  TXT", 6, false));
+        check_eq!(basis_name("a long name.html"), (*b"ALONGNAMHTM", 8, false));
+        check_eq!(basis_name(".profile"), (*b"PROFILE    ", 7, false));
+        check_eq!(basis_name("+.+"), (*b"_          ", 1, false));
+        check_eq!(with_numeric_tail(b"ALONGNAMHTM", 8, 1), *b"ALONGN~1HTM");
+        check_eq!(with_numeric_tail(b"ALONGNAMHTM", 8, 12345), *b"AL~12345HTM");
+        check_eq!(with_numeric_tail(b"AB         ", 2, 3), *b"AB~3       ");
+        Ok(())
+    }
+
+    fn names_from_short() -> TestResult {
+        check_eq!(Name::from_short(&short_entry(b"README  TXT", 0)).as_str(), "README.TXT");
+        check_eq!(Name::from_short(&short_entry(b"README  TXT", NTRES_LOWER_BASE | NTRES_LOWER_EXT)).as_str(), "readme.txt");
+        check_eq!(Name::from_short(&short_entry(b"README  TXT", NTRES_LOWER_EXT)).as_str(), "README.txt");
+        check_eq!(Name::from_short(&short_entry(b"MAKEFILE   ", 0)).as_str(), "MAKEFILE");
+        // an initial 0x05 is really 0xE5, which isn't ASCII
+        check_eq!(Name::from_short(&short_entry(b"\x05AB        ", 0)).as_str(), "?AB");
+        Ok(())
+    }
+
+    fn long_names() -> TestResult {
+        let name = "a long name.html";
+        let short = short_entry(b"ALONGN~1HTM", 0);
+        let checksum = short_name_checksum(&short);
+        let mut long_name = LongName::new();
+        long_name.add(&lfn_entry(name, 2, true, checksum));
+        long_name.add(&lfn_entry(name, 1, false, checksum));
+        check!(long_name.matches(&short));
+        check_eq!(long_name.entries, 2);
+        check_eq!(Name::from_ucs2(&long_name.chars).as_str(), name);
+        check!(!long_name.matches(&short_entry(b"ALONGN~2HTM", 0)));
+
+        // out of order
+        long_name.reset();
+        long_name.add(&lfn_entry(name, 1, false, checksum));
+        long_name.add(&lfn_entry(name, 2, true, checksum));
+        long_name.add(&lfn_entry(name, 1, false, checksum.wrapping_add(1)));
+        check!(!long_name.matches(&short));
+
+        // a fragment numbered zero after a complete run, as a corrupt directory might have
+        long_name.reset();
+        long_name.add(&lfn_entry(name, 2, true, checksum));
+        long_name.add(&lfn_entry(name, 1, false, checksum));
+        let mut zero = lfn_entry(name, 1, false, checksum);
+        zero[0] = 0;
+        long_name.add(&zero);
+        check!(!long_name.matches(&short));
+        zero[0] = LFN_LAST;
+        long_name.add(&zero);
+        check!(!long_name.matches(&short));
+        Ok(())
+    }
+
+    test_cases!(checksums, short_names, names_from_short, long_names; target: files);
+}
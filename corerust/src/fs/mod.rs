@@ -0,0 +1,6 @@
+// filesystems, on top of the block layer, and the tree they're mounted into
+
+pub mod vfs;
+pub mod ramfs;
+pub mod archive;
+pub mod fat;
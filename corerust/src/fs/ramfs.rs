@@ -0,0 +1,388 @@
+use ::core;
+use ::mantle::KError;
+use ::memory::LinkedList;
+use ::fs::archive;
+use ::fs::vfs;
+use ::fs::vfs::{FileSystem, Inode, Kind, Stat, MAX_NAME_LEN};
+
+// a filesystem that lives on the heap and is gone at reboot. files from the boot archive are used where they are,
+// and only copied onto the heap once they're written to.
+
+// a LinkedList holds its first element inline, so a node and its first chunk have to fit in one allocation together
+const CHUNK_SIZE: usize = 512;
+
+const ROOT_INODE: Inode = 1;
+
+enum Data {
+    None,
+    Static(&'static [u8]),
+    // last chunk first, so that they can be added and removed at the front
+    Chunks { chunks: LinkedList<[u8; CHUNK_SIZE]>, count: usize, len: u64 }
+}
+
+struct RamNode {
+    inode: Inode,
+    parent: Inode,
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    kind: Kind,
+    data: Data
+}
+
+impl RamNode {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap()
+    }
+
+    fn len(&self) -> u64 {
+        match self.data {
+            Data::None => 0,
+            Data::Static(data) => data.len() as u64,
+            Data::Chunks { len, .. } => len
+        }
+    }
+
+    fn chunk(&mut self, n: usize) -> &mut [u8; CHUNK_SIZE] {
+        self.data.chunk(n)
+    }
+
+    // moves the contents onto the heap, if they aren't there already. they're left where they are if that fails.
+    fn make_writable(&mut self) -> Result<(), KError> {
+        if let Data::Static(data) = self.data {
+            let mut copy = Data::Chunks { chunks: LinkedList::empty(), count: 0, len: 0 };
+            copy.grow(data.len() as u64)?;
+            for (i, piece) in data.chunks(CHUNK_SIZE).enumerate() {
+                copy.chunk(i)[..piece.len()].copy_from_slice(piece);
+            }
+            self.data = copy;
+        }
+        Ok(())
+    }
+
+    fn grow(&mut self, new_len: u64) -> Result<(), KError> {
+        self.data.grow(new_len)
+    }
+}
+
+impl Data {
+    fn chunk(&mut self, n: usize) -> &mut [u8; CHUNK_SIZE] {
+        match *self {
+            Data::Chunks { ref mut chunks, count, .. } => chunks.get_mut(count - 1 - n).unwrap(),
+            _ => panic!("not a heap file")
+        }
+    }
+
+    // extends with zeroes
+    fn grow(&mut self, new_len: u64) -> Result<(), KError> {
+        if let Data::Chunks { ref mut chunks, ref mut count, ref mut len } = *self {
+            while (*count as u64) * (CHUNK_SIZE as u64) < new_len {
+                if chunks.pushmut([0; CHUNK_SIZE]).is_err() {
+                    return Err(KError::NotEnoughMemory);
+                }
+                *count += 1;
+            }
+            if new_len > *len {
+                *len = new_len;
+            }
+            return Ok(());
+        }
+        Err(KError::IllegalOperation)
+    }
+}
+
+pub struct RamFs {
+    nodes: LinkedList<RamNode>,
+    next_inode: Inode
+}
+
+impl RamFs {
+    pub fn new() -> RamFs {
+        RamFs { nodes: LinkedList::empty(), next_inode: ROOT_INODE + 1 }
+    }
+
+    fn node(&mut self, inode: Inode) -> Result<&mut RamNode, KError> {
+        if inode == ROOT_INODE {
+            return Err(KError::IllegalOperation); // the root has no node, and is only ever a directory
+        }
+        self.nodes.find_mut(|n| n.inode == inode).ok_or(KError::FailedLookup)
+    }
+
+    fn kind(&mut self, inode: Inode) -> Result<Kind, KError> {
+        if inode == ROOT_INODE {
+            Ok(Kind::Directory)
+        } else {
+            self.node(inode).map(|n| n.kind)
+        }
+    }
+
+    fn add(&mut self, dir: Inode, name: &str, kind: Kind, data: Data) -> Result<Inode, KError> {
+        if !vfs::is_valid_name(name) {
+            return Err(KError::InvalidArgument);
+        }
+        if self.kind(dir)? != Kind::Directory {
+            return Err(KError::IllegalOperation);
+        }
+        if self.nodes.find(|n| n.parent == dir && n.name() == name).is_some() {
+            return Err(KError::IllegalOperation); // already exists
+        }
+        let inode = self.next_inode;
+        let mut node = RamNode { inode, parent: dir, name: [0; MAX_NAME_LEN], name_len: name.len(), kind, data };
+        node.name[..name.len()].copy_from_slice(name.as_bytes());
+        if self.nodes.push_back(node).is_err() {
+            return Err(KError::NotEnoughMemory);
+        }
+        self.next_inode += 1;
+        Ok(inode)
+    }
+
+    // makes any directories along the way that don't exist yet
+    fn add_path(&mut self, path: &str, kind: Kind, data: Data) -> Result<(), KError> {
+        let mut dir = ROOT_INODE;
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        while let Some(name) = components.next() {
+            let last = components.peek().is_none();
+            match FileSystem::lookup(self, dir, name) {
+                Ok(inode) if last && kind == Kind::Directory && self.kind(inode)? == Kind::Directory => return Ok(()),
+                Ok(_) if last => return Err(KError::IllegalOperation),
+                Ok(inode) => dir = inode,
+                Err(KError::FailedLookup) if last => {
+                    self.add(dir, name, kind, data)?;
+                    return Ok(());
+                }
+                Err(KError::FailedLookup) => dir = self.add(dir, name, Kind::Directory, Data::None)?,
+                Err(err) => return Err(err)
+            }
+        }
+        Err(KError::InvalidArgument)
+    }
+
+    // adds everything in a ustar archive, returning how many files there were
+    pub fn add_archive(&mut self, data: &'static [u8]) -> Result<usize, KError> {
+        let mut count = 0;
+        archive::for_each(data, |path, kind, contents| {
+            match kind {
+                Kind::File => {
+                    count += 1;
+                    self.add_path(path, kind, Data::Static(contents))
+                }
+                Kind::Directory => self.add_path(path, kind, Data::None)
+            }
+        })?;
+        Ok(count)
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, KError> {
+        if inode == ROOT_INODE {
+            return Ok(Stat { kind: Kind::Directory, size: 0 });
+        }
+        let node = self.node(inode)?;
+        Ok(Stat { kind: node.kind, size: node.len() })
+    }
+
+    fn lookup(&mut self, dir: Inode, name: &str) -> Result<Inode, KError> {
+        if self.kind(dir)? != Kind::Directory {
+            return Err(KError::IllegalOperation);
+        }
+        self.nodes.find(|n| n.parent == dir && n.name() == name).map(|n| n.inode).ok_or(KError::FailedLookup)
+    }
+
+    fn read_dir(&mut self, dir: Inode, f: &mut FnMut(&str, Inode, Kind) -> bool) -> Result<(), KError> {
+        if self.kind(dir)? != Kind::Directory {
+            return Err(KError::IllegalOperation);
+        }
+        for node in &self.nodes {
+            if node.parent == dir && f(node.name(), node.inode, node.kind) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, inode: Inode, offset: u64, buf: &mut [u8]) -> Result<usize, KError> {
+        let node = self.node(inode)?;
+        if node.kind != Kind::File {
+            return Err(KError::IllegalOperation);
+        }
+        let len = node.len();
+        if offset >= len {
+            return Ok(0);
+        }
+        let n = core::cmp::min(buf.len() as u64, len - offset) as usize;
+        let offset = offset as usize;
+        if let Data::Static(data) = node.data {
+            buf[..n].copy_from_slice(&data[offset..offset + n]);
+            return Ok(n);
+        }
+        let mut done = 0;
+        while done < n {
+            let pos = offset + done;
+            let within = pos % CHUNK_SIZE;
+            let step = core::cmp::min(CHUNK_SIZE - within, n - done);
+            buf[done..done + step].copy_from_slice(&node.chunk(pos / CHUNK_SIZE)[within..within + step]);
+            done += step;
+        }
+        Ok(n)
+    }
+
+    // writing past the end leaves zeroes in between
+    fn write(&mut self, inode: Inode, offset: u64, data: &[u8]) -> Result<usize, KError> {
+        let node = self.node(inode)?;
+        if node.kind != Kind::File {
+            return Err(KError::IllegalOperation);
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        node.make_writable()?;
+        node.grow(offset + data.len() as u64)?;
+        let offset = offset as usize;
+        let mut done = 0;
+        while done < data.len() {
+            let pos = offset + done;
+            let within = pos % CHUNK_SIZE;
+            let step = core::cmp::min(CHUNK_SIZE - within, data.len() - done);
+            node.chunk(pos / CHUNK_SIZE)[within..within + step].copy_from_slice(&data[done..done + step]);
+            done += step;
+        }
+        Ok(done)
+    }
+
+    fn truncate(&mut self, inode: Inode, new_len: u64) -> Result<(), KError> {
+        let node = self.node(inode)?;
+        if node.kind != Kind::File {
+            return Err(KError::IllegalOperation);
+        }
+        if new_len >= node.len() {
+            return Ok(());
+        }
+        if let Data::Static(data) = node.data {
+            node.data = Data::Static(&data[..new_len as usize]);
+            return Ok(());
+        }
+        let keep = (new_len as usize + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        if let Data::Chunks { ref mut chunks, ref mut count, ref mut len } = node.data {
+            while *count > keep {
+                chunks.popmut();
+                *count -= 1;
+            }
+            *len = new_len;
+        }
+        // the rest of the last chunk has to read back as zeroes if the file grows again
+        let within = new_len as usize % CHUNK_SIZE;
+        if within != 0 {
+            for b in node.chunk(keep - 1)[within..].iter_mut() {
+                *b = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn create(&mut self, dir: Inode, name: &str, kind: Kind) -> Result<Inode, KError> {
+        let data = match kind {
+            Kind::File => Data::Chunks { chunks: LinkedList::empty(), count: 0, len: 0 },
+            Kind::Directory => Data::None
+        };
+        self.add(dir, name, kind, data)
+    }
+
+    fn remove(&mut self, dir: Inode, name: &str) -> Result<(), KError> {
+        let inode = FileSystem::lookup(self, dir, name)?;
+        if self.nodes.find(|n| n.parent == inode).is_some() {
+            return Err(KError::DeleteFirst);
+        }
+        self.nodes.remove_mut(|n| n.inode == inode);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), KError> {
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn read_write() -> TestResult {
+        let mut fs = RamFs::new();
+        let root = fs.root();
+        let file = check_ok!(fs.create(root, "file", Kind::File));
+        let mut data = [0u8; 1000];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = (i % 251) as u8;
+        }
+        check_eq!(check_ok!(fs.write(file, 0, &data)), data.len());
+        let mut buf = [0u8; 1200];
+        check_eq!(check_ok!(fs.read(file, 0, &mut buf)), data.len());
+        check!(&buf[..1000] == &data[..]);
+        // across the chunk boundary, from the middle
+        check_eq!(check_ok!(fs.read(file, 500, &mut buf[..100])), 100);
+        check!(&buf[..100] == &data[500..600]);
+        check_eq!(check_ok!(fs.read(file, 1000, &mut buf)), 0);
+
+        check_ok!(fs.write(file, 1500, b"xy"));
+        check_eq!(check_ok!(fs.stat(file)).size, 1502);
+        check_eq!(check_ok!(fs.read(file, 1000, &mut buf)), 502);
+        check!(buf[..500].iter().all(|&b| b == 0));
+        check!(&buf[500..502] == b"xy");
+        Ok(())
+    }
+
+    fn truncate() -> TestResult {
+        let mut fs = RamFs::new();
+        let root = fs.root();
+        let file = check_ok!(fs.create(root, "file", Kind::File));
+        check_ok!(fs.write(file, 0, &[0xFF; 1200]));
+        check_ok!(fs.truncate(file, 700));
+        check_eq!(check_ok!(fs.stat(file)).size, 700);
+        // growing again has to bring back zeroes, not what was there before
+        check_ok!(fs.write(file, 1100, b"z"));
+        let mut buf = [0u8; 600];
+        check_eq!(check_ok!(fs.read(file, 600, &mut buf)), 501);
+        check!(buf[..100].iter().all(|&b| b == 0xFF));
+        check!(buf[100..500].iter().all(|&b| b == 0));
+        check_eq!(buf[500], b'z');
+        check_ok!(fs.truncate(file, 0));
+        check_eq!(check_ok!(fs.stat(file)).size, 0);
+        Ok(())
+    }
+
+    fn directories() -> TestResult {
+        let mut fs = RamFs::new();
+        let root = fs.root();
+        let dir = check_ok!(fs.create(root, "dir", Kind::Directory));
+        let file = check_ok!(fs.create(dir, "file", Kind::File));
+        check_eq!(check_ok!(fs.lookup(dir, "file")), file);
+        check_eq!(fs.lookup(root, "file").err(), Some(KError::FailedLookup));
+        check_eq!(fs.create(dir, "file", Kind::Directory).err(), Some(KError::IllegalOperation));
+        check_eq!(fs.create(dir, "..", Kind::File).err(), Some(KError::InvalidArgument));
+        check_eq!(fs.create(file, "inside", Kind::File).err(), Some(KError::IllegalOperation));
+
+        let (mut count, mut found) = (0, false);
+        check_ok!(fs.read_dir(root, &mut |name, inode, kind| {
+            count += 1;
+            found = name == "dir" && inode == dir && kind == Kind::Directory;
+            false
+        }));
+        check!(count == 1 && found);
+
+        check_eq!(fs.remove(root, "dir").err(), Some(KError::DeleteFirst));
+        check_ok!(fs.remove(dir, "file"));
+        check_ok!(fs.remove(root, "dir"));
+        check_eq!(fs.lookup(root, "dir").err(), Some(KError::FailedLookup));
+        Ok(())
+    }
+
+    test_cases!(read_write, truncate, directories);
+}
@@ -0,0 +1,475 @@
+use ::core;
+use ::core::cell::RefCell;
+use ::mantle::KError;
+use ::mantle::concurrency::SingleThreaded;
+use ::memory::{Box, LinkedList};
+use ::fs::archive;
+use ::fs::ramfs::RamFs;
+
+// one tree of absolute paths over every mounted filesystem, so that nothing opening a file needs to know what holds it
+
+// identifies a file or directory within one filesystem, for as long as it exists
+pub type Inode = u64;
+
+// enough for any FAT long name, once it's UTF-8
+pub const MAX_NAME_LEN: usize = 255 * 3;
+
+const MAX_PATH_LEN: usize = 256;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Directory
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Stat {
+    pub kind: Kind,
+    pub size: u64
+}
+
+pub trait FileSystem {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> Inode;
+    fn stat(&mut self, inode: Inode) -> Result<Stat, KError>;
+    fn lookup(&mut self, dir: Inode, name: &str) -> Result<Inode, KError>;
+    // calls back with each entry apart from . and .., until the callback returns true
+    fn read_dir(&mut self, dir: Inode, f: &mut FnMut(&str, Inode, Kind) -> bool) -> Result<(), KError>;
+    // returns how much was read, which is less than asked for at the end of the file
+    fn read(&mut self, inode: Inode, offset: u64, buf: &mut [u8]) -> Result<usize, KError>;
+    fn write(&mut self, inode: Inode, offset: u64, data: &[u8]) -> Result<usize, KError>;
+    // only ever shortens
+    fn truncate(&mut self, inode: Inode, len: u64) -> Result<(), KError>;
+    fn create(&mut self, dir: Inode, name: &str, kind: Kind) -> Result<Inode, KError>;
+    fn remove(&mut self, dir: Inode, name: &str) -> Result<(), KError>;
+    fn sync(&mut self) -> Result<(), KError>;
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && name.len() <= MAX_NAME_LEN && !name.contains('/')
+}
+
+type MountId = usize;
+
+struct Mount {
+    id: MountId,
+    path: [u8; MAX_PATH_LEN],
+    path_len: usize,
+    // the directory this is mounted over, or None for the root
+    covers: Option<(MountId, Inode)>,
+    fs: Box<FileSystem>,
+    open: usize
+}
+
+impl Mount {
+    fn path(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap()
+    }
+}
+
+struct Vfs {
+    mounts: LinkedList<Mount>,
+    next_id: MountId
+}
+
+static VFS: SingleThreaded<RefCell<Vfs>> = SingleThreaded(RefCell::new(Vfs { mounts: LinkedList::empty(), next_id: 0 }));
+
+impl Vfs {
+    fn mount(&mut self, id: MountId) -> Result<&mut Mount, KError> {
+        self.mounts.find_mut(|m| m.id == id).ok_or(KError::FailedLookup)
+    }
+
+    fn fs(&mut self, id: MountId) -> Result<&mut FileSystem, KError> {
+        self.mount(id).map(|m| &mut *m.fs)
+    }
+
+    fn root(&self) -> Result<(MountId, Inode), KError> {
+        match self.mounts.find(|m| m.covers.is_none()) {
+            Some(mount) => Ok((mount.id, mount.fs.root())),
+            None => Err(KError::FailedLookup)
+        }
+    }
+
+    // steps into whatever is mounted over a directory
+    fn cross(&self, at: (MountId, Inode)) -> (MountId, Inode) {
+        match self.mounts.find(|m| m.covers == Some(at)) {
+            Some(mount) => self.cross((mount.id, mount.fs.root())),
+            None => at
+        }
+    }
+
+    // .. is followed back up through the directories that were walked down, so it works across mount points
+    fn resolve(&mut self, path: &str) -> Result<(MountId, Inode), KError> {
+        if !path.starts_with('/') {
+            return Err(KError::InvalidArgument);
+        }
+        let root = self.root()?;
+        let mut stack = [root; MAX_DEPTH];
+        let mut depth = 0;
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    if depth > 0 {
+                        depth -= 1;
+                    }
+                }
+                name => {
+                    if depth + 1 == MAX_DEPTH {
+                        return Err(KError::RangeError);
+                    }
+                    let (mount, dir) = stack[depth];
+                    let inode = self.fs(mount)?.lookup(dir, name)?;
+                    depth += 1;
+                    stack[depth] = self.cross((mount, inode));
+                }
+            }
+        }
+        Ok(stack[depth])
+    }
+
+    // the directory that would hold a path, and the name within it
+    fn resolve_parent<'a>(&mut self, path: &'a str) -> Result<((MountId, Inode), &'a str), KError> {
+        let path = path.trim_right_matches('/');
+        let (parent, name) = match path.rfind('/') {
+            Some(i) => (&path[..i + 1], &path[i + 1..]),
+            None => return Err(KError::InvalidArgument)
+        };
+        if !is_valid_name(name) {
+            return Err(KError::InvalidArgument);
+        }
+        Ok((self.resolve(parent)?, name))
+    }
+
+    fn open(&mut self, at: (MountId, Inode)) -> Result<(), KError> {
+        self.mount(at.0)?.open += 1;
+        Ok(())
+    }
+}
+
+pub struct File {
+    mount: MountId,
+    inode: Inode,
+    pos: u64
+}
+
+impl File {
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, KError> {
+        let n = VFS.get().borrow_mut().fs(self.mount)?.read(self.inode, self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, KError> {
+        let n = VFS.get().borrow_mut().fs(self.mount)?.write(self.inode, self.pos, data)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    // reads until the buffer is full or the file ends
+    pub fn read_all(&mut self, buf: &mut [u8]) -> Result<usize, KError> {
+        let mut done = 0;
+        while done < buf.len() {
+            match self.read(&mut buf[done..])? {
+                0 => break,
+                n => done += n
+            }
+        }
+        Ok(done)
+    }
+
+    pub fn seek(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn stat(&self) -> Result<Stat, KError> {
+        VFS.get().borrow_mut().fs(self.mount)?.stat(self.inode)
+    }
+
+    pub fn truncate(&mut self, len: u64) -> Result<(), KError> {
+        VFS.get().borrow_mut().fs(self.mount)?.truncate(self.inode, len)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        if let Ok(mount) = VFS.get().borrow_mut().mount(self.mount) {
+            mount.open -= 1;
+        }
+    }
+}
+
+pub struct DirEntry {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    pub kind: Kind
+}
+
+impl DirEntry {
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap()
+    }
+}
+
+// iterates over a directory's entries. filesystems only offer a callback, so each step skips past the entries
+// already seen, which is fine for directories of the sizes we have.
+pub struct Dir {
+    mount: MountId,
+    inode: Inode,
+    index: usize
+}
+
+impl Iterator for Dir {
+    type Item = Result<DirEntry, KError>;
+
+    fn next(&mut self) -> Option<Result<DirEntry, KError>> {
+        let mut vfs = VFS.get().borrow_mut();
+        let fs = match vfs.fs(self.mount) {
+            Ok(fs) => fs,
+            Err(err) => return Some(Err(err))
+        };
+        let mut seen = 0;
+        let mut found = None;
+        let index = self.index;
+        let result = fs.read_dir(self.inode, &mut |name, _, kind| {
+            if seen == index {
+                let mut entry = DirEntry { name: [0; MAX_NAME_LEN], name_len: name.len(), kind };
+                entry.name[..name.len()].copy_from_slice(name.as_bytes());
+                found = Some(entry);
+                return true;
+            }
+            seen += 1;
+            false
+        });
+        if let Err(err) = result {
+            return Some(Err(err));
+        }
+        self.index += 1;
+        found.map(Ok)
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        if let Ok(mount) = VFS.get().borrow_mut().mount(self.mount) {
+            mount.open -= 1;
+        }
+    }
+}
+
+pub fn open(path: &str) -> Result<File, KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let at = vfs.resolve(path)?;
+    if vfs.fs(at.0)?.stat(at.1)?.kind != Kind::File {
+        return Err(KError::IllegalOperation);
+    }
+    vfs.open(at)?;
+    Ok(File { mount: at.0, inode: at.1, pos: 0 })
+}
+
+// opens a file for writing, creating it if need be, and otherwise emptying it
+pub fn create(path: &str) -> Result<File, KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let (dir, name) = vfs.resolve_parent(path)?;
+    let inode = {
+        let fs = vfs.fs(dir.0)?;
+        match fs.lookup(dir.1, name) {
+            Ok(inode) => {
+                if fs.stat(inode)?.kind != Kind::File {
+                    return Err(KError::IllegalOperation);
+                }
+                fs.truncate(inode, 0)?;
+                inode
+            }
+            Err(KError::FailedLookup) => fs.create(dir.1, name, Kind::File)?,
+            Err(err) => return Err(err)
+        }
+    };
+    vfs.open((dir.0, inode))?;
+    Ok(File { mount: dir.0, inode, pos: 0 })
+}
+
+pub fn open_dir(path: &str) -> Result<Dir, KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let at = vfs.resolve(path)?;
+    if vfs.fs(at.0)?.stat(at.1)?.kind != Kind::Directory {
+        return Err(KError::IllegalOperation);
+    }
+    vfs.open(at)?;
+    Ok(Dir { mount: at.0, inode: at.1, index: 0 })
+}
+
+pub fn stat(path: &str) -> Result<Stat, KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let at = vfs.resolve(path)?;
+    vfs.fs(at.0)?.stat(at.1)
+}
+
+pub fn mkdir(path: &str) -> Result<(), KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let (dir, name) = vfs.resolve_parent(path)?;
+    vfs.fs(dir.0)?.create(dir.1, name, Kind::Directory).map(|_| ())
+}
+
+pub fn remove(path: &str) -> Result<(), KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let (dir, name) = vfs.resolve_parent(path)?;
+    let inode = vfs.fs(dir.0)?.lookup(dir.1, name)?;
+    if vfs.cross((dir.0, inode)) != (dir.0, inode) {
+        return Err(KError::DeleteFirst); // has something mounted over it
+    }
+    vfs.fs(dir.0)?.remove(dir.1, name)
+}
+
+// the first filesystem mounted must go at /; after that, filesystems go over existing directories
+pub fn mount(path: &str, fs: Box<FileSystem>) -> Result<(), KError> {
+    if path.len() > MAX_PATH_LEN {
+        return Err(KError::RangeError);
+    }
+    let mut vfs = VFS.get().borrow_mut();
+    let covers = if vfs.mounts.is_empty() {
+        if path != "/" {
+            return Err(KError::FailedLookup);
+        }
+        None
+    } else {
+        let at = vfs.resolve(path)?;
+        if vfs.fs(at.0)?.stat(at.1)?.kind != Kind::Directory {
+            return Err(KError::IllegalOperation);
+        }
+        let root = vfs.fs(at.0)?.root();
+        if at.1 == root {
+            return Err(KError::IllegalOperation); // already a mount point
+        }
+        Some(at)
+    };
+    let mut mount = Mount { id: vfs.next_id, path: [0; MAX_PATH_LEN], path_len: path.len(), covers, fs, open: 0 };
+    mount.path[..path.len()].copy_from_slice(path.as_bytes());
+    if vfs.mounts.push_back(mount).is_err() {
+        return Err(KError::NotEnoughMemory);
+    }
+    vfs.next_id += 1;
+    Ok(())
+}
+
+// writes everything out first; fails while files are open or other filesystems are mounted within it
+pub fn unmount(path: &str) -> Result<(), KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    let (id, inode) = vfs.resolve(path)?;
+    {
+        let mount = vfs.mount(id)?;
+        if mount.covers.is_none() || inode != mount.fs.root() {
+            return Err(KError::IllegalOperation);
+        }
+        if mount.open > 0 {
+            return Err(KError::DeleteFirst);
+        }
+    }
+    if vfs.mounts.find(|m| m.covers.map_or(false, |(parent, _)| parent == id)).is_some() {
+        return Err(KError::DeleteFirst);
+    }
+    vfs.fs(id)?.sync()?;
+    vfs.mounts.remove_mut(|m| m.id == id);
+    Ok(())
+}
+
+pub fn sync_all() -> Result<(), KError> {
+    let mut vfs = VFS.get().borrow_mut();
+    for i in 0..vfs.mounts.len() {
+        vfs.mounts.get_mut(i).unwrap().fs.sync()?;
+    }
+    Ok(())
+}
+
+pub fn print_mounts(writer: &mut core::fmt::Write) -> core::fmt::Result {
+    let vfs = VFS.get().borrow();
+    for mount in &vfs.mounts {
+        writeln!(writer, "{} on {} ({} open)", mount.fs.name(), mount.path(), mount.open)?;
+    }
+    Ok(())
+}
+
+// mounts a ramfs as the root, holding whatever is in the boot archive
+pub fn init() {
+    let mut root = RamFs::new();
+    if let Some(data) = archive::boot_archive() {
+        match root.add_archive(data) {
+            Ok(count) => debug!("loaded {} files from the boot archive", count),
+            Err(err) => warn!("could not load the boot archive: {:?}", err)
+        };
+    }
+    mount("/", Box::new(root)).unwrap();
+}
+
+#[cfg(feature = "ktest")]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    // these work in the real tree, so each uses a directory of its own under the root, and removes it at the end
+
+    fn files() -> TestResult {
+        check_ok!(mkdir("/ktest-files"));
+        {
+            let mut file = check_ok!(create("/ktest-files/a"));
+            check_eq!(check_ok!(file.write(b"hello")), 5);
+            check_eq!(file.position(), 5);
+        }
+        let mut file = check_ok!(open("/ktest-files/./a"));
+        let mut buf = [0u8; 16];
+        check_eq!(check_ok!(file.read_all(&mut buf)), 5);
+        check!(&buf[..5] == b"hello");
+        file.seek(1);
+        check_eq!(check_ok!(file.read(&mut buf[..2])), 2);
+        check!(&buf[..2] == b"el");
+        check_eq!(check_ok!(stat("/ktest-files/a")).size, 5);
+        // create empties an existing file
+        drop(check_ok!(create("/ktest-files/a")));
+        check_eq!(check_ok!(file.stat()).size, 0);
+        drop(file);
+
+        let mut count = 0;
+        for entry in check_ok!(open_dir("/ktest-files")) {
+            let entry = check_ok!(entry);
+            check!(entry.name() == "a" && entry.kind == Kind::File);
+            count += 1;
+        }
+        check_eq!(count, 1);
+        check_eq!(remove("/ktest-files").err(), Some(KError::DeleteFirst));
+        check_ok!(remove("/ktest-files/a"));
+        check_ok!(remove("/ktest-files"));
+        Ok(())
+    }
+
+    fn mounts() -> TestResult {
+        check_ok!(mkdir("/ktest-mount"));
+        check_ok!(mount("/ktest-mount", Box::new(RamFs::new())));
+        check_eq!(mount("/ktest-mount", Box::new(RamFs::new())).err(), Some(KError::IllegalOperation));
+        let file = check_ok!(create("/ktest-mount/x"));
+        check_eq!(check_ok!(stat("/ktest-mount/../ktest-mount/x")).kind, Kind::File);
+        check_eq!(unmount("/ktest-mount").err(), Some(KError::DeleteFirst));
+        drop(file);
+        check_eq!(remove("/ktest-mount").err(), Some(KError::DeleteFirst));
+        check_ok!(unmount("/ktest-mount"));
+        // the directory underneath is back, and empty
+        check_eq!(stat("/ktest-mount/x").err(), Some(KError::FailedLookup));
+        check_ok!(remove("/ktest-mount"));
+        Ok(())
+    }
+
+    fn bad_paths() -> TestResult {
+        check_eq!(open("/ktest-missing").err(), Some(KError::FailedLookup));
+        check_eq!(open("/").err(), Some(KError::IllegalOperation));
+        check!(open_dir("/").is_ok());
+        check_eq!(unmount("/").err(), Some(KError::IllegalOperation));
+        check_eq!(mkdir("/..").err(), Some(KError::InvalidArgument));
+        Ok(())
+    }
+
+    test_cases!(files, mounts, bad_paths);
+}
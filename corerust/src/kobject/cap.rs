@@ -71,7 +71,7 @@ impl Cap {
     }
 
     pub fn delete(self) -> CapSlot {
-        assert!(mantle::calls::cnode_delete(crust::ROOT_SLOT, self.peek_index(), crust::ROOT_BITS as u8).is_okay());
+        assert!(mantle::syscalls().cnode_delete(crust::ROOT_SLOT, self.peek_index(), crust::ROOT_BITS as u8).is_okay());
         self.loc
     }
 }
@@ -118,3 +118,50 @@ impl core::fmt::Display for CapRange {
         write!(f, "[{}, {})", self.start, self.end)
     }
 }
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn chop() -> TestResult {
+        let mut range = CapRange::range(10, 15);
+        check_eq!(range.chop_1(), Some(10));
+        let front = range.chop_n(3).unwrap();
+        check_eq!((front.start(), front.len()), (11, 3));
+        check_eq!((range.start(), range.len()), (14, 1));
+        check!(range.chop_n(2).is_none());
+        check_eq!(range.chop_1(), Some(14));
+        check!(range.is_empty());
+        check_eq!(range.chop_1(), None);
+        Ok(())
+    }
+
+    fn intersection() -> TestResult {
+        let a = CapRange::range(0, 8);
+        let overlap = a.intersection(&CapRange::range(5, 12)).unwrap();
+        check_eq!((overlap.start(), overlap.len()), (5, 3));
+        let overlap = CapRange::range(5, 12).intersection(&a).unwrap();
+        check_eq!((overlap.start(), overlap.len()), (5, 3));
+        check!(a.intersection(&CapRange::range(8, 9)).is_none());
+        Ok(())
+    }
+
+    fn join() -> TestResult {
+        let joined = CapRange::range(4, 6).join(CapRange::range(6, 9)).ok().unwrap();
+        check_eq!((joined.start(), joined.len()), (4, 5));
+        let joined = CapRange::range(6, 9).join(CapRange::range(4, 6)).ok().unwrap();
+        check_eq!((joined.start(), joined.len()), (4, 5));
+        check!(CapRange::range(0, 2).join(CapRange::range(3, 4)).is_err());
+
+        let mut range = CapRange::single(3);
+        check!(range.join_mut(CapRange::range(4, 6)).is_none());
+        check!(range.could_join(&CapRange::single(2)));
+        check!(!range.could_join(&CapRange::single(7)));
+        check!(range.join_mut(CapRange::single(7)).is_some());
+        check_eq!((range.start(), range.len()), (3, 3));
+        Ok(())
+    }
+
+    test_cases!(chop, intersection, join);
+}
@@ -195,3 +195,50 @@ impl CapSet {
         self.backing.equivalent_empty_set()
     }
 }
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn take() -> TestResult {
+        let mut set = CapRange::range(20, 24).to_set_asserted_full();
+        check!(set.full());
+        check_eq!(set.take_front().unwrap().deconstruct(), 20);
+        check_eq!(set.take_back().unwrap().deconstruct(), 23);
+        check_eq!(set.count(), 2);
+        check!(!set.full());
+        check_eq!(set.take_front().unwrap().deconstruct(), 21);
+        check_eq!(set.take_front().unwrap().deconstruct(), 22);
+        check!(!set.remaining());
+        check!(set.take_front().is_none() && set.take_back().is_none());
+        Ok(())
+    }
+
+    fn readd() -> TestResult {
+        let mut set = CapRange::range(20, 24).to_set_empty();
+        check_eq!(set.capacity(), 4);
+        // into an empty set, a slot can go anywhere
+        set.readd(CapSlot::from_index(22));
+        set.readd(CapSlot::from_index(21));
+        set.readd(CapSlot::from_index(23));
+        set.readd(CapSlot::from_index(20));
+        check!(set.full());
+        let range = set.deconstruct();
+        check_eq!((range.start(), range.len()), (20, 4));
+        Ok(())
+    }
+
+    fn derive_capset() -> TestResult {
+        let mut slots = CapRange::range(20, 23).to_set_asserted_full();
+        let mut caps = slots.assert_derive_capset();
+        check!(!slots.remaining());
+        check!(caps.full());
+        check_eq!(caps.count(), 3);
+        caps.assert_empty();
+        check_eq!(caps.count(), 0);
+        Ok(())
+    }
+
+    test_cases!(take, readd, derive_capset);
+}
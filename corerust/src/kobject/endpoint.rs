@@ -0,0 +1,37 @@
+use ::mantle;
+use ::mantle::kernel;
+use ::kobject::*;
+
+pub struct Endpoint {
+    cap: Cap,
+    parent: Untyped
+}
+
+impl Endpoint {
+    pub fn from_retyping(cap: Cap, parent: Untyped) -> Endpoint {
+        Endpoint { cap, parent }
+    }
+
+    pub fn free(self) -> (Untyped, CapSlot) {
+        (self.parent, self.cap.delete())
+    }
+
+    pub fn peek_index(&self) -> usize {
+        self.cap.peek_index()
+    }
+
+    pub fn wait(&self) -> (kernel::MessageInfo, usize) {
+        mantle::syscalls().recv(self.cap.peek_index())
+    }
+
+    // returns the message label, if anything was waiting to be received
+    pub fn poll(&self) -> Option<u32> {
+        let (info, _) = mantle::syscalls().nbrecv(self.cap.peek_index());
+        let label = kernel::messageinfo_get_label(info);
+        if label != 0 { Some(label) } else { None }
+    }
+
+    pub fn signal(&self) {
+        mantle::signal(self.cap.peek_index())
+    }
+}
@@ -14,7 +14,7 @@ impl IRQControl {
     }
 
     pub fn get(&self, irq: u32, output_slot: CapSlot) -> core::result::Result<IRQHandler, (KError, CapSlot)> {
-        let err = mantle::irqcontrol_get(self.cap.peek_index(), irq, crust::ROOT_SLOT, output_slot.peek_index(), crust::ROOT_BITS);
+        let err = mantle::syscalls().irqcontrol_get(self.cap.peek_index(), irq, crust::ROOT_SLOT, output_slot.peek_index(), crust::ROOT_BITS);
         if err.is_error() {
             Err((err, output_slot))
         } else {
@@ -33,14 +33,14 @@ impl IRQHandler {
     }
 
     pub fn ack(&self) -> core::result::Result<(), KError> {
-        mantle::irqhandler_ack(self.cap.peek_index()).to_result()
+        mantle::syscalls().irqhandler_ack(self.cap.peek_index()).to_result()
     }
 
     pub fn clear(&self) -> core::result::Result<(), KError> {
-        mantle::irqhandler_clear(self.cap.peek_index()).to_result()
+        mantle::syscalls().irqhandler_clear(self.cap.peek_index()).to_result()
     }
 
     pub fn set_notification(&self, notification: &Notification) -> core::result::Result<(), KError> {
-        mantle::irqhandler_set_notification(self.cap.peek_index(), notification.peek_index()).to_result()
+        mantle::syscalls().irqhandler_set_notification(self.cap.peek_index(), notification.peek_index()).to_result()
     }
 }
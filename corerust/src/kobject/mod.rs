@@ -5,6 +5,8 @@ mod untyped;
 mod page4k;
 mod notification;
 mod irq;
+mod tcb;
+mod endpoint;
 
 pub use self::cap::{Cap, CapSlot};
 pub use self::capset::{CapSet, CapSlotSet};
@@ -12,4 +14,11 @@ pub use self::caprange::CapRange;
 pub use self::untyped::{Untyped, UntypedSet};
 pub use self::page4k::{Page4K, RegionMappedPage4K, FixedMappedPage4K, PageTable};
 pub use self::notification::Notification;
-pub use self::irq::{IRQControl, IRQHandler};
\ No newline at end of file
+pub use self::irq::{IRQControl, IRQHandler};
+pub use self::tcb::TCB;
+pub use self::endpoint::Endpoint;
+
+#[cfg(feature = "ktest")]
+pub use self::caprange::tests as caprange_tests;
+#[cfg(feature = "ktest")]
+pub use self::capset::tests as capset_tests;
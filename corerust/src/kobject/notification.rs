@@ -33,3 +33,47 @@ impl Notification {
 
     // TODO: badging
 }
+
+#[cfg(test)]
+mod tests {
+    use ::core;
+    use ::crust;
+    use ::kobject::*;
+    use ::ktest::TestResult;
+    use ::mantle::kernel::SMALL_BITS;
+    use ::mantle::sim;
+
+    // from an untyped of the simulated kernel's; everything involved is leaked at the end
+    fn small_untyped(paddr: usize) -> Untyped {
+        Untyped::from_cap(CapSlot::from_index(sim::add_untyped(paddr, SMALL_BITS)).assert_populated(), SMALL_BITS)
+    }
+
+    fn signals() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let slot = check_ok!(crust::capalloc::allocate_cap_slot());
+        let notification = check_ok!(small_untyped(0x1000).become_notification(slot).map_err(|(err, ut, slot)| {
+            core::mem::forget((ut, slot));
+            err
+        }));
+        check_eq!(notification.poll(), 0);
+        check_eq!(sim::pending(notification.peek_index()), None);
+        notification.signal();
+        notification.signal();
+        check_eq!(sim::pending(notification.peek_index()), Some(0));
+        // both signals are taken at once
+        check_eq!(notification.wait(), 0);
+        check_eq!(sim::pending(notification.peek_index()), None);
+        core::mem::forget(notification);
+
+        let slot = check_ok!(crust::capalloc::allocate_cap_slot());
+        let endpoint = check_ok!(small_untyped(0x2000).become_endpoint(slot).map_err(|(err, ut, slot)| {
+            core::mem::forget((ut, slot));
+            err
+        }));
+        check_eq!(endpoint.poll(), None);
+        core::mem::forget(endpoint);
+        Ok(())
+    }
+
+    test_cases!(; host: signals);
+}
@@ -68,11 +68,24 @@ impl Page4K {
 
     fn map_at_address(&self, vaddr: usize, writable: bool) -> KError {
         let crights = if writable { 3 } else { 2 };
-        mantle::x86_page_map(self.cap.peek_index(), crust::ROOT_PAGEDIR, vaddr, crights, 0)
+        let err = mantle::syscalls().x86_page_map(self.cap.peek_index(), crust::ROOT_PAGEDIR, vaddr, crights, 0);
+        if err == KError::NoError {
+            crust::vspace::note_mapped(vaddr, true);
+        }
+        err
     }
 
-    fn unmap(&self) -> KError {
-        mantle::x86_page_unmap(self.cap.peek_index())
+    fn unmap_at(&self, vaddr: usize) -> KError {
+        let err = mantle::syscalls().x86_page_unmap(self.cap.peek_index());
+        if err == KError::NoError {
+            crust::vspace::note_mapped(vaddr, false);
+        }
+        err
+    }
+
+    pub fn get_paddr(&self) -> core::result::Result<usize, KError> {
+        let (err, paddr) = mantle::syscalls().x86_page_get_address(self.cap.peek_index());
+        err.to_result().map(|()| paddr)
     }
 
     pub fn map_into_addr(self, vaddr: usize, writable: bool) -> core::result::Result<FixedMappedPage4K, (Page4K, KError)> {
@@ -134,8 +147,12 @@ impl FixedMappedPage4K {
         out
     }
 
+    pub fn get_paddr(&self) -> core::result::Result<usize, KError> {
+        self.page.get_paddr()
+    }
+
     pub fn unmap(self) -> Page4K {
-        assert!(self.page.unmap() == KError::NoError);
+        assert!(self.page.unmap_at(self.vaddr) == KError::NoError);
         self.page
     }
 }
@@ -150,6 +167,10 @@ impl RegionMappedPage4K {
         self.vregion.to_4k_address()
     }
 
+    pub fn peek_index(&self) -> usize {
+        self.page.cap.peek_index()
+    }
+
     pub fn get_ptr(&mut self) -> *mut u8 {
         self.get_addr() as *mut u8
     }
@@ -161,7 +182,7 @@ impl RegionMappedPage4K {
     }
 
     pub fn unmap(self) -> Page4K {
-        assert!(self.page.unmap() == KError::NoError);
+        assert!(self.page.unmap_at(self.vregion.to_4k_address()) == KError::NoError);
         crust::vspace::free_vregion(self.vregion);
         self.page
     }
@@ -183,11 +204,11 @@ impl PageTable {
     }
 
     fn map_at_address(&self, vaddr: usize) -> KError {
-        mantle::x86_page_table_map(self.cap.peek_index(), crust::ROOT_PAGEDIR, vaddr, 0)
+        mantle::syscalls().x86_page_table_map(self.cap.peek_index(), crust::ROOT_PAGEDIR, vaddr, 0)
     }
 
     fn unmap(&self) -> KError {
-        mantle::x86_page_table_unmap(self.cap.peek_index())
+        mantle::syscalls().x86_page_table_unmap(self.cap.peek_index())
     }
 
     pub fn map_into_addr(self, vaddr: usize) -> core::result::Result<MappedPageTable, (PageTable, KError)> {
@@ -0,0 +1,61 @@
+use ::kobject::*;
+use ::core;
+use ::mantle;
+use ::mantle::KError;
+use ::mantle::kernel::UserContext;
+use ::crust;
+
+pub struct TCB {
+    cap: Cap,
+    parent: Option<Untyped> // only for those we retyped ourselves
+}
+
+impl TCB {
+    pub fn from_cap(cap: Cap) -> TCB {
+        TCB { cap, parent: None }
+    }
+
+    pub fn from_retyping(cap: Cap, parent: Untyped) -> TCB {
+        TCB { cap, parent: Some(parent) }
+    }
+
+    pub fn free(self) -> (Untyped, CapSlot) {
+        (self.parent.expect("freeing a TCB that wasn't retyped"), self.cap.delete())
+    }
+
+    pub fn peek_index(&self) -> usize {
+        self.cap.peek_index()
+    }
+
+    pub fn read_registers(&self, suspend: bool) -> core::result::Result<UserContext, KError> {
+        let (err, regs) = mantle::syscalls().tcb_read_registers(self.cap.peek_index(), suspend, 0);
+        err.to_result()?;
+        Ok(regs)
+    }
+
+    pub fn write_registers(&self, regs: &UserContext, resume: bool) -> core::result::Result<(), KError> {
+        mantle::syscalls().tcb_write_registers(self.cap.peek_index(), resume, 0, regs).to_result()
+    }
+
+    pub fn suspend(&self) -> core::result::Result<(), KError> {
+        mantle::syscalls().tcb_suspend(self.cap.peek_index()).to_result()
+    }
+
+    pub fn resume(&self) -> core::result::Result<(), KError> {
+        mantle::syscalls().tcb_resume(self.cap.peek_index()).to_result()
+    }
+
+    // gives it our own cspace and vspace, and where its faults are sent, if anywhere
+    pub fn set_space(&self, faults: Option<&Endpoint>) -> core::result::Result<(), KError> {
+        let fault_ep = faults.map_or(::mantle::kernel::CAP_NULL, |ep| ep.peek_index());
+        mantle::syscalls().tcb_set_space(self.cap.peek_index(), fault_ep, crust::ROOT_SLOT, 0, crust::ROOT_PAGEDIR, 0).to_result()
+    }
+
+    pub fn set_ipc_buffer(&self, page: &RegionMappedPage4K) -> core::result::Result<(), KError> {
+        mantle::syscalls().tcb_set_ipc_buffer(self.cap.peek_index(), page.get_addr(), page.peek_index()).to_result()
+    }
+
+    pub fn set_priority(&self, priority: u8) -> core::result::Result<(), KError> {
+        mantle::syscalls().tcb_set_priority(self.cap.peek_index(), priority).to_result()
+    }
+}
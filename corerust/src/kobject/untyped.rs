@@ -31,9 +31,9 @@ impl Untyped {
         assert!(capslots.full());
         assert!(capslots.count() > 0);
         assert!(capslots.count() == capslots.capacity());
-        let err = mantle::untyped_retype(self.cap.peek_index(), objtype as usize, size_bits as usize,
-                                         crust::ROOT_SLOT, 0, 0,
-                                         capslots.start(), capslots.count());
+        let err = mantle::syscalls().untyped_retype(self.cap.peek_index(), objtype as usize, size_bits as usize,
+                                                    crust::ROOT_SLOT, 0, 0,
+                                                    capslots.start(), capslots.count());
         if err.is_okay() {
             Ok(capslots.assert_derive_capset())
         } else {
@@ -92,6 +92,15 @@ impl Untyped {
         }
     }
 
+    // a TCB is smaller than a page, but this way it comes from the same pool as pages do
+    pub fn become_tcb(self, capslot: CapSlot) -> core::result::Result<TCB, (KError, Untyped, CapSlot)> {
+        assert!(self.size_bits == PAGE_4K_BITS);
+        match self.retype_raw_one(ObjectType::TCBObject, 0, capslot) {
+            Ok(cap) => Ok(TCB::from_retyping(cap, self)),
+            Err((err, capslot)) => Err((err, self, capslot))
+        }
+    }
+
     pub fn become_notification(self, capslot: CapSlot) -> core::result::Result<Notification, (KError, Untyped, CapSlot)> {
         assert!(self.size_bits == SMALL_BITS);
         match self.retype_raw_one(ObjectType::NotificationObject, 0, capslot) {
@@ -99,6 +108,14 @@ impl Untyped {
             Err((err, capslot)) => Err((err, self, capslot))
         }
     }
+
+    pub fn become_endpoint(self, capslot: CapSlot) -> core::result::Result<Endpoint, (KError, Untyped, CapSlot)> {
+        assert!(self.size_bits == SMALL_BITS);
+        match self.retype_raw_one(ObjectType::EndpointObject, 0, capslot) {
+            Ok(cap) => Ok(Endpoint::from_retyping(cap, self)),
+            Err((err, capslot)) => Err((err, self, capslot))
+        }
+    }
 }
 
 impl core::fmt::Display for Untyped {
@@ -0,0 +1,37 @@
+use std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+use ::crust;
+use ::kobject::CapRange;
+use ::ktest::TestResult;
+use ::mantle::sim;
+
+// running the tests under `cargo test`. the simulated kernel belongs to one thread, but the allocators above it are
+// shared by the whole process, so the tests that need the kernel take turns, through setup.
+
+// where the root task's image is, as far as vspace is concerned
+const IMAGE_START: usize = 0x400000;
+const IMAGE_LEN: usize = 0x100000;
+
+static mut LOCK: Option<Mutex<()>> = None;
+static INIT: Once = ONCE_INIT;
+
+pub fn assert_passes(result: TestResult) {
+    if let Err(failure) = result {
+        panic!("{} at {}:{}", failure.message, failure.file, failure.line);
+    }
+}
+
+// gives the calling test a fresh simulated kernel, and the allocators to itself until the guard is dropped. cap slots
+// and virtual memory that earlier tests didn't give back stay allocated, but there's plenty of both.
+pub fn setup() -> MutexGuard<'static, ()> {
+    INIT.call_once(|| {
+        unsafe { LOCK = Some(Mutex::new(())) };
+        crust::capalloc::init_cslots(CapRange::range(sim::FIRST_EMPTY_SLOT, sim::SLOT_COUNT));
+        crust::vspace::init_vspace(IMAGE_START, IMAGE_LEN);
+    });
+    let guard = match unsafe { LOCK.as_ref() }.unwrap().lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner() // an earlier test failed while it had the lock, which is fine here
+    };
+    sim::reset();
+    guard
+}
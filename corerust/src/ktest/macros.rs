@@ -0,0 +1,82 @@
+// for use in the `tests` submodules; everything here returns from the test with a Failure rather than panicking,
+// so that the run can go on to the next one
+
+// lists a module's tests. the first run everywhere; those after `target:` need the real kernel, like anything that
+// touches mapped memory, and those after `host:` need the simulated one, like anything that makes up its own untypeds.
+// the host-only tests themselves should be marked #[cfg(test)].
+macro_rules! test_cases {
+    ($($name:ident),*) => (test_cases!($($name),*; target: ; host: ););
+    ($($name:ident),*; target: $($target:ident),*) => (test_cases!($($name),*; target: $($target),*; host: ););
+    ($($name:ident),*; host: $($host:ident),*) => (test_cases!($($name),*; target: ; host: $($host),*););
+    ($($name:ident),*; target: $($target:ident),*; host: $($host:ident),*) => (
+        #[cfg(feature = "ktest")]
+        pub const TESTS: &'static [::ktest::TestCase] = &[
+            $(::ktest::TestCase { name: stringify!($name), run: $name },)*
+            $(::ktest::TestCase { name: stringify!($target), run: $target },)*
+        ];
+
+        #[cfg(test)]
+        mod host {
+            $(
+                #[test]
+                fn $name() {
+                    ::ktest::host::assert_passes(super::$name())
+                }
+            )*
+            $(
+                #[test]
+                fn $host() {
+                    ::ktest::host::assert_passes(super::$host())
+                }
+            )*
+            $(
+                #[test]
+                #[ignore]
+                fn $target() {
+                    let _ = super::$target; // only runs in the test build of init.elf
+                }
+            )*
+        }
+    )
+}
+
+macro_rules! fail {
+    ($message:expr) => (
+        return Err(::ktest::Failure { message: $message, file: file!(), line: line!() })
+    )
+}
+
+macro_rules! check {
+    ($cond:expr) => (
+        if !$cond {
+            fail!(concat!("check failed: ", stringify!($cond)));
+        }
+    )
+}
+
+macro_rules! check_eq {
+    ($left:expr, $right:expr) => (
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    debugc!("# left: {:?}", left);
+                    debugc!("# right: {:?}", right);
+                    fail!(concat!("check failed: ", stringify!($left), " == ", stringify!($right)));
+                }
+            }
+        }
+    )
+}
+
+// the value inside an Ok, or else a failure that shows the error
+macro_rules! check_ok {
+    ($result:expr) => (
+        match $result {
+            Ok(value) => value,
+            Err(err) => {
+                debugc!("# error: {:?}", err);
+                fail!(concat!("check failed: ", stringify!($result), " returned an error"));
+            }
+        }
+    )
+}
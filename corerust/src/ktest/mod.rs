@@ -0,0 +1,27 @@
+// tests, written once and run in two places: inside the booted root task, in an init.elf built with `./build.sh test`
+// (see runner), and on the host with `cargo test`, against mantle::sim (see host). each module with tests has a
+// `tests` submodule that lists them with test_cases!, saying which can only run on the real kernel, and which need
+// the simulated one.
+
+#[macro_use]
+mod macros;
+#[cfg(feature = "ktest")]
+mod runner;
+#[cfg(test)]
+pub mod host;
+
+#[cfg(feature = "ktest")]
+pub use self::runner::{run_all, report_panic};
+
+pub struct Failure {
+    pub message: &'static str,
+    pub file: &'static str,
+    pub line: u32
+}
+
+pub type TestResult = Result<(), Failure>;
+
+pub struct TestCase {
+    pub name: &'static str,
+    pub run: fn() -> TestResult
+}
@@ -0,0 +1,109 @@
+use ::core::cell::Cell;
+use ::mantle;
+use ::mantle::exit::can_exit;
+use ::mantle::concurrency::SingleThreaded;
+use ::ktest::TestCase;
+
+// runs the tests inside the booted root task. results go to the debug output in TAP form, so that whatever is
+// watching the serial port can pick them out:
+//     1..12
+//     ok 1 - memory::linkedlist::push_back
+//     not ok 2 - fs::ramfs::truncate
+//     # check failed: len == 10 at src/fs/ramfs.rs:340
+//     # passed 11, failed 1
+// there's no unwinding, so a test that panics ends the run; it's still reported as failed, and the summary printed.
+
+struct Suite {
+    name: &'static str,
+    tests: &'static [TestCase]
+}
+
+const SUITES: &'static [Suite] = &[
+    Suite { name: "kobject::caprange", tests: ::kobject::caprange_tests::TESTS },
+    Suite { name: "kobject::capset", tests: ::kobject::capset_tests::TESTS },
+    Suite { name: "crust::vspace", tests: ::crust::vspace::tests::TESTS },
+    Suite { name: "crust::thread", tests: ::crust::thread::tests::TESTS },
+    Suite { name: "memory::linkedlist", tests: ::memory::linkedlist_tests::TESTS },
+    Suite { name: "memory::alloc", tests: ::memory::alloc_tests::TESTS },
+    Suite { name: "memory::global", tests: ::memory::global_tests::TESTS },
+    Suite { name: "memory::heapdebug", tests: ::memory::heapdebug_tests::TESTS },
+    Suite { name: "memory::report", tests: ::memory::report_tests::TESTS },
+    Suite { name: "memory::string", tests: ::memory::string::tests::TESTS },
+    Suite { name: "memory::untyped", tests: ::memory::untyped::tests::TESTS },
+    Suite { name: "memory::dma", tests: ::memory::dma::tests::TESTS },
+    Suite { name: "drivers::ansi", tests: ::drivers::ansi::tests::TESTS },
+    Suite { name: "drivers::block", tests: ::drivers::block::tests::TESTS },
+    Suite { name: "drivers::vga", tests: ::drivers::vga::tests::TESTS },
+    Suite { name: "fs::ramfs", tests: ::fs::ramfs::tests::TESTS },
+    Suite { name: "fs::fat", tests: ::fs::fat::tests::TESTS },
+    Suite { name: "fs::vfs", tests: ::fs::vfs::tests::TESTS }
+];
+
+// set with `./build.sh test <filter>`: only tests with the filter somewhere in their suite's name or their own are run,
+// so `linkedlist` picks out a suite and `push_back` a test
+const FILTER: Option<&'static str> = option_env!("KTEST_FILTER");
+
+#[derive(Copy, Clone)]
+struct Progress {
+    number: usize,
+    suite: &'static str,
+    test: &'static str,
+    passed: usize,
+    failed: usize
+}
+
+static RUNNING: SingleThreaded<Cell<Option<Progress>>> = SingleThreaded(Cell::new(None));
+
+fn is_selected(suite: &Suite, test: &TestCase) -> bool {
+    match FILTER {
+        Some(filter) => suite.name.contains(filter) || test.name.contains(filter),
+        None => true
+    }
+}
+
+fn print_summary(progress: &Progress) {
+    debugc!("# passed {}, failed {}", progress.passed, progress.failed);
+}
+
+// runs every selected test, then exits through mantle::exit with whether they all passed. if there's no way to
+// exit, it returns instead, and the root task carries on as usual.
+pub fn run_all() {
+    let total = SUITES.iter().map(|s| s.tests.iter().filter(|t| is_selected(s, t)).count()).sum::<usize>();
+    debugc!("1..{}", total);
+    let mut progress = Progress { number: 0, suite: "", test: "", passed: 0, failed: 0 };
+    for suite in SUITES {
+        for test in suite.tests.iter().filter(|t| is_selected(suite, t)) {
+            progress.number += 1;
+            progress.suite = suite.name;
+            progress.test = test.name;
+            RUNNING.get().set(Some(progress));
+            match (test.run)() {
+                Ok(()) => {
+                    progress.passed += 1;
+                    debugc!("ok {} - {}::{}", progress.number, suite.name, test.name);
+                }
+                Err(failure) => {
+                    progress.failed += 1;
+                    debugc!("not ok {} - {}::{}", progress.number, suite.name, test.name);
+                    debugc!("# {} at {}:{}", failure.message, failure.file, failure.line);
+                }
+            }
+        }
+    }
+    RUNNING.get().set(None);
+    print_summary(&progress);
+    if can_exit() {
+        mantle::exit(if progress.failed == 0 { mantle::EXIT_SUCCESS } else { mantle::EXIT_FAILURE });
+    }
+}
+
+// called on panic, before anything else is done about it
+pub fn report_panic() {
+    if let Some(mut progress) = RUNNING.get().get() {
+        RUNNING.get().set(None);
+        progress.failed += 1;
+        debugc!("not ok {} - {}::{}", progress.number, progress.suite, progress.test);
+        debugc!("# panicked; the rest of the tests were not run");
+        print_summary(&progress);
+    }
+}
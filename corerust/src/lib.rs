@@ -3,30 +3,32 @@
 #![feature(asm)]
 #![feature(const_fn)]
 #![feature(drop_types_in_const)]
-// just for Box reimpl
-#![feature(fundamental)]
-#![feature(box_syntax)]
-#![feature(generic_param_attrs)]
-#![feature(dropck_eyepatch)]
-#![feature(custom_attribute)]
-#![feature(unboxed_closures)]
-#![feature(fused)]
-#![feature(unsize)]
-#![feature(coerce_unsized)]
-#![feature(placement_new_protocol)]
-#![feature(unique)]
-#![feature(exact_size_is_empty)]
-#![feature(fn_traits)]
-#![feature(core_intrinsics)]
+#![feature(alloc)]
+#![feature(allocator_api)]
+#![feature(global_allocator)]
+#![feature(linkage)]
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+
+// Box, Vec, String and the rest, allocated through memory::global
+extern crate alloc;
+// under `cargo test` this is built against std for the host, with mantle::sim standing in for the kernel
+#[cfg(test)]
+extern crate core;
 
 #[macro_use]
 pub mod mantle;
+// before everything else, so that its macros can be used in their tests
+#[cfg(any(test, feature = "ktest"))]
+#[macro_use]
+mod ktest;
+// likewise, for at_site!
+#[macro_use]
+mod memory;
 mod kobject;
 mod crust;
-mod memory;
 mod drivers;
+mod fs;
 
 use core::fmt::Write;
 
@@ -37,6 +39,30 @@ pub fn main(bootinfo: &mantle::kernel::BootInfo) {
     com1.send_str("RECEIVED: '");
     com1.send_str(line.as_str());
     com1.send_str("'\n"); */
-    drivers::keyboard::init();
+    drivers::debugexit::init();
+    drivers::acpi::init(bootinfo);
+    drivers::acpi::print_tables(mantle::debug()).unwrap();
+    drivers::pci::scan();
+    drivers::pci::print_devices(mantle::debug()).unwrap();
+    fs::vfs::init();
+    crust::shell::start(drivers::console::SHELL_CONSOLE);
+    drivers::keyboard::set_handler(drivers::console::handle_key);
+    drivers::driver::add_isa_device(drivers::driver::ISA_PS2_CONTROLLER);
+    drivers::driver::add_isa_device(drivers::ata::ISA_IDE_PRIMARY);
+    drivers::driver::add_isa_device(drivers::ata::ISA_IDE_SECONDARY);
+    drivers::driver::register(memory::Box::new(drivers::keyboard::PS2Driver));
+    drivers::driver::register(memory::Box::new(drivers::ata::AtaDriver::new()));
+    drivers::driver::register(memory::Box::new(drivers::virtioblk::VirtioBlkDriver));
+    drivers::driver::register(memory::Box::new(drivers::bga::BgaDriver));
+    drivers::driver::bind_all();
+    drivers::driver::print_bindings(mantle::debug()).unwrap();
+    #[cfg(feature = "hires")]
+    {
+        if let Err(err) = drivers::console::enter_hires(1024, 768, 32) {
+            warn!("could not switch to high resolution: {:?}", err);
+        }
+    }
+    #[cfg(feature = "ktest")]
+    ktest::run_all();
     drivers::irq::mainloop();
 }
@@ -32,11 +32,26 @@ fn handle_err(outputs: (u32, usize, usize, usize, usize), quiet: bool) -> KError
     result
 }
 
+// port IO is far too frequent to report on every call
+fn is_ioport_in(label: u32) -> bool {
+    label == kernel::TAG_X86_IOPORT_IN8 || label == kernel::TAG_X86_IOPORT_IN16 || label == kernel::TAG_X86_IOPORT_IN32
+}
+
+fn is_ioport_out(label: u32) -> bool {
+    label == kernel::TAG_X86_IOPORT_OUT8 || label == kernel::TAG_X86_IOPORT_OUT16 || label == kernel::TAG_X86_IOPORT_OUT32
+}
+
 unsafe fn call_0(service: usize, label: u32, caps: u8) -> KError {
     let tag = kernel::messageinfo_new(label, 0, caps, 0);
     handle_err(kio::call_with_mrs(service, tag, 0, 0, 0, 0), false)
 }
 
+unsafe fn call_0o(service: usize, label: u32, caps: u8) -> (KError, usize, usize, usize, usize) {
+    let tag = kernel::messageinfo_new(label, 0, caps, 0);
+    let outputs = kio::call_with_mrs(service, tag, 0, 0, 0, 0);
+    (handle_err(outputs, false), outputs.1, outputs.2, outputs.3, outputs.4)
+}
+
 unsafe fn call_1(service: usize, label: u32, caps: u8, mr0: usize) -> KError {
     let tag = kernel::messageinfo_new(label, 0, caps, 2);
     handle_err(kio::call_with_mrs(service, tag, mr0, 0, 0, 0), false)
@@ -45,12 +60,18 @@ unsafe fn call_1(service: usize, label: u32, caps: u8, mr0: usize) -> KError {
 unsafe fn call_1o(service: usize, label: u32, caps: u8, mr0: usize) -> (KError, usize, usize, usize, usize) {
     let tag = kernel::messageinfo_new(label, 0, caps, 2);
     let outputs = kio::call_with_mrs(service, tag, mr0, 0, 0, 0);
-    (handle_err(outputs, label == kernel::TAG_X86_IOPORT_IN8), outputs.1, outputs.2, outputs.3, outputs.4)
+    (handle_err(outputs, is_ioport_in(label)), outputs.1, outputs.2, outputs.3, outputs.4)
 }
 
 unsafe fn call_2(service: usize, label: u32, caps: u8, mr0: usize, mr1: usize) -> KError {
     let tag = kernel::messageinfo_new(label, 0, caps, 2);
-    handle_err(kio::call_with_mrs(service, tag, mr0, mr1, 0, 0), label == kernel::TAG_X86_IOPORT_OUT8)
+    handle_err(kio::call_with_mrs(service, tag, mr0, mr1, 0, 0), is_ioport_out(label))
+}
+
+unsafe fn call_2o(service: usize, label: u32, caps: u8, mr0: usize, mr1: usize) -> (KError, usize, usize, usize, usize) {
+    let tag = kernel::messageinfo_new(label, 0, caps, 2);
+    let outputs = kio::call_with_mrs(service, tag, mr0, mr1, 0, 0);
+    (handle_err(outputs, false), outputs.1, outputs.2, outputs.3, outputs.4)
 }
 
 unsafe fn call_3(service: usize, label: u32, caps: u8, mr0: usize, mr1: usize, mr2: usize) -> KError {
@@ -99,6 +120,12 @@ pub fn x86_page_unmap(service: usize) -> KError {
     unsafe { call_0(service, kernel::TAG_X86_PAGE_UNMAP, 0) }
 }
 
+pub fn x86_page_get_address(service: usize) -> (KError, usize) {
+    debugnl!("performing x86_page_get_address(service={})", service);
+    let out = unsafe { call_0o(service, kernel::TAG_X86_PAGE_GET_ADDRESS, 0) };
+    (out.0, out.1)
+}
+
 pub fn x86_page_table_map(service: usize, vroot: usize, vaddr: usize, vmattrs: usize) -> KError {
     debugnl!("performing x86_page_table_map(service={}, vroot={}, vaddr={:#X}, vmattrs={})",
         service, vroot, vaddr, vmattrs);
@@ -122,6 +149,24 @@ pub fn x86_ioport_out8(service: usize, port: u16, data: u8) -> KError {
     unsafe { call_2(service, kernel::TAG_X86_IOPORT_OUT8, 0, port as usize, data as usize) }
 }
 
+pub fn x86_ioport_in16(service: usize, port: u16) -> (KError, u16) {
+    let out = unsafe { call_1o(service, kernel::TAG_X86_IOPORT_IN16, 0, port as usize) };
+    (out.0, out.1 as u16)
+}
+
+pub fn x86_ioport_out16(service: usize, port: u16, data: u16) -> KError {
+    unsafe { call_2(service, kernel::TAG_X86_IOPORT_OUT16, 0, port as usize, data as usize) }
+}
+
+pub fn x86_ioport_in32(service: usize, port: u16) -> (KError, u32) {
+    let out = unsafe { call_1o(service, kernel::TAG_X86_IOPORT_IN32, 0, port as usize) };
+    (out.0, out.1 as u32)
+}
+
+pub fn x86_ioport_out32(service: usize, port: u16, data: u32) -> KError {
+    unsafe { call_2(service, kernel::TAG_X86_IOPORT_OUT32, 0, port as usize, data as usize) }
+}
+
 pub fn irqcontrol_get(service: usize, irq: u32, root: usize, index: usize, depth: usize) -> KError {
     debugnl!("performing irqcontrol_get(service={}, irq={}, root={}, index={}, depth={})", service, irq, root, index, depth);
     kio::set_cap(0, root);
@@ -143,3 +188,65 @@ pub fn irqhandler_clear(service: usize) -> KError {
     debugnl!("performing irqhandler_clear(service={})", service);
     unsafe { call_0(service, kernel::TAG_IRQ_CLEAR_IRQ_HANDLER, 0) }
 }
+
+pub fn tcb_read_registers(service: usize, suspend_source: bool, arch_flags: u8) -> (KError, kernel::UserContext) {
+    debugnl!("performing tcb_read_registers(service={}, suspend_source={}, arch_flags={})", service, suspend_source, arch_flags);
+    let flags = (if suspend_source { 1 } else { 0 }) | ((arch_flags as usize) << 8);
+    let mut regs: kernel::UserContext = [0; kernel::USER_CONTEXT_LEN];
+    let (err, mr0, mr1, mr2, mr3) = unsafe {
+        call_2o(service, kernel::TAG_TCB_READ_REGISTERS, 0, flags, kernel::USER_CONTEXT_LEN)
+    };
+    if err.is_okay() {
+        regs[0] = mr0;
+        regs[1] = mr1;
+        regs[2] = mr2;
+        regs[3] = mr3;
+        for i in 4..kernel::USER_CONTEXT_LEN {
+            regs[i] = kio::get_mr(i as u32);
+        }
+    }
+    (err, regs)
+}
+
+pub fn tcb_write_registers(service: usize, resume_target: bool, arch_flags: u8, regs: &kernel::UserContext) -> KError {
+    debugnl!("performing tcb_write_registers(service={}, resume_target={}, arch_flags={})", service, resume_target, arch_flags);
+    let flags = (if resume_target { 1 } else { 0 }) | ((arch_flags as usize) << 8);
+    // the first two message registers are taken by the flags and count, so the context starts at mr2
+    for i in 2..kernel::USER_CONTEXT_LEN {
+        kio::set_mr((i + 2) as u32, regs[i]);
+    }
+    let tag = kernel::messageinfo_new(kernel::TAG_TCB_WRITE_REGISTERS, 0, 0, (kernel::USER_CONTEXT_LEN + 2) as u8);
+    unsafe {
+        handle_err(kio::call_with_mrs(service, tag, flags, kernel::USER_CONTEXT_LEN, regs[0], regs[1]), false)
+    }
+}
+
+pub fn tcb_suspend(service: usize) -> KError {
+    debugnl!("performing tcb_suspend(service={})", service);
+    unsafe { call_0(service, kernel::TAG_TCB_SUSPEND, 0) }
+}
+
+pub fn tcb_resume(service: usize) -> KError {
+    debugnl!("performing tcb_resume(service={})", service);
+    unsafe { call_0(service, kernel::TAG_TCB_RESUME, 0) }
+}
+
+pub fn tcb_set_space(service: usize, fault_ep: usize, cspace_root: usize, cspace_root_data: usize,
+                     vspace_root: usize, vspace_root_data: usize) -> KError {
+    debugnl!("performing tcb_set_space(service={}, fault_ep={}, cspace_root={}, cspace_root_data={}, vspace_root={}, vspace_root_data={})",
+        service, fault_ep, cspace_root, cspace_root_data, vspace_root, vspace_root_data);
+    kio::set_cap(0, cspace_root);
+    kio::set_cap(1, vspace_root);
+    unsafe { call_3(service, kernel::TAG_TCB_SET_SPACE, 2, fault_ep, cspace_root_data, vspace_root_data) }
+}
+
+pub fn tcb_set_ipc_buffer(service: usize, buffer: usize, buffer_frame: usize) -> KError {
+    debugnl!("performing tcb_set_ipc_buffer(service={}, buffer={:#X}, buffer_frame={})", service, buffer, buffer_frame);
+    kio::set_cap(0, buffer_frame);
+    unsafe { call_1(service, kernel::TAG_TCB_SET_IPC_BUFFER, 1, buffer) }
+}
+
+pub fn tcb_set_priority(service: usize, priority: u8) -> KError {
+    debugnl!("performing tcb_set_priority(service={}, priority={})", service, priority);
+    unsafe { call_1(service, kernel::TAG_TCB_SET_PRIORITY, 0, priority as usize) }
+}
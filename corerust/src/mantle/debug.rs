@@ -1,7 +1,7 @@
 use ::core;
 use ::core::fmt::*;
 use mantle::concurrency::SingleThreaded;
-use mantle::kio;
+use mantle::syscalls;
 use core::ops::DerefMut;
 use memory::Box;
 
@@ -13,7 +13,7 @@ impl Write for DebugOutput {
     fn write_str(&mut self, s: &str) -> Result {
         if DEBUG_ON {
             for c in s.bytes() {
-                kio::debug_put_char(c);
+                syscalls().debug_put_char(c);
             }
             let mut rmut: core::cell::RefMut<Option<&'static mut Write>> = DEBUG_MIRROR.get().borrow_mut();
             let rmutr: &mut Option<&'static mut Write> = rmut.deref_mut();
@@ -46,6 +46,17 @@ macro_rules! debug {
     ($fmt:expr, $($arg:tt)*) => (write!(::mantle::debug::out(), concat!("[debug] ", $fmt, "\n"), $($arg)*));
 }
 
+// log levels are colored with ANSI escapes, which both serial terminals and VGAOutput understand
+macro_rules! warn {
+    ($fmt:expr) => (write!(::mantle::debug::out(), concat!("\x1b[33m[warn]\x1b[0m ", $fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (write!(::mantle::debug::out(), concat!("\x1b[33m[warn]\x1b[0m ", $fmt, "\n"), $($arg)*));
+}
+
+macro_rules! error {
+    ($fmt:expr) => (write!(::mantle::debug::out(), concat!("\x1b[31m[error]\x1b[0m ", $fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (write!(::mantle::debug::out(), concat!("\x1b[31m[error]\x1b[0m ", $fmt, "\n"), $($arg)*));
+}
+
 macro_rules! debugc {
     ($fmt:expr) => (write!(::mantle::debug::out(), concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => (write!(::mantle::debug::out(), concat!($fmt, "\n"), $($arg)*));
@@ -0,0 +1,30 @@
+use ::core::cell::Cell;
+use mantle::concurrency::SingleThreaded;
+
+// ending the run with a status that whoever started it can see. the root task can't exit as such, so this depends on
+// something like QEMU's isa-debug-exit device having been found; otherwise there's nothing to do but hang.
+//
+// success isn't 0: isa-debug-exit would make that QEMU status 1, which is also what QEMU exits with when it can't
+// start at all.
+
+pub const EXIT_SUCCESS: u8 = 0x10;
+pub const EXIT_FAILURE: u8 = 1;
+
+static EXIT_HANDLER: SingleThreaded<Cell<Option<fn(u8)>>> = SingleThreaded(Cell::new(None));
+
+// the handler doesn't return if it worked
+pub fn set_exit_handler(handler: fn(u8)) {
+    EXIT_HANDLER.get().set(Some(handler));
+}
+
+pub fn can_exit() -> bool {
+    EXIT_HANDLER.get().get().is_some()
+}
+
+pub fn exit(code: u8) -> ! {
+    if let Some(handler) = EXIT_HANDLER.get().get() {
+        handler(code);
+    }
+    error!("could not exit with code {}; hanging", code);
+    loop {}
+}
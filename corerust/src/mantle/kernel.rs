@@ -9,6 +9,12 @@ pub const PAGE_2M_SIZE: usize = 1 << PAGE_2M_BITS;
 
 pub const SMALL_BITS: u8 = 4;
 
+// a thread's IPC buffer has to be aligned to its size
+pub const IPC_BUFFER_SIZE_BITS: u8 = 10;
+pub const IPC_BUFFER_SIZE: usize = 1 << IPC_BUFFER_SIZE_BITS;
+
+pub const MAX_PRIORITY: u8 = 255; // which the root task starts out with
+
 pub const FAN_OUT_LIMIT_BITS: u8 = 8;
 pub const FAN_OUT_LIMIT: usize = 1 << FAN_OUT_LIMIT_BITS; // configured in kernel
 
@@ -242,3 +248,28 @@ pub fn messageinfo_new(label: u32, caps_unwrapped: u8, extra_caps: u8, length: u
 pub fn messageinfo_get_label(info: MessageInfo) -> u32 {
     (info & 0xfffff000u32) >> 12
 }
+
+// seL4_UserContext for x86_64, in the order used by TCB read/write registers
+pub const USER_CONTEXT_LEN: usize = 19;
+
+pub const REG_RIP: usize = 0;
+pub const REG_RSP: usize = 1;
+pub const REG_RFLAGS: usize = 2;
+pub const REG_RAX: usize = 3;
+pub const REG_RBX: usize = 4;
+pub const REG_RCX: usize = 5;
+pub const REG_RDX: usize = 6;
+pub const REG_RSI: usize = 7;
+pub const REG_RDI: usize = 8;
+pub const REG_RBP: usize = 9;
+pub const REG_R8: usize = 10;
+pub const REG_R15: usize = 17;
+pub const REG_TLS_BASE: usize = 18;
+
+pub type UserContext = [usize; USER_CONTEXT_LEN];
+
+pub const FAULT_NULL: u32 = 0;
+pub const FAULT_CAP: u32 = 1;
+pub const FAULT_UNKNOWN_SYSCALL: u32 = 2;
+pub const FAULT_USER_EXCEPTION: u32 = 3;
+pub const FAULT_VM: u32 = 5;
@@ -1,18 +1,39 @@
 // a mantle surrounds the core
 
+// the host has its own memcpy and friends under `cargo test`
+#[cfg(not(test))]
 extern crate rlibc;
 
 #[macro_use]
 pub mod debug;
 pub mod kernel;
 pub mod kio;
+// the entry point and lang items; under `cargo test`, the host's runtime provides these
+#[cfg(not(test))]
 pub mod start;
-pub mod calls;
+mod calls;
+pub mod syscalls;
+#[cfg(test)]
+pub mod sim;
 pub mod concurrency;
+pub mod exit;
 
 pub use self::kernel::KError;
-pub use self::calls::*;
-pub use self::kio::{signal, wait, poll};
+pub use self::syscalls::{Syscalls, syscalls};
+pub use self::exit::{exit, EXIT_SUCCESS, EXIT_FAILURE};
+
+pub fn signal(dest: usize) {
+    syscalls().signal(dest)
+}
+
+// both return the badge
+pub fn wait(src: usize) -> usize {
+    syscalls().recv(src).1
+}
+
+pub fn poll(src: usize) -> usize {
+    syscalls().nbrecv(src).1
+}
 
 pub fn debug() -> &'static mut ::core::fmt::Write {
     debug::out()
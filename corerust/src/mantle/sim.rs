@@ -0,0 +1,458 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use mantle::kernel;
+use mantle::kernel::{ObjectType, PAGE_4K_BITS, PAGE_2M_BITS, PAGE_2M_SIZE, SMALL_BITS};
+use mantle::syscalls::Syscalls;
+use mantle::KError;
+
+// an in-memory stand-in for seL4, for running under `cargo test` on the host. it keeps track of what every capability
+// slot holds and checks retypes, deletes and mappings the way the kernel would, but there's no memory behind any of
+// it: a mapped page can't actually be read or written.
+//
+// the state is per thread, but the cap slot and address space allocators above it are shared by the whole process, so
+// tests can't really run side by side: ktest::host::setup makes them take turns, and gives each a fresh kernel with
+// nothing but the initial capabilities. untypeds to work with are added with add_untyped, into the slots below
+// FIRST_EMPTY_SLOT, much as bootinfo would list them.
+//
+// there's only the one thread, so IPC can't go anywhere: notifications remember being signalled until they're received
+// from, but sending to an endpoint, or waiting on anything with nothing pending, would block forever, and panics.
+
+pub const FIRST_UNTYPED_SLOT: usize = kernel::CAP_INIT_COUNT;
+pub const FIRST_EMPTY_SLOT: usize = 1024;
+pub const SLOT_COUNT: usize = 1 << 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Object {
+    // bootinfo's initial capabilities, which are never retyped or deleted here
+    Initial,
+    Untyped { paddr: usize, size_bits: u8, watermark: usize },
+    Page { paddr: usize, mapped_at: Option<usize> },
+    PageTable { paddr: usize, mapped_at: Option<usize> },
+    IrqHandler { irq: u32 },
+    Other { objtype: usize, paddr: usize }
+}
+
+struct Entry {
+    object: Object,
+    // the untyped it was retyped from; an untyped only goes back to empty once it has no children left
+    parent: Option<usize>
+}
+
+struct State {
+    slots: BTreeMap<usize, Entry>,
+    next_untyped: usize,
+    ports: BTreeMap<u16, u32>,
+    registers: BTreeMap<usize, kernel::UserContext>,
+    // the words of the notifications that have been signalled, by paddr, so that every cap to one sees the same
+    notifications: BTreeMap<usize, usize>
+}
+
+impl State {
+    fn new() -> State {
+        let mut slots = BTreeMap::new();
+        for slot in 1..kernel::CAP_INIT_COUNT {
+            slots.insert(slot, Entry { object: Object::Initial, parent: None });
+        }
+        State { slots, next_untyped: FIRST_UNTYPED_SLOT, ports: BTreeMap::new(), registers: BTreeMap::new(),
+                notifications: BTreeMap::new() }
+    }
+
+    fn object(&self, slot: usize) -> Option<Object> {
+        self.slots.get(&slot).map(|e| e.object)
+    }
+
+    fn set_object(&mut self, slot: usize, object: Object) {
+        self.slots.get_mut(&slot).unwrap().object = object;
+    }
+
+    fn has_children(&self, slot: usize) -> bool {
+        self.slots.values().any(|e| e.parent == Some(slot))
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::new());
+}
+
+fn with_state<R, F: FnOnce(&mut State) -> R>(f: F) -> R {
+    STATE.with(|state| f(&mut *state.borrow_mut()))
+}
+
+// drops everything that this thread's kernel holds, and starts over
+pub fn reset() {
+    with_state(|state| *state = State::new());
+}
+
+// returns the slot that now holds the untyped
+pub fn add_untyped(paddr: usize, size_bits: u8) -> usize {
+    assert!(paddr & ((1 << size_bits) - 1) == 0);
+    with_state(|state| {
+        let slot = state.next_untyped;
+        assert!(slot < FIRST_EMPTY_SLOT, "too many simulated untypeds");
+        state.next_untyped += 1;
+        state.slots.insert(slot, Entry { object: Object::Untyped { paddr, size_bits, watermark: 0 }, parent: None });
+        slot
+    })
+}
+
+pub fn object(slot: usize) -> Option<Object> {
+    with_state(|state| state.object(slot))
+}
+
+pub fn occupied_slots() -> usize {
+    with_state(|state| state.slots.len())
+}
+
+// the notification's word, if it's been signalled since it was last received from
+pub fn pending(slot: usize) -> Option<usize> {
+    with_state(|state| match state.object(slot) {
+        Some(Object::Other { objtype, paddr }) if objtype == ObjectType::NotificationObject as usize =>
+            state.notifications.get(&paddr).cloned(),
+        _ => None
+    })
+}
+
+fn object_size_bits(objtype: usize, size_bits: usize) -> Option<usize> {
+    Some(match objtype {
+        t if t == ObjectType::UntypedObject as usize => size_bits,
+        t if t == ObjectType::TCBObject as usize => 10,
+        t if t == ObjectType::EndpointObject as usize => SMALL_BITS as usize,
+        t if t == ObjectType::NotificationObject as usize => SMALL_BITS as usize,
+        t if t == ObjectType::CapTableObject as usize => size_bits + 5,
+        t if t == ObjectType::X86LargePageObject as usize => PAGE_2M_BITS as usize,
+        t if t == ObjectType::X864K as usize || t == ObjectType::X86PDPTObject as usize || t == ObjectType::X64PML4Object as usize
+            || t == ObjectType::X86PageTableObject as usize || t == ObjectType::X86PageDirectoryObject as usize => PAGE_4K_BITS as usize,
+        _ => return None
+    })
+}
+
+pub struct SimKernel;
+
+pub static SIM: SimKernel = SimKernel;
+
+impl SimKernel {
+    fn page_map(&self, service: usize, vroot: usize, vaddr: usize, table: bool) -> KError {
+        if vroot != kernel::CAP_INIT_VSPACE {
+            return KError::InvalidCapability;
+        }
+        with_state(|state| {
+            let covering = vaddr & !(PAGE_2M_SIZE - 1);
+            let (paddr, mapped_at) = match state.object(service) {
+                Some(Object::Page { paddr, mapped_at }) if !table => (paddr, mapped_at),
+                Some(Object::PageTable { paddr, mapped_at }) if table => (paddr, mapped_at),
+                _ => return KError::InvalidCapability
+            };
+            if mapped_at.is_some() {
+                return KError::InvalidCapability; // already mapped somewhere
+            }
+            if vaddr & ((1 << PAGE_4K_BITS) - 1) != 0 || (table && vaddr != covering) {
+                return KError::AlignmentError;
+            }
+            let has_table = state.slots.values().any(|e| match e.object {
+                Object::PageTable { mapped_at: Some(at), .. } => at == covering,
+                _ => false
+            });
+            if table && has_table {
+                return KError::DeleteFirst;
+            }
+            if !table {
+                if !has_table {
+                    return KError::FailedLookup;
+                }
+                let taken = state.slots.values().any(|e| match e.object {
+                    Object::Page { mapped_at: Some(at), .. } => at == vaddr,
+                    _ => false
+                });
+                if taken {
+                    return KError::DeleteFirst;
+                }
+            }
+            state.set_object(service, if table {
+                Object::PageTable { paddr, mapped_at: Some(vaddr) }
+            } else {
+                Object::Page { paddr, mapped_at: Some(vaddr) }
+            });
+            KError::NoError
+        })
+    }
+
+    fn page_unmap(&self, service: usize, table: bool) -> KError {
+        with_state(|state| {
+            let object = match state.object(service) {
+                Some(Object::Page { paddr, .. }) if !table => Object::Page { paddr, mapped_at: None },
+                Some(Object::PageTable { paddr, .. }) if table => Object::PageTable { paddr, mapped_at: None },
+                _ => return KError::InvalidCapability
+            };
+            state.set_object(service, object);
+            KError::NoError
+        })
+    }
+
+    fn ioport_in(&self, service: usize, port: u16) -> (KError, u32) {
+        if service != kernel::CAP_INIT_IOPORT {
+            return (KError::InvalidCapability, 0);
+        }
+        // nothing is attached, so reads float high unless something was written there
+        (KError::NoError, with_state(|state| state.ports.get(&port).cloned().unwrap_or(0xFFFFFFFF)))
+    }
+
+    fn ioport_out(&self, service: usize, port: u16, data: u32) -> KError {
+        if service != kernel::CAP_INIT_IOPORT {
+            return KError::InvalidCapability;
+        }
+        with_state(|state| state.ports.insert(port, data));
+        KError::NoError
+    }
+
+    fn irqhandler(&self, service: usize) -> KError {
+        match object(service) {
+            Some(Object::IrqHandler { .. }) => KError::NoError,
+            _ => KError::InvalidCapability
+        }
+    }
+
+    fn receive(&self, src: usize, block: bool) -> (kernel::MessageInfo, usize) {
+        let word = with_state(|state| match state.object(src) {
+            Some(Object::Other { objtype, paddr }) if objtype == ObjectType::NotificationObject as usize =>
+                state.notifications.remove(&paddr),
+            Some(Object::Other { objtype, .. }) if objtype == ObjectType::EndpointObject as usize => None,
+            other => panic!("receive from slot {}, which holds {:?}", src, other)
+        });
+        match word {
+            Some(word) => (0, word),
+            None if block => panic!("receive from slot {} would block forever: nothing else runs here", src),
+            None => (0, 0)
+        }
+    }
+
+    fn tcb(&self, service: usize) -> KError {
+        match object(service) {
+            Some(Object::Other { objtype, .. }) if objtype == ObjectType::TCBObject as usize => KError::NoError,
+            Some(Object::Initial) if service == kernel::CAP_INIT_TCB => KError::NoError,
+            _ => KError::InvalidCapability
+        }
+    }
+}
+
+impl Syscalls for SimKernel {
+    fn untyped_retype(&self, service: usize, objtype: usize, size_bits: usize, root: usize,
+                      node_index: usize, node_depth: usize, node_offset: usize, num_objects: usize) -> KError {
+        if root != kernel::CAP_INIT_CNODE || node_index != 0 || node_depth != 0 {
+            return KError::FailedLookup; // only the root CNode itself is supported as a destination
+        }
+        if num_objects == 0 || num_objects > kernel::FAN_OUT_LIMIT {
+            return KError::RangeError;
+        }
+        let object_bits = match object_size_bits(objtype, size_bits) {
+            Some(bits) => bits,
+            None => return KError::InvalidArgument
+        };
+        with_state(|state| {
+            let (paddr, parent_bits, mut watermark) = match state.object(service) {
+                Some(Object::Untyped { paddr, size_bits, watermark }) => (paddr, size_bits as usize, watermark),
+                _ => return KError::InvalidCapability
+            };
+            if objtype == ObjectType::UntypedObject as usize && (object_bits < 4 || object_bits > parent_bits) {
+                return KError::RangeError;
+            }
+            if node_offset + num_objects > SLOT_COUNT {
+                return KError::RangeError;
+            }
+            if (node_offset..node_offset + num_objects).any(|slot| state.slots.contains_key(&slot)) {
+                return KError::DeleteFirst;
+            }
+            if !state.has_children(service) {
+                watermark = 0;
+            }
+            let object_size = 1usize << object_bits;
+            let start = (watermark + object_size - 1) & !(object_size - 1);
+            if start + object_size * num_objects > 1 << parent_bits {
+                return KError::NotEnoughMemory;
+            }
+            for i in 0..num_objects {
+                let child_paddr = paddr + start + object_size * i;
+                let object = match objtype {
+                    t if t == ObjectType::UntypedObject as usize =>
+                        Object::Untyped { paddr: child_paddr, size_bits: object_bits as u8, watermark: 0 },
+                    t if t == ObjectType::X864K as usize => Object::Page { paddr: child_paddr, mapped_at: None },
+                    t if t == ObjectType::X86PageTableObject as usize => Object::PageTable { paddr: child_paddr, mapped_at: None },
+                    _ => Object::Other { objtype, paddr: child_paddr }
+                };
+                state.notifications.remove(&child_paddr); // from whatever was here before
+                state.slots.insert(node_offset + i, Entry { object, parent: Some(service) });
+            }
+            state.set_object(service, Object::Untyped {
+                paddr, size_bits: parent_bits as u8, watermark: start + object_size * num_objects
+            });
+            KError::NoError
+        })
+    }
+
+    fn cnode_delete(&self, service: usize, index: usize, depth: u8) -> KError {
+        if service != kernel::CAP_INIT_CNODE || depth != 64 {
+            return KError::FailedLookup;
+        }
+        with_state(|state| {
+            match state.object(index) {
+                Some(Object::Initial) => return KError::IllegalOperation,
+                Some(Object::Untyped { .. }) if state.has_children(index) => return KError::RevokeFirst,
+                _ => {}
+            }
+            // deleting the last cap to a page unmaps it, as the kernel would
+            state.slots.remove(&index);
+            KError::NoError
+        })
+    }
+
+    fn x86_page_map(&self, service: usize, vroot: usize, vaddr: usize, _rights: usize, _vmattrs: usize) -> KError {
+        self.page_map(service, vroot, vaddr, false)
+    }
+
+    fn x86_page_unmap(&self, service: usize) -> KError {
+        self.page_unmap(service, false)
+    }
+
+    fn x86_page_get_address(&self, service: usize) -> (KError, usize) {
+        match object(service) {
+            Some(Object::Page { paddr, .. }) => (KError::NoError, paddr),
+            _ => (KError::InvalidCapability, 0)
+        }
+    }
+
+    fn x86_page_table_map(&self, service: usize, vroot: usize, vaddr: usize, _vmattrs: usize) -> KError {
+        self.page_map(service, vroot, vaddr, true)
+    }
+
+    fn x86_page_table_unmap(&self, service: usize) -> KError {
+        self.page_unmap(service, true)
+    }
+
+    fn x86_ioport_in8(&self, service: usize, port: u16) -> (KError, u8) {
+        let (err, value) = self.ioport_in(service, port);
+        (err, value as u8)
+    }
+
+    fn x86_ioport_out8(&self, service: usize, port: u16, data: u8) -> KError {
+        self.ioport_out(service, port, data as u32)
+    }
+
+    fn x86_ioport_in16(&self, service: usize, port: u16) -> (KError, u16) {
+        let (err, value) = self.ioport_in(service, port);
+        (err, value as u16)
+    }
+
+    fn x86_ioport_out16(&self, service: usize, port: u16, data: u16) -> KError {
+        self.ioport_out(service, port, data as u32)
+    }
+
+    fn x86_ioport_in32(&self, service: usize, port: u16) -> (KError, u32) {
+        self.ioport_in(service, port)
+    }
+
+    fn x86_ioport_out32(&self, service: usize, port: u16, data: u32) -> KError {
+        self.ioport_out(service, port, data)
+    }
+
+    fn irqcontrol_get(&self, service: usize, irq: u32, root: usize, index: usize, depth: usize) -> KError {
+        if service != kernel::CAP_INIT_IRQCONTROL || root != kernel::CAP_INIT_CNODE || depth != 64 {
+            return KError::InvalidCapability;
+        }
+        with_state(|state| {
+            if state.slots.contains_key(&index) {
+                return KError::DeleteFirst;
+            }
+            let taken = state.slots.values().any(|e| e.object == Object::IrqHandler { irq });
+            if taken {
+                return KError::RevokeFirst;
+            }
+            state.slots.insert(index, Entry { object: Object::IrqHandler { irq }, parent: None });
+            KError::NoError
+        })
+    }
+
+    fn irqhandler_ack(&self, service: usize) -> KError {
+        self.irqhandler(service)
+    }
+
+    fn irqhandler_set_notification(&self, service: usize, notification: usize) -> KError {
+        match object(notification) {
+            Some(Object::Other { objtype, .. }) if objtype == ObjectType::NotificationObject as usize => self.irqhandler(service),
+            _ => KError::InvalidCapability
+        }
+    }
+
+    fn irqhandler_clear(&self, service: usize) -> KError {
+        self.irqhandler(service)
+    }
+
+    fn tcb_read_registers(&self, service: usize, _suspend_source: bool, _arch_flags: u8) -> (KError, kernel::UserContext) {
+        let err = self.tcb(service);
+        let regs = with_state(|state| state.registers.get(&service).cloned()).unwrap_or([0; kernel::USER_CONTEXT_LEN]);
+        (err, regs)
+    }
+
+    fn tcb_write_registers(&self, service: usize, _resume_target: bool, _arch_flags: u8, regs: &kernel::UserContext) -> KError {
+        let err = self.tcb(service);
+        if err.is_okay() {
+            with_state(|state| state.registers.insert(service, *regs));
+        }
+        err
+    }
+
+    fn tcb_suspend(&self, service: usize) -> KError {
+        self.tcb(service)
+    }
+
+    fn tcb_resume(&self, service: usize) -> KError {
+        self.tcb(service)
+    }
+
+    // only our own cspace and vspace can be given
+    fn tcb_set_space(&self, service: usize, fault_ep: usize, cspace_root: usize, _cspace_root_data: usize,
+                     vspace_root: usize, _vspace_root_data: usize) -> KError {
+        if cspace_root != kernel::CAP_INIT_CNODE || vspace_root != kernel::CAP_INIT_VSPACE {
+            return KError::InvalidCapability;
+        }
+        match object(fault_ep) {
+            Some(Object::Other { objtype, .. }) if objtype == ObjectType::EndpointObject as usize => self.tcb(service),
+            None if fault_ep == kernel::CAP_NULL => self.tcb(service),
+            _ => KError::InvalidCapability
+        }
+    }
+
+    fn tcb_set_ipc_buffer(&self, service: usize, buffer: usize, buffer_frame: usize) -> KError {
+        match object(buffer_frame) {
+            Some(Object::Page { .. }) if buffer & (kernel::IPC_BUFFER_SIZE - 1) != 0 => KError::AlignmentError,
+            Some(Object::Page { .. }) => self.tcb(service),
+            _ => KError::InvalidCapability
+        }
+    }
+
+    fn tcb_set_priority(&self, service: usize, _priority: u8) -> KError {
+        self.tcb(service)
+    }
+
+    fn signal(&self, dest: usize) {
+        with_state(|state| match state.object(dest) {
+            // our caps are unbadged, so there's nothing to add to the word
+            Some(Object::Other { objtype, paddr }) if objtype == ObjectType::NotificationObject as usize => {
+                state.notifications.entry(paddr).or_insert(0);
+            }
+            Some(Object::Other { objtype, .. }) if objtype == ObjectType::EndpointObject as usize =>
+                panic!("send to endpoint in slot {} would block forever: nothing else runs here", dest),
+            other => panic!("signal to slot {}, which holds {:?}", dest, other)
+        })
+    }
+
+    fn recv(&self, src: usize) -> (kernel::MessageInfo, usize) {
+        self.receive(src, true)
+    }
+
+    fn nbrecv(&self, src: usize) -> (kernel::MessageInfo, usize) {
+        self.receive(src, false)
+    }
+
+    fn debug_put_char(&self, c: u8) {
+        print!("{}", c as char);
+    }
+}
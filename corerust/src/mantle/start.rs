@@ -1,5 +1,6 @@
 use mantle::kernel;
 use mantle::kio;
+use mantle::exit;
 use ::core;
 
 static mut DEFAULT_STACK: [u8; 65536] = [0; 65536];
@@ -37,7 +38,12 @@ extern fn eh_personality() {}
 #[lang = "panic_fmt"]
 #[no_mangle]
 pub extern fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
-    debug!("panicked at {}:{}: {}", file, line, fmt);
+    error!("panicked at {}:{}: {}", file, line, fmt);
+    #[cfg(feature = "ktest")]
+    ::ktest::report_panic();
+    if exit::can_exit() {
+        exit::exit(exit::EXIT_FAILURE);
+    }
     for c in "[panic] HANG\n".bytes() {
         kio::debug_put_char(c);
     }
@@ -0,0 +1,173 @@
+use mantle::calls;
+use mantle::kernel;
+use mantle::kio;
+use mantle::KError;
+
+// everything the root task asks of the kernel. on seL4 these are the calls in mantle::calls and the IPC in mantle::kio;
+// under `cargo test`, mantle::sim stands in for the kernel, so that the code above can run on the host.
+pub trait Syscalls {
+    fn untyped_retype(&self, service: usize, objtype: usize, size_bits: usize, root: usize,
+                      node_index: usize, node_depth: usize, node_offset: usize, num_objects: usize) -> KError;
+    fn cnode_delete(&self, service: usize, index: usize, depth: u8) -> KError;
+    fn x86_page_map(&self, service: usize, vroot: usize, vaddr: usize, rights: usize, vmattrs: usize) -> KError;
+    fn x86_page_unmap(&self, service: usize) -> KError;
+    fn x86_page_get_address(&self, service: usize) -> (KError, usize);
+    fn x86_page_table_map(&self, service: usize, vroot: usize, vaddr: usize, vmattrs: usize) -> KError;
+    fn x86_page_table_unmap(&self, service: usize) -> KError;
+    fn x86_ioport_in8(&self, service: usize, port: u16) -> (KError, u8);
+    fn x86_ioport_out8(&self, service: usize, port: u16, data: u8) -> KError;
+    fn x86_ioport_in16(&self, service: usize, port: u16) -> (KError, u16);
+    fn x86_ioport_out16(&self, service: usize, port: u16, data: u16) -> KError;
+    fn x86_ioport_in32(&self, service: usize, port: u16) -> (KError, u32);
+    fn x86_ioport_out32(&self, service: usize, port: u16, data: u32) -> KError;
+    fn irqcontrol_get(&self, service: usize, irq: u32, root: usize, index: usize, depth: usize) -> KError;
+    fn irqhandler_ack(&self, service: usize) -> KError;
+    fn irqhandler_set_notification(&self, service: usize, notification: usize) -> KError;
+    fn irqhandler_clear(&self, service: usize) -> KError;
+    fn tcb_read_registers(&self, service: usize, suspend_source: bool, arch_flags: u8) -> (KError, kernel::UserContext);
+    fn tcb_write_registers(&self, service: usize, resume_target: bool, arch_flags: u8, regs: &kernel::UserContext) -> KError;
+    fn tcb_suspend(&self, service: usize) -> KError;
+    fn tcb_resume(&self, service: usize) -> KError;
+    fn tcb_set_space(&self, service: usize, fault_ep: usize, cspace_root: usize, cspace_root_data: usize,
+                     vspace_root: usize, vspace_root_data: usize) -> KError;
+    fn tcb_set_ipc_buffer(&self, service: usize, buffer: usize, buffer_frame: usize) -> KError;
+    fn tcb_set_priority(&self, service: usize, priority: u8) -> KError;
+    // IPC, as far as labels and badges go; message registers are still read and written through kio
+    fn signal(&self, dest: usize);
+    fn recv(&self, src: usize) -> (kernel::MessageInfo, usize);
+    fn nbrecv(&self, src: usize) -> (kernel::MessageInfo, usize);
+    fn debug_put_char(&self, c: u8);
+}
+
+// not used under `cargo test`, but still built, so that it's checked along with the trait
+#[cfg_attr(test, allow(dead_code))]
+pub struct Sel4;
+
+impl Syscalls for Sel4 {
+    fn untyped_retype(&self, service: usize, objtype: usize, size_bits: usize, root: usize,
+                      node_index: usize, node_depth: usize, node_offset: usize, num_objects: usize) -> KError {
+        calls::untyped_retype(service, objtype, size_bits, root, node_index, node_depth, node_offset, num_objects)
+    }
+
+    fn cnode_delete(&self, service: usize, index: usize, depth: u8) -> KError {
+        calls::cnode_delete(service, index, depth)
+    }
+
+    fn x86_page_map(&self, service: usize, vroot: usize, vaddr: usize, rights: usize, vmattrs: usize) -> KError {
+        calls::x86_page_map(service, vroot, vaddr, rights, vmattrs)
+    }
+
+    fn x86_page_unmap(&self, service: usize) -> KError {
+        calls::x86_page_unmap(service)
+    }
+
+    fn x86_page_get_address(&self, service: usize) -> (KError, usize) {
+        calls::x86_page_get_address(service)
+    }
+
+    fn x86_page_table_map(&self, service: usize, vroot: usize, vaddr: usize, vmattrs: usize) -> KError {
+        calls::x86_page_table_map(service, vroot, vaddr, vmattrs)
+    }
+
+    fn x86_page_table_unmap(&self, service: usize) -> KError {
+        calls::x86_page_table_unmap(service)
+    }
+
+    fn x86_ioport_in8(&self, service: usize, port: u16) -> (KError, u8) {
+        calls::x86_ioport_in8(service, port)
+    }
+
+    fn x86_ioport_out8(&self, service: usize, port: u16, data: u8) -> KError {
+        calls::x86_ioport_out8(service, port, data)
+    }
+
+    fn x86_ioport_in16(&self, service: usize, port: u16) -> (KError, u16) {
+        calls::x86_ioport_in16(service, port)
+    }
+
+    fn x86_ioport_out16(&self, service: usize, port: u16, data: u16) -> KError {
+        calls::x86_ioport_out16(service, port, data)
+    }
+
+    fn x86_ioport_in32(&self, service: usize, port: u16) -> (KError, u32) {
+        calls::x86_ioport_in32(service, port)
+    }
+
+    fn x86_ioport_out32(&self, service: usize, port: u16, data: u32) -> KError {
+        calls::x86_ioport_out32(service, port, data)
+    }
+
+    fn irqcontrol_get(&self, service: usize, irq: u32, root: usize, index: usize, depth: usize) -> KError {
+        calls::irqcontrol_get(service, irq, root, index, depth)
+    }
+
+    fn irqhandler_ack(&self, service: usize) -> KError {
+        calls::irqhandler_ack(service)
+    }
+
+    fn irqhandler_set_notification(&self, service: usize, notification: usize) -> KError {
+        calls::irqhandler_set_notification(service, notification)
+    }
+
+    fn irqhandler_clear(&self, service: usize) -> KError {
+        calls::irqhandler_clear(service)
+    }
+
+    fn tcb_read_registers(&self, service: usize, suspend_source: bool, arch_flags: u8) -> (KError, kernel::UserContext) {
+        calls::tcb_read_registers(service, suspend_source, arch_flags)
+    }
+
+    fn tcb_write_registers(&self, service: usize, resume_target: bool, arch_flags: u8, regs: &kernel::UserContext) -> KError {
+        calls::tcb_write_registers(service, resume_target, arch_flags, regs)
+    }
+
+    fn tcb_suspend(&self, service: usize) -> KError {
+        calls::tcb_suspend(service)
+    }
+
+    fn tcb_resume(&self, service: usize) -> KError {
+        calls::tcb_resume(service)
+    }
+
+    fn tcb_set_space(&self, service: usize, fault_ep: usize, cspace_root: usize, cspace_root_data: usize,
+                     vspace_root: usize, vspace_root_data: usize) -> KError {
+        calls::tcb_set_space(service, fault_ep, cspace_root, cspace_root_data, vspace_root, vspace_root_data)
+    }
+
+    fn tcb_set_ipc_buffer(&self, service: usize, buffer: usize, buffer_frame: usize) -> KError {
+        calls::tcb_set_ipc_buffer(service, buffer, buffer_frame)
+    }
+
+    fn tcb_set_priority(&self, service: usize, priority: u8) -> KError {
+        calls::tcb_set_priority(service, priority)
+    }
+
+    fn signal(&self, dest: usize) {
+        kio::signal(dest)
+    }
+
+    fn recv(&self, src: usize) -> (kernel::MessageInfo, usize) {
+        unsafe { kio::recv(src) }
+    }
+
+    fn nbrecv(&self, src: usize) -> (kernel::MessageInfo, usize) {
+        unsafe { kio::nbrecv(src) }
+    }
+
+    fn debug_put_char(&self, c: u8) {
+        kio::debug_put_char(c)
+    }
+}
+
+#[cfg(not(test))]
+static SEL4: Sel4 = Sel4;
+
+#[cfg(not(test))]
+pub fn syscalls() -> &'static Syscalls {
+    &SEL4
+}
+
+#[cfg(test)]
+pub fn syscalls() -> &'static Syscalls {
+    &::mantle::sim::SIM
+}
@@ -1,22 +1,155 @@
 use ::core;
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::heapdebug;
 
 const TRACE: bool = false;
-pub const MAX_ALLOC_LEN: usize = 255 * 8;
+// see memory::heapdebug
+#[cfg(not(test))]
+const DEBUG_HEAP: bool = cfg!(feature = "heapdebug");
 
-mod fixed_alloc {
-    const HEAP_KB: usize = 64;
-    const HEAP_U64: usize = HEAP_KB * (1024 / 8);
-    static mut EARLY_HEAP: [u64; HEAP_U64] = [0; HEAP_U64]; // start with 64KB of memory
+// small allocations are rounded up to one of these size classes, each of which has a free list of its own. a class
+// gets its memory in runs of whole pages, carved into blocks of its size, and keeps it once the blocks are freed:
+// runs come from the early heap, which is part of our image, or from dynamic_alloc, which only ever moves forward
+// through its region, and nothing records which blocks of a run are still live. giving runs back would need both a
+// count per run, kept somewhere other than this heap, and a page allocator that can unmap from the middle.
+// each block is aligned to the largest power of two that divides its size. anything larger than MAX_SMALL_LEN gets
+// pages of its own, straight from untyped memory, which go back to the untyped pool when it's freed.
+pub const CLASS_COUNT: usize = 15;
+pub const CLASS_SIZES: [usize; CLASS_COUNT] = [8, 16, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 1536, 2048];
+pub const MAX_SMALL_LEN: usize = 2048;
+
+fn class_align(class: usize) -> usize {
+    let size = CLASS_SIZES[class];
+    size & size.wrapping_neg()
+}
+
+// the powers of two fit evenly into one page, and the rest into three
+fn run_pages(class: usize) -> usize {
+    if CLASS_SIZES[class].is_power_of_two() { 1 } else { 3 }
+}
+
+pub fn class_for(size: usize, align: usize) -> Option<usize> {
+    (0..CLASS_COUNT).find(|&class| CLASS_SIZES[class] >= size && class_align(class) >= align)
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ClassStats {
+    pub size: usize,
+    // in blocks
+    pub in_use: usize,
+    pub free: usize,
+    pub pages: usize
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct HeapStats {
+    pub classes: [ClassStats; CLASS_COUNT],
+    pub large_allocations: usize,
+    pub large_pages: usize,
+    // in bytes, counting whole blocks and pages
+    pub in_use: usize,
+    pub peak: usize,
+    // where the classes' runs came from
+    pub early_heap_pages: usize,
+    pub dynamic_pages: usize
+}
+
+pub struct Heap {
+    // each free block holds the address of the next
+    free_lists: [*mut u8; CLASS_COUNT],
+    // blocks handed out, and pages taken, by each class
+    in_use: [usize; CLASS_COUNT],
+    pages: [usize; CLASS_COUNT]
+}
+
+// `source` hands out runs of page-aligned memory, this many pages long. getting a run can allocate from this same heap,
+// so no reference to it is held while the source runs, and the free list is only looked at once the run is here.
+pub unsafe fn heap_alloc(heap: *mut Heap, class: usize, source: fn(usize) -> Option<*mut u8>) -> Option<*mut u8> {
+    if let Some(block) = (*heap).pop(class) {
+        return Some(block);
+    }
+    let run = match source(run_pages(class)) {
+        Some(run) => run,
+        None => return None
+    };
+    (*heap).add_run(class, run);
+    (*heap).pop(class)
+}
+
+impl Heap {
+    pub const fn new() -> Heap {
+        Heap { free_lists: [core::ptr::null_mut(); CLASS_COUNT], in_use: [0; CLASS_COUNT], pages: [0; CLASS_COUNT] }
+    }
+
+    fn add_run(&mut self, class: usize, run: *mut u8) {
+        let size = CLASS_SIZES[class];
+        let count = run_pages(class) * PAGE_4K_SIZE / size;
+        // linked from the back, so that they're handed out lowest address first
+        for i in 0..count {
+            let block = unsafe { run.offset(((count - 1 - i) * size) as isize) };
+            unsafe { *(block as *mut *mut u8) = self.free_lists[class] };
+            self.free_lists[class] = block;
+        }
+        self.pages[class] += run_pages(class);
+        if TRACE {
+            debug!("added a run of {} blocks of {} bytes", count, size);
+        }
+    }
+
+    // None once the class has run out, until another run is added
+    fn pop(&mut self, class: usize) -> Option<*mut u8> {
+        let block = self.free_lists[class];
+        if block.is_null() {
+            return None;
+        }
+        self.free_lists[class] = unsafe { *(block as *mut *mut u8) };
+        self.in_use[class] += 1;
+        Some(block)
+    }
+
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, class: usize) {
+        assert!(!ptr.is_null() && (ptr as usize) & (class_align(class) - 1) == 0);
+        *(ptr as *mut *mut u8) = self.free_lists[class];
+        self.free_lists[class] = ptr;
+        assert!(self.in_use[class] > 0);
+        self.in_use[class] -= 1;
+    }
+
+    pub fn class_stats(&self, class: usize) -> ClassStats {
+        ClassStats { size: CLASS_SIZES[class], in_use: self.in_use[class], free: self.free_count(class), pages: self.pages[class] }
+    }
+
+    pub fn free_count(&self, class: usize) -> usize {
+        let mut count = 0;
+        let mut block = self.free_lists[class];
+        while !block.is_null() {
+            count += 1;
+            block = unsafe { *(block as *mut *mut u8) };
+        }
+        count
+    }
+}
+
+// where runs come from before init_allocator, while there's no way to map memory yet
+#[cfg(not(test))]
+mod early_heap {
+    use ::mantle::kernel::PAGE_4K_SIZE;
+
+    const HEAP_KB: usize = 128;
+    // with room to line the start up with a page boundary
+    const HEAP_U64: usize = (HEAP_KB * 1024 + PAGE_4K_SIZE) / 8;
+    static mut EARLY_HEAP: [u64; HEAP_U64] = [0; HEAP_U64];
     static mut FIRST_FREE: usize = 0;
 
-    pub fn alloc_fixblks(size: u8) -> Option<*mut u64> {
-        assert!(size != 0);
+    pub fn alloc_pages(pages: usize) -> Option<*mut u8> {
+        assert!(pages != 0);
         unsafe {
-            if FIRST_FREE + (size as usize) <= HEAP_U64 {
-                let nptr = &mut EARLY_HEAP[FIRST_FREE] as *mut u64;
-                FIRST_FREE += size as usize;
+            let base = (&mut EARLY_HEAP[0] as *mut u64 as usize + PAGE_4K_SIZE - 1) & !(PAGE_4K_SIZE - 1);
+            if FIRST_FREE + pages * PAGE_4K_SIZE <= HEAP_KB * 1024 {
+                let nptr = (base + FIRST_FREE) as *mut u8;
+                FIRST_FREE += pages * PAGE_4K_SIZE;
                 if super::TRACE {
-                    debug!("allocated {} blocks from unallocated memory --> {}/{}", size, FIRST_FREE, HEAP_U64);
+                    debug!("allocated {} pages from the early heap --> {}/{}", pages, FIRST_FREE, HEAP_KB * 1024);
                 }
                 Some(nptr)
             } else {
@@ -24,8 +157,14 @@ mod fixed_alloc {
             }
         }
     }
+
+    pub fn pages_used() -> usize {
+        unsafe { FIRST_FREE / PAGE_4K_SIZE }
+    }
 }
 
+// where runs come from after init_allocator: a region of virtual memory, mapped a page at a time as it's used up
+#[cfg_attr(test, allow(dead_code))]
 mod dynamic_alloc {
     use ::core;
     use ::crust;
@@ -106,17 +245,17 @@ mod dynamic_alloc {
             }
         }
 
-        fn alloc_fixblks(&mut self, size: u8) -> Option<*mut u64> {
-            let real_size = (size as usize) * 8;
+        fn alloc_pages(&mut self, pages: usize) -> Option<*mut u8> {
+            let real_size = pages * kernel::PAGE_4K_SIZE;
             let until = self.next_avail + real_size;
             if let Err(err) = self.alloc_forward(until) {
                 debug!("Could not allocate additional dynamic memory: {:?}", err);
                 return None;
             }
-            let ptr = (self.next_avail + self.vregion.start()) as *mut u64;
+            let ptr = (self.next_avail + self.vregion.start()) as *mut u8;
             self.next_avail += real_size;
             if super::TRACE {
-                debug!("allocated {} blocks from unallocated memory --> {}/{}/{}", size, self.next_avail / 8, self.next_unalloc / 8, self.vregion.len() / 8);
+                debug!("allocated {} pages from dynamic memory --> {}/{}/{}", pages, self.next_avail, self.next_unalloc, self.vregion.len());
             }
             Some(ptr)
         }
@@ -132,67 +271,226 @@ mod dynamic_alloc {
         }
     }
 
-    pub fn alloc_fixblks(size: u8) -> Option<*mut u64> {
+    pub fn alloc_pages(pages: usize) -> Option<*mut u8> {
         if let &mut Some(ref mut dyna) = unsafe { &mut ALLOC } {
-            dyna.alloc_fixblks(size)
+            dyna.alloc_pages(pages)
         } else {
             None
         }
     }
-}
 
-mod recycle_alloc {
-    // each bucket is a multiple of 8 bytes. the maximum fixblk is 255 * 8 bytes == 2040 bytes, so we have 255 buckets.
-    static mut BUCKETS: [*mut u64; 255] = [::core::ptr::null_mut(); 255];
-
-    unsafe fn deref_seq(ptr: *mut u64) -> Option<*mut u64> {
-        if ptr.is_null() {
-            None
+    // those actually mapped, which runs ahead of what's been handed out
+    pub fn pages_mapped() -> usize {
+        if let &Some(ref dyna) = unsafe { &ALLOC } {
+            dyna.next_unalloc / kernel::PAGE_4K_SIZE
         } else {
-            let target_addr: u64 = *ptr;
-            *ptr = 0;
-            Some(target_addr as *mut u64)
+            0
         }
     }
+}
+
+// allocations over MAX_SMALL_LEN, each in a region of its own
+#[cfg_attr(test, allow(dead_code))]
+mod large_alloc {
+    use ::crust;
+    use ::crust::vspace::VRegion;
+    use ::kobject::*;
+    use ::mantle::kernel::PAGE_4K_SIZE;
+    use ::memory::LinkedList;
+    use ::memory::untyped;
 
-    unsafe fn reref_seq(ptr: *mut u64, older: *mut u64) -> *mut u64 {
-        assert!(!ptr.is_null());
-        *ptr = older as u64;
-        ptr
+    struct LargeBlock {
+        vregion: VRegion,
+        pages: LinkedList<FixedMappedPage4K>
     }
 
-    pub fn alloc_fixblks(size: u8) -> Option<*mut u64> {
-        assert!(size != 0);
-        unsafe {
-            let ptr = BUCKETS[(size - 1) as usize];
-            if let Some(nptr) = deref_seq(ptr) {
-                BUCKETS[(size - 1) as usize] = nptr;
-                if super::TRACE {
-                    debug!("allocated {} blocks from recycled memory", size);
+    static mut BLOCKS: LinkedList<LargeBlock> = LinkedList::empty();
+
+    fn release(mut pages: LinkedList<FixedMappedPage4K>, vregion: VRegion) {
+        while let Some(page) = pages.popmut() {
+            untyped::free_page4k(page.unmap());
+        }
+        crust::vspace::free_vregion(vregion);
+    }
+
+    pub fn alloc_pages(len: usize) -> Option<*mut u8> {
+        let count = (len + PAGE_4K_SIZE - 1) / PAGE_4K_SIZE;
+        let vregion = match crust::vspace::allocate_vregion(count * PAGE_4K_SIZE) {
+            Ok(vregion) => vregion,
+            Err(err) => {
+                debug!("could not allocate a region for {} bytes: {:?}", len, err);
+                return None;
+            }
+        };
+        let mut pages: LinkedList<FixedMappedPage4K> = LinkedList::empty();
+        for i in 0..count {
+            let page = match untyped::allocate_page4k() {
+                Ok(page) => page,
+                Err(err) => {
+                    debug!("could not allocate a page for {} bytes: {:?}", len, err);
+                    release(pages, vregion);
+                    return None;
+                }
+            };
+            match page.map_into_addr(vregion.start() + i * PAGE_4K_SIZE, true) {
+                Ok(mapping) => {
+                    if let Err(mapping) = pages.pushmut(mapping) {
+                        untyped::free_page4k(mapping.unmap());
+                        release(pages, vregion);
+                        return None;
+                    }
+                }
+                Err((page, err)) => {
+                    debug!("could not map a page for {} bytes: {:?}", len, err);
+                    untyped::free_page4k(page);
+                    release(pages, vregion);
+                    return None;
                 }
-                Some(ptr)
-            } else {
-                None
             }
         }
+        let ptr = vregion.start() as *mut u8;
+        if let Err(block) = unsafe { BLOCKS.pushmut(LargeBlock { vregion, pages }) } {
+            release(block.pages, block.vregion);
+            return None;
+        }
+        Some(ptr)
+    }
+
+    // how many allocations there are, and how many pages they take up
+    pub fn usage() -> (usize, usize) {
+        let (mut count, mut pages) = (0, 0);
+        for block in unsafe { &BLOCKS } {
+            count += 1;
+            pages += block.vregion.len() / PAGE_4K_SIZE;
+        }
+        (count, pages)
+    }
+
+    pub unsafe fn dealloc_pages(ptr: *mut u8, len: usize) {
+        let block = BLOCKS.remove_mut(|b| b.vregion.start() == ptr as usize).expect("not a large allocation");
+        assert!(block.vregion.len() == (len + PAGE_4K_SIZE - 1) & !(PAGE_4K_SIZE - 1));
+        release(block.pages, block.vregion);
+    }
+}
+
+// under `cargo test` there are no pages to be had from the kernel, and tests run on several threads at once, so
+// everything comes from the host's heap instead
+#[cfg(test)]
+mod host_alloc {
+    use ::alloc::allocator::{Alloc, Layout};
+    use ::alloc::heap::Heap;
+
+    pub fn alloc(size: usize, align: usize) -> Option<*mut u8> {
+        unsafe { Heap.alloc(Layout::from_size_align(size, align).unwrap()) }.ok()
     }
 
-    pub unsafe fn dealloc_fixblks(ptr: *mut u64, size: u8) {
-        assert!(size != 0);
-        BUCKETS[(size - 1) as usize] = reref_seq(ptr, BUCKETS[(size - 1) as usize]);
+    pub unsafe fn dealloc(ptr: *mut u8, size: usize, align: usize) {
+        Heap.dealloc(ptr, Layout::from_size_align(size, align).unwrap())
     }
 }
 
-pub fn alloc_fix(size: u16) -> Option<*mut u64> {
-    assert!(size >= 1 && size <= MAX_ALLOC_LEN as u16);
-    let blks = ((size + 7) / 8) as u8;
-    recycle_alloc::alloc_fixblks(blks).or_else(|| fixed_alloc::alloc_fixblks(blks)).or_else(|| dynamic_alloc::alloc_fixblks(blks))
+#[cfg(not(test))]
+fn take_run(pages: usize) -> Option<*mut u8> {
+    early_heap::alloc_pages(pages).or_else(|| dynamic_alloc::alloc_pages(pages))
+}
+
+#[cfg(not(test))]
+static mut HEAP: Heap = Heap::new();
+#[cfg(not(test))]
+static mut IN_USE: usize = 0;
+#[cfg(not(test))]
+static mut PEAK: usize = 0;
+
+#[cfg(not(test))]
+fn block_len(size: usize, align: usize) -> usize {
+    match class_for(size, align) {
+        Some(class) => CLASS_SIZES[class],
+        None => (size + PAGE_4K_SIZE - 1) & !(PAGE_4K_SIZE - 1)
+    }
+}
+
+#[cfg(not(test))]
+fn raw_alloc(size: usize, align: usize) -> Option<*mut u8> {
+    if align > PAGE_4K_SIZE {
+        return None;
+    }
+    let out = match class_for(size, align) {
+        Some(class) => unsafe { heap_alloc(&mut HEAP, class, take_run) },
+        None => large_alloc::alloc_pages(size)
+    };
+    if out.is_some() {
+        unsafe {
+            IN_USE += block_len(size, align);
+            PEAK = core::cmp::max(PEAK, IN_USE);
+        }
+    }
+    if TRACE {
+        debug!("allocated {} bytes --> {:?}", size, out);
+    }
+    out
+}
+
+#[cfg(not(test))]
+unsafe fn raw_dealloc(ptr: *mut u8, size: usize, align: usize) {
+    if TRACE {
+        debug!("deallocated {} bytes at {:?}", size, ptr);
+    }
+    IN_USE -= block_len(size, align);
+    match class_for(size, align) {
+        Some(class) => HEAP.dealloc(ptr, class),
+        None => large_alloc::dealloc_pages(ptr, size)
+    }
+}
+
+// align has to be a power of two, up to the size of a page
+#[inline(never)]
+pub fn alloc(size: usize, align: usize) -> Option<*mut u8> {
+    alloc_from(size, align, heapdebug::frame())
+}
+
+// frame is the frame pointer of the outermost allocator function that was called, which the debug heap starts from
+// to record where the allocation was made
+#[cfg(not(test))]
+pub fn alloc_from(size: usize, align: usize, frame: usize) -> Option<*mut u8> {
+    assert!(size != 0 && align.is_power_of_two());
+    if DEBUG_HEAP {
+        let (raw_size, raw_align) = heapdebug::padded(size, align);
+        raw_alloc(raw_size, raw_align).map(|raw| unsafe { heapdebug::on_alloc(raw, size, align, frame) })
+    } else {
+        raw_alloc(size, align)
+    }
+}
+
+#[cfg(test)]
+pub fn alloc_from(size: usize, align: usize, _frame: usize) -> Option<*mut u8> {
+    assert!(size != 0 && align.is_power_of_two());
+    if align > PAGE_4K_SIZE {
+        return None;
+    }
+    host_alloc::alloc(size, align)
+}
+
+// size and align have to be what the memory was allocated with
+#[cfg(not(test))]
+pub unsafe fn dealloc(ptr: *mut u8, size: usize, align: usize) {
+    assert!(!ptr.is_null() && size != 0);
+    if DEBUG_HEAP {
+        let (raw_size, raw_align) = heapdebug::padded(size, align);
+        raw_dealloc(heapdebug::on_dealloc(ptr, size, align), raw_size, raw_align)
+    } else {
+        raw_dealloc(ptr, size, align)
+    }
+}
+
+#[cfg(test)]
+pub unsafe fn dealloc(ptr: *mut u8, size: usize, align: usize) {
+    assert!(!ptr.is_null() && size != 0);
+    host_alloc::dealloc(ptr, size, align)
 }
 
 pub fn alloc_type<T>(x: T) -> core::result::Result<*mut T, T> {
     let size: usize = core::mem::size_of::<T>();
-    assert!(size < 65536);
-    if let Some(ptr) = alloc_fix(size as u16) {
+    if let Some(ptr) = alloc(size, core::mem::align_of::<T>()) {
         let cptr = ptr as *mut T;
         unsafe {
             core::ptr::write(cptr, x);
@@ -204,41 +502,158 @@ pub fn alloc_type<T>(x: T) -> core::result::Result<*mut T, T> {
     }
 }
 
-pub fn alloc_aligned(size: usize, align: usize) -> *mut u8 {
-    assert!(align == 1 || align == 2 || align == 4 || align == 8);
-    assert!(size >= 1 && size <= MAX_ALLOC_LEN);
-    let m: *mut u64 = alloc_fix(size as u16).unwrap_or(::core::ptr::null_mut());
-    m as *mut u8
-}
-
-// as per heap::EMPTY (a non-null pointer for arbitrary allocations)
-pub const HEAP_EMPTY: *mut () = 0xCA as *mut ();
-
-pub unsafe fn dealloc_fix(ptr: *mut u64, size: u16) {
-    assert!(size >= 1 && size <= 255 * 8);
-    recycle_alloc::dealloc_fixblks(ptr, ((size + 7) / 8) as u8)
-}
-
 pub unsafe fn dealloc_type<T>(ptr: *mut T) -> T {
     assert!(!ptr.is_null());
     let out = core::ptr::read(ptr);
-    // TODO: zero out first?
-    let size: usize = core::mem::size_of::<T>();
-    assert!(size < 65536);
-    if TRACE {
-        debug!("deallocated {} bytes", size);
-    }
-    dealloc_fix(ptr as *mut u64, size as u16);
+    // left as it is, unless the debug heap is on, which poisons it
+    dealloc(ptr as *mut u8, core::mem::size_of::<T>(), core::mem::align_of::<T>());
     out
 }
 
-pub unsafe fn dealloc_aligned(ptr: *mut u8, size: usize, align: usize) {
-    assert!(align == 1 || align == 2 || align == 4 || align == 8);
-    assert!(size >= 1 && size <= MAX_ALLOC_LEN);
-    assert!(!ptr.is_null());
-    dealloc_fix(ptr as *mut u64, size as u16)
+// with the debug heap, this includes the headers and redzones
+#[cfg(not(test))]
+pub fn heap_stats() -> HeapStats {
+    let mut stats = HeapStats::default();
+    for class in 0..CLASS_COUNT {
+        stats.classes[class] = unsafe { HEAP.class_stats(class) };
+    }
+    let (count, pages) = large_alloc::usage();
+    stats.large_allocations = count;
+    stats.large_pages = pages;
+    unsafe {
+        stats.in_use = IN_USE;
+        stats.peak = PEAK;
+    }
+    stats.early_heap_pages = early_heap::pages_used();
+    stats.dynamic_pages = dynamic_alloc::pages_mapped();
+    stats
+}
+
+// the host's heap isn't counted
+#[cfg(test)]
+pub fn heap_stats() -> HeapStats {
+    let mut stats = HeapStats::default();
+    for class in 0..CLASS_COUNT {
+        stats.classes[class].size = CLASS_SIZES[class];
+    }
+    stats
 }
 
 pub fn init_allocator() {
     dynamic_alloc::init();
-}
\ No newline at end of file
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::crust;
+    use ::ktest::TestResult;
+
+    // a heap of its own, so that what it hands out is predictable
+    const TEST_PAGES: usize = 8;
+    static mut TEST_MEMORY: [u64; (TEST_PAGES + 1) * PAGE_4K_SIZE / 8] = [0; (TEST_PAGES + 1) * PAGE_4K_SIZE / 8];
+    static mut TEST_USED: usize = 0;
+
+    fn test_run(pages: usize) -> Option<*mut u8> {
+        unsafe {
+            let base = (&mut TEST_MEMORY[0] as *mut u64 as usize + PAGE_4K_SIZE - 1) & !(PAGE_4K_SIZE - 1);
+            if TEST_USED + pages > TEST_PAGES {
+                return None;
+            }
+            TEST_USED += pages;
+            Some((base + (TEST_USED - pages) * PAGE_4K_SIZE) as *mut u8)
+        }
+    }
+
+    fn classes() -> TestResult {
+        check_eq!(class_for(1, 1).map(|c| CLASS_SIZES[c]), Some(8));
+        check_eq!(class_for(9, 8).map(|c| CLASS_SIZES[c]), Some(16));
+        check_eq!(class_for(33, 1).map(|c| CLASS_SIZES[c]), Some(48));
+        // 48-byte blocks are only aligned to 16
+        check_eq!(class_for(40, 32).map(|c| CLASS_SIZES[c]), Some(64));
+        check_eq!(class_for(MAX_SMALL_LEN, 8).map(|c| CLASS_SIZES[c]), Some(MAX_SMALL_LEN));
+        check_eq!(class_for(MAX_SMALL_LEN + 1, 8), None);
+        check_eq!(class_for(8, PAGE_4K_SIZE), None);
+        for class in 0..CLASS_COUNT {
+            check_eq!(run_pages(class) * PAGE_4K_SIZE % CLASS_SIZES[class], 0);
+        }
+        Ok(())
+    }
+
+    fn free_lists() -> TestResult {
+        let mut heap = Heap::new();
+        let class = class_for(48, 16).unwrap();
+        let first = check_ok!(unsafe { heap_alloc(&mut heap, class, test_run) }.ok_or(()));
+        let second = check_ok!(unsafe { heap_alloc(&mut heap, class, test_run) }.ok_or(()));
+        check_eq!(first as usize % PAGE_4K_SIZE, 0);
+        check_eq!(second as usize - first as usize, 48);
+        check_eq!(heap.free_count(class), 3 * PAGE_4K_SIZE / 48 - 2);
+        let stats = heap.class_stats(class);
+        check_eq!((stats.size, stats.in_use, stats.pages), (48, 2, 3));
+        // the last one freed is the first one reused
+        unsafe { heap.dealloc(first, class) };
+        check_eq!(unsafe { heap_alloc(&mut heap, class, test_run) }, Some(first));
+        // and each class has a list of its own
+        let large = class_for(MAX_SMALL_LEN, 8).unwrap();
+        let a = check_ok!(unsafe { heap_alloc(&mut heap, large, test_run) }.ok_or(()));
+        let b = check_ok!(unsafe { heap_alloc(&mut heap, large, test_run) }.ok_or(()));
+        check_eq!(heap.free_count(large), 0);
+        unsafe { heap.dealloc(a, large) };
+        check_eq!(heap.free_count(large), 1);
+        check_eq!(heap.free_count(class), 3 * PAGE_4K_SIZE / 48 - 2);
+        unsafe { heap.dealloc(b, large) };
+        Ok(())
+    }
+
+    fn sizes() -> TestResult {
+        for &size in &[1usize, 7, 8, 9, 64, 1000, MAX_SMALL_LEN, MAX_SMALL_LEN + 1, 5000, 5 * PAGE_4K_SIZE] {
+            let ptr = check_ok!(alloc(size, 8).ok_or(()));
+            check!(ptr as usize % 8 == 0);
+            unsafe {
+                for i in 0..size as isize {
+                    *ptr.offset(i) = i as u8;
+                }
+                for i in 0..size as isize {
+                    check_eq!(*ptr.offset(i), i as u8);
+                }
+                dealloc(ptr, size, 8);
+            }
+        }
+        Ok(())
+    }
+
+    fn recycles() -> TestResult {
+        let first = check_ok!(alloc(24, 8).ok_or(()));
+        unsafe { dealloc(first, 24, 8) };
+        // both in the 32-byte class
+        let second = check_ok!(alloc(20, 4).ok_or(()));
+        check_eq!(first, second);
+        unsafe { dealloc(second, 20, 4) };
+        Ok(())
+    }
+
+    fn typed() -> TestResult {
+        let ptr = alloc_type([7u32; 100]).ok().unwrap();
+        let value = unsafe { dealloc_type(ptr) };
+        check!(value.iter().all(|&x| x == 7));
+        Ok(())
+    }
+
+    fn large() -> TestResult {
+        let len = 3 * PAGE_4K_SIZE + 1;
+        let ptr = check_ok!(alloc(len, 64).ok_or(()));
+        check_eq!(ptr as usize % 64, 0);
+        check!(crust::vspace::is_allocated(ptr as usize, len));
+        let bytes = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+        check!(bytes.iter().all(|&b| b == 0));
+        for b in bytes.iter_mut() {
+            *b = 0x5A;
+        }
+        unsafe { dealloc(ptr, len, 64) };
+        // the pages are gone, and so is the region they were in
+        check!(!crust::vspace::is_allocated(ptr as usize, PAGE_4K_SIZE));
+        Ok(())
+    }
+
+    test_cases!(classes, free_lists; target: sizes, recycles, typed, large);
+}
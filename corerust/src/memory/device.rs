@@ -1,5 +1,7 @@
 use memory::LinkedList;
 use crust::capalloc;
+use crust::vspace;
+use crust::vspace::VRegion;
 use core;
 use mantle::kernel;
 use mantle::KError;
@@ -377,6 +379,76 @@ pub fn return_mapped_device_page(addr: usize, page: RegionMappedPage4K) {
     return_device_page(addr, page.unmap());
 }
 
+// a physically contiguous device region, mapped page by page into one contiguous virtual region
+pub struct MappedDeviceRegion {
+    paddr: usize,
+    vregion: VRegion,
+    pages: LinkedList<FixedMappedPage4K>
+}
+
+impl MappedDeviceRegion {
+    pub fn get_addr(&self) -> usize {
+        self.vregion.start()
+    }
+
+    pub fn get_ptr(&mut self) -> *mut u8 {
+        self.get_addr() as *mut u8
+    }
+
+    pub fn get_paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn len(&self) -> usize {
+        self.vregion.len()
+    }
+
+    fn unmap_all(&mut self) {
+        while let Some(page) = self.pages.popmut() {
+            let paddr = self.paddr + (page.get_addr() - self.vregion.start());
+            return_device_page(paddr, page.unmap());
+        }
+    }
+}
+
+// the region is rounded out to whole pages, so the returned address may be before paddr
+pub fn get_mapped_device_region(paddr: usize, len: usize) -> core::result::Result<MappedDeviceRegion, KError> {
+    assert!(len > 0);
+    let start = paddr & !(kernel::PAGE_4K_SIZE - 1);
+    let end = (paddr + len + kernel::PAGE_4K_SIZE - 1) & !(kernel::PAGE_4K_SIZE - 1);
+    let vregion = vspace::allocate_vregion(end - start)?;
+    let mut region = MappedDeviceRegion { paddr: start, vregion, pages: LinkedList::empty() };
+    for i in 0..((end - start) / kernel::PAGE_4K_SIZE) {
+        let offset = i * kernel::PAGE_4K_SIZE;
+        let page = match get_device_page(start + offset) {
+            Ok(page) => page,
+            Err(err) => {
+                return_mapped_device_region(region);
+                return Err(err);
+            }
+        };
+        let mapping = match page.map_into_addr(region.get_addr() + offset, true) {
+            Ok(mapping) => mapping,
+            Err((page, err)) => {
+                return_device_page(start + offset, page);
+                return_mapped_device_region(region);
+                return Err(err);
+            }
+        };
+        if let Err(mapping) = region.pages.pushmut(mapping) {
+            return_device_page(start + offset, mapping.unmap());
+            return_mapped_device_region(region);
+            return Err(KError::NotEnoughMemory);
+        }
+    }
+    Ok(region)
+}
+
+pub fn return_mapped_device_region(mut region: MappedDeviceRegion) {
+    region.unmap_all();
+    vspace::free_vregion(region.vregion);
+}
+
 pub fn init_untyped(untyped: CapRange, untyped_list: [kernel::UntypedDesc; 230usize]) {
     let count = untyped.len();
     // these are sorted!
@@ -0,0 +1,159 @@
+use memory::LinkedList;
+use memory::untyped;
+use crust::capalloc;
+use crust::vspace;
+use crust::vspace::VRegion;
+use core;
+use mantle::kernel;
+use mantle::KError;
+use kobject::*;
+
+// the most that can be allocated in one piece; see UntypedAllocator::allocate_contiguous_small_pages
+pub const MAX_LEN: usize = kernel::PAGE_2M_SIZE / 2;
+
+// physically contiguous memory that devices can be pointed at, mapped into one contiguous virtual region.
+// it starts out zeroed, since the kernel clears pages as they're retyped.
+pub struct DmaRegion {
+    paddr: usize,
+    vregion: VRegion,
+    pages: LinkedList<FixedMappedPage4K>
+}
+
+impl DmaRegion {
+    pub fn get_addr(&self) -> usize {
+        self.vregion.start()
+    }
+
+    pub fn get_ptr(&mut self) -> *mut u8 {
+        self.get_addr() as *mut u8
+    }
+
+    pub fn get_paddr(&self) -> usize {
+        self.paddr
+    }
+
+    // the physical address corresponding to an address within the region
+    pub fn paddr_of(&self, vaddr: usize) -> usize {
+        assert!(vaddr >= self.get_addr() && vaddr < self.get_addr() + self.len());
+        self.paddr + (vaddr - self.get_addr())
+    }
+
+    pub fn len(&self) -> usize {
+        self.vregion.len()
+    }
+}
+
+fn map_page(region: &mut DmaRegion, ut: Untyped, offset: usize) -> core::result::Result<(), KError> {
+    let slot = match capalloc::allocate_cap_slot() {
+        Ok(slot) => slot,
+        Err(err) => {
+            untyped::free_untyped_4k(ut);
+            return Err(err);
+        }
+    };
+    let page = match ut.become_page_4k(slot) {
+        Ok(page) => page,
+        Err((err, ut, slot)) => {
+            capalloc::free_cap_slot(slot);
+            untyped::free_untyped_4k(ut);
+            return Err(err);
+        }
+    };
+    let mapping = match page.map_into_addr(region.get_addr() + offset, true) {
+        Ok(mapping) => mapping,
+        Err((page, err)) => {
+            untyped::free_page4k(page);
+            return Err(err);
+        }
+    };
+    let paddr = match mapping.get_paddr() {
+        Ok(paddr) => paddr,
+        Err(err) => {
+            untyped::free_page4k(mapping.unmap());
+            return Err(err);
+        }
+    };
+    if offset == 0 {
+        region.paddr = paddr;
+    } else if paddr != region.paddr + offset {
+        // would mean the untyped allocator handed out something that isn't contiguous
+        warn!("DMA page at {:#X} is not contiguous with region at {:#X}", paddr, region.paddr);
+        untyped::free_page4k(mapping.unmap());
+        return Err(KError::UnknownError);
+    }
+    if let Err(mapping) = region.pages.pushmut(mapping) {
+        untyped::free_page4k(mapping.unmap());
+        return Err(KError::NotEnoughMemory);
+    }
+    Ok(())
+}
+
+pub fn allocate_dma_region(len: usize) -> core::result::Result<DmaRegion, KError> {
+    assert!(len > 0);
+    if len > MAX_LEN {
+        return Err(KError::RangeError);
+    }
+    let count = (len + kernel::PAGE_4K_SIZE - 1) / kernel::PAGE_4K_SIZE;
+    let mut untypeds = untyped::allocate_contiguous_untyped_4k(count)?;
+    let vregion = match vspace::allocate_vregion(count * kernel::PAGE_4K_SIZE) {
+        Ok(vregion) => vregion,
+        Err(err) => {
+            while let Some(ut) = untypeds.popmut() {
+                untyped::free_untyped_4k(ut);
+            }
+            return Err(err);
+        }
+    };
+    let mut region = DmaRegion { paddr: 0, vregion, pages: LinkedList::empty() };
+    let mut offset = 0;
+    while let Some(ut) = untypeds.popmut() {
+        if let Err(err) = map_page(&mut region, ut, offset) {
+            while let Some(ut) = untypeds.popmut() {
+                untyped::free_untyped_4k(ut);
+            }
+            free_dma_region(region);
+            return Err(err);
+        }
+        offset += kernel::PAGE_4K_SIZE;
+    }
+    Ok(region)
+}
+
+pub fn free_dma_region(mut region: DmaRegion) {
+    while let Some(page) = region.pages.popmut() {
+        untyped::free_page4k(page.unmap());
+    }
+    vspace::free_vregion(region.vregion);
+}
+
+#[cfg(feature = "ktest")]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn contiguous() -> TestResult {
+        let region = check_ok!(allocate_dma_region(3 * kernel::PAGE_4K_SIZE + 1));
+        check_eq!(region.len(), 4 * kernel::PAGE_4K_SIZE);
+        check_eq!(region.pages.len(), 4);
+        for page in &region.pages {
+            check_eq!(check_ok!(page.get_paddr()), region.paddr_of(page.get_addr()));
+        }
+        free_dma_region(region);
+        Ok(())
+    }
+
+    fn zeroed() -> TestResult {
+        let mut region = check_ok!(allocate_dma_region(kernel::PAGE_4K_SIZE));
+        let bytes = unsafe { core::slice::from_raw_parts_mut(region.get_ptr(), region.len()) };
+        check!(bytes.iter().all(|&b| b == 0));
+        free_dma_region(region);
+        Ok(())
+    }
+
+    fn too_large() -> TestResult {
+        check_eq!(allocate_dma_region(MAX_LEN + 1).err(), Some(KError::RangeError));
+        Ok(())
+    }
+
+    test_cases!(contiguous, zeroed, too_large);
+}
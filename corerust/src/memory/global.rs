@@ -0,0 +1,82 @@
+use ::core;
+use ::alloc::allocator::{Alloc, AllocErr, Layout};
+use ::mantle::kernel::PAGE_4K_SIZE;
+use ::memory::alloc;
+use ::memory::heapdebug;
+
+// the global allocator, which is what Box, Vec, String, BTreeMap and the rest of the alloc crate allocate through.
+// everything goes to memory::alloc, which can't align anything past a page.
+
+pub struct KernelAllocator;
+
+#[cfg(not(test))]
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+unsafe impl<'a> Alloc for &'a KernelAllocator {
+    // the debug heap records callers from __rust_alloc's frame, which is the one above this, so that the first is
+    // whatever called into the allocator
+    #[inline(never)]
+    unsafe fn alloc(&mut self, layout: Layout) -> core::result::Result<*mut u8, AllocErr> {
+        if layout.size() == 0 {
+            return Err(AllocErr::Unsupported { details: "zero-sized allocation" });
+        }
+        if layout.align() > PAGE_4K_SIZE {
+            return Err(AllocErr::Unsupported { details: "alignment over a page" });
+        }
+        let frame = heapdebug::outer(heapdebug::frame());
+        alloc::alloc_from(layout.size(), layout.align(), frame).ok_or(AllocErr::Exhausted { request: layout })
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        alloc::dealloc(ptr, layout.size(), layout.align())
+    }
+
+    fn oom(&mut self, err: AllocErr) -> ! {
+        panic!("out of memory: {:?}", err);
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn layouts() -> TestResult {
+        let mut allocator = &KernelAllocator;
+        for &(size, align) in &[(1, 1), (3, 2), (24, 8), (40, 32), (3000, 4), (100, PAGE_4K_SIZE)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = check_ok!(unsafe { allocator.alloc(layout.clone()) });
+            check_eq!(ptr as usize % align, 0);
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        check!(unsafe { allocator.alloc(Layout::from_size_align(64, 2 * PAGE_4K_SIZE).unwrap()) }.is_err());
+        Ok(())
+    }
+
+    // only in the target, since under `cargo test` the host's allocator is the global one
+    fn collections() -> TestResult {
+        use ::alloc::btree_map::BTreeMap;
+        use ::alloc::string::String;
+        use ::alloc::vec::Vec;
+        let mut numbers: Vec<usize> = Vec::new();
+        for i in 0..100 {
+            numbers.push(i * i);
+        }
+        check_eq!(numbers.iter().sum::<usize>(), 328350);
+        let mut names: BTreeMap<String, usize> = BTreeMap::new();
+        for (i, name) in ["zero", "one", "two", "three"].iter().enumerate() {
+            names.insert(String::from(*name), i);
+        }
+        check_eq!(names.get("two"), Some(&2));
+        check_eq!(names.keys().next().map(|s| s.as_str()), Some("one"));
+        let mut text = String::new();
+        for name in names.keys() {
+            text.push_str(name);
+        }
+        check_eq!(text.as_str(), "onethreetwozero");
+        Ok(())
+    }
+
+    test_cases!(layouts; target: collections);
+}
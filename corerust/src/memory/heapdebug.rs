@@ -0,0 +1,291 @@
+use ::core;
+use ::core::fmt::Write;
+
+// the debug heap, in builds with the heapdebug feature (`HEAP_DEBUG=1 ./build.sh`). memory::alloc gives each
+// allocation room for a header and redzones around it:
+//
+//     [header][canaries ... ][allocation][canaries]
+//
+// the canaries are checked when it's freed, and its contents are poisoned, so that anything still reading it sees
+// POISON rather than plausible data. if the block is handed out again and the poison has changed in the meantime,
+// something wrote to it after it was freed. the header records where the allocation was made: the file and line of
+// the nearest at_site!, if there is one, or else the return addresses above the allocator, found by following frame
+// pointers (build.sh forces them on with HEAP_DEBUG=1), which `addr2line -e init.elf` can turn into lines. the walk
+// starts from the frame that memory::alloc is given, which is that of the outermost allocator function, so none of
+// the addresses recorded are the allocator's own.
+//
+// dump lists everything still allocated.
+
+pub const CANARY: u8 = 0xCB;
+pub const POISON: u8 = 0xDD;
+const REDZONE: usize = 16;
+const CALLER_DEPTH: usize = 4;
+const MAX_FRAME_LEN: usize = 1 << 20;
+
+const LIVE_MAGIC: u64 = 0x6865617020697420;
+const FREED_MAGIC: u64 = 0x6672656564206974;
+
+#[repr(C)]
+struct Header {
+    // where the heap's free list keeps its link, once the block is freed
+    free_link: usize,
+    magic: u64,
+    size: usize,
+    align: usize,
+    seq: usize,
+    file: &'static str,
+    line: u32,
+    callers: [usize; CALLER_DEPTH],
+    prev: *mut Header,
+    next: *mut Header
+}
+
+static mut OUTSTANDING: *mut Header = 0 as *mut Header;
+static mut NEXT_SEQ: usize = 0;
+static mut SITE: Option<(&'static str, u32)> = None;
+
+// tags whatever the expression allocates with where it is in the source. only does anything with the debug heap.
+macro_rules! at_site {
+    ($e:expr) => ({
+        let outer = ::memory::heapdebug::enter_site(file!(), line!());
+        let out = $e;
+        ::memory::heapdebug::leave_site(outer);
+        out
+    })
+}
+
+pub fn enter_site(file: &'static str, line: u32) -> Option<(&'static str, u32)> {
+    unsafe { core::mem::replace(&mut SITE, Some((file, line))) }
+}
+
+pub fn leave_site(outer: Option<(&'static str, u32)>) {
+    unsafe { SITE = outer };
+}
+
+fn header_len() -> usize {
+    core::mem::size_of::<Header>()
+}
+
+fn prefix_len(align: usize) -> usize {
+    (header_len() + REDZONE + align - 1) & !(align - 1)
+}
+
+// what to ask the heap for, to hold an allocation of this size and alignment
+pub fn padded(size: usize, align: usize) -> (usize, usize) {
+    (prefix_len(align) + size + REDZONE, core::cmp::max(align, 8))
+}
+
+unsafe fn fill(start: *mut u8, len: usize, value: u8) {
+    core::ptr::write_bytes(start, value, len);
+}
+
+// the offset of the first byte that isn't value, if any
+unsafe fn find_other(start: *const u8, len: usize, value: u8) -> Option<usize> {
+    core::slice::from_raw_parts(start, len).iter().position(|&b| b != value)
+}
+
+// the frame pointer of the function this is inlined into
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn frame() -> usize {
+    let frame: usize;
+    unsafe {
+        asm!("movq %rbp, $0" : "=r" (frame) : : : "volatile");
+    }
+    frame
+}
+
+// the frame pointer of whatever called the function with this frame
+pub fn outer(frame: usize) -> usize {
+    if frame == 0 || frame & 7 != 0 {
+        return 0;
+    }
+    unsafe { *(frame as *const usize) }
+}
+
+// the return addresses in this frame and the ones above it
+fn callers(mut frame: usize) -> [usize; CALLER_DEPTH] {
+    let mut out = [0; CALLER_DEPTH];
+    for i in 0..CALLER_DEPTH {
+        if frame == 0 || frame & 7 != 0 {
+            break;
+        }
+        let (next, ret) = unsafe { (*(frame as *const usize), *((frame + 8) as *const usize)) };
+        out[i] = ret;
+        // the stack grows down, so anything that isn't a little further up isn't a frame
+        if next <= frame || next - frame > MAX_FRAME_LEN {
+            break;
+        }
+        frame = next;
+    }
+    out
+}
+
+struct Site<'a>(&'a Header);
+
+impl<'a> core::fmt::Display for Site<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if !self.0.file.is_empty() {
+            return write!(f, "{}:{}", self.0.file, self.0.line);
+        }
+        for (i, &addr) in self.0.callers.iter().take_while(|&&addr| addr != 0).enumerate() {
+            write!(f, "{}{:#X}", if i == 0 { "" } else { " <- " }, addr)?;
+        }
+        Ok(())
+    }
+}
+
+unsafe fn user_ptr(header: *mut Header) -> *mut u8 {
+    (header as *mut u8).offset(prefix_len((*header).align) as isize)
+}
+
+unsafe fn check_redzones(header: *mut Header) -> Result<(), &'static str> {
+    let user = user_ptr(header);
+    let front = (header as *mut u8).offset(header_len() as isize);
+    if find_other(front, user as usize - front as usize, CANARY).is_some() {
+        return Err("written before the start");
+    }
+    if find_other(user.offset((*header).size as isize), REDZONE, CANARY).is_some() {
+        return Err("written past the end");
+    }
+    Ok(())
+}
+
+// the offset into a freed allocation of the first byte that isn't poison, if any
+unsafe fn check_poison(header: *mut Header) -> Option<usize> {
+    find_other(user_ptr(header), (*header).size, POISON)
+}
+
+// sets up a block from the heap, of the size given by padded, and returns the allocation inside it. frame is where
+// to start looking for callers, as passed to memory::alloc::alloc_from.
+pub unsafe fn on_alloc(raw: *mut u8, size: usize, align: usize, frame: usize) -> *mut u8 {
+    let header = raw as *mut Header;
+    if (*header).magic == FREED_MAGIC {
+        if let Some(offset) = check_poison(header) {
+            panic!("heap: {} bytes at {:#X} were written {} bytes in, after being freed; allocated at {}",
+                   (*header).size, user_ptr(header) as usize, offset, Site(&*header));
+        }
+    }
+    let (file, line) = SITE.unwrap_or(("", 0));
+    core::ptr::write(header, Header {
+        free_link: 0, magic: LIVE_MAGIC, size, align, seq: NEXT_SEQ, file, line,
+        callers: if file.is_empty() { callers(frame) } else { [0; CALLER_DEPTH] },
+        prev: core::ptr::null_mut(), next: OUTSTANDING
+    });
+    NEXT_SEQ += 1;
+    if !OUTSTANDING.is_null() {
+        (*OUTSTANDING).prev = header;
+    }
+    OUTSTANDING = header;
+    let user = user_ptr(header);
+    let front = raw.offset(header_len() as isize);
+    fill(front, user as usize - front as usize, CANARY);
+    fill(user.offset(size as isize), REDZONE, CANARY);
+    user
+}
+
+unsafe fn unlink(header: *mut Header) {
+    if (*header).prev.is_null() {
+        OUTSTANDING = (*header).next;
+    } else {
+        (*(*header).prev).next = (*header).next;
+    }
+    if !(*header).next.is_null() {
+        (*(*header).next).prev = (*header).prev;
+    }
+}
+
+// checks an allocation that's being freed, and poisons it. returns the block to give back to the heap.
+pub unsafe fn on_dealloc(ptr: *mut u8, size: usize, align: usize) -> *mut u8 {
+    let header = ptr.offset(-(prefix_len(align) as isize)) as *mut Header;
+    match (*header).magic {
+        LIVE_MAGIC => {}
+        FREED_MAGIC => panic!("heap: {:#X} freed twice; allocated at {}", ptr as usize, Site(&*header)),
+        _ => panic!("heap: freeing {:#X}, which isn't an allocation, or has had its header overwritten", ptr as usize)
+    }
+    if (*header).size != size || (*header).align != align {
+        panic!("heap: freeing {} bytes at {:#X} as {} bytes aligned to {}; allocated at {}",
+               (*header).size, ptr as usize, size, align, Site(&*header));
+    }
+    if let Err(what) = check_redzones(header) {
+        panic!("heap: {} bytes at {:#X} {}; allocated at {}", size, ptr as usize, what, Site(&*header));
+    }
+    unlink(header);
+    (*header).magic = FREED_MAGIC;
+    fill(ptr, size, POISON);
+    header as *mut u8
+}
+
+pub fn dump(writer: &mut Write) -> core::fmt::Result {
+    if !cfg!(feature = "heapdebug") {
+        return writeln!(writer, "the debug heap is off; build with HEAP_DEBUG=1 to track allocations");
+    }
+    writeln!(writer, "outstanding allocations:")?;
+    let (mut count, mut bytes) = (0, 0);
+    let mut header = unsafe { OUTSTANDING };
+    while !header.is_null() {
+        let h = unsafe { &*header };
+        write!(writer, "  #{} {:#X}: {} bytes, from {}", h.seq, unsafe { user_ptr(header) } as usize, h.size, Site(h))?;
+        match unsafe { check_redzones(header) } {
+            Ok(()) => writeln!(writer, "")?,
+            Err(what) => writeln!(writer, " -- {}!", what)?
+        }
+        count += 1;
+        bytes += h.size;
+        header = h.next;
+    }
+    writeln!(writer, "  {} allocations, {} bytes", count, bytes)
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    // takes the test's allocation off the list of outstanding ones if a check fails before it's freed, since it's
+    // on the test's stack
+    struct Linked(*mut Header);
+
+    impl Drop for Linked {
+        fn drop(&mut self) {
+            unsafe {
+                if (*self.0).magic == LIVE_MAGIC {
+                    unlink(self.0);
+                }
+            }
+        }
+    }
+
+    // in a buffer of its own, rather than from the heap. all in one test, since they share the list of allocations.
+    fn checks() -> TestResult {
+        const SIZE: usize = 40;
+        let mut buf = [0u64; 32];
+        let raw = buf.as_mut_ptr() as *mut u8;
+        let header = raw as *mut Header;
+        check!(padded(SIZE, 8).0 <= buf.len() * 8);
+        let ptr = at_site!(unsafe { on_alloc(raw, SIZE, 8, frame()) });
+        let _linked = Linked(header);
+        check_eq!(ptr as usize % 8, 0);
+        unsafe {
+            check_eq!((*header).file, file!());
+            check_eq!(OUTSTANDING, header);
+            fill(ptr, SIZE, 0x11);
+            check!(check_redzones(header).is_ok());
+            *ptr.offset(SIZE as isize) = 0;
+            check_eq!(check_redzones(header), Err("written past the end"));
+            *ptr.offset(SIZE as isize) = CANARY;
+            *ptr.offset(-1) = 0;
+            check_eq!(check_redzones(header), Err("written before the start"));
+            *ptr.offset(-1) = CANARY;
+
+            check_eq!(on_dealloc(ptr, SIZE, 8), raw);
+            check!(OUTSTANDING != header);
+            check_eq!(check_poison(header), None);
+            *ptr.offset(12) = 0;
+            check_eq!(check_poison(header), Some(12));
+        }
+        Ok(())
+    }
+
+    test_cases!(checks);
+}
@@ -139,6 +139,16 @@ impl<T> LinkedList<T> {
         }
     }
 
+    // appends instead of prepending, so that the list keeps the order things were added in
+    pub fn push_back(&mut self, x: T) -> core::result::Result<(), T> {
+        let mut cur: &mut LinkedList<T> = self;
+        while !cur.is_empty() {
+            let tmp = cur;
+            cur = tmp.tailmut().unwrap();
+        }
+        cur.pushmut(x)
+    }
+
     pub fn pop(self) -> Option<(T, LinkedList<T>)> {
         if let LinkedList::List(pair) = self {
             Some(pair.split())
@@ -384,3 +394,63 @@ impl<'a, T: core::fmt::Debug> core::fmt::Debug for &'a LinkedList<T> {
         }
     }
 }
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn push_and_pop() -> TestResult {
+        let mut list: LinkedList<usize> = LinkedList::empty();
+        check!(list.is_empty());
+        for i in 0..5 {
+            check!(list.pushmut(i).is_ok());
+        }
+        check_eq!(list.len(), 5);
+        check_eq!(list.head(), Some(&4));
+        for i in (0..5).rev() {
+            check_eq!(list.popmut(), Some(i));
+        }
+        check_eq!(list.popmut(), None);
+        check!(list.is_empty());
+        Ok(())
+    }
+
+    fn push_back() -> TestResult {
+        let mut list: LinkedList<usize> = LinkedList::empty();
+        for i in 0..5 {
+            check!(list.push_back(i).is_ok());
+        }
+        for (i, &x) in (&list).into_iter().enumerate() {
+            check_eq!(x, i);
+        }
+        check!(list.pushmut(10).is_ok());
+        check_eq!(list.get(0), Some(&10));
+        check_eq!(list.get(5), Some(&4));
+        check_eq!(list.get(6), None);
+        Ok(())
+    }
+
+    fn collect() -> TestResult {
+        let list = LinkedList::collect(0..4).unwrap();
+        check_eq!(list.len(), 4);
+        check_eq!(list.get(3), Some(&3));
+        check!(LinkedList::collect(0..0).unwrap().is_empty());
+        Ok(())
+    }
+
+    fn find_and_remove() -> TestResult {
+        let mut list = LinkedList::collect(0..6).unwrap();
+        check_eq!(list.find_index(|&x| x == 4), Some(4));
+        check_eq!(list.find(|&x| x > 2), Some(&3));
+        *list.find_mut(|&x| x == 2).unwrap() = 20;
+        check_eq!(list.remove_mut(|&x| x == 20), Some(20));
+        check_eq!(list.remove_mut(|&x| x == 20), None);
+        check_eq!(list.remove_mut(|&x| x == 0), Some(0));
+        check_eq!(list.len(), 4);
+        check_eq!(list.find_index(|&x| x == 5), Some(3));
+        Ok(())
+    }
+
+    test_cases!(push_and_pop, push_back, collect, find_and_remove);
+}
@@ -1,26 +1,19 @@
 use ::memory;
 use ::core;
 
-// TODO: don't base this off of the slab allocator......
-
-pub const MAX_VARALLOC_LEN: usize = memory::alloc::MAX_ALLOC_LEN - 8;
-const VARALLOC_MAGIC: u64 = 0x737A200737A20000;
-const VARALLOC_MAGIC_MASK: u64 = 0xFFFFFFFFFFFFF800;
+const VARALLOC_MAGIC: u64 = 0x737A000000000000;
+const VARALLOC_MAGIC_MASK: u64 = 0xFFFF000000000000;
 
 pub fn malloc(len: usize) -> Option<*mut u8> {
-    if len <= MAX_VARALLOC_LEN {
-        let total_len = (len + 8) as u64; // 8 for usize for tracking real length
-        assert!((total_len & VARALLOC_MAGIC_MASK) == 0);
-        if let Some(ptr) = memory::alloc::alloc_fix(total_len as u16) {
-            unsafe {
-                *ptr = total_len | VARALLOC_MAGIC;
-                Some((ptr as *mut u8).offset(8))
-            }
-        } else {
-            None
+    let total_len = len + 8; // 8 for usize for tracking real length
+    assert!((total_len as u64 & VARALLOC_MAGIC_MASK) == 0);
+    if let Some(ptr) = memory::alloc::alloc(total_len, 8) {
+        unsafe {
+            *(ptr as *mut u64) = total_len as u64 | VARALLOC_MAGIC;
+            Some(ptr.offset(8))
         }
     } else {
-        panic!("not yet implemented: allocations larger than {} bytes", MAX_VARALLOC_LEN);
+        None
     }
 }
 
@@ -30,12 +23,12 @@ pub unsafe fn get_len(ptr: *mut u8) -> usize {
     let len = *realptr;
     assert!((len & VARALLOC_MAGIC_MASK) == VARALLOC_MAGIC); // otherwise it's probably not a real allocation
     let reallen = len & !VARALLOC_MAGIC_MASK;
-    assert!(reallen <= memory::alloc::MAX_ALLOC_LEN as u64);
+    assert!(reallen >= 8);
     (reallen - 8) as usize
 }
 
 pub unsafe fn free(ptr: *mut u8) {
-    memory::alloc::dealloc_fix((ptr.offset(-8)) as *mut u64, (get_len(ptr) + 8) as u16);
+    memory::alloc::dealloc(ptr.offset(-8), get_len(ptr) + 8, 8);
 }
 
 // on failure, passed-in pointer is still valid
@@ -1,12 +1,31 @@
+// first, for at_site!
+#[macro_use]
+pub mod heapdebug;
 mod alloc;
-mod boxed;
+mod global;
 mod linkedlist;
 mod malloc;
+mod report;
 pub mod string;
 pub mod device;
+pub mod dma;
 pub mod untyped;
 pub mod smalluntyped;
 
 pub use self::alloc::init_allocator;
-pub use self::boxed::Box;
+pub use ::alloc::boxed::Box;
 pub use self::linkedlist::LinkedList;
+pub use self::heapdebug::dump as dump_heap;
+pub use self::alloc::{ClassStats, HeapStats};
+pub use self::report::{stats, MemoryStats};
+
+#[cfg(feature = "ktest")]
+pub use self::alloc::tests as alloc_tests;
+#[cfg(feature = "ktest")]
+pub use self::global::tests as global_tests;
+#[cfg(feature = "ktest")]
+pub use self::heapdebug::tests as heapdebug_tests;
+#[cfg(feature = "ktest")]
+pub use self::linkedlist::tests as linkedlist_tests;
+#[cfg(feature = "ktest")]
+pub use self::report::tests as report_tests;
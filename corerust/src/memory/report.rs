@@ -0,0 +1,79 @@
+use ::core;
+use ::crust;
+use ::memory::alloc::{self, HeapStats};
+use ::memory::untyped;
+
+// everything we know about where memory has gone, in one place: the heap, our address space, cap slots and the
+// untypeds that are left. printed by the shell's `mem`.
+
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryStats {
+    pub heap: HeapStats,
+    // in bytes, of the region between our image and the kernel
+    pub vspace_used: usize,
+    pub vspace_free: usize,
+    pub free_cap_slots: usize,
+    // untypeds not yet handed out, of 4K and 2M
+    pub free_small_untypeds: usize,
+    pub free_large_untypeds: usize
+}
+
+pub fn stats() -> MemoryStats {
+    let untypeds = untyped::get_allocator();
+    MemoryStats {
+        heap: alloc::heap_stats(),
+        vspace_used: crust::vspace::used_len(),
+        vspace_free: crust::vspace::free_len(),
+        free_cap_slots: crust::capalloc::free_slot_count(),
+        free_small_untypeds: untypeds.small_page_count(),
+        free_large_untypeds: untypeds.large_page_count()
+    }
+}
+
+impl core::fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let heap = &self.heap;
+        writeln!(f, "heap: {} KB in use, {} KB at peak", heap.in_use >> 10, heap.peak >> 10)?;
+        for class in heap.classes.iter().filter(|c| c.pages > 0) {
+            writeln!(f, "  {:>5} bytes: {} in use ({} bytes), {} free, {} pages",
+                     class.size, class.in_use, class.in_use * class.size, class.free, class.pages)?;
+        }
+        writeln!(f, "  large: {} allocations, {} pages", heap.large_allocations, heap.large_pages)?;
+        writeln!(f, "  pages: {} from the early heap, {} mapped dynamically", heap.early_heap_pages, heap.dynamic_pages)?;
+        writeln!(f, "address space: {} MB used, {} MB free", self.vspace_used >> 20, self.vspace_free >> 20)?;
+        writeln!(f, "cap slots: {} free", self.free_cap_slots)?;
+        let untyped_kb = self.free_small_untypeds * 4 + self.free_large_untypeds * 2048;
+        write!(f, "untyped: {} 4K, {} 2M free; {} KB = {} MB",
+               self.free_small_untypeds, self.free_large_untypeds, untyped_kb, untyped_kb >> 10)
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    // only in the target, since under `cargo test` the host's heap isn't counted
+    fn counts() -> TestResult {
+        let before = stats();
+        let ptr = check_ok!(alloc::alloc(100, 8).ok_or(()));
+        let during = stats();
+        check!(during.heap.in_use > before.heap.in_use);
+        check!(during.heap.peak >= during.heap.in_use);
+        unsafe { alloc::dealloc(ptr, 100, 8) };
+        check_eq!(stats().heap.in_use, before.heap.in_use);
+
+        let slot = check_ok!(crust::capalloc::allocate_cap_slot());
+        check_eq!(stats().free_cap_slots, before.free_cap_slots - 1);
+        crust::capalloc::free_cap_slot(slot);
+        check_eq!(stats().free_cap_slots, before.free_cap_slots);
+
+        let region = check_ok!(crust::vspace::allocate_vregion(16 * ::mantle::kernel::PAGE_4K_SIZE));
+        check_eq!(stats().vspace_used, before.vspace_used + region.len());
+        crust::vspace::free_vregion(region);
+        check_eq!(stats().vspace_free, before.vspace_free);
+        Ok(())
+    }
+
+    test_cases!(; target: counts);
+}
@@ -80,4 +80,30 @@ pub fn free_notification(not: Notification) {
     let (ut, slot) = not.free();
     crust::capalloc::free_cap_slot(slot);
     free_untyped_16b(ut)
-}
\ No newline at end of file
+}
+
+pub fn allocate_endpoint() -> core::result::Result<Endpoint, KError> {
+    let ut: Untyped = allocate_untyped_16b()?;
+    match crust::capalloc::allocate_cap_slot() {
+        Ok(slot) => {
+            match ut.become_endpoint(slot) {
+                Ok(ep) => Ok(ep),
+                Err((err, ut, slot)) => {
+                    crust::capalloc::free_cap_slot(slot);
+                    free_untyped_16b(ut);
+                    Err(err)
+                }
+            }
+        },
+        Err(err) => {
+            free_untyped_16b(ut);
+            Err(err)
+        }
+    }
+}
+
+pub fn free_endpoint(ep: Endpoint) {
+    let (ut, slot) = ep.free();
+    crust::capalloc::free_cap_slot(slot);
+    free_untyped_16b(ut)
+}
@@ -1,10 +1,6 @@
 use ::memory;
 use ::core;
 
-// TODO: expand to support sizes larger than 2032, which will depend on a better malloc
-
-pub const MAX_STRING_LEN: usize = memory::alloc::MAX_ALLOC_LEN - 8;
-
 pub struct VarStr<'a> {
     memory: &'a [u8]
 }
@@ -21,43 +17,54 @@ impl<'a> Drop for VarStr<'a> {
     }
 }
 
+// grows on the heap as it's added to, and is only truncated if the heap runs out
 pub struct StringBuilder {
-    memory: [u8; MAX_STRING_LEN],
+    memory: *mut u8, // from malloc, once there's anything to hold
+    capacity: usize,
     index: usize,
     was_truncated: bool
 }
 
+const MIN_CAPACITY: usize = 64;
+
 impl StringBuilder {
     pub fn new() -> StringBuilder {
-        StringBuilder { index: 0, was_truncated: false, memory: [0; MAX_STRING_LEN] }
+        StringBuilder { memory: core::ptr::null_mut(), capacity: 0, index: 0, was_truncated: false }
     }
 
     pub fn is_truncated(&self) -> bool {
         self.was_truncated
     }
 
-    pub fn add_u8(&mut self, b: u8) {
-        if self.index < MAX_STRING_LEN {
-            self.memory[self.index] = b;
-            self.index += 1;
-        } else {
-            self.was_truncated = true; // oops!
+    // makes room for up to `extra` more bytes, returning how many there's room for
+    fn reserve(&mut self, extra: usize) -> usize {
+        if self.index + extra > self.capacity {
+            let capacity = core::cmp::max(core::cmp::max(self.capacity * 2, self.index + extra), MIN_CAPACITY);
+            let grown = if self.memory.is_null() {
+                memory::malloc::malloc(capacity)
+            } else {
+                unsafe { memory::malloc::realloc(self.memory, capacity) }
+            };
+            if let Some(ptr) = grown {
+                self.memory = ptr;
+                self.capacity = capacity;
+            }
         }
+        core::cmp::min(extra, self.capacity - self.index)
+    }
+
+    pub fn add_u8(&mut self, b: u8) {
+        self.add_bytes(&[b])
     }
 
     pub fn add_bytes(&mut self, b: &[u8]) {
-        if self.index + b.len() <= MAX_STRING_LEN {
-            for i in 0 .. b.len() {
-                self.memory[self.index + i] = b[i]
-            }
-            self.index += b.len()
-        } else {
-            let remain = MAX_STRING_LEN - b.len();
-            for i in 0 .. remain {
-                self.memory[self.index + i] = b[i]
-            }
-            self.index += remain;
-            self.was_truncated = true;
+        let n = self.reserve(b.len());
+        if n > 0 {
+            unsafe { core::ptr::copy_nonoverlapping(b.as_ptr(), self.memory.offset(self.index as isize), n) };
+            self.index += n;
+        }
+        if n < b.len() {
+            self.was_truncated = true; // oops!
         }
     }
 
@@ -73,7 +80,9 @@ impl StringBuilder {
     pub fn to_str(&self) -> Option<VarStr<'static>> {
         if let Some(ptr) = memory::malloc::malloc(self.index) {
             unsafe {
-                core::ptr::copy_nonoverlapping(self.memory.as_ptr(), ptr, self.index);
+                if self.index > 0 {
+                    core::ptr::copy_nonoverlapping(self.memory, ptr, self.index);
+                }
                 Some(VarStr { memory: core::slice::from_raw_parts(ptr, self.index) })
             }
         } else {
@@ -81,3 +90,39 @@ impl StringBuilder {
         }
     }
 }
+
+impl Drop for StringBuilder {
+    fn drop(&mut self) {
+        if !self.memory.is_null() {
+            unsafe { memory::malloc::free(self.memory) }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn grows() -> TestResult {
+        let mut sb = StringBuilder::new();
+        for i in 0..5000 {
+            sb.add_char((b'a' + (i % 26) as u8) as char);
+        }
+        sb.add_str("end");
+        check!(!sb.is_truncated());
+        let s = check_ok!(sb.to_str().ok_or(()));
+        check_eq!(s.as_str().len(), 5003);
+        check!(s.as_str().starts_with("abc"));
+        check!(s.as_str().ends_with("end"));
+        Ok(())
+    }
+
+    fn empty() -> TestResult {
+        let s = check_ok!(StringBuilder::new().to_str().ok_or(()));
+        check_eq!(s.as_str(), "");
+        Ok(())
+    }
+
+    test_cases!(grows, empty);
+}
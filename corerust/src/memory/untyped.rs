@@ -145,6 +145,56 @@ impl UntypedAllocator {
         Ok(self.small_pages.popmut().unwrap())
     }
 
+    // pages split from one untyped are laid out in order, so these are physically contiguous, lowest address first.
+    // limited to half a large page, which is split off and cut up so the leftovers can be used as small pages.
+    pub fn allocate_contiguous_small_pages(&mut self, count: usize) -> core::result::Result<LinkedList<Untyped>, KError> {
+        let split_bits = kernel::PAGE_2M_BITS - 1 - kernel::PAGE_4K_BITS;
+        assert!(count > 0 && count <= 1 << split_bits);
+        let large_page = self.allocate_large_page()?;
+        let mut halves: UntypedSet = match large_page.split_calloc(1) {
+            Ok(uts) => uts,
+            Err((err, ut)) => {
+                self.add_large_page(ut);
+                return Err(err);
+            }
+        };
+        let half = halves.take_front().unwrap();
+        self.add_midsize_block(halves.take_front().unwrap());
+        assert!(self.stashed.pushmut(halves).is_ok());
+        let mut pages: UntypedSet = match half.split_calloc(split_bits) {
+            Ok(uts) => uts,
+            Err((err, ut)) => {
+                self.add_midsize_block(ut);
+                return Err(err);
+            }
+        };
+        for _ in count..pages.count() {
+            self.add_small_page(pages.take_back().unwrap());
+        }
+        // taken from the back, because pushing onto the list puts them in front
+        let mut out: LinkedList<Untyped> = LinkedList::empty();
+        while let Some(ut) = pages.take_back() {
+            if let Err(ut) = out.pushmut(ut) {
+                self.add_small_page(ut);
+                while let Some(ut) = pages.take_back().or_else(|| out.popmut()) {
+                    self.add_small_page(ut);
+                }
+                assert!(self.stashed.pushmut(pages).is_ok());
+                return Err(KError::NotEnoughMemory);
+            }
+        }
+        assert!(self.stashed.pushmut(pages).is_ok());
+        Ok(out)
+    }
+
+    pub fn small_page_count(&self) -> usize {
+        self.small_pages.len()
+    }
+
+    pub fn large_page_count(&self) -> usize {
+        self.large_pages.len()
+    }
+
     pub fn print_info(&self, writer: &mut core::fmt::Write) -> core::fmt::Result {
         writeln!(writer, "memory info:")?;
         writeln!(writer, "  number of large blocks: {}", self.large_pages.len())?;
@@ -227,6 +277,35 @@ pub fn allocate_page4k() -> core::result::Result<Page4K, KError> {
     }
 }
 
+pub fn allocate_tcb() -> core::result::Result<TCB, KError> {
+    let slot = crust::capalloc::allocate_cap_slot()?;
+    let ut = match allocate_untyped_4k() {
+        Ok(ut) => ut,
+        Err(err) => {
+            crust::capalloc::free_cap_slot(slot);
+            return Err(err);
+        }
+    };
+    match ut.become_tcb(slot) {
+        Ok(tcb) => Ok(tcb),
+        Err((err, ut, cs)) => {
+            crust::capalloc::free_cap_slot(cs);
+            free_untyped_4k(ut);
+            Err(err)
+        }
+    }
+}
+
+pub fn free_tcb(tcb: TCB) {
+    let (ut, cs) = tcb.free();
+    free_untyped_4k(ut);
+    crust::capalloc::free_cap_slot(cs);
+}
+
+pub fn allocate_contiguous_untyped_4k(count: usize) -> core::result::Result<LinkedList<Untyped>, KError> {
+    get_allocator().allocate_contiguous_small_pages(count)
+}
+
 pub fn free_untyped_4k(ut: Untyped) {
     assert!(ut.size_bits() == kernel::PAGE_4K_BITS);
     get_allocator().add_small_page(ut);
@@ -236,4 +315,112 @@ pub fn free_page4k(page: Page4K) {
     let (ut, cs) = page.free();
     free_untyped_4k(ut);
     crust::capalloc::free_cap_slot(cs);
-}
\ No newline at end of file
+}
+#[cfg(any(test, feature = "ktest"))]
+pub mod tests {
+    use super::*;
+    use ::ktest::TestResult;
+
+    fn page_roundtrip() -> TestResult {
+        let page = check_ok!(allocate_page4k());
+        check_eq!(check_ok!(page.get_paddr()) % kernel::PAGE_4K_SIZE, 0);
+        let mut mapping = match page.map_into_vspace(true) {
+            Ok(mapping) => mapping,
+            Err((page, err)) => {
+                free_page4k(page);
+                debugc!("# error: {:?}", err);
+                fail!("could not map the page");
+            }
+        };
+        // pages come zeroed from the kernel
+        check!(mapping.get_array().iter().all(|&b| b == 0));
+        for (i, b) in mapping.get_array().iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        check!(mapping.get_array().iter().enumerate().all(|(i, &b)| b == i as u8));
+        free_page4k(mapping.unmap());
+        Ok(())
+    }
+
+    fn contiguous() -> TestResult {
+        let mut untypeds = check_ok!(allocate_contiguous_untyped_4k(4));
+        check_eq!(untypeds.len(), 4);
+        while let Some(ut) = untypeds.popmut() {
+            check_eq!(ut.size_bits(), kernel::PAGE_4K_BITS);
+            free_untyped_4k(ut);
+        }
+        Ok(())
+    }
+
+    // the rest make an allocator of their own, out of untypeds from the simulated kernel, and leak it at the end,
+    // since none of what it holds can be dropped
+
+    #[cfg(test)]
+    fn sim_allocator(blocks: &[(usize, u8)]) -> UntypedAllocator {
+        let mut alloc = UntypedAllocator { small_pages: LinkedList::empty(), large_pages: LinkedList::empty(), stashed: LinkedList::empty() };
+        for &(paddr, size_bits) in blocks {
+            let slot = ::mantle::sim::add_untyped(paddr, size_bits);
+            alloc.add_initial_block(Untyped::from_cap(CapSlot::from_index(slot).assert_populated(), size_bits));
+        }
+        alloc
+    }
+
+    #[cfg(test)]
+    fn paddr_of(ut: Untyped) -> core::result::Result<usize, KError> {
+        match ut.become_page_4k(crust::capalloc::allocate_cap_slot()?) {
+            Ok(page) => {
+                let paddr = page.get_paddr();
+                core::mem::forget(page);
+                paddr
+            }
+            Err((err, ut, slot)) => {
+                core::mem::forget((ut, slot));
+                Err(err)
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn initial_blocks() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let alloc = sim_allocator(&[(0x80000000, kernel::PAGE_2M_BITS + 10), (0x200000, kernel::PAGE_2M_BITS),
+                                    (0x100000, kernel::PAGE_4K_BITS + 3), (0x1000, kernel::PAGE_4K_BITS)]);
+        check_eq!(alloc.large_pages.len(), (1 << 10) + 1);
+        check_eq!(alloc.small_pages.len(), 8 + 1);
+        core::mem::forget(alloc);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn small_from_large() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let mut alloc = sim_allocator(&[(0x200000, kernel::PAGE_2M_BITS)]);
+        let ut = check_ok!(alloc.allocate_small_page());
+        check_eq!(ut.size_bits(), kernel::PAGE_4K_BITS);
+        check!(alloc.allocate_large_page().is_err());
+        check_eq!(alloc.small_pages.len(), 511);
+        let paddr = check_ok!(paddr_of(ut));
+        check!(paddr >= 0x200000 && paddr < 0x400000 && paddr % kernel::PAGE_4K_SIZE == 0);
+        core::mem::forget(alloc);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn contiguous_paddrs() -> TestResult {
+        let _guard = ::ktest::host::setup();
+        let mut alloc = sim_allocator(&[(0x200000, kernel::PAGE_2M_BITS)]);
+        let mut pages = check_ok!(alloc.allocate_contiguous_small_pages(4));
+        check_eq!(pages.len(), 4);
+        // the other half of the large page, and what was left of this half
+        check_eq!(alloc.small_pages.len(), 256 + 252);
+        let mut expected = 0x200000;
+        while let Some(ut) = pages.popmut() {
+            check_eq!(check_ok!(paddr_of(ut)), expected);
+            expected += kernel::PAGE_4K_SIZE;
+        }
+        core::mem::forget(alloc);
+        Ok(())
+    }
+
+    test_cases!(; target: page_roundtrip, contiguous; host: initial_blocks, small_from_large, contiguous_paddrs);
+}
@@ -0,0 +1,21 @@
+#!/bin/bash -e
+# builds the test init.elf and runs it without a display; the results come out over serial, in TAP form.
+# exits with 0 if everything passed. `./ktest.sh <filter>` runs only the matching tests.
+cd $(dirname $0)/sysroot
+export SYSROOT=$(pwd)
+cd ../corerust
+./build.sh test "$1"
+cd ..
+status=0
+timeout 300 qemu-system-x86_64 -m 256 -display none -serial stdio -kernel sysroot/boot/sel4-dev -initrd sysroot/boot/init.elf \
+    -device isa-debug-exit,iobase=0xf4,iosize=0x04 || status=$?
+# isa-debug-exit makes qemu's status (code << 1) | 1, so mantle::EXIT_SUCCESS (0x10) is 33. anything else is a
+# failure, including 1, which is also what qemu exits with when it can't start.
+if [ $status -eq 33 ]; then
+    exit 0
+elif [ $status -gt 1 ] && [ $((status & 1)) -eq 1 ]; then
+    echo "tests failed (exit code $((status >> 1)))"
+else
+    echo "tests did not finish (qemu status $status)"
+fi
+exit 1
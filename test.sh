@@ -1,5 +1,25 @@
 #!/bin/bash -e
 
 echo "Use Ctrl-A x to quit qemu"
+# COM2 is for the shell's gdb command: 'target remote :1234' from gdb once it's running
 # -nographic 
-qemu-system-x86_64 -m 256 -display sdl -serial stdio -kernel sysroot/boot/sel4-dev -initrd sysroot/boot/init.elf
+# isa-debug-exit makes qemu's status (code << 1) | 1 when the root task calls mantle::exit(code). only
+# mantle::EXIT_SUCCESS (0x10, so 33) is a success: qemu exits with 1 itself when it fails to start.
+# FAT_DIR=somewhere shares a host directory as the first IDE disk, for the shell's `mount hda <directory>`. qemu
+# makes it FAT16; use fat:32:rw: for FAT32.
+DRIVES=""
+if [ -n "$FAT_DIR" ]; then
+    DRIVES="-drive file=fat:rw:$FAT_DIR,format=raw,if=ide,index=0"
+fi
+status=0
+qemu-system-x86_64 -m 256 -display sdl -serial stdio -serial tcp::1234,server,nowait -kernel sysroot/boot/sel4-dev -initrd sysroot/boot/init.elf \
+    $DRIVES -device isa-debug-exit,iobase=0xf4,iosize=0x04 || status=$?
+if [ $status -eq 33 ]; then
+    echo "exited successfully"
+    exit 0
+elif [ $status -gt 1 ] && [ $((status & 1)) -eq 1 ]; then
+    echo "exited with code $((status >> 1))"
+else
+    echo "qemu exited with status $status"
+fi
+exit 1